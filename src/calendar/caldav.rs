@@ -0,0 +1,243 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use reqwest::Method;
+
+use super::ical::{self, Component};
+use super::{CalendarProvider, CreatedEvent, EventDetails, EventRef};
+use crate::models::BusyPeriod;
+
+/// CalDAV calendar implementation (Fastmail, Nextcloud, Radicale, ...)
+pub struct CalDavCalendar {
+    client: reqwest::Client,
+    calendar_url: String,
+    username: String,
+    password: String,
+}
+
+impl CalDavCalendar {
+    /// Create a new CalDAV client for the calendar collection at `calendar_url`
+    pub fn new(calendar_url: &str, username: &str, password: &str) -> Result<Self> {
+        let parsed = url::Url::parse(calendar_url).context("Invalid CalDAV URL")?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            anyhow::bail!("CalDAV URL must use http or https");
+        }
+
+        // Collection URLs need a trailing slash so event URLs resolve inside them
+        let mut calendar_url = calendar_url.to_string();
+        if !calendar_url.ends_with('/') {
+            calendar_url.push('/');
+        }
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            calendar_url,
+            username: username.to_string(),
+            password: password.to_string(),
+        })
+    }
+
+    fn request(&self, method: Method, url: &str) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, url)
+            .basic_auth(&self.username, Some(&self.password))
+    }
+
+    /// URL of the resource holding the event with `uid`
+    fn event_url(&self, uid: &str) -> String {
+        format!("{}{}.ics", self.calendar_url, urlencoding::encode(uid))
+    }
+
+    /// Write an event resource; `create` refuses to overwrite an existing one
+    async fn put_event(&self, uid: &str, event: Component, create: bool) -> Result<CreatedEvent> {
+        let body = ical::vcalendar().with_component(event).to_string();
+
+        let request = self
+            .request(Method::PUT, &self.event_url(uid))
+            .header("Content-Type", "text/calendar; charset=utf-8");
+        let request = if create {
+            request.header("If-None-Match", "*")
+        } else {
+            request.header("If-Match", "*")
+        };
+
+        let resp = request
+            .body(body)
+            .send()
+            .await
+            .context("Failed to write CalDAV event")?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let error = resp.text().await.unwrap_or_default();
+            anyhow::bail!("CalDAV event write failed: {} - {}", status, error);
+        }
+
+        Ok(CreatedEvent {
+            event: EventRef::new(uid),
+            html_link: None,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl CalendarProvider for CalDavCalendar {
+    async fn get_busy_periods(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<BusyPeriod>> {
+        let body = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<C:free-busy-query xmlns:C="urn:ietf:params:xml:ns:caldav">
+  <C:time-range start="{}" end="{}"/>
+</C:free-busy-query>"#,
+            ical::format_utc(start),
+            ical::format_utc(end)
+        );
+
+        let resp = self
+            .request(Method::from_bytes(b"REPORT").unwrap(), &self.calendar_url)
+            .header("Depth", "1")
+            .header("Content-Type", "application/xml; charset=utf-8")
+            .body(body)
+            .send()
+            .await
+            .context("Failed to query CalDAV free/busy")?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let error = resp.text().await.unwrap_or_default();
+            anyhow::bail!("CalDAV free/busy query failed: {} - {}", status, error);
+        }
+
+        let text = resp
+            .text()
+            .await
+            .context("Failed to read free/busy response")?;
+
+        parse_free_busy(&text)
+    }
+
+    async fn create_event(&self, event: &EventDetails) -> Result<CreatedEvent> {
+        let uid = format!("{}@meetd", uuid::Uuid::new_v4());
+        let event = ical::vevent(&uid, event);
+        self.put_event(&uid, event, true).await
+    }
+
+    async fn update_event(&self, event: &EventRef, details: &EventDetails) -> Result<CreatedEvent> {
+        let vevent = ical::vevent(&event.id, details);
+        self.put_event(&event.id, vevent, false).await
+    }
+
+    async fn delete_event(&self, event: &EventRef) -> Result<()> {
+        let resp = self
+            .request(Method::DELETE, &self.event_url(&event.id))
+            .send()
+            .await
+            .context("Failed to delete CalDAV event")?;
+
+        // Already gone is as good as deleted
+        if !resp.status().is_success() && resp.status() != reqwest::StatusCode::NOT_FOUND {
+            let status = resp.status();
+            let error = resp.text().await.unwrap_or_default();
+            anyhow::bail!("CalDAV event deletion failed: {} - {}", status, error);
+        }
+
+        Ok(())
+    }
+}
+
+/// Extract busy periods from a VFREEBUSY response
+fn parse_free_busy(text: &str) -> Result<Vec<BusyPeriod>> {
+    let mut busy_periods = Vec::new();
+
+    for root in ical::parse(text)? {
+        for fb in root.find_all("VFREEBUSY") {
+            for prop in fb.properties_named("FREEBUSY") {
+                // FBTYPE defaults to BUSY; FREE periods carry no information for us
+                let fb_type = prop.param("FBTYPE").unwrap_or("BUSY");
+                if fb_type.eq_ignore_ascii_case("FREE") {
+                    continue;
+                }
+
+                for period in prop.value.split(',') {
+                    let (start, rest) = period
+                        .split_once('/')
+                        .with_context(|| format!("Invalid FREEBUSY period: {}", period))?;
+                    let start = ical::parse_date_time(start)?;
+                    let end = if rest.starts_with(['P', '+', '-']) {
+                        start + ical::parse_duration(rest)?
+                    } else {
+                        ical::parse_date_time(rest)?
+                    };
+                    busy_periods.push(BusyPeriod {
+                        start,
+                        end,
+                        title: None,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(busy_periods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_free_busy() {
+        let response = "BEGIN:VCALENDAR\r\n\
+            VERSION:2.0\r\n\
+            BEGIN:VFREEBUSY\r\n\
+            DTSTART:20260203T000000Z\r\n\
+            DTEND:20260204T000000Z\r\n\
+            FREEBUSY:20260203T100000Z/20260203T110000Z,20260203T140000Z/PT30M\r\n\
+            FREEBUSY;FBTYPE=BUSY-TENTATIVE:20260203T160000Z/20260203T170000Z\r\n\
+            FREEBUSY;FBTYPE=FREE:20260203T180000Z/20260203T190000Z\r\n\
+            END:VFREEBUSY\r\n\
+            END:VCALENDAR\r\n";
+
+        let busy = parse_free_busy(response).unwrap();
+        assert_eq!(busy.len(), 3);
+        assert_eq!(ical::format_utc(busy[0].start), "20260203T100000Z");
+        assert_eq!(ical::format_utc(busy[0].end), "20260203T110000Z");
+        assert_eq!(ical::format_utc(busy[1].end), "20260203T143000Z");
+        assert_eq!(ical::format_utc(busy[2].start), "20260203T160000Z");
+    }
+
+    /// Runs against a real CalDAV server, e.g. a local Radicale:
+    ///
+    /// ```sh
+    /// MEETD_TEST_CALDAV_URL=http://localhost:5232/alice/calendar/ \
+    /// MEETD_TEST_CALDAV_USER=alice MEETD_TEST_CALDAV_PASSWORD=secret \
+    /// cargo test caldav -- --ignored
+    /// ```
+    #[tokio::test]
+    #[ignore]
+    async fn test_caldav_server_round_trip() {
+        let url = std::env::var("MEETD_TEST_CALDAV_URL").unwrap();
+        let user = std::env::var("MEETD_TEST_CALDAV_USER").unwrap_or_default();
+        let password = std::env::var("MEETD_TEST_CALDAV_PASSWORD").unwrap_or_default();
+        let cal = CalDavCalendar::new(&url, &user, &password).unwrap();
+
+        let start = Utc::now() + chrono::Duration::days(30);
+        let start =
+            DateTime::from_timestamp(start.timestamp() - start.timestamp() % 60, 0).unwrap();
+        let end = start + chrono::Duration::minutes(45);
+        cal.create_event(&EventDetails::new("meetd test", start, end))
+            .await
+            .unwrap();
+
+        let busy = cal
+            .get_busy_periods(
+                start - chrono::Duration::hours(1),
+                end + chrono::Duration::hours(1),
+            )
+            .await
+            .unwrap();
+        assert!(busy.iter().any(|b| b.start <= start && b.end >= end));
+    }
+}
@@ -4,13 +4,20 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use super::{CalendarProvider, CreatedEvent};
-use crate::models::BusyPeriod;
+use super::{merge_busy_periods, CalendarProvider, CreatedEvent, EventDetails, EventRef};
+use crate::models::{BusyPeriod, CalendarSelection, Conference, ConferenceKind};
+
+/// The calendar Google resolves to the account's own calendar
+const PRIMARY_CALENDAR: &str = "primary";
 
 /// Google Calendar implementation using direct API calls
 pub struct GoogleCalendar {
     client: reqwest::Client,
     token_manager: Arc<RwLock<TokenManager>>,
+    /// Calendars whose events count as busy time
+    busy_calendars: Vec<String>,
+    /// Calendar that new events are created in
+    write_calendar: String,
 }
 
 struct TokenManager {
@@ -89,9 +96,36 @@ impl GoogleCalendar {
         Ok(Self {
             client,
             token_manager,
+            busy_calendars: vec![PRIMARY_CALENDAR.to_string()],
+            write_calendar: PRIMARY_CALENDAR.to_string(),
         })
     }
 
+    /// Use the chosen calendars instead of just `primary`
+    pub fn with_selection(mut self, selection: &CalendarSelection) -> Self {
+        if !selection.busy.is_empty() {
+            self.busy_calendars = selection.busy.clone();
+        }
+        if let Some(write) = &selection.write {
+            self.write_calendar = write.clone();
+        }
+        self
+    }
+
+    /// Events collection of `calendar`
+    fn events_url(calendar: &str) -> String {
+        format!(
+            "https://www.googleapis.com/calendar/v3/calendars/{}/events",
+            urlencoding::encode(calendar)
+        )
+    }
+
+    /// The calendar `event` was made in; the write calendar for events
+    /// recorded before that was kept
+    fn event_calendar<'a>(&'a self, event: &'a EventRef) -> &'a str {
+        event.calendar_id.as_deref().unwrap_or(&self.write_calendar)
+    }
+
     async fn get_token(&self) -> Result<String> {
         let mut manager = self.token_manager.write().await;
         manager.get_access_token(&self.client).await
@@ -255,9 +289,11 @@ impl CalendarProvider for GoogleCalendar {
         let request = FreeBusyRequest {
             time_min: start,
             time_max: end,
-            items: vec![FreeBusyItem {
-                id: "primary".to_string(),
-            }],
+            items: self
+                .busy_calendars
+                .iter()
+                .map(|id| FreeBusyItem { id: id.clone() })
+                .collect(),
         };
 
         let resp = self
@@ -282,6 +318,12 @@ impl CalendarProvider for GoogleCalendar {
         #[derive(Deserialize)]
         struct CalendarBusy {
             busy: Option<Vec<BusySlot>>,
+            errors: Option<Vec<CalendarError>>,
+        }
+
+        #[derive(Deserialize)]
+        struct CalendarError {
+            reason: String,
         }
 
         #[derive(Deserialize)]
@@ -298,7 +340,11 @@ impl CalendarProvider for GoogleCalendar {
         let mut busy_periods = Vec::new();
 
         if let Some(calendars) = response.calendars {
-            for (_cal_id, cal_data) in calendars {
+            for (cal_id, cal_data) in calendars {
+                // An unreadable calendar would otherwise look completely free
+                if let Some(error) = cal_data.errors.as_ref().and_then(|e| e.first()) {
+                    anyhow::bail!("Free/busy query failed for {}: {}", cal_id, error.reason);
+                }
                 if let Some(busy_list) = cal_data.busy {
                     for busy in busy_list {
                         busy_periods.push(BusyPeriod {
@@ -311,57 +357,72 @@ impl CalendarProvider for GoogleCalendar {
             }
         }
 
-        Ok(busy_periods)
+        Ok(merge_busy_periods(busy_periods))
     }
 
-    async fn create_event(
+    async fn get_busy_events(
         &self,
-        title: &str,
-        description: Option<&str>,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
-        attendee_email: Option<&str>,
-    ) -> Result<CreatedEvent> {
+    ) -> Result<Vec<BusyPeriod>> {
         let token = self.get_token().await?;
 
-        #[derive(Serialize)]
-        #[serde(rename_all = "camelCase")]
-        struct EventRequest {
-            summary: String,
-            #[serde(skip_serializing_if = "Option::is_none")]
-            description: Option<String>,
-            start: EventDateTime,
-            end: EventDateTime,
-            #[serde(skip_serializing_if = "Option::is_none")]
-            attendees: Option<Vec<Attendee>>,
-        }
+        let mut busy_periods = Vec::new();
+        for calendar_id in &self.busy_calendars {
+            let mut page_token: Option<String> = None;
+            loop {
+                let mut query = vec![
+                    ("timeMin", start.to_rfc3339()),
+                    ("timeMax", end.to_rfc3339()),
+                    ("singleEvents", "true".to_string()),
+                    ("maxResults", "2500".to_string()),
+                ];
+                if let Some(page) = page_token.take() {
+                    query.push(("pageToken", page));
+                }
 
-        #[derive(Serialize)]
-        #[serde(rename_all = "camelCase")]
-        struct EventDateTime {
-            date_time: DateTime<Utc>,
-        }
+                let resp = self
+                    .client
+                    .get(format!(
+                        "https://www.googleapis.com/calendar/v3/calendars/{}/events",
+                        urlencoding::encode(calendar_id)
+                    ))
+                    .bearer_auth(&token)
+                    .query(&query)
+                    .send()
+                    .await
+                    .context("Failed to list events")?;
+
+                if !resp.status().is_success() {
+                    let error = resp.text().await.unwrap_or_default();
+                    anyhow::bail!("Event list failed for {}: {}", calendar_id, error);
+                }
 
-        #[derive(Serialize)]
-        struct Attendee {
-            email: String,
+                let page: EventList = resp.json().await.context("Failed to parse event list")?;
+
+                busy_periods.extend(page.items.iter().filter_map(EventItem::busy_period));
+
+                match page.next_page_token {
+                    Some(next) => page_token = Some(next),
+                    None => break,
+                }
+            }
         }
 
-        let event = EventRequest {
-            summary: title.to_string(),
-            description: description.map(|s| s.to_string()),
-            start: EventDateTime { date_time: start },
-            end: EventDateTime { date_time: end },
-            attendees: attendee_email.map(|e| {
-                vec![Attendee {
-                    email: e.to_string(),
-                }]
-            }),
-        };
+        Ok(merge_busy_periods(busy_periods))
+    }
+
+    async fn create_event(&self, event: &EventDetails) -> Result<CreatedEvent> {
+        let token = self.get_token().await?;
+        let event = EventRequest::new(event);
 
+        // conferenceDataVersion=1 lets Google create the Meet link we ask for
         let resp = self
             .client
-            .post("https://www.googleapis.com/calendar/v3/calendars/primary/events?sendNotifications=true")
+            .post(format!(
+                "{}?sendNotifications=true&conferenceDataVersion=1",
+                Self::events_url(&self.write_calendar)
+            ))
             .bearer_auth(&token)
             .json(&event)
             .send()
@@ -373,21 +434,260 @@ impl CalendarProvider for GoogleCalendar {
             anyhow::bail!("Event creation failed: {}", error);
         }
 
-        #[derive(Deserialize)]
-        #[serde(rename_all = "camelCase")]
-        struct EventResponse {
-            id: Option<String>,
-            html_link: Option<String>,
+        created_event(resp, &self.write_calendar).await
+    }
+
+    async fn update_event(&self, event: &EventRef, details: &EventDetails) -> Result<CreatedEvent> {
+        let token = self.get_token().await?;
+        let calendar = self.event_calendar(event);
+        // The event keeps the conference it was created with
+        let request = EventRequest {
+            conference_data: None,
+            ..EventRequest::new(details)
+        };
+
+        // sendUpdates moves the meeting in the attendee's calendar too
+        let resp = self
+            .client
+            .patch(format!(
+                "{}/{}?sendUpdates=all",
+                Self::events_url(calendar),
+                urlencoding::encode(&event.id)
+            ))
+            .bearer_auth(&token)
+            .json(&request)
+            .send()
+            .await
+            .context("Failed to update event")?;
+
+        if !resp.status().is_success() {
+            let error = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Event update failed: {}", error);
         }
 
-        let created: EventResponse = resp
-            .json()
+        created_event(resp, calendar).await
+    }
+
+    async fn delete_event(&self, event: &EventRef) -> Result<()> {
+        let token = self.get_token().await?;
+
+        let resp = self
+            .client
+            .delete(format!(
+                "{}/{}?sendUpdates=all",
+                Self::events_url(self.event_calendar(event)),
+                urlencoding::encode(&event.id)
+            ))
+            .bearer_auth(&token)
+            .send()
             .await
-            .context("Failed to parse event response")?;
+            .context("Failed to delete event")?;
+
+        // 410 Gone: already deleted
+        if !resp.status().is_success() && resp.status() != reqwest::StatusCode::GONE {
+            let error = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Event deletion failed: {}", error);
+        }
+
+        Ok(())
+    }
+}
 
-        Ok(CreatedEvent {
+/// Body of an event insert or update
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EventRequest {
+    summary: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    start: EventDateTime,
+    end: EventDateTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attendees: Option<Vec<Attendee>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recurrence: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conference_data: Option<ConferenceData>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConferenceData {
+    create_request: ConferenceCreateRequest,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConferenceCreateRequest {
+    request_id: String,
+    conference_solution_key: ConferenceSolutionKey,
+}
+
+#[derive(Serialize)]
+struct ConferenceSolutionKey {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EventDateTime {
+    date_time: DateTime<Utc>,
+    /// Required by Google for recurring events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_zone: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct Attendee {
+    email: String,
+}
+
+impl EventRequest {
+    fn new(details: &EventDetails) -> Self {
+        let time_zone = details
+            .recurrence
+            .as_ref()
+            .map(|_| details.time_zone.name());
+
+        // Google only creates Meet conferences; other join links go in the description
+        let conference_data = match &details.conference {
+            Some(Conference::Generated {
+                kind: ConferenceKind::GoogleMeet,
+                ..
+            }) => Some(ConferenceData {
+                create_request: ConferenceCreateRequest {
+                    request_id: uuid::Uuid::new_v4().to_string(),
+                    conference_solution_key: ConferenceSolutionKey {
+                        kind: "hangoutsMeet",
+                    },
+                },
+            }),
+            _ => None,
+        };
+        let description = match details.conference.as_ref().and_then(|c| c.url()) {
+            Some(url) => Some(match &details.description {
+                Some(description) => format!("{}\n\nJoin: {}", description, url),
+                None => format!("Join: {}", url),
+            }),
+            None => details.description.clone(),
+        };
+
+        Self {
+            summary: details.title.clone(),
+            description,
+            start: EventDateTime {
+                date_time: details.start,
+                time_zone,
+            },
+            end: EventDateTime {
+                date_time: details.end,
+                time_zone,
+            },
+            attendees: details.attendee_email.as_ref().map(|e| {
+                vec![Attendee {
+                    email: e.to_string(),
+                }]
+            }),
+            recurrence: details
+                .recurrence
+                .as_ref()
+                .map(|rule| vec![format!("RRULE:{}", rule)]),
+            location: details.location.clone(),
+            conference_data,
+        }
+    }
+}
+
+/// Read the event Google sent back after an insert or update in `calendar`
+async fn created_event(resp: reqwest::Response, calendar: &str) -> Result<CreatedEvent> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct EventResponse {
+        id: Option<String>,
+        html_link: Option<String>,
+    }
+
+    let created: EventResponse = resp
+        .json()
+        .await
+        .context("Failed to parse event response")?;
+
+    Ok(CreatedEvent {
+        event: EventRef {
             id: created.id.unwrap_or_default(),
-            html_link: created.html_link,
+            account_id: None,
+            calendar_id: Some(calendar.to_string()),
+        },
+        html_link: created.html_link,
+    })
+}
+
+/// One page of `events.list`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventList {
+    #[serde(default)]
+    items: Vec<EventItem>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EventItem {
+    status: Option<String>,
+    summary: Option<String>,
+    transparency: Option<String>,
+    start: Option<EventTime>,
+    end: Option<EventTime>,
+    #[serde(default)]
+    attendees: Vec<EventAttendee>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventTime {
+    date_time: Option<DateTime<Utc>>,
+    /// All-day events only have a date
+    date: Option<chrono::NaiveDate>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventAttendee {
+    #[serde(rename = "self", default)]
+    is_self: bool,
+    response_status: Option<String>,
+}
+
+impl EventTime {
+    fn utc(&self) -> Option<DateTime<Utc>> {
+        self.date_time.or_else(|| {
+            self.date
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|d| d.and_utc())
+        })
+    }
+}
+
+impl EventItem {
+    /// The busy time this event blocks, matching what freeBusy would report
+    fn busy_period(&self) -> Option<BusyPeriod> {
+        let free = self.status.as_deref() == Some("cancelled")
+            || self.transparency.as_deref() == Some("transparent")
+            || self
+                .attendees
+                .iter()
+                .any(|a| a.is_self && a.response_status.as_deref() == Some("declined"));
+        if free {
+            return None;
+        }
+
+        Some(BusyPeriod {
+            start: self.start.as_ref()?.utc()?,
+            end: self.end.as_ref()?.utc()?,
+            title: self.summary.clone(),
         })
     }
 }
@@ -402,7 +702,45 @@ pub struct OAuthCredentials {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::calendar::{find_available_slots, score_slot};
+    use crate::calendar::{
+        can_attend, find_available_slots, intersect_availability, merge_busy_periods, occurrences,
+        rrule::RecurrenceRule, score_slot,
+    };
+    use crate::models::{SlotPreferences, SlotRules, TimeSlot};
+    use chrono_tz::Tz;
+
+    #[test]
+    fn test_occurrences() {
+        let start = DateTime::parse_from_rfc3339("2026-02-03T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let slot = TimeSlot::new(start, start + chrono::Duration::minutes(30));
+
+        assert_eq!(occurrences(&slot, None, &Tz::UTC, 10).len(), 1);
+
+        let weekly = RecurrenceRule::parse("FREQ=WEEKLY;COUNT=3").unwrap();
+        let slots = occurrences(&slot, Some(&weekly), &Tz::UTC, 10);
+        assert_eq!(slots.len(), 3);
+        assert_eq!(slots[2].start, start + chrono::Duration::weeks(2));
+        assert_eq!(slots[2].duration_minutes(), 30);
+
+        let daily = RecurrenceRule::parse("FREQ=DAILY").unwrap();
+        assert_eq!(occurrences(&slot, Some(&daily), &Tz::UTC, 5).len(), 5);
+
+        // 09:00 in New York is 14:00 UTC before DST starts on 8 March, 13:00 after
+        let new_york: Tz = "America/New_York".parse().unwrap();
+        let start = DateTime::parse_from_rfc3339("2026-03-03T14:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let slot = TimeSlot::new(start, start + chrono::Duration::minutes(30));
+        let slots = occurrences(&slot, Some(&weekly), &new_york, 10);
+        assert_eq!(slots[0].start, start);
+        assert_eq!(
+            slots[1].start,
+            start + chrono::Duration::weeks(1) - chrono::Duration::hours(1)
+        );
+        assert_eq!(slots[1].duration_minutes(), 30);
+    }
 
     #[test]
     fn test_find_available_slots() {
@@ -416,7 +754,14 @@ mod tests {
             title: None,
         }];
 
-        let slots = find_available_slots(&busy, window_start, window_end, 30);
+        let slots = find_available_slots(
+            &busy,
+            window_start,
+            window_end,
+            30,
+            &[],
+            &SlotRules::default(),
+        );
 
         assert!(!slots.is_empty());
         assert_eq!(slots[0].start, window_start);
@@ -426,6 +771,227 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_find_available_slots_with_buffers_and_step() {
+        let now = Utc::now();
+        let window_start = now;
+        let window_end = now + chrono::Duration::hours(4);
+
+        let busy = vec![BusyPeriod {
+            start: now + chrono::Duration::hours(2),
+            end: now + chrono::Duration::hours(3),
+            title: None,
+        }];
+
+        let rules = SlotRules::combine(
+            &SlotPreferences {
+                step_minutes: Some(15),
+                buffer_after_minutes: Some(10),
+                ..Default::default()
+            },
+            &[&SlotPreferences {
+                buffer_before_minutes: Some(15),
+                ..Default::default()
+            }],
+        );
+
+        let slots = find_available_slots(&busy, window_start, window_end, 30, &[], &rules);
+
+        assert_eq!(
+            slots[1].start - slots[0].start,
+            chrono::Duration::minutes(15)
+        );
+        for slot in &slots {
+            assert!(
+                slot.end + chrono::Duration::minutes(10) <= busy[0].start
+                    || slot.start >= busy[0].end + chrono::Duration::minutes(15)
+            );
+        }
+        // First slot after the meeting waits out the buffer
+        let after = slots.iter().find(|s| s.start >= busy[0].end).unwrap();
+        assert_eq!(after.start, busy[0].end + chrono::Duration::minutes(15));
+    }
+
+    #[test]
+    fn test_slot_rules_clamp_window() {
+        let now = Utc::now();
+        let rules = SlotRules::combine(
+            &SlotPreferences {
+                min_notice_minutes: Some(240),
+                max_horizon_days: Some(14),
+                ..Default::default()
+            },
+            &[&SlotPreferences {
+                max_horizon_days: Some(7),
+                ..Default::default()
+            }],
+        );
+
+        let (start, end) = rules.clamp_window(now, now + chrono::Duration::days(30), now);
+        assert_eq!(start, now + chrono::Duration::hours(4));
+        assert_eq!(end, now + chrono::Duration::days(7));
+    }
+
+    #[test]
+    fn test_intersect_availability_many_participants() {
+        let now = Utc::now();
+        let period = |start: i64, end: i64| BusyPeriod {
+            start: now + chrono::Duration::hours(start),
+            end: now + chrono::Duration::hours(end),
+            title: None,
+        };
+
+        let rules = SlotRules::default();
+        let required = vec![vec![period(0, 1)], vec![period(2, 3)], vec![period(4, 5)]];
+        let slots = intersect_availability(
+            &required,
+            now,
+            now + chrono::Duration::hours(6),
+            60,
+            &[],
+            &rules,
+        );
+
+        assert_eq!(slots.len(), 3);
+        for slot in &slots {
+            for busy in required.iter().flatten() {
+                assert!(slot.end <= busy.start || slot.start >= busy.end);
+            }
+        }
+
+        // An optional participant busy from 3-4 can only make the other two
+        let optional = [period(3, 4)];
+        let attendable: Vec<_> = slots
+            .iter()
+            .filter(|slot| can_attend(slot, &optional, None, &rules))
+            .collect();
+        assert_eq!(attendable.len(), 2);
+    }
+
+    #[test]
+    fn test_event_list_busy_periods() {
+        let page: EventList = serde_json::from_str(
+            r#"{
+                "items": [
+                    {"summary": "Standup", "start": {"dateTime": "2026-02-03T09:00:00Z"},
+                     "end": {"dateTime": "2026-02-03T09:15:00Z"}},
+                    {"summary": "Offsite", "start": {"date": "2026-02-04"},
+                     "end": {"date": "2026-02-05"}},
+                    {"summary": "Focus", "transparency": "transparent",
+                     "start": {"dateTime": "2026-02-03T10:00:00Z"},
+                     "end": {"dateTime": "2026-02-03T11:00:00Z"}},
+                    {"summary": "Skipped", "attendees": [{"self": true, "responseStatus": "declined"}],
+                     "start": {"dateTime": "2026-02-03T12:00:00Z"},
+                     "end": {"dateTime": "2026-02-03T13:00:00Z"}},
+                    {"status": "cancelled"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let busy: Vec<BusyPeriod> = page
+            .items
+            .iter()
+            .filter_map(EventItem::busy_period)
+            .collect();
+        assert_eq!(busy.len(), 2);
+        assert_eq!(busy[0].title.as_deref(), Some("Standup"));
+        assert_eq!(busy[1].end - busy[1].start, chrono::Duration::days(1));
+    }
+
+    #[test]
+    fn test_event_request_location_and_conference() {
+        let start = Utc::now();
+        let details = EventDetails {
+            location: Some("Room 4".to_string()),
+            conference: Some(Conference::google_meet()),
+            ..EventDetails::new("Sync", start, start + chrono::Duration::minutes(30))
+        };
+        let json = serde_json::to_value(EventRequest::new(&details)).unwrap();
+        assert_eq!(json["location"], "Room 4");
+        assert_eq!(
+            json["conferenceData"]["createRequest"]["conferenceSolutionKey"]["type"],
+            "hangoutsMeet"
+        );
+
+        let details = EventDetails {
+            description: Some("Agenda".to_string()),
+            conference: Some(Conference::Url("https://zoom.us/j/123".to_string())),
+            ..details
+        };
+        let json = serde_json::to_value(EventRequest::new(&details)).unwrap();
+        assert!(json.get("conferenceData").is_none());
+        assert_eq!(json["description"], "Agenda\n\nJoin: https://zoom.us/j/123");
+    }
+
+    #[test]
+    fn test_merge_busy_periods() {
+        let now = Utc::now();
+        let period = |start: i64, end: i64, title: Option<&str>| BusyPeriod {
+            start: now + chrono::Duration::minutes(start),
+            end: now + chrono::Duration::minutes(end),
+            title: title.map(|t| t.to_string()),
+        };
+
+        let merged = merge_busy_periods(vec![
+            period(60, 90, Some("Team sync")),
+            period(0, 30, None),
+            period(30, 45, None),
+            period(70, 120, Some("Dentist")),
+            period(200, 210, None),
+        ]);
+
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[0].end, now + chrono::Duration::minutes(45));
+        assert_eq!(merged[1].start, now + chrono::Duration::minutes(60));
+        assert_eq!(merged[1].end, now + chrono::Duration::minutes(120));
+        assert_eq!(merged[1].title.as_deref(), Some("Team sync, Dentist"));
+        assert_eq!(merged[2].start, now + chrono::Duration::minutes(200));
+    }
+
+    #[test]
+    fn test_find_available_slots_within_working_hours() {
+        use crate::models::WorkingHours;
+
+        // Tuesday 2026-02-03, whole day free for both
+        let window_start = DateTime::parse_from_rfc3339("2026-02-03T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let window_end = window_start + chrono::Duration::days(1);
+
+        let berlin = WorkingHours::default_in("Europe/Berlin").unwrap();
+        let new_york = WorkingHours::default_in("America/New_York").unwrap();
+
+        // 09:00-17:00 Berlin is 08:00-16:00 UTC; New York is 14:00-22:00 UTC
+        let slots = find_available_slots(
+            &[],
+            window_start,
+            window_end,
+            60,
+            &[berlin.clone(), new_york.clone()],
+            &SlotRules::default(),
+        );
+        assert!(!slots.is_empty());
+        assert_eq!(slots[0].start.format("%H:%M").to_string(), "14:00");
+        assert_eq!(
+            slots.last().unwrap().end.format("%H:%M").to_string(),
+            "16:00"
+        );
+
+        // Scoring happens in local time: 14:00 UTC is 15:00 in Berlin, 09:00 in New York
+        let slot = &slots[0];
+        let now = window_start - chrono::Duration::days(2);
+        let local = score_slot(slot, now, &[berlin, new_york]);
+        assert!(
+            local
+                > score_slot(
+                    slot,
+                    now,
+                    &[WorkingHours::default_in("Asia/Tokyo").unwrap()]
+                )
+        );
+    }
+
     #[test]
     fn test_score_slot() {
         let now = Utc::now();
@@ -435,7 +1001,7 @@ mod tests {
             now + chrono::Duration::hours(24) + chrono::Duration::minutes(30),
         );
 
-        let score = score_slot(&good_slot, now);
+        let score = score_slot(&good_slot, now, &[]);
         assert!(score > 0.5);
     }
 }
@@ -0,0 +1,555 @@
+//! Minimal iCalendar (RFC 5545) reading and writing
+//!
+//! Only covers what meetd needs to talk to CalDAV servers and ICS feeds:
+//! content line (un)folding, nested components, date-times (UTC or in an
+//! IANA `TZID`), durations and text escaping.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+
+use super::EventDetails;
+use crate::models::local_to_utc;
+
+/// A single content line, e.g. `DTSTART;TZID=Europe/Berlin:20260203T100000`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    pub name: String,
+    pub params: Vec<(String, String)>,
+    pub value: String,
+}
+
+impl Property {
+    pub fn new(name: &str, value: impl Into<String>) -> Self {
+        Self {
+            name: name.to_ascii_uppercase(),
+            params: Vec::new(),
+            value: value.into(),
+        }
+    }
+
+    /// Add a parameter (builder style)
+    pub fn with_param(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.params.push((name.to_ascii_uppercase(), value.into()));
+        self
+    }
+
+    /// Get a parameter value by name
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// A component such as VCALENDAR, VEVENT or VFREEBUSY
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Component {
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub components: Vec<Component>,
+}
+
+impl Component {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_ascii_uppercase(),
+            properties: Vec::new(),
+            components: Vec::new(),
+        }
+    }
+
+    /// Add a property (builder style)
+    pub fn with_property(mut self, property: Property) -> Self {
+        self.properties.push(property);
+        self
+    }
+
+    /// Add a nested component (builder style)
+    pub fn with_component(mut self, component: Component) -> Self {
+        self.components.push(component);
+        self
+    }
+
+    /// Get the first property with the given name
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Iterate over all properties with the given name
+    pub fn properties_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Property> {
+        self.properties
+            .iter()
+            .filter(move |p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Recursively collect all descendant components with the given name
+    pub fn find_all(&self, name: &str) -> Vec<&Component> {
+        let mut found = Vec::new();
+        for child in &self.components {
+            if child.name.eq_ignore_ascii_case(name) {
+                found.push(child);
+            }
+            found.extend(child.find_all(name));
+        }
+        found
+    }
+}
+
+impl std::fmt::Display for Component {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\r\n", fold_line(&format!("BEGIN:{}", self.name)))?;
+        for prop in &self.properties {
+            let mut line = prop.name.clone();
+            for (name, value) in &prop.params {
+                line.push(';');
+                line.push_str(name);
+                line.push('=');
+                if value.contains([':', ';', ',']) {
+                    line.push('"');
+                    line.push_str(value);
+                    line.push('"');
+                } else {
+                    line.push_str(value);
+                }
+            }
+            line.push(':');
+            line.push_str(&prop.value);
+            write!(f, "{}\r\n", fold_line(&line))?;
+        }
+        for child in &self.components {
+            write!(f, "{}", child)?;
+        }
+        write!(f, "{}\r\n", fold_line(&format!("END:{}", self.name)))
+    }
+}
+
+/// An empty VCALENDAR with the standard meetd headers
+pub fn vcalendar() -> Component {
+    Component::new("VCALENDAR")
+        .with_property(Property::new("VERSION", "2.0"))
+        .with_property(Property::new("PRODID", "-//meetd//meetd//EN"))
+}
+
+/// Build a VEVENT for a meeting created by meetd
+///
+/// Recurring meetings outside UTC are written in local time with a TZID, so
+/// the calendar repeats them at the same wall-clock time meetd checked.
+pub fn vevent(uid: &str, details: &EventDetails) -> Component {
+    let time = |name: &str, at: DateTime<Utc>| {
+        if details.recurrence.is_none() || details.time_zone == Tz::UTC {
+            return Property::new(name, format_utc(at));
+        }
+        let local = at.with_timezone(&details.time_zone).naive_local();
+        Property::new(name, local.format("%Y%m%dT%H%M%S").to_string())
+            .with_param("TZID", details.time_zone.name())
+    };
+    let mut event = Component::new("VEVENT")
+        .with_property(Property::new("UID", uid))
+        .with_property(Property::new("DTSTAMP", format_utc(Utc::now())))
+        .with_property(time("DTSTART", details.start))
+        .with_property(time("DTEND", details.end))
+        .with_property(Property::new("SUMMARY", escape_text(&details.title)));
+
+    if let Some(rule) = &details.recurrence {
+        event = event.with_property(Property::new("RRULE", rule.as_str()));
+    }
+
+    if let Some(description) = &details.description {
+        event = event.with_property(Property::new("DESCRIPTION", escape_text(description)));
+    }
+
+    if let Some(location) = &details.location {
+        event = event.with_property(Property::new("LOCATION", escape_text(location)));
+    }
+
+    // RFC 7986 CONFERENCE; generated conferences need a provider that can create them
+    if let Some(url) = details.conference.as_ref().and_then(|c| c.url()) {
+        event = event.with_property(
+            Property::new("CONFERENCE", url)
+                .with_param("VALUE", "URI")
+                .with_param("FEATURE", "VIDEO"),
+        );
+    }
+
+    if let Some(email) = &details.attendee_email {
+        event = event.with_property(
+            Property::new("ATTENDEE", format!("mailto:{}", email))
+                .with_param("RSVP", "TRUE")
+                .with_param("PARTSTAT", "NEEDS-ACTION"),
+        );
+    }
+
+    event
+}
+
+/// Parse an iCalendar stream into its top-level components
+pub fn parse(input: &str) -> Result<Vec<Component>> {
+    let mut stack: Vec<Component> = Vec::new();
+    let mut roots = Vec::new();
+
+    for line in unfold(input) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let prop = parse_line(&line)?;
+
+        if prop.name == "BEGIN" {
+            stack.push(Component::new(&prop.value));
+        } else if prop.name == "END" {
+            let component = stack
+                .pop()
+                .with_context(|| format!("Unexpected END:{}", prop.value))?;
+            if !component.name.eq_ignore_ascii_case(&prop.value) {
+                anyhow::bail!("Mismatched END:{} for BEGIN:{}", prop.value, component.name);
+            }
+            match stack.last_mut() {
+                Some(parent) => parent.components.push(component),
+                None => roots.push(component),
+            }
+        } else if let Some(current) = stack.last_mut() {
+            current.properties.push(prop);
+        }
+    }
+
+    if let Some(open) = stack.last() {
+        anyhow::bail!("Unterminated component {}", open.name);
+    }
+
+    Ok(roots)
+}
+
+/// Join folded content lines (a line break followed by a space or tab)
+fn unfold(input: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in input.split('\n') {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        if let Some(rest) = raw.strip_prefix([' ', '\t']) {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(rest);
+                continue;
+            }
+        }
+        lines.push(raw.to_string());
+    }
+    lines
+}
+
+/// Parse one unfolded content line into a property
+fn parse_line(line: &str) -> Result<Property> {
+    // Find the first ':' that is not inside a quoted parameter value
+    let mut in_quotes = false;
+    let mut split_at = None;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ':' if !in_quotes => {
+                split_at = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let split_at = split_at.with_context(|| format!("Invalid content line: {}", line))?;
+    let (head, value) = (&line[..split_at], &line[split_at + 1..]);
+
+    let mut parts = head.split(';');
+    let name = parts.next().unwrap_or_default().to_ascii_uppercase();
+    let params = parts
+        .filter_map(|p| {
+            let (k, v) = p.split_once('=')?;
+            Some((k.to_ascii_uppercase(), v.trim_matches('"').to_string()))
+        })
+        .collect();
+
+    Ok(Property {
+        name,
+        params,
+        value: value.to_string(),
+    })
+}
+
+/// Fold a content line at 75 octets without splitting UTF-8 sequences
+fn fold_line(line: &str) -> String {
+    let mut out = String::with_capacity(line.len() + line.len() / 74 * 3);
+    let mut width = 0;
+    for c in line.chars() {
+        let len = c.len_utf8();
+        if width + len > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += len;
+    }
+    out
+}
+
+/// Format a UTC date-time as `YYYYMMDDTHHMMSSZ`
+pub fn format_utc(dt: DateTime<Utc>) -> String {
+    dt.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Parse a DATE or DATE-TIME value that has no time zone of its own
+///
+/// UTC (`Z`) values are exact; floating values and all-day DATE values
+/// (midnight) are read as UTC. Use [`parse_zoned`] for properties that may
+/// carry a `TZID`.
+pub fn parse_date_time(value: &str) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+    if let Some(utc) = value.strip_suffix('Z') {
+        return Ok(NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .with_context(|| format!("Invalid date-time: {}", value))?
+            .and_utc());
+    }
+    if let Ok(dt) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        return Ok(dt.and_utc());
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y%m%d")
+        .with_context(|| format!("Invalid date-time: {}", value))?;
+    Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+}
+
+/// A DATE or DATE-TIME as wall-clock time in a time zone
+///
+/// Recurrences repeat in wall-clock time, so they're expanded on `local`
+/// and each occurrence converted to UTC on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZonedTime {
+    pub local: NaiveDateTime,
+    pub tz: Tz,
+}
+
+impl ZonedTime {
+    pub fn to_utc(&self) -> DateTime<Utc> {
+        local_to_utc(&self.tz, self.local)
+    }
+}
+
+/// Parse a DATE or DATE-TIME value along with its `TZID` parameter
+///
+/// UTC (`Z`) values are in UTC whatever the `TZID`. Floating values, DATE
+/// values and `TZID`s that aren't IANA names are taken to be in `default_tz`.
+pub fn parse_zoned(value: &str, tzid: Option<&str>, default_tz: Tz) -> Result<ZonedTime> {
+    let value = value.trim();
+    let tz = if value.ends_with('Z') {
+        Tz::UTC
+    } else {
+        tzid.and_then(resolve_tzid).unwrap_or(default_tz)
+    };
+    Ok(ZonedTime {
+        local: parse_date_time(value)?.naive_utc(),
+        tz,
+    })
+}
+
+/// The IANA zone a `TZID` names
+///
+/// Some producers prefix the name with their own path (e.g.
+/// `/citadel.org/20190103_1/Europe/Berlin`), so trailing parts are tried too.
+pub fn resolve_tzid(tzid: &str) -> Option<Tz> {
+    let tzid = tzid.trim().trim_matches('"');
+    let mut rest = tzid;
+    loop {
+        if let Ok(tz) = rest.parse::<Tz>() {
+            return Some(tz);
+        }
+        rest = rest.split_once('/')?.1;
+    }
+}
+
+/// Parse a duration like `PT1H30M`, `P1D` or `-PT15M`
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let (negative, rest) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let rest = rest
+        .strip_prefix('P')
+        .with_context(|| format!("Invalid duration: {}", value))?;
+
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in rest.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' => number.push(c),
+            unit => {
+                let n: i64 = number
+                    .parse()
+                    .with_context(|| format!("Invalid duration: {}", value))?;
+                number.clear();
+                total += match (unit, in_time) {
+                    ('W', false) => Duration::weeks(n),
+                    ('D', false) => Duration::days(n),
+                    ('H', true) => Duration::hours(n),
+                    ('M', true) => Duration::minutes(n),
+                    ('S', true) => Duration::seconds(n),
+                    _ => anyhow::bail!("Invalid duration: {}", value),
+                };
+            }
+        }
+    }
+    if !number.is_empty() {
+        anyhow::bail!("Invalid duration: {}", value);
+    }
+
+    Ok(if negative { -total } else { total })
+}
+
+/// Escape a TEXT value
+pub fn escape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Unescape a TEXT value
+pub fn unescape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => out.push('\n'),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Conference;
+
+    #[test]
+    fn test_parse_nested_and_folded() {
+        let input = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nSUMMARY:Long\r\n  title\r\nDTSTART;TZID=\"Europe/Berlin\":20260203T100000\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let roots = parse(input).unwrap();
+        assert_eq!(roots.len(), 1);
+
+        let events = roots[0].find_all("VEVENT");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].property("SUMMARY").unwrap().value, "Long title");
+        let dtstart = events[0].property("DTSTART").unwrap();
+        assert_eq!(dtstart.param("tzid"), Some("Europe/Berlin"));
+        assert_eq!(dtstart.value, "20260203T100000");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cal = Component::new("VCALENDAR").with_component(
+            Component::new("VEVENT")
+                .with_property(Property::new("SUMMARY", escape_text("Sync; notes, etc")))
+                .with_property(
+                    Property::new("ATTENDEE", "mailto:bob@example.com").with_param("CN", "Bob"),
+                ),
+        );
+        let text = cal.to_string();
+        let parsed = parse(&text).unwrap();
+        assert_eq!(parsed, vec![cal]);
+        let summary = &parsed[0].find_all("VEVENT")[0]
+            .property("SUMMARY")
+            .unwrap()
+            .value;
+        assert_eq!(unescape_text(summary), "Sync; notes, etc");
+    }
+
+    #[test]
+    fn test_vevent() {
+        let start = parse_date_time("20260203T100000Z").unwrap();
+        let end = start + Duration::minutes(30);
+        let details = EventDetails {
+            description: Some("Agenda, notes".to_string()),
+            attendee_email: Some("bob@example.com".to_string()),
+            recurrence: Some("FREQ=WEEKLY;COUNT=4".to_string()),
+            location: Some("Room 4; 2nd floor".to_string()),
+            conference: Some(Conference::Url("https://zoom.us/j/123".to_string())),
+            ..EventDetails::new("Sync", start, end)
+        };
+        let cal = vcalendar().with_component(vevent("abc@meetd", &details));
+
+        let text = cal.to_string();
+        assert!(text.contains("RRULE:FREQ=WEEKLY;COUNT=4\r\n"));
+        assert!(text.contains("LOCATION:Room 4\\; 2nd floor\r\n"));
+        assert!(text.contains("CONFERENCE;VALUE=URI;FEATURE=VIDEO:https://zoom.us/j/123\r\n"));
+        assert!(text.contains("DTSTART:20260203T100000Z\r\n"));
+        assert!(text.contains("DESCRIPTION:Agenda\\, notes\r\n"));
+        assert!(text.contains("ATTENDEE;RSVP=TRUE;PARTSTAT=NEEDS-ACTION:mailto:bob@example.com"));
+
+        // Recurring meetings repeat in the organiser's timezone
+        let details = EventDetails {
+            time_zone: "Europe/Berlin".parse().unwrap(),
+            ..details
+        };
+        let text = vevent("abc@meetd", &details).to_string();
+        assert!(text.contains("DTSTART;TZID=Europe/Berlin:20260203T110000\r\n"));
+        assert!(text.contains("DTEND;TZID=Europe/Berlin:20260203T113000\r\n"));
+    }
+
+    #[test]
+    fn test_parse_zoned() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let winter = parse_zoned("20260203T100000", Some("Europe/Berlin"), Tz::UTC).unwrap();
+        assert_eq!(winter.tz, berlin);
+        assert_eq!(format_utc(winter.to_utc()), "20260203T090000Z");
+        let summer = parse_zoned("20260703T100000", Some("Europe/Berlin"), Tz::UTC).unwrap();
+        assert_eq!(format_utc(summer.to_utc()), "20260703T080000Z");
+
+        // Prefixed TZIDs, floating times and explicit UTC
+        let prefixed = parse_zoned(
+            "20260203T100000",
+            Some("/citadel.org/20190103_1/Europe/Berlin"),
+            Tz::UTC,
+        )
+        .unwrap();
+        assert_eq!(prefixed.tz, berlin);
+        let floating = parse_zoned("20260203T100000", None, berlin).unwrap();
+        assert_eq!(format_utc(floating.to_utc()), "20260203T090000Z");
+        let utc = parse_zoned("20260203T100000Z", Some("Europe/Berlin"), berlin).unwrap();
+        assert_eq!(format_utc(utc.to_utc()), "20260203T100000Z");
+        assert_eq!(resolve_tzid("Eastern Standard Time"), None);
+    }
+
+    #[test]
+    fn test_fold_long_line() {
+        let line = "X".repeat(200);
+        let folded = fold_line(&line);
+        assert!(folded.split("\r\n").all(|l| l.len() <= 75));
+        assert_eq!(unfold(&folded).concat(), line);
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let dt = parse_date_time("20260203T100000Z").unwrap();
+        assert_eq!(format_utc(dt), "20260203T100000Z");
+        assert_eq!(
+            parse_date_time("20260203").unwrap(),
+            parse_date_time("20260203T000000Z").unwrap()
+        );
+
+        assert_eq!(parse_duration("PT1H30M").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("P1DT2H").unwrap(), Duration::hours(26));
+        assert_eq!(parse_duration("-PT15M").unwrap(), Duration::minutes(-15));
+        assert!(parse_duration("1H").is_err());
+    }
+}
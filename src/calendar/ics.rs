@@ -0,0 +1,613 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use super::ical::{self, Component, Property, ZonedTime};
+use super::rrule::RecurrenceRule;
+use super::{CalendarProvider, CreatedEvent, EventDetails, EventRef};
+use crate::models::BusyPeriod;
+
+/// Serializes appends so concurrent acceptances don't clobber each other
+static WRITE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Calendar backed by an `.ics` file or URL
+///
+/// Busy time is read from `source` (a local path or an http(s) URL), and
+/// accepted meetings are appended to a local `write_path`, if one is set.
+pub struct IcsCalendar {
+    client: reqwest::Client,
+    source: String,
+    write_path: Option<PathBuf>,
+}
+
+impl IcsCalendar {
+    pub fn new(source: &str, write_path: Option<&str>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            source: source.to_string(),
+            write_path: write_path.map(PathBuf::from),
+        }
+    }
+
+    /// Whether a source refers to a remote URL rather than a local file
+    pub fn is_remote(source: &str) -> bool {
+        source.starts_with("http://") || source.starts_with("https://")
+    }
+
+    async fn load(&self) -> Result<String> {
+        if Self::is_remote(&self.source) {
+            let resp = self
+                .client
+                .get(&self.source)
+                .send()
+                .await
+                .context("Failed to fetch ICS feed")?;
+
+            if !resp.status().is_success() {
+                anyhow::bail!("ICS feed fetch failed: {}", resp.status());
+            }
+
+            resp.text().await.context("Failed to read ICS feed")
+        } else {
+            tokio::fs::read_to_string(&self.source)
+                .await
+                .context("Failed to read ICS file")
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl CalendarProvider for IcsCalendar {
+    async fn get_busy_periods(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<BusyPeriod>> {
+        let text = self.load().await?;
+        busy_periods_from_ics(&text, start, end, false)
+    }
+
+    async fn get_busy_events(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<BusyPeriod>> {
+        let text = self.load().await?;
+        busy_periods_from_ics(&text, start, end, true)
+    }
+
+    async fn create_event(&self, event: &EventDetails) -> Result<CreatedEvent> {
+        let path = self
+            .write_path
+            .as_ref()
+            .context("This ICS calendar is read-only")?;
+
+        let uid = format!("{}@meetd", uuid::Uuid::new_v4());
+        let event = ical::vevent(&uid, event);
+
+        let _guard = WRITE_LOCK.lock().await;
+        let existing = read_existing(path).await?;
+
+        let updated = append_event(existing.as_deref(), event);
+        tokio::fs::write(path, updated)
+            .await
+            .context("Failed to write ICS file")?;
+
+        Ok(CreatedEvent {
+            event: EventRef::new(&uid),
+            html_link: None,
+        })
+    }
+
+    async fn update_event(&self, event: &EventRef, details: &EventDetails) -> Result<CreatedEvent> {
+        let path = self
+            .write_path
+            .as_ref()
+            .context("This ICS calendar is read-only")?;
+
+        let vevent = ical::vevent(&event.id, details);
+
+        let _guard = WRITE_LOCK.lock().await;
+        let existing = read_existing(path).await?;
+        let remaining = existing
+            .as_deref()
+            .map(|text| remove_event(text, &event.id));
+
+        let updated = append_event(remaining.as_deref(), vevent);
+        tokio::fs::write(path, updated)
+            .await
+            .context("Failed to write ICS file")?;
+
+        Ok(CreatedEvent {
+            event: event.clone(),
+            html_link: None,
+        })
+    }
+
+    async fn delete_event(&self, event: &EventRef) -> Result<()> {
+        let path = self
+            .write_path
+            .as_ref()
+            .context("This ICS calendar is read-only")?;
+
+        let _guard = WRITE_LOCK.lock().await;
+        if let Some(text) = read_existing(path).await? {
+            tokio::fs::write(path, remove_event(&text, &event.id))
+                .await
+                .context("Failed to write ICS file")?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the writable ICS file, if it exists yet
+async fn read_existing(path: &PathBuf) -> Result<Option<String>> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context("Failed to read ICS file"),
+    }
+}
+
+/// Compute busy periods overlapping `start..end`, expanding recurring events
+///
+/// Times are read in their `TZID` zone, and recurrences are expanded in that
+/// zone's wall-clock time so they keep their local hour across DST changes.
+/// Floating times are in the feed's `X-WR-TIMEZONE`, or UTC without one.
+/// With `titles`, each period carries its event's SUMMARY.
+fn busy_periods_from_ics(
+    text: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    titles: bool,
+) -> Result<Vec<BusyPeriod>> {
+    let roots = ical::parse(text)?;
+    let events: Vec<&Component> = roots.iter().flat_map(|r| r.find_all("VEVENT")).collect();
+    let calendar_tz = roots
+        .iter()
+        .find_map(|r| r.property("X-WR-TIMEZONE"))
+        .and_then(|p| ical::resolve_tzid(&p.value))
+        .unwrap_or(Tz::UTC);
+    let zoned = |prop: &Property, default_tz: Tz| {
+        ical::parse_zoned(&prop.value, prop.param("TZID"), default_tz)
+    };
+
+    // Instances of a recurring series that were moved or edited individually
+    let overridden: HashSet<(String, DateTime<Utc>)> = events
+        .iter()
+        .filter_map(|e| {
+            let uid = e.property("UID")?.value.clone();
+            let rid = zoned(e.property("RECURRENCE-ID")?, calendar_tz).ok()?;
+            Some((uid, rid.to_utc()))
+        })
+        .collect();
+
+    let mut busy_periods = Vec::new();
+
+    for event in events {
+        if event
+            .property("TRANSP")
+            .is_some_and(|p| p.value.eq_ignore_ascii_case("TRANSPARENT"))
+            || event
+                .property("STATUS")
+                .is_some_and(|p| p.value.eq_ignore_ascii_case("CANCELLED"))
+        {
+            continue;
+        }
+
+        // Skip events we can't make sense of rather than failing the whole feed
+        let Some(dtstart_prop) = event.property("DTSTART") else {
+            continue;
+        };
+        let Ok(dtstart) = zoned(dtstart_prop, calendar_tz) else {
+            continue;
+        };
+        let first_start = dtstart.to_utc();
+
+        let duration = if let Some(dtend) = event.property("DTEND") {
+            match zoned(dtend, dtstart.tz) {
+                Ok(dtend) => dtend.to_utc() - first_start,
+                Err(_) => continue,
+            }
+        } else if let Some(duration) = event.property("DURATION") {
+            match ical::parse_duration(&duration.value) {
+                Ok(d) => d,
+                Err(_) => continue,
+            }
+        } else if dtstart_prop.value.len() == 8 {
+            // All-day event without an end
+            Duration::days(1)
+        } else {
+            Duration::zero()
+        };
+
+        if duration <= Duration::zero() {
+            continue;
+        }
+
+        let uid = event.property("UID").map(|p| p.value.as_str());
+        let title = event
+            .property("SUMMARY")
+            .filter(|_| titles)
+            .map(|p| ical::unescape_text(&p.value));
+        let rrule = event
+            .property("RRULE")
+            .filter(|_| event.property("RECURRENCE-ID").is_none())
+            .and_then(|p| RecurrenceRule::parse(&p.value).ok());
+
+        let starts: Vec<DateTime<Utc>> = match rrule {
+            Some(rule) => {
+                // UNTIL is in UTC when DTSTART has a TZID
+                let rule = match dtstart_prop.param("TZID") {
+                    Some(_) => rule.with_until_in(&dtstart.tz),
+                    None => rule,
+                };
+                let excluded: HashSet<DateTime<Utc>> = event
+                    .properties_named("EXDATE")
+                    .flat_map(|p| {
+                        p.value.split(',').filter_map(move |v| {
+                            ical::parse_zoned(v, p.param("TZID"), dtstart.tz).ok()
+                        })
+                    })
+                    .map(|z| z.to_utc())
+                    .collect();
+
+                // Local and UTC times differ by less than a day
+                let local = |t: DateTime<Utc>| t.with_timezone(&dtstart.tz).naive_local();
+                rule.between(
+                    dtstart.local,
+                    local(start - duration) - Duration::days(1),
+                    local(end) + Duration::days(1),
+                )
+                .into_iter()
+                .map(|local| ZonedTime { local, ..dtstart }.to_utc())
+                .filter(|o| !excluded.contains(o))
+                .filter(|o| match uid {
+                    Some(uid) => !overridden.contains(&(uid.to_string(), *o)),
+                    None => true,
+                })
+                .collect()
+            }
+            None => vec![first_start],
+        };
+
+        for occurrence in starts {
+            let occurrence_end = occurrence + duration;
+            if occurrence < end && occurrence_end > start {
+                busy_periods.push(BusyPeriod {
+                    start: occurrence,
+                    end: occurrence_end,
+                    title: title.clone(),
+                });
+            }
+        }
+    }
+
+    busy_periods.sort_by_key(|p| p.start);
+    Ok(busy_periods)
+}
+
+/// Drop the VEVENT with the given UID from an ICS document, keeping everything else as-is
+fn remove_event(text: &str, uid: &str) -> String {
+    let uid_line = format!("UID:{}", uid);
+    let mut kept = String::with_capacity(text.len());
+    let mut event: Option<(String, bool)> = None;
+
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        match &mut event {
+            None if content == "BEGIN:VEVENT" => event = Some((line.to_string(), false)),
+            None => kept.push_str(line),
+            Some((buffer, matches)) => {
+                buffer.push_str(line);
+                *matches |= content == uid_line;
+                if content == "END:VEVENT" {
+                    let (buffer, matches) = event.take().unwrap();
+                    if !matches {
+                        kept.push_str(&buffer);
+                    }
+                }
+            }
+        }
+    }
+    // An unterminated event is kept rather than silently dropped
+    if let Some((buffer, _)) = event {
+        kept.push_str(&buffer);
+    }
+    kept
+}
+
+/// Append an event to an existing ICS document, or start a new one
+fn append_event(existing: Option<&str>, event: Component) -> String {
+    let event_text = event.to_string();
+
+    if let Some(text) = existing {
+        if let Some(pos) = text.rfind("END:VCALENDAR") {
+            let mut updated = String::with_capacity(text.len() + event_text.len());
+            updated.push_str(&text[..pos]);
+            if !updated.is_empty() && !updated.ends_with('\n') {
+                updated.push_str("\r\n");
+            }
+            updated.push_str(&event_text);
+            updated.push_str(&text[pos..]);
+            return updated;
+        }
+    }
+
+    ical::vcalendar().with_component(event).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        ical::parse_date_time(s).unwrap()
+    }
+
+    const FEED: &str = "BEGIN:VCALENDAR\r\n\
+        VERSION:2.0\r\n\
+        BEGIN:VEVENT\r\n\
+        UID:standup\r\n\
+        SUMMARY:Standup\r\n\
+        DTSTART:20260202T090000Z\r\n\
+        DTEND:20260202T091500Z\r\n\
+        RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR\r\n\
+        EXDATE:20260204T090000Z\r\n\
+        END:VEVENT\r\n\
+        BEGIN:VEVENT\r\n\
+        UID:standup\r\n\
+        RECURRENCE-ID:20260205T090000Z\r\n\
+        DTSTART:20260205T100000Z\r\n\
+        DTEND:20260205T101500Z\r\n\
+        END:VEVENT\r\n\
+        BEGIN:VEVENT\r\n\
+        UID:lunch\r\n\
+        SUMMARY:Lunch\r\n\
+        DTSTART:20260203T120000Z\r\n\
+        DURATION:PT1H\r\n\
+        END:VEVENT\r\n\
+        BEGIN:VEVENT\r\n\
+        UID:reminder\r\n\
+        DTSTART:20260203T150000Z\r\n\
+        DTEND:20260203T160000Z\r\n\
+        TRANSP:TRANSPARENT\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+
+    #[test]
+    fn test_busy_periods_expand_recurrence() {
+        let busy = busy_periods_from_ics(
+            FEED,
+            utc("20260202T000000Z"),
+            utc("20260207T000000Z"),
+            false,
+        )
+        .unwrap();
+        let starts: Vec<String> = busy.iter().map(|b| ical::format_utc(b.start)).collect();
+
+        assert_eq!(
+            starts,
+            vec![
+                "20260202T090000Z", // Mon standup
+                "20260203T090000Z", // Tue standup
+                "20260203T120000Z", // lunch
+                // Wed excluded via EXDATE
+                "20260205T100000Z", // Thu standup moved to 10:00
+                "20260206T090000Z", // Fri standup
+            ]
+        );
+    }
+
+    #[test]
+    fn test_busy_periods_clip_to_window() {
+        let busy = busy_periods_from_ics(
+            FEED,
+            utc("20260203T091000Z"),
+            utc("20260203T120000Z"),
+            false,
+        )
+        .unwrap();
+        assert_eq!(busy.len(), 1);
+        assert_eq!(ical::format_utc(busy[0].start), "20260203T090000Z");
+        assert_eq!(busy[0].title, None);
+    }
+
+    #[test]
+    fn test_busy_periods_with_titles() {
+        let busy =
+            busy_periods_from_ics(FEED, utc("20260203T000000Z"), utc("20260204T000000Z"), true)
+                .unwrap();
+        let titles: Vec<Option<&str>> = busy.iter().map(|b| b.title.as_deref()).collect();
+        assert_eq!(titles, vec![Some("Standup"), Some("Lunch")]);
+    }
+
+    #[test]
+    fn test_busy_periods_in_tzid() {
+        let feed = "BEGIN:VCALENDAR\r\n\
+            X-WR-TIMEZONE:Europe/Berlin\r\n\
+            BEGIN:VEVENT\r\n\
+            UID:call\r\n\
+            DTSTART;TZID=America/New_York:20260203T090000\r\n\
+            DTEND;TZID=America/New_York:20260203T093000\r\n\
+            END:VEVENT\r\n\
+            BEGIN:VEVENT\r\n\
+            UID:floating\r\n\
+            DTSTART:20260203T120000\r\n\
+            DTEND:20260203T130000\r\n\
+            END:VEVENT\r\n\
+            END:VCALENDAR\r\n";
+        let busy = busy_periods_from_ics(
+            feed,
+            utc("20260203T000000Z"),
+            utc("20260204T000000Z"),
+            false,
+        )
+        .unwrap();
+        let periods: Vec<(String, String)> = busy
+            .iter()
+            .map(|b| (ical::format_utc(b.start), ical::format_utc(b.end)))
+            .collect();
+        assert_eq!(
+            periods,
+            vec![
+                // Floating times are in the feed's X-WR-TIMEZONE
+                (
+                    "20260203T110000Z".to_string(),
+                    "20260203T120000Z".to_string()
+                ),
+                (
+                    "20260203T140000Z".to_string(),
+                    "20260203T143000Z".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recurrence_keeps_local_time_across_dst() {
+        // Europe/Berlin moves to summer time on 2026-03-29
+        let feed = "BEGIN:VCALENDAR\r\n\
+            BEGIN:VEVENT\r\n\
+            UID:weekly\r\n\
+            DTSTART;TZID=Europe/Berlin:20260319T090000\r\n\
+            DTEND;TZID=Europe/Berlin:20260319T093000\r\n\
+            RRULE:FREQ=WEEKLY;UNTIL=20260409T070000Z\r\n\
+            EXDATE;TZID=Europe/Berlin:20260326T090000\r\n\
+            END:VEVENT\r\n\
+            END:VCALENDAR\r\n";
+        let busy = busy_periods_from_ics(
+            feed,
+            utc("20260301T000000Z"),
+            utc("20260501T000000Z"),
+            false,
+        )
+        .unwrap();
+        let starts: Vec<String> = busy.iter().map(|b| ical::format_utc(b.start)).collect();
+        assert_eq!(
+            starts,
+            vec![
+                "20260319T080000Z", // 09:00 CET
+                // 2026-03-26 excluded
+                "20260402T070000Z", // 09:00 CEST
+                "20260409T070000Z", // UNTIL is inclusive
+            ]
+        );
+        assert!(busy
+            .iter()
+            .all(|b| b.end - b.start == Duration::minutes(30)));
+    }
+
+    #[test]
+    fn test_append_event() {
+        let start = utc("20260210T100000Z");
+        let event = ical::vevent("new@meetd", &EventDetails::new("Sync", start, start));
+
+        let updated = append_event(Some(FEED), event.clone());
+        let roots = ical::parse(&updated).unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].find_all("VEVENT").len(), 5);
+
+        let fresh = append_event(None, event);
+        assert_eq!(ical::parse(&fresh).unwrap()[0].find_all("VEVENT").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_create_event_writes_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("meetd.ics");
+        let cal = IcsCalendar::new(path.to_str().unwrap(), path.to_str());
+
+        let start = utc("20260210T100000Z");
+        cal.create_event(&EventDetails::new(
+            "Sync",
+            start,
+            start + Duration::minutes(30),
+        ))
+        .await
+        .unwrap();
+
+        let busy = cal
+            .get_busy_periods(start - Duration::hours(1), start + Duration::hours(1))
+            .await
+            .unwrap();
+        assert_eq!(busy.len(), 1);
+        assert_eq!(busy[0].end, start + Duration::minutes(30));
+    }
+
+    #[tokio::test]
+    async fn test_create_recurring_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("meetd.ics");
+        let cal = IcsCalendar::new(path.to_str().unwrap(), path.to_str());
+
+        let start = utc("20260210T100000Z");
+        let details = EventDetails {
+            recurrence: Some("FREQ=WEEKLY;COUNT=3".to_string()),
+            ..EventDetails::new("Sync", start, start + Duration::minutes(30))
+        };
+        cal.create_event(&details).await.unwrap();
+
+        let busy = cal
+            .get_busy_periods(start, start + Duration::weeks(5))
+            .await
+            .unwrap();
+        let starts: Vec<DateTime<Utc>> = busy.iter().map(|b| b.start).collect();
+        assert_eq!(
+            starts,
+            vec![
+                start,
+                start + Duration::weeks(1),
+                start + Duration::weeks(2)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_and_delete_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("meetd.ics");
+        let cal = IcsCalendar::new(path.to_str().unwrap(), path.to_str());
+
+        let start = utc("20260210T100000Z");
+        let window = (start - Duration::hours(1), start + Duration::hours(4));
+        let keep = cal
+            .create_event(&EventDetails::new(
+                "Keep",
+                start,
+                start + Duration::minutes(30),
+            ))
+            .await
+            .unwrap();
+        let moved = cal
+            .create_event(&EventDetails::new(
+                "Sync",
+                start,
+                start + Duration::minutes(30),
+            ))
+            .await
+            .unwrap();
+
+        let later = start + Duration::hours(2);
+        cal.update_event(
+            &moved.event,
+            &EventDetails::new("Sync", later, later + Duration::minutes(30)),
+        )
+        .await
+        .unwrap();
+        let busy = cal.get_busy_periods(window.0, window.1).await.unwrap();
+        assert_eq!(busy.len(), 2);
+        assert_eq!(busy[1].start, later);
+
+        cal.delete_event(&moved.event).await.unwrap();
+        let busy = cal.get_busy_events(window.0, window.1).await.unwrap();
+        assert_eq!(busy.len(), 1);
+        assert_eq!(busy[0].title.as_deref(), Some("Keep"));
+
+        cal.delete_event(&keep.event).await.unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(ical::parse(&text).unwrap()[0].find_all("VEVENT").len(), 0);
+    }
+}
@@ -0,0 +1,214 @@
+//! iTIP (RFC 5546) scheduling messages for recipients without meetd
+//!
+//! Proposals go out as `METHOD:REQUEST` invitations that any calendar client
+//! can answer, carrying the signed proposal in meetd extension properties.
+//! The `METHOD:REPLY` that comes back names the invitation by its UID, which
+//! this server derives from its own key so replies can't be forged.
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use super::ical::{self, Component, Property};
+use super::EventDetails;
+use crate::crypto::Keypair;
+use crate::models::SignedProposal;
+
+/// Signature of the proposal an invitation was made from
+pub const SIGNATURE_PROPERTY: &str = "X-MEETD-SIGNATURE";
+
+/// The signed proposal itself (base64), for clients that want to verify it
+pub const PROPOSAL_PROPERTY: &str = "X-MEETD-PROPOSAL";
+
+/// UID of the invitation for `proposal_id`, tagged with this server's key
+pub fn invite_uid(proposal_id: &str, server_key: &Keypair, domain: &str) -> String {
+    format!(
+        "{}.{}@{}",
+        proposal_id,
+        uid_tag(proposal_id, server_key),
+        domain
+    )
+}
+
+/// The proposal an invitation UID was made for, if this server made it
+pub fn proposal_id_from_uid(uid: &str, server_key: &Keypair) -> Option<String> {
+    let (local, _) = uid.rsplit_once('@')?;
+    let (proposal_id, tag) = local.rsplit_once('.')?;
+    constant_time_eq(tag.as_bytes(), uid_tag(proposal_id, server_key).as_bytes())
+        .then(|| proposal_id.to_string())
+}
+
+/// Compare without stopping at the first differing byte, so the time taken
+/// doesn't give away how much of a guessed tag was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn uid_tag(proposal_id: &str, server_key: &Keypair) -> String {
+    let signature = server_key.sign(&format!("itip:{}", proposal_id));
+    hex::encode(Sha256::digest(signature.as_bytes()))[..32].to_string()
+}
+
+/// A `METHOD:REQUEST` invitation to the meeting `signed` proposes
+///
+/// `organizer` is the mailbox replies should go to; the sender is named as
+/// its common name.
+pub fn request(
+    uid: &str,
+    organizer: &str,
+    details: &EventDetails,
+    signed: &SignedProposal,
+) -> Component {
+    let event = ical::vevent(uid, details)
+        .with_property(Property::new("SEQUENCE", "0"))
+        .with_property(
+            Property::new("ORGANIZER", format!("mailto:{}", organizer))
+                .with_param("CN", signed.from.as_str()),
+        )
+        .with_property(Property::new(SIGNATURE_PROPERTY, signed.signature.as_str()))
+        .with_property(Property::new(PROPOSAL_PROPERTY, signed.to_base64()));
+
+    ical::vcalendar()
+        .with_property(Property::new("METHOD", "REQUEST"))
+        .with_component(event)
+}
+
+/// An attendee's answer to an invitation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub uid: String,
+    /// Email of the attendee who answered
+    pub attendee: String,
+    /// Their participation status, e.g. `ACCEPTED` or `DECLINED`
+    pub partstat: String,
+}
+
+/// Parse a `METHOD:REPLY` iCalendar object
+pub fn parse_reply(input: &str) -> Result<Reply> {
+    let calendar = ical::parse(input)?
+        .into_iter()
+        .find(|c| c.name == "VCALENDAR")
+        .context("No VCALENDAR found")?;
+    let method = calendar.property("METHOD").map(|p| p.value.as_str());
+    if !method.is_some_and(|m| m.eq_ignore_ascii_case("REPLY")) {
+        anyhow::bail!(
+            "Expected METHOD:REPLY, got {}",
+            method.unwrap_or("no method")
+        );
+    }
+
+    let event = calendar
+        .find_all("VEVENT")
+        .into_iter()
+        .next()
+        .context("Reply has no VEVENT")?;
+    let uid = event.property("UID").context("Reply has no UID")?;
+    let attendee = event
+        .property("ATTENDEE")
+        .context("Reply has no ATTENDEE")?;
+    let email = attendee
+        .value
+        .get(..7)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("mailto:"))
+        .map(|_| &attendee.value[7..])
+        .context("ATTENDEE is not a mailto: address")?;
+    let partstat = attendee
+        .param("PARTSTAT")
+        .context("ATTENDEE has no PARTSTAT")?;
+
+    Ok(Reply {
+        uid: uid.value.clone(),
+        attendee: email.to_string(),
+        partstat: partstat.to_ascii_uppercase(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::PublicKey;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn test_invite_uid() {
+        let key = Keypair::generate().unwrap();
+        let uid = invite_uid("prop_abc123", &key, "meetd.example.com");
+        assert!(uid.starts_with("prop_abc123."));
+        assert_eq!(
+            proposal_id_from_uid(&uid, &key).as_deref(),
+            Some("prop_abc123")
+        );
+
+        // Another proposal's ID, or another server's key, doesn't match the tag
+        let forged = uid.replace("prop_abc123", "prop_other");
+        assert_eq!(proposal_id_from_uid(&forged, &key), None);
+        let other = Keypair::generate().unwrap();
+        assert_eq!(proposal_id_from_uid(&uid, &other), None);
+
+        // A truncated tag doesn't match either
+        let (local, domain) = uid.rsplit_once('@').unwrap();
+        let truncated = format!("{}@{}", &local[..local.len() - 1], domain);
+        assert_eq!(proposal_id_from_uid(&truncated, &key), None);
+    }
+
+    #[test]
+    fn test_request() {
+        let keypair = Keypair::generate().unwrap();
+        let start = Utc::now() + Duration::days(1);
+        let mut signed = SignedProposal {
+            version: 1,
+            from: "alice@example.com".to_string(),
+            from_pubkey: keypair.public_key_base64(),
+            to: "carol@elsewhere.org".to_string(),
+            slot: crate::models::ProposalSlot {
+                start,
+                duration_minutes: 30,
+            },
+            slots: vec![],
+            title: Some("Sync".to_string()),
+            description: None,
+            recurrence: None,
+            location: None,
+            conference: None,
+            nonce: uuid::Uuid::new_v4().to_string(),
+            expires_at: start,
+            created_at: None,
+            signature: String::new(),
+        };
+        keypair.sign_proposal(&mut signed);
+
+        let details = EventDetails {
+            attendee_email: Some(signed.to.clone()),
+            ..EventDetails::new("Sync", start, start + Duration::minutes(30))
+        };
+        let text = request("uid@meetd", "invites@meetd.example.com", &details, &signed).to_string();
+        assert!(text.contains("METHOD:REQUEST\r\n"));
+        assert!(text.contains("ORGANIZER;CN=alice@example.com:mailto:invites@meetd.example.com"));
+
+        // The signed proposal survives the trip through iCalendar
+        let event = ical::parse(&text).unwrap()[0].find_all("VEVENT")[0].clone();
+        assert_eq!(
+            event.property(SIGNATURE_PROPERTY).unwrap().value,
+            signed.signature
+        );
+        let carried =
+            SignedProposal::from_base64(&event.property(PROPOSAL_PROPERTY).unwrap().value).unwrap();
+        let pub_key = PublicKey::from_base64(&keypair.public_key_base64()).unwrap();
+        assert!(pub_key.verify_proposal(&carried).unwrap());
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let reply = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nMETHOD:REPLY\r\nBEGIN:VEVENT\r\nUID:prop_abc.tag@meetd\r\nATTENDEE;PARTSTAT=accepted;CN=Carol:MAILTO:carol@elsewhere.org\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        assert_eq!(
+            parse_reply(reply).unwrap(),
+            Reply {
+                uid: "prop_abc.tag@meetd".to_string(),
+                attendee: "carol@elsewhere.org".to_string(),
+                partstat: "ACCEPTED".to_string(),
+            }
+        );
+
+        let request = reply.replace("METHOD:REPLY", "METHOD:REQUEST");
+        assert!(parse_reply(&request).is_err());
+    }
+}
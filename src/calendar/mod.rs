@@ -1,12 +1,26 @@
+mod caldav;
 mod google;
+pub mod ical;
+mod ics;
+pub mod itip;
+mod registry;
+pub mod rrule;
 
+pub use caldav::*;
 pub use google::*;
+pub use ics::*;
+pub use registry::*;
 
 use anyhow::Result;
 use async_trait::async_trait;
 use chrono::{DateTime, Datelike, Timelike, Utc};
+use chrono_tz::Tz;
 
-use crate::models::{BusyPeriod, TimeSlot};
+use crate::models::{local_to_utc, BusyPeriod, Conference, SlotRules, TimeSlot, WorkingHours};
+use rrule::RecurrenceRule;
+
+/// How many occurrences of a recurring meeting are checked for clashes
+pub const CHECKED_OCCURRENCES: usize = 10;
 
 /// Trait for calendar providers
 #[async_trait]
@@ -18,36 +32,151 @@ pub trait CalendarProvider: Send + Sync {
         end: DateTime<Utc>,
     ) -> Result<Vec<BusyPeriod>>;
 
-    /// Create a calendar event
-    async fn create_event(
+    /// Get busy periods along with event titles, where the provider can see them
+    ///
+    /// Only needed when the owner shares full details; defaults to
+    /// `get_busy_periods`, which leaves titles empty.
+    async fn get_busy_events(
         &self,
-        title: &str,
-        description: Option<&str>,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
-        attendee_email: Option<&str>,
-    ) -> Result<CreatedEvent>;
+    ) -> Result<Vec<BusyPeriod>> {
+        self.get_busy_periods(start, end).await
+    }
+
+    /// Create a calendar event
+    async fn create_event(&self, event: &EventDetails) -> Result<CreatedEvent>;
+
+    /// Replace the details of an event made by `create_event`
+    async fn update_event(&self, event: &EventRef, details: &EventDetails) -> Result<CreatedEvent>;
+
+    /// Remove an event made by `create_event`
+    async fn delete_event(&self, event: &EventRef) -> Result<()>;
+}
+
+/// What goes into a calendar event for a meeting
+#[derive(Debug, Clone)]
+pub struct EventDetails {
+    pub title: String,
+    pub description: Option<String>,
+    /// Start of the (first) occurrence
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub attendee_email: Option<String>,
+    /// RRULE value (e.g. `FREQ=WEEKLY;COUNT=10`) for recurring meetings
+    pub recurrence: Option<String>,
+    /// Timezone the recurrence repeats in (see [`occurrences`])
+    pub time_zone: Tz,
+    pub location: Option<String>,
+    /// Join link, or a conference for the provider to create
+    pub conference: Option<Conference>,
+}
+
+impl EventDetails {
+    /// A one-off event with no description or attendee
+    pub fn new(title: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        Self {
+            title: title.to_string(),
+            description: None,
+            start,
+            end,
+            attendee_email: None,
+            recurrence: None,
+            time_zone: Tz::UTC,
+            location: None,
+            conference: None,
+        }
+    }
 }
 
 /// Result of creating a calendar event
 #[derive(Debug, Clone)]
 pub struct CreatedEvent {
-    pub id: String,
+    pub event: EventRef,
     pub html_link: Option<String>,
 }
 
+/// Where an event made by `create_event` lives, so it can be changed later
+///
+/// The user may link other accounts, or pick another calendar, after the
+/// event was made; updates and deletes go to where it was made regardless.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventRef {
+    /// Provider ID of the event
+    pub id: String,
+    /// Calendar account the event was made in
+    pub account_id: Option<String>,
+    /// Calendar within the account, for providers with several (Google)
+    pub calendar_id: Option<String>,
+}
+
+impl EventRef {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Merge overlapping or touching busy periods into one sorted, non-overlapping list
+///
+/// Titles of merged periods are kept, joined with ", ".
+pub fn merge_busy_periods(mut periods: Vec<BusyPeriod>) -> Vec<BusyPeriod> {
+    periods.sort_by_key(|p| (p.start, p.end));
+
+    let mut merged: Vec<BusyPeriod> = Vec::with_capacity(periods.len());
+    for period in periods {
+        match merged.last_mut() {
+            Some(last) if period.start <= last.end => {
+                last.end = last.end.max(period.end);
+                last.title = match (last.title.take(), period.title) {
+                    (Some(a), Some(b)) if a != b => Some(format!("{}, {}", a, b)),
+                    (a, b) => a.or(b),
+                };
+            }
+            _ => merged.push(period),
+        }
+    }
+
+    merged
+}
+
 /// Find available slots given busy periods
+///
+/// Slots only fall inside everyone's `working_hours`; pass an empty slice to
+/// search around the clock. Candidate starts are `rules.step` apart, and each
+/// slot keeps `rules.buffer_before`/`rules.buffer_after` clear of busy time.
+/// Notice and horizon are not applied here; see [`SlotRules::clamp_window`].
 pub fn find_available_slots(
     busy_periods: &[BusyPeriod],
     window_start: DateTime<Utc>,
     window_end: DateTime<Utc>,
     duration_minutes: i32,
+    working_hours: &[WorkingHours],
+    rules: &SlotRules,
 ) -> Vec<TimeSlot> {
     let mut available = Vec::new();
     let duration = chrono::Duration::minutes(duration_minutes as i64);
 
+    // Pad meetings so a slot can't start right after or end right before one
+    let padded: Vec<BusyPeriod> = busy_periods
+        .iter()
+        .map(|busy| BusyPeriod {
+            start: busy.start - rules.buffer_after,
+            end: busy.end + rules.buffer_before,
+            title: None,
+        })
+        .collect();
+
+    // Time outside anyone's working hours is treated as busy
+    let off_hours: Vec<BusyPeriod> = working_hours
+        .iter()
+        .flat_map(|hours| hours.off_hours(window_start, window_end))
+        .collect();
+
     // Sort busy periods by start time
-    let mut sorted_busy: Vec<_> = busy_periods.iter().collect();
+    let mut sorted_busy: Vec<_> = padded.iter().chain(off_hours.iter()).collect();
     sorted_busy.sort_by_key(|p| p.start);
 
     let mut current = window_start;
@@ -56,11 +185,11 @@ pub fn find_available_slots(
         // If there's a gap before this busy period, check if it fits the duration
         if busy.start > current {
             let gap_end = busy.start;
-            // Add slots in 30-minute increments within the gap
+            // Add slots in step increments within the gap
             let mut slot_start = current;
             while slot_start + duration <= gap_end {
                 available.push(TimeSlot::new(slot_start, slot_start + duration));
-                slot_start += chrono::Duration::minutes(30);
+                slot_start += rules.step;
             }
         }
         // Move current to the end of this busy period
@@ -73,28 +202,48 @@ pub fn find_available_slots(
     let mut slot_start = current;
     while slot_start + duration <= window_end {
         available.push(TimeSlot::new(slot_start, slot_start + duration));
-        slot_start += chrono::Duration::minutes(30);
+        slot_start += rules.step;
     }
 
     available
 }
 
 /// Score a time slot based on various factors
-pub fn score_slot(slot: &TimeSlot, now: DateTime<Utc>) -> f64 {
+///
+/// Time-of-day preferences are judged in each party's local time (from their
+/// `working_hours`) and averaged; with no working hours, UTC is used.
+pub fn score_slot(slot: &TimeSlot, now: DateTime<Utc>, working_hours: &[WorkingHours]) -> f64 {
     let mut score: f64 = 0.5;
 
-    // Prefer slots during working hours (9 AM - 5 PM UTC-like)
-    let hour = slot.start.time().hour();
-    if (9..17).contains(&hour) {
-        score += 0.2;
-    } else if (8..18).contains(&hour) {
-        score += 0.1;
-    }
+    let local_times: Vec<(u32, chrono::Weekday, u32)> = if working_hours.is_empty() {
+        vec![(
+            slot.start.time().hour(),
+            slot.start.weekday(),
+            slot.start.time().minute(),
+        )]
+    } else {
+        working_hours
+            .iter()
+            .map(|hours| {
+                let local = hours.local(slot.start);
+                (local.hour(), local.weekday(), local.minute())
+            })
+            .collect()
+    };
+    let parties = local_times.len() as f64;
 
-    // Prefer weekdays
-    let weekday = slot.start.weekday();
-    if weekday.num_days_from_monday() < 5 {
-        score += 0.1;
+    for (hour, weekday, _) in &local_times {
+        // Prefer slots during core hours
+        if (9..17).contains(hour) {
+            score += 0.2 / parties;
+        } else if (8..18).contains(hour) {
+            score += 0.1 / parties;
+        }
+
+        // Prefer weekdays
+        if weekday.num_days_from_monday() < 5 {
+            score += 0.1 / parties;
+        }
     }
 
     // Prefer slots that are not too soon (give time to prepare)
@@ -105,26 +254,77 @@ pub fn score_slot(slot: &TimeSlot, now: DateTime<Utc>) -> f64 {
         score += 0.05;
     }
 
-    // Prefer slots that start at common meeting times (:00 or :30)
-    let minute = slot.start.time().minute();
-    if minute == 0 || minute == 30 {
+    // Prefer slots that start at common meeting times (:00 or :30) for everyone
+    if local_times
+        .iter()
+        .all(|(_, _, minute)| *minute == 0 || *minute == 30)
+    {
         score += 0.1;
     }
 
     score.min(1.0_f64)
 }
 
-/// Intersect availability between two sets of busy periods
+/// Intersect availability across everyone's busy periods
 pub fn intersect_availability(
-    busy_a: &[BusyPeriod],
-    busy_b: &[BusyPeriod],
+    busy: &[Vec<BusyPeriod>],
     window_start: DateTime<Utc>,
     window_end: DateTime<Utc>,
     duration_minutes: i32,
+    working_hours: &[WorkingHours],
+    rules: &SlotRules,
 ) -> Vec<TimeSlot> {
     // Combine all busy periods
-    let mut all_busy: Vec<BusyPeriod> = busy_a.to_vec();
-    all_busy.extend(busy_b.iter().cloned());
+    let all_busy: Vec<BusyPeriod> = busy.iter().flatten().cloned().collect();
+
+    find_available_slots(
+        &all_busy,
+        window_start,
+        window_end,
+        duration_minutes,
+        working_hours,
+        rules,
+    )
+}
+
+/// The first `n` occurrences of a meeting at `slot` (just `slot` when `rule` is `None`)
+///
+/// Occurrences are expanded in `tz`, the organiser's timezone, so a weekly
+/// 09:00 meeting stays at 09:00 local time when the clocks change.
+pub fn occurrences(
+    slot: &TimeSlot,
+    rule: Option<&RecurrenceRule>,
+    tz: &Tz,
+    n: usize,
+) -> Vec<TimeSlot> {
+    let Some(rule) = rule else {
+        return vec![slot.clone()];
+    };
+    let length = slot.end - slot.start;
+    rule.clone()
+        .with_until_in(tz)
+        .first_n(slot.start.with_timezone(tz).naive_local(), n)
+        .into_iter()
+        .map(|local| {
+            let start = local_to_utc(tz, local);
+            TimeSlot::new(start, start + length)
+        })
+        .collect()
+}
+
+/// Whether someone with these busy periods and working hours can attend `slot`
+///
+/// Uses the same buffers as [`find_available_slots`].
+pub fn can_attend(
+    slot: &TimeSlot,
+    busy: &[BusyPeriod],
+    working_hours: Option<&WorkingHours>,
+    rules: &SlotRules,
+) -> bool {
+    let clear_of_busy = busy.iter().all(|b| {
+        slot.end + rules.buffer_after <= b.start || slot.start >= b.end + rules.buffer_before
+    });
+    let in_hours = working_hours.map_or(true, |hours| hours.contains(slot.start, slot.end));
 
-    find_available_slots(&all_busy, window_start, window_end, duration_minutes)
+    clear_of_busy && in_hours
 }
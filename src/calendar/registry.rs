@@ -0,0 +1,311 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+
+use super::{
+    merge_busy_periods, CalDavCalendar, CalendarProvider, CreatedEvent, EventDetails, EventRef,
+    GoogleCalendar, IcsCalendar,
+};
+use crate::models::{BusyPeriod, CalendarAccount, CalendarConfig, CalendarSelection};
+
+/// Resolves stored calendar accounts into live providers
+pub struct CalendarRegistry {
+    google_client_id: String,
+    google_client_secret: String,
+}
+
+impl CalendarRegistry {
+    pub fn new(google_client_id: &str, google_client_secret: &str) -> Self {
+        Self {
+            google_client_id: google_client_id.to_string(),
+            google_client_secret: google_client_secret.to_string(),
+        }
+    }
+
+    /// Connect to a single calendar account, using the provider's default calendars
+    pub async fn connect(&self, config: &CalendarConfig) -> Result<Box<dyn CalendarProvider>> {
+        self.connect_with(config, &CalendarSelection::default())
+            .await
+    }
+
+    /// Connect to a single calendar account, using the chosen calendars
+    pub async fn connect_with(
+        &self,
+        config: &CalendarConfig,
+        selection: &CalendarSelection,
+    ) -> Result<Box<dyn CalendarProvider>> {
+        Ok(match config {
+            CalendarConfig::Google { refresh_token, .. } => Box::new(
+                GoogleCalendar::new(
+                    &self.google_client_id,
+                    &self.google_client_secret,
+                    refresh_token,
+                )
+                .await?
+                .with_selection(selection),
+            ),
+            CalendarConfig::Caldav {
+                url,
+                username,
+                password,
+            } => Box::new(CalDavCalendar::new(url, username, password)?),
+            CalendarConfig::Ics { source, write_path } => {
+                Box::new(IcsCalendar::new(source, write_path.as_deref()))
+            }
+        })
+    }
+
+    /// Connect to all of a user's accounts as one calendar
+    ///
+    /// Returns `None` if the user has no accounts linked. Even a single
+    /// account is wrapped in a [`UserCalendars`], so the events it makes
+    /// record which account they're in.
+    pub async fn for_accounts(
+        &self,
+        accounts: &[CalendarAccount],
+    ) -> Result<Option<Box<dyn CalendarProvider>>> {
+        let mut ordered: Vec<&CalendarAccount> = accounts.iter().collect();
+        // Primary first, so it is the one that receives new events
+        ordered.sort_by_key(|a| !a.is_primary);
+
+        let mut calendars = Vec::with_capacity(ordered.len());
+        for account in ordered {
+            let calendar = self
+                .connect_with(&account.config, &account.selection)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to connect calendar {} ({})",
+                        account.id,
+                        account.config.describe()
+                    )
+                })?;
+            calendars.push((account.id.clone(), calendar));
+        }
+
+        Ok(match calendars.len() {
+            0 => None,
+            _ => Some(Box::new(UserCalendars { calendars })),
+        })
+    }
+}
+
+/// Several calendar accounts acting as one
+///
+/// Busy time is the union of all accounts; events are created in the first
+/// (primary) account, and changed in whichever account they were made in.
+pub struct UserCalendars {
+    /// Account ID and provider, primary first
+    calendars: Vec<(String, Box<dyn CalendarProvider>)>,
+}
+
+impl UserCalendars {
+    fn primary(&self) -> Result<(&str, &dyn CalendarProvider)> {
+        self.calendars
+            .first()
+            .map(|(id, cal)| (id.as_str(), cal.as_ref()))
+            .context("No calendar accounts linked")
+    }
+
+    /// The account `event` was made in, if it's still linked
+    ///
+    /// Events recorded before their account was kept were made in the primary.
+    fn account_of(&self, event: &EventRef) -> Result<Option<&dyn CalendarProvider>> {
+        match &event.account_id {
+            Some(account_id) => Ok(self
+                .calendars
+                .iter()
+                .find(|(id, _)| id == account_id)
+                .map(|(_, cal)| cal.as_ref())),
+            None => self.primary().map(|(_, cal)| Some(cal)),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl CalendarProvider for UserCalendars {
+    async fn get_busy_periods(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<BusyPeriod>> {
+        let results = futures::future::join_all(
+            self.calendars
+                .iter()
+                .map(|(_, cal)| cal.get_busy_periods(start, end)),
+        )
+        .await;
+
+        // A calendar we can't read would make us report busy time as free
+        let mut busy_periods = Vec::new();
+        for result in results {
+            busy_periods.extend(result?);
+        }
+
+        Ok(merge_busy_periods(busy_periods))
+    }
+
+    async fn get_busy_events(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<BusyPeriod>> {
+        let results = futures::future::join_all(
+            self.calendars
+                .iter()
+                .map(|(_, cal)| cal.get_busy_events(start, end)),
+        )
+        .await;
+
+        let mut busy_periods = Vec::new();
+        for result in results {
+            busy_periods.extend(result?);
+        }
+
+        Ok(merge_busy_periods(busy_periods))
+    }
+
+    async fn create_event(&self, event: &EventDetails) -> Result<CreatedEvent> {
+        let (account_id, primary) = self.primary()?;
+        let mut created = primary.create_event(event).await?;
+        created.event.account_id = Some(account_id.to_string());
+        Ok(created)
+    }
+
+    /// Update `event` in its own account
+    ///
+    /// If that account has been unlinked, the old event is out of reach, so
+    /// the meeting is made afresh in the primary account.
+    async fn update_event(&self, event: &EventRef, details: &EventDetails) -> Result<CreatedEvent> {
+        let Some(calendar) = self.account_of(event)? else {
+            return self.create_event(details).await;
+        };
+        let mut updated = calendar.update_event(event, details).await?;
+        updated.event.account_id = event.account_id.clone();
+        Ok(updated)
+    }
+
+    async fn delete_event(&self, event: &EventRef) -> Result<()> {
+        match self.account_of(event)? {
+            Some(calendar) => calendar.delete_event(event).await,
+            None => anyhow::bail!(
+                "Calendar account {} is no longer linked",
+                event.account_id.as_deref().unwrap_or_default()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ics_account(id: &str, source: &str, is_primary: bool) -> CalendarAccount {
+        CalendarAccount {
+            id: id.to_string(),
+            user_id: "user".to_string(),
+            config: CalendarConfig::Ics {
+                source: source.to_string(),
+                write_path: Some(source.to_string()),
+            },
+            label: None,
+            is_primary,
+            selection: CalendarSelection::default(),
+            created_at: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_accounts_combine_busy_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let work = dir.path().join("work.ics");
+        let home = dir.path().join("home.ics");
+        let registry = CalendarRegistry::new("", "");
+
+        let accounts = vec![
+            ics_account("work", work.to_str().unwrap(), false),
+            ics_account("home", home.to_str().unwrap(), true),
+        ];
+        let calendar = registry.for_accounts(&accounts).await.unwrap().unwrap();
+
+        let start = Utc::now();
+        let end = start + chrono::Duration::hours(1);
+        calendar
+            .create_event(&EventDetails::new("Dentist", start, end))
+            .await
+            .unwrap();
+
+        // New events land in the primary account
+        assert!(home.exists());
+        assert!(!work.exists());
+
+        let work_calendar = IcsCalendar::new(work.to_str().unwrap(), work.to_str());
+        work_calendar
+            .create_event(&EventDetails::new(
+                "Standup",
+                end + chrono::Duration::minutes(15),
+                end + chrono::Duration::minutes(30),
+            ))
+            .await
+            .unwrap();
+
+        let busy = calendar
+            .get_busy_periods(
+                start - chrono::Duration::hours(1),
+                end + chrono::Duration::hours(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(busy.len(), 2);
+
+        assert!(registry.for_accounts(&[]).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_events_stay_in_their_account() {
+        let dir = tempfile::tempdir().unwrap();
+        let work = dir.path().join("work.ics");
+        let home = dir.path().join("home.ics");
+        let registry = CalendarRegistry::new("", "");
+        let events_in = |path: &std::path::Path| {
+            let text = std::fs::read_to_string(path).unwrap_or_default();
+            text.matches("BEGIN:VEVENT").count()
+        };
+
+        let accounts = vec![
+            ics_account("work", work.to_str().unwrap(), false),
+            ics_account("home", home.to_str().unwrap(), true),
+        ];
+        let calendar = registry.for_accounts(&accounts).await.unwrap().unwrap();
+        let start = Utc::now();
+        let details = EventDetails::new("Sync", start, start + chrono::Duration::minutes(30));
+        let created = calendar.create_event(&details).await.unwrap();
+        assert_eq!(created.event.account_id.as_deref(), Some("home"));
+
+        // Making another account primary doesn't move where the event is changed
+        let switched = vec![
+            ics_account("work", work.to_str().unwrap(), true),
+            ics_account("home", home.to_str().unwrap(), false),
+        ];
+        let calendar = registry.for_accounts(&switched).await.unwrap().unwrap();
+        let moved = calendar
+            .update_event(&created.event, &details)
+            .await
+            .unwrap();
+        assert_eq!(moved.event, created.event);
+        assert_eq!((events_in(&home), events_in(&work)), (1, 0));
+        calendar.delete_event(&created.event).await.unwrap();
+        assert_eq!(events_in(&home), 0);
+
+        // Once its account is unlinked, an event is made afresh in the primary
+        let created = calendar.create_event(&details).await.unwrap();
+        let unlinked = vec![ics_account("home", home.to_str().unwrap(), true)];
+        let calendar = registry.for_accounts(&unlinked).await.unwrap().unwrap();
+        assert!(calendar.delete_event(&created.event).await.is_err());
+        let remade = calendar
+            .update_event(&created.event, &details)
+            .await
+            .unwrap();
+        assert_eq!(remade.event.account_id.as_deref(), Some("home"));
+        assert_eq!(events_in(&home), 1);
+    }
+}
@@ -0,0 +1,474 @@
+//! RFC 5545 recurrence rule (RRULE) parsing and expansion
+//!
+//! Supports the subset calendars produce in practice: FREQ (DAILY, WEEKLY,
+//! MONTHLY, YEARLY), INTERVAL, COUNT, UNTIL, BYDAY (with ordinals for
+//! monthly/yearly rules), BYMONTHDAY and BYMONTH.
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Weekday};
+use chrono_tz::Tz;
+
+use super::ical;
+
+/// Upper bound on generated periods, so a bad rule can't spin forever
+const MAX_PERIODS: i64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A parsed recurrence rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<NaiveDateTime>,
+    /// Weekdays, optionally with an ordinal (`2MO` = second Monday, `-1FR` = last Friday)
+    pub by_day: Vec<(Option<i32>, Weekday)>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+}
+
+impl RecurrenceRule {
+    /// Parse an RRULE value such as `FREQ=WEEKLY;BYDAY=MO,WE;COUNT=10`
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        let value = value.strip_prefix("RRULE:").unwrap_or(value);
+
+        let mut freq = None;
+        let mut rule = Self {
+            freq: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+        };
+
+        for part in value.split(';').filter(|p| !p.is_empty()) {
+            let (key, val) = part
+                .split_once('=')
+                .with_context(|| format!("Invalid RRULE part: {}", part))?;
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match val.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        other => anyhow::bail!("Unsupported FREQ: {}", other),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = val.parse().context("Invalid INTERVAL")?;
+                    if !(1..=1000).contains(&rule.interval) {
+                        anyhow::bail!("INTERVAL must be between 1 and 1000");
+                    }
+                }
+                "COUNT" => rule.count = Some(val.parse().context("Invalid COUNT")?),
+                "UNTIL" => rule.until = Some(ical::parse_date_time(val)?.naive_utc()),
+                "BYDAY" => {
+                    rule.by_day = val
+                        .split(',')
+                        .map(parse_by_day)
+                        .collect::<Result<Vec<_>>>()?
+                }
+                "BYMONTHDAY" => {
+                    rule.by_month_day = val
+                        .split(',')
+                        .map(|d| d.parse().context("Invalid BYMONTHDAY"))
+                        .collect::<Result<Vec<_>>>()?
+                }
+                "BYMONTH" => {
+                    rule.by_month = val
+                        .split(',')
+                        .map(|m| m.parse().context("Invalid BYMONTH"))
+                        .collect::<Result<Vec<_>>>()?
+                }
+                // WKST, BYSETPOS etc. are accepted but ignored
+                _ => {}
+            }
+        }
+
+        rule.freq = freq.context("RRULE is missing FREQ")?;
+        Ok(rule)
+    }
+
+    /// The rule for a DTSTART in `tz`, with a UTC `UNTIL` moved to wall-clock
+    /// time there (occurrences are expanded in local time)
+    pub fn with_until_in(mut self, tz: &Tz) -> Self {
+        self.until = self
+            .until
+            .map(|until| until.and_utc().with_timezone(tz).naive_local());
+        self
+    }
+
+    /// Expand the rule starting at `dtstart`, returning every occurrence up to
+    /// and including `limit` (the first occurrence is always `dtstart`)
+    pub fn expand(&self, dtstart: NaiveDateTime, limit: NaiveDateTime) -> Vec<NaiveDateTime> {
+        self.expand_from_period(dtstart, 0, limit)
+    }
+
+    /// Occurrences that start within `from..=to`
+    ///
+    /// Rules without COUNT skip straight to the periods near `from`, so a
+    /// daily event that started years ago stays cheap to expand.
+    pub fn between(
+        &self,
+        dtstart: NaiveDateTime,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Vec<NaiveDateTime> {
+        let first_period = if self.count.is_none() && from > dtstart {
+            let elapsed = match self.freq {
+                Frequency::Daily => (from - dtstart).num_days(),
+                Frequency::Weekly => (from - dtstart).num_weeks(),
+                Frequency::Monthly => {
+                    (from.year() - dtstart.year()) as i64 * 12 + from.month() as i64
+                        - dtstart.month() as i64
+                }
+                Frequency::Yearly => (from.year() - dtstart.year()) as i64,
+            };
+            (elapsed / self.interval as i64 - 1).max(0)
+        } else {
+            0
+        };
+
+        self.expand_from_period(dtstart, first_period, to)
+            .into_iter()
+            .filter(|o| *o >= from)
+            .collect()
+    }
+
+    fn expand_from_period(
+        &self,
+        dtstart: NaiveDateTime,
+        first_period: i64,
+        limit: NaiveDateTime,
+    ) -> Vec<NaiveDateTime> {
+        let mut occurrences = Vec::new();
+        let end = match self.until {
+            Some(until) => until.min(limit),
+            None => limit,
+        };
+
+        for period in first_period..first_period + MAX_PERIODS {
+            let offset = period * self.interval as i64;
+            let mut candidates = self.candidates(dtstart, offset);
+            candidates.sort();
+
+            for candidate in candidates {
+                if candidate < dtstart {
+                    continue;
+                }
+                if candidate > end {
+                    return occurrences;
+                }
+                occurrences.push(candidate);
+                if let Some(count) = self.count {
+                    if occurrences.len() >= count as usize {
+                        return occurrences;
+                    }
+                }
+            }
+
+            if self.period_start(dtstart, offset) > end {
+                break;
+            }
+        }
+
+        occurrences
+    }
+
+    /// Expand the first `n` occurrences (bounded by COUNT/UNTIL if set)
+    pub fn first_n(&self, dtstart: NaiveDateTime, n: usize) -> Vec<NaiveDateTime> {
+        let capped = Self {
+            count: Some(self.count.map_or(n as u32, |c| c.min(n as u32))),
+            ..self.clone()
+        };
+        capped.expand(dtstart, NaiveDateTime::MAX)
+    }
+
+    /// The first day of the `offset`-th period after the one containing `dtstart`
+    fn period_start(&self, dtstart: NaiveDateTime, offset: i64) -> NaiveDateTime {
+        let date = dtstart.date();
+        let start = match self.freq {
+            Frequency::Daily => date
+                .checked_add_signed(Duration::days(offset))
+                .unwrap_or(NaiveDate::MAX),
+            Frequency::Weekly => {
+                let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+                monday
+                    .checked_add_signed(Duration::weeks(offset))
+                    .unwrap_or(NaiveDate::MAX)
+            }
+            Frequency::Monthly => {
+                let (y, m) = add_months(date.year(), date.month(), offset);
+                NaiveDate::from_ymd_opt(y, m, 1).unwrap_or(NaiveDate::MAX)
+            }
+            Frequency::Yearly => {
+                NaiveDate::from_ymd_opt(date.year() + offset as i32, 1, 1).unwrap_or(NaiveDate::MAX)
+            }
+        };
+        start.and_time(chrono::NaiveTime::MIN)
+    }
+
+    /// All candidate occurrences in the `offset`-th period
+    fn candidates(&self, dtstart: NaiveDateTime, offset: i64) -> Vec<NaiveDateTime> {
+        let date = dtstart.date();
+        let time = dtstart.time();
+
+        let dates: Vec<NaiveDate> = match self.freq {
+            Frequency::Daily => date
+                .checked_add_signed(Duration::days(offset))
+                .into_iter()
+                .collect(),
+            Frequency::Weekly => {
+                if self.by_day.is_empty() {
+                    date.checked_add_signed(Duration::weeks(offset))
+                        .into_iter()
+                        .collect()
+                } else {
+                    let monday = self.period_start(dtstart, offset).date();
+                    self.by_day
+                        .iter()
+                        .filter_map(|(_, wd)| {
+                            monday.checked_add_signed(Duration::days(
+                                wd.num_days_from_monday() as i64
+                            ))
+                        })
+                        .collect()
+                }
+            }
+            Frequency::Monthly => {
+                let (y, m) = add_months(date.year(), date.month(), offset);
+                self.month_dates(y, m, date.day())
+            }
+            Frequency::Yearly => {
+                let year = date.year() + offset as i32;
+                let months = if self.by_month.is_empty() {
+                    vec![date.month()]
+                } else {
+                    self.by_month.clone()
+                };
+                months
+                    .into_iter()
+                    .flat_map(|m| self.month_dates(year, m, date.day()))
+                    .collect()
+            }
+        };
+
+        dates
+            .into_iter()
+            .filter(|d| self.by_month.is_empty() || self.by_month.contains(&d.month()))
+            .filter(|d| {
+                // BYDAY restricts daily rules; weekly/monthly/yearly already expanded it
+                self.freq != Frequency::Daily
+                    || self.by_day.is_empty()
+                    || self.by_day.iter().any(|(_, wd)| *wd == d.weekday())
+            })
+            .map(|d| d.and_time(time))
+            .collect()
+    }
+
+    /// Dates within one month for MONTHLY/YEARLY rules
+    fn month_dates(&self, year: i32, month: u32, default_day: u32) -> Vec<NaiveDate> {
+        let Some(first) = NaiveDate::from_ymd_opt(year, month, 1) else {
+            return Vec::new();
+        };
+        let days_in_month = days_in_month(year, month);
+
+        if !self.by_month_day.is_empty() {
+            return self
+                .by_month_day
+                .iter()
+                .filter_map(|&d| {
+                    let day = if d < 0 {
+                        days_in_month as i32 + d + 1
+                    } else {
+                        d
+                    };
+                    u32::try_from(day)
+                        .ok()
+                        .and_then(|day| NaiveDate::from_ymd_opt(year, month, day))
+                })
+                .collect();
+        }
+
+        if !self.by_day.is_empty() {
+            let mut dates = Vec::new();
+            for (ordinal, weekday) in &self.by_day {
+                let matching: Vec<NaiveDate> = (0..days_in_month)
+                    .map(|i| first + Duration::days(i as i64))
+                    .filter(|d| d.weekday() == *weekday)
+                    .collect();
+                match ordinal {
+                    None => dates.extend(matching),
+                    Some(n) if *n > 0 => dates.extend(matching.get(*n as usize - 1)),
+                    Some(n) => {
+                        let idx = matching.len() as i32 + n;
+                        if idx >= 0 {
+                            dates.extend(matching.get(idx as usize));
+                        }
+                    }
+                }
+            }
+            return dates;
+        }
+
+        NaiveDate::from_ymd_opt(year, month, default_day)
+            .into_iter()
+            .collect()
+    }
+}
+
+fn parse_by_day(s: &str) -> Result<(Option<i32>, Weekday)> {
+    let s = s.trim();
+    if s.len() < 2 {
+        anyhow::bail!("Invalid BYDAY: {}", s);
+    }
+    let (ordinal, day) = s.split_at(s.len() - 2);
+    let weekday = match day.to_ascii_uppercase().as_str() {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => anyhow::bail!("Invalid BYDAY: {}", s),
+    };
+    let ordinal = if ordinal.is_empty() {
+        None
+    } else {
+        Some(ordinal.parse().context("Invalid BYDAY ordinal")?)
+    };
+    Ok((ordinal, weekday))
+}
+
+fn add_months(year: i32, month: u32, offset: i64) -> (i32, u32) {
+    let total = year as i64 * 12 + (month as i64 - 1) + offset;
+    (total.div_euclid(12) as i32, total.rem_euclid(12) as u32 + 1)
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (ny, nm) = add_months(year, month, 1);
+    NaiveDate::from_ymd_opt(ny, nm, 1)
+        .and_then(|d| d.pred_opt())
+        .map(|d| d.day())
+        .unwrap_or(28)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dt(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_weekly_by_day_with_count() {
+        let rule = RecurrenceRule::parse("FREQ=WEEKLY;BYDAY=MO,WE;COUNT=4").unwrap();
+        // 2026-02-02 is a Monday
+        let occurrences = rule.expand(dt("2026-02-02 10:00"), dt("2027-01-01 00:00"));
+        assert_eq!(
+            occurrences,
+            vec![
+                dt("2026-02-02 10:00"),
+                dt("2026-02-04 10:00"),
+                dt("2026-02-09 10:00"),
+                dt("2026-02-11 10:00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_daily_interval_until() {
+        let rule = RecurrenceRule::parse("FREQ=DAILY;INTERVAL=2;UNTIL=20260207T235959Z").unwrap();
+        let occurrences = rule.expand(dt("2026-02-01 09:00"), dt("2027-01-01 00:00"));
+        assert_eq!(
+            occurrences,
+            vec![
+                dt("2026-02-01 09:00"),
+                dt("2026-02-03 09:00"),
+                dt("2026-02-05 09:00"),
+                dt("2026-02-07 09:00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_monthly_last_friday() {
+        let rule = RecurrenceRule::parse("FREQ=MONTHLY;BYDAY=-1FR;COUNT=3").unwrap();
+        let occurrences = rule.expand(dt("2026-01-30 15:00"), dt("2027-01-01 00:00"));
+        assert_eq!(
+            occurrences,
+            vec![
+                dt("2026-01-30 15:00"),
+                dt("2026-02-27 15:00"),
+                dt("2026-03-27 15:00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_monthly_skips_short_months() {
+        let rule = RecurrenceRule::parse("FREQ=MONTHLY;COUNT=3").unwrap();
+        let occurrences = rule.expand(dt("2026-01-31 12:00"), dt("2027-01-01 00:00"));
+        assert_eq!(
+            occurrences,
+            vec![
+                dt("2026-01-31 12:00"),
+                dt("2026-03-31 12:00"),
+                dt("2026-05-31 12:00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_stops_at_limit() {
+        let rule = RecurrenceRule::parse("FREQ=WEEKLY").unwrap();
+        let occurrences = rule.expand(dt("2026-02-02 10:00"), dt("2026-02-20 00:00"));
+        assert_eq!(occurrences.len(), 3);
+
+        let first = rule.first_n(dt("2026-02-02 10:00"), 5);
+        assert_eq!(first.len(), 5);
+        assert_eq!(first[4], dt("2026-03-02 10:00"));
+    }
+
+    #[test]
+    fn test_between_skips_old_periods() {
+        let rule = RecurrenceRule::parse("FREQ=DAILY").unwrap();
+        let occurrences = rule.between(
+            dt("1990-01-01 08:00"),
+            dt("2026-02-03 00:00"),
+            dt("2026-02-05 00:00"),
+        );
+        assert_eq!(
+            occurrences,
+            vec![dt("2026-02-03 08:00"), dt("2026-02-04 08:00")]
+        );
+
+        let rule = RecurrenceRule::parse("FREQ=MONTHLY;BYMONTHDAY=15").unwrap();
+        let occurrences = rule.between(
+            dt("2001-06-15 08:00"),
+            dt("2026-02-01 00:00"),
+            dt("2026-03-01 00:00"),
+        );
+        assert_eq!(occurrences, vec![dt("2026-02-15 08:00")]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(RecurrenceRule::parse("COUNT=3").is_err());
+        assert!(RecurrenceRule::parse("FREQ=HOURLY").is_err());
+        assert!(RecurrenceRule::parse("FREQ=WEEKLY;BYDAY=XX").is_err());
+    }
+}
@@ -1,7 +1,11 @@
 use anyhow::{Context, Result};
 
 use super::{get_api_client, get_api_key, get_server_url, OutputFormat};
-use crate::models::AcceptProposalResponse;
+use crate::models::{
+    AcceptProposalRequest, AcceptProposalResponse, DeclineProposalRequest, DeclineProposalResponse,
+    InboxProposal, LocalConfig, ProposalResponse, ProposalStatus, RescheduleRequest,
+    StatusResponse,
+};
 
 impl std::fmt::Display for AcceptProposalResponse {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -17,18 +21,97 @@ impl std::fmt::Display for AcceptProposalResponse {
     }
 }
 
+/// Sign the answer to a proposal with the local key, if the key is kept here
+///
+/// `option` picks the accepted slot as in [`run_accept`]. `None` when the
+/// server holds the key (it signs for us) or the proposal has no hash to
+/// refer to.
+async fn sign_locally(
+    proposal_id: &str,
+    status: ProposalStatus,
+    option: Option<usize>,
+) -> Result<Option<ProposalResponse>> {
+    let Some(keypair) = LocalConfig::load_signing_key().context("Failed to load signing key")?
+    else {
+        return Ok(None);
+    };
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+    let email = LocalConfig::load()?
+        .email
+        .ok_or_else(|| anyhow::anyhow!("Not logged in. Run 'meetd login' first."))?;
+
+    let client = get_api_client();
+    let resp = client
+        .get(format!("{}/v1/proposals/{}", server_url, proposal_id))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await
+        .context("Failed to get proposal")?;
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to get proposal: {}", error);
+    }
+    let proposal: InboxProposal = resp.json().await.context("Failed to parse response")?;
+    let Some(proposal_hash) = proposal.proposal_hash else {
+        return Ok(None);
+    };
+
+    let slot = match status {
+        ProposalStatus::Accepted if proposal.options.is_empty() => Some(proposal.slot),
+        ProposalStatus::Accepted => {
+            let i = option.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "This proposal offers {} options; choose one with --option",
+                    proposal.options.len()
+                )
+            })?;
+            let slot = proposal
+                .options
+                .get(i)
+                .ok_or_else(|| anyhow::anyhow!("Option {} doesn't exist", i))?;
+            Some(slot.clone())
+        }
+        _ => None,
+    };
+
+    let mut response = ProposalResponse::new(
+        &proposal_hash,
+        &email,
+        &keypair.public_key_base64(),
+        &proposal.from,
+        status,
+        slot,
+    );
+    keypair.sign_response(&mut response);
+    Ok(Some(response))
+}
+
 /// Accept a proposal by ID
-pub async fn run_accept(proposal_id: &str, format: OutputFormat) -> Result<()> {
+///
+/// `option` picks one slot of a multi-slot proposal (0-based, as shown in the inbox).
+/// `force` books the slot even if it now clashes with someone's calendar.
+pub async fn run_accept(
+    proposal_id: &str,
+    option: Option<usize>,
+    force: bool,
+    format: OutputFormat,
+) -> Result<()> {
     let api_key = get_api_key()?;
     let server_url = get_server_url();
 
+    let response = sign_locally(proposal_id, ProposalStatus::Accepted, option).await?;
+
+    let url = match option {
+        Some(i) => format!("{}/v1/proposals/{}/accept/{}", server_url, proposal_id, i),
+        None => format!("{}/v1/proposals/{}/accept", server_url, proposal_id),
+    };
+
     let client = get_api_client();
     let resp = client
-        .post(format!(
-            "{}/v1/proposals/{}/accept",
-            server_url, proposal_id
-        ))
+        .post(url)
         .header("Authorization", format!("Bearer {}", api_key))
+        .json(&AcceptProposalRequest { force, response })
         .send()
         .await
         .context("Failed to accept proposal")?;
@@ -48,6 +131,8 @@ pub async fn run_decline(proposal_id: &str, format: OutputFormat) -> Result<()>
     let api_key = get_api_key()?;
     let server_url = get_server_url();
 
+    let response = sign_locally(proposal_id, ProposalStatus::Declined, None).await?;
+
     let client = get_api_client();
     let resp = client
         .post(format!(
@@ -55,6 +140,7 @@ pub async fn run_decline(proposal_id: &str, format: OutputFormat) -> Result<()>
             server_url, proposal_id
         ))
         .header("Authorization", format!("Bearer {}", api_key))
+        .json(&DeclineProposalRequest { response })
         .send()
         .await
         .context("Failed to decline proposal")?;
@@ -64,15 +150,27 @@ pub async fn run_decline(proposal_id: &str, format: OutputFormat) -> Result<()>
         anyhow::bail!("Failed to decline proposal: {}", error);
     }
 
-    let response = super::SuccessResponse {
-        message: format!("Proposal {} declined", proposal_id),
-    };
-    format.print(&response);
+    let response: DeclineProposalResponse =
+        resp.json().await.context("Failed to parse response")?;
+    match format {
+        OutputFormat::Human => {
+            println!("Proposal {} declined", proposal_id);
+            if let Some(warning) = &response.warning {
+                eprintln!("Warning: {}", warning);
+            }
+        }
+        OutputFormat::Json => format.print_json(&response),
+    }
     Ok(())
 }
 
 /// Accept a proposal via signed payload (agent-to-agent)
-pub async fn run_accept_signed(signed_proposal: &str, format: OutputFormat) -> Result<()> {
+pub async fn run_accept_signed(
+    signed_proposal: &str,
+    option: Option<usize>,
+    force: bool,
+    format: OutputFormat,
+) -> Result<()> {
     let api_key = get_api_key()?;
     let server_url = get_server_url();
 
@@ -83,6 +181,8 @@ pub async fn run_accept_signed(signed_proposal: &str, format: OutputFormat) -> R
         .json(&serde_json::json!({
             "signed_proposal": signed_proposal,
             "action": "accept",
+            "option": option,
+            "force": force,
         }))
         .send()
         .await
@@ -97,3 +197,112 @@ pub async fn run_accept_signed(signed_proposal: &str, format: OutputFormat) -> R
     format.print_json(&response);
     Ok(())
 }
+
+/// Cancel an accepted meeting
+pub async fn run_cancel(proposal_id: &str, format: OutputFormat) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+
+    let client = get_api_client();
+    let resp = client
+        .post(format!(
+            "{}/v1/proposals/{}/cancel",
+            server_url, proposal_id
+        ))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await
+        .context("Failed to cancel meeting")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to cancel meeting: {}", error);
+    }
+
+    let status: StatusResponse = resp.json().await.context("Failed to parse response")?;
+    let response = super::SuccessResponse {
+        message: with_warning(format!("Meeting {} cancelled", proposal_id), status),
+    };
+    format.print(&response);
+    Ok(())
+}
+
+/// Move an accepted meeting to a new time
+///
+/// `force` moves it even if the new slot clashes with someone's calendar.
+pub async fn run_reschedule(
+    proposal_id: &str,
+    slot: &str,
+    duration: Option<&str>,
+    force: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+
+    let request = RescheduleRequest {
+        slot_start: super::availability::parse_slot_time(slot)?,
+        duration_minutes: duration
+            .map(super::availability::parse_duration)
+            .transpose()?,
+        force,
+    };
+
+    let client = get_api_client();
+    let resp = client
+        .post(format!(
+            "{}/v1/proposals/{}/reschedule",
+            server_url, proposal_id
+        ))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&request)
+        .send()
+        .await
+        .context("Failed to reschedule meeting")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to reschedule meeting: {}", error);
+    }
+
+    let response: AcceptProposalResponse = resp.json().await.context("Failed to parse response")?;
+    format.print_json(&response);
+    Ok(())
+}
+
+/// Withdraw a proposal you sent
+pub async fn run_withdraw(proposal_id: &str, format: OutputFormat) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+
+    let client = get_api_client();
+    let resp = client
+        .post(format!(
+            "{}/v1/proposals/{}/withdraw",
+            server_url, proposal_id
+        ))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await
+        .context("Failed to withdraw proposal")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to withdraw proposal: {}", error);
+    }
+
+    let status: StatusResponse = resp.json().await.context("Failed to parse response")?;
+    let response = super::SuccessResponse {
+        message: with_warning(format!("Proposal {} withdrawn", proposal_id), status),
+    };
+    format.print(&response);
+    Ok(())
+}
+
+/// Add the server's warning, if any, to a success message
+fn with_warning(message: String, status: StatusResponse) -> String {
+    match status.warning {
+        Some(warning) => format!("{} (warning: {})", message, warning),
+        None => message,
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+
+use super::OutputFormat;
+use crate::crypto::{MasterKey, MASTER_KEY_FILE_ENV};
+use crate::db::Database;
+
+/// Result of re-encrypting the database
+#[derive(Debug, Serialize)]
+pub struct RotateMasterKeyResponse {
+    pub rotated: usize,
+    pub key_id: String,
+    pub key_file: String,
+    pub key_created: bool,
+}
+
+impl std::fmt::Display for RotateMasterKeyResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.key_created {
+            writeln!(f, "Generated new master key in {}", self.key_file)?;
+        }
+        writeln!(
+            f,
+            "Re-encrypted {} secrets with master key {}",
+            self.rotated, self.key_id
+        )?;
+        write!(
+            f,
+            "Restart the server with {}={} (and remove the old key)",
+            MASTER_KEY_FILE_ENV, self.key_file
+        )
+    }
+}
+
+/// Re-encrypt every stored secret with the key in `new_key_file`
+///
+/// The current key comes from `MEETD_MASTER_KEY` / `MEETD_MASTER_KEY_FILE`;
+/// without one, plaintext secrets are encrypted for the first time. A missing
+/// key file is created with a fresh key.
+pub fn run_rotate_master_key(
+    db_path: &str,
+    new_key_file: &Path,
+    format: OutputFormat,
+) -> Result<()> {
+    let key_created = !new_key_file.exists();
+    let new_key = if key_created {
+        let key = MasterKey::generate();
+        key.write_file(new_key_file)?;
+        key
+    } else {
+        MasterKey::load_file(new_key_file)?
+    };
+
+    let mut db = Database::open(db_path)?;
+    if let Some(current) = MasterKey::from_env()? {
+        db = db.with_master_key(current);
+    }
+    let rotated = db.rotate_master_key(&new_key)?;
+
+    let response = RotateMasterKeyResponse {
+        rotated,
+        key_id: new_key.id().to_string(),
+        key_file: new_key_file.display().to_string(),
+        key_created,
+    };
+    format.print(&response);
+    Ok(())
+}
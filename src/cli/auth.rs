@@ -1,11 +1,12 @@
 use anyhow::{Context, Result};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::net::TcpListener;
 use std::sync::Arc;
 use tokio::sync::oneshot;
 
-use super::OutputFormat;
-use crate::models::LocalConfig;
+use super::{get_api_client, get_api_key, get_server_url, OutputFormat};
+use crate::crypto::Keypair;
+use crate::models::{LocalConfig, RevocationReason};
 
 /// Response from login command
 #[derive(Debug, Serialize)]
@@ -13,18 +14,28 @@ pub struct LoginResponse {
     pub email: String,
     pub api_key: String,
     pub user_id: String,
+    /// Public half of the signing key kept on this machine (`--local-key`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_key: Option<String>,
 }
 
 impl std::fmt::Display for LoginResponse {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Logged in as {}", self.email)?;
         writeln!(f, "Your API key: {}", self.api_key)?;
-        writeln!(f, "User ID: {}", self.user_id)
+        writeln!(f, "User ID: {}", self.user_id)?;
+        if let Some(public_key) = &self.signing_key {
+            writeln!(f, "Signing locally with public key: {}", public_key)?;
+        }
+        Ok(())
     }
 }
 
 /// Run the login command using server-side OAuth
-pub async fn run_login(server_url: &str, format: OutputFormat) -> Result<()> {
+///
+/// With `local_key`, proposals are signed with a key kept on this machine
+/// instead of one held by the server (see [`use_local_signing_key`]).
+pub async fn run_login(server_url: &str, local_key: bool, format: OutputFormat) -> Result<()> {
     // Find an available port for the local callback server
     let listener = TcpListener::bind("127.0.0.1:0").context("Failed to bind local server")?;
     let port = listener.local_addr()?.port();
@@ -77,7 +88,106 @@ pub async fn run_login(server_url: &str, format: OutputFormat) -> Result<()> {
     // Cancel the listener task
     handle.abort();
 
-    // Save config locally
+    finish_login(server_url, result, local_key, format).await
+}
+
+/// Run the login command against a CalDAV calendar instead of Google
+///
+/// The password is read from `MEETD_CALDAV_PASSWORD`, or prompted for on stdin.
+pub async fn run_login_caldav(
+    server_url: &str,
+    email: &str,
+    caldav_url: &str,
+    username: &str,
+    local_key: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let password = match std::env::var("MEETD_CALDAV_PASSWORD") {
+        Ok(p) => p,
+        Err(_) => {
+            use std::io::Write;
+            print!("CalDAV password for {}: ", username);
+            std::io::stdout().flush()?;
+            let mut line = String::new();
+            std::io::stdin()
+                .read_line(&mut line)
+                .context("Failed to read password")?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+
+    let body = serde_json::json!({
+        "email": email,
+        "url": caldav_url,
+        "username": username,
+        "password": password,
+    });
+    register_direct(server_url, email, "caldav", &body, local_key, format).await
+}
+
+/// Run the login command against an iCalendar file or feed instead of Google
+pub async fn run_login_ics(
+    server_url: &str,
+    email: &str,
+    source: &str,
+    write_path: Option<&str>,
+    local_key: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let body = serde_json::json!({
+        "email": email,
+        "source": source,
+        "write_path": write_path,
+    });
+    register_direct(server_url, email, "ics", &body, local_key, format).await
+}
+
+/// Register through `/auth/register/<provider>` and save the credentials locally
+async fn register_direct(
+    server_url: &str,
+    email: &str,
+    provider: &str,
+    body: &serde_json::Value,
+    local_key: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let client = get_api_client();
+    let resp = client
+        .post(format!("{}/auth/register/{}", server_url, provider))
+        .json(body)
+        .send()
+        .await
+        .context("Failed to register")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to register {} calendar: {}", provider, error);
+    }
+
+    #[derive(serde::Deserialize)]
+    struct RegisterResponse {
+        user_id: String,
+        api_key: String,
+    }
+
+    let registered: RegisterResponse = resp.json().await.context("Failed to parse response")?;
+    let result = LoginResponse {
+        email: email.to_string(),
+        api_key: registered.api_key,
+        user_id: registered.user_id,
+        signing_key: None,
+    };
+
+    finish_login(server_url, result, local_key, format).await
+}
+
+/// Save the credentials locally, switching to a local signing key if asked
+async fn finish_login(
+    server_url: &str,
+    mut result: LoginResponse,
+    local_key: bool,
+    format: OutputFormat,
+) -> Result<()> {
     let mut config = LocalConfig::load().unwrap_or_default();
     config.api_key = Some(result.api_key.clone());
     config.server_url = Some(server_url.to_string());
@@ -85,10 +195,163 @@ pub async fn run_login(server_url: &str, format: OutputFormat) -> Result<()> {
     config.user_id = Some(result.user_id.clone());
     config.save().context("Failed to save config")?;
 
+    if local_key {
+        result.signing_key = Some(use_local_signing_key(server_url, &result.api_key).await?);
+    }
+
     format.print(&result);
     Ok(())
 }
 
+/// Sign proposals with a key kept in `~/.config/meetd` from now on
+///
+/// Reuses the stored key if there is one, otherwise generates it. Only the
+/// public key is sent to the server, which drops its own copy of the
+/// private key. Returns the public key.
+pub async fn use_local_signing_key(server_url: &str, api_key: &str) -> Result<String> {
+    let keypair = match LocalConfig::load_signing_key().context("Failed to load signing key")? {
+        Some(keypair) => keypair,
+        None => {
+            let keypair = Keypair::generate()?;
+            LocalConfig::save_signing_key(&keypair).context("Failed to save signing key")?;
+            keypair
+        }
+    };
+    let public_key = keypair.public_key_base64();
+
+    let client = get_api_client();
+    let resp = client
+        .put(format!("{}/auth/signing-key", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({ "public_key": public_key }))
+        .send()
+        .await
+        .context("Failed to register signing key")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to register signing key: {}", error);
+    }
+
+    Ok(public_key)
+}
+
+/// Response from rotating the signing key
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateSigningKeyResponse {
+    pub public_key: String,
+    pub previous_key: String,
+    pub reason: RevocationReason,
+}
+
+impl std::fmt::Display for RotateSigningKeyResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "New signing key: {}", self.public_key)?;
+        write!(
+            f,
+            "Retired key ({}): {}",
+            self.reason.as_str(),
+            self.previous_key
+        )
+    }
+}
+
+/// Replace the signing key, locally or on the server depending on who holds it
+///
+/// With `compromised`, proposals signed with the old key stop verifying;
+/// otherwise those made before the rotation stay valid.
+pub async fn run_rotate_signing_key(compromised: bool, format: OutputFormat) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+    let reason = if compromised {
+        RevocationReason::Compromised
+    } else {
+        RevocationReason::Superseded
+    };
+
+    // A locally held key is replaced by a new local one
+    let local = if LocalConfig::load_signing_key()
+        .context("Failed to load signing key")?
+        .is_some()
+    {
+        Some(Keypair::generate()?)
+    } else {
+        None
+    };
+
+    let client = get_api_client();
+    let resp = client
+        .post(format!("{}/auth/signing-key/rotate", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({
+            "public_key": local.as_ref().map(|k| k.public_key_base64()),
+            "reason": reason,
+        }))
+        .send()
+        .await
+        .context("Failed to rotate signing key")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to rotate signing key: {}", error);
+    }
+
+    let response: RotateSigningKeyResponse =
+        resp.json().await.context("Failed to parse response")?;
+    if let Some(keypair) = &local {
+        LocalConfig::save_signing_key(keypair).context("Failed to save signing key")?;
+    }
+
+    format.print(&response);
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyEmailResponse {
+    pub email: String,
+    pub verified: bool,
+}
+
+impl std::fmt::Display for VerifyEmailResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.verified {
+            write!(f, "{} is already verified", self.email)
+        } else {
+            write!(
+                f,
+                "Sent a verification link to {}; open it to confirm the address",
+                self.email
+            )
+        }
+    }
+}
+
+/// Ask the server to mail a link proving you own your address
+///
+/// Until it's followed, other users' contacts, domain and allowlist sharing
+/// don't recognise you. Google sign-in verifies the address by itself.
+pub async fn run_verify_email(format: OutputFormat) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+
+    let client = get_api_client();
+    let resp = client
+        .post(format!("{}/auth/email/verify", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await
+        .context("Failed to request email verification")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to request email verification: {}", error);
+    }
+
+    let response: VerifyEmailResponse = resp.json().await.context("Failed to parse response")?;
+    format.print(&response);
+    Ok(())
+}
+
 async fn handle_callback(
     mut stream: tokio::net::TcpStream,
     tx: Arc<std::sync::Mutex<Option<oneshot::Sender<Result<LoginResponse>>>>>,
@@ -209,6 +472,7 @@ fn parse_callback_request(request: &str) -> Result<LoginResponse> {
         email,
         api_key,
         user_id,
+        signing_key: None,
     })
 }
 
@@ -3,12 +3,18 @@ use chrono::{DateTime, Utc};
 use serde::Serialize;
 
 use super::{get_api_client, get_api_key, get_server_url, OutputFormat};
-use crate::models::{AvailabilityRequest, AvailableSlot, TimeWindow};
+use crate::models::{
+    AvailabilityRequest, AvailableSlot, BusyPeriod, BusyRequest, Participant, SlotPreferences,
+    TimeWindow, Visibility,
+};
 
 /// Response from availability query
 #[derive(Debug, Serialize, serde::Deserialize)]
 pub struct AvailResponse {
     pub slots: Vec<AvailableSlot>,
+    /// Participants whose calendars weren't checked
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unchecked: Vec<String>,
 }
 
 impl std::fmt::Display for AvailResponse {
@@ -18,23 +24,37 @@ impl std::fmt::Display for AvailResponse {
         } else {
             writeln!(f, "Available slots:")?;
             for slot in &self.slots {
-                writeln!(
+                write!(
                     f,
                     "  {} - {} (score: {:.2})",
                     slot.start.format("%Y-%m-%d %H:%M"),
                     slot.end.format("%H:%M"),
                     slot.score
                 )?;
+                if !slot.unavailable.is_empty() {
+                    write!(f, " without {}", slot.unavailable.join(", "))?;
+                }
+                writeln!(f)?;
             }
         }
+        if !self.unchecked.is_empty() {
+            writeln!(
+                f,
+                "Calendars not checked (not on meetd, not shared with you, or unreadable): {}",
+                self.unchecked.join(", ")
+            )?;
+        }
         Ok(())
     }
 }
 
-/// Parse duration string like "30m", "1h", "90m"
+/// Parse duration string like "30m", "1h", "90m", "2d" into minutes
 pub fn parse_duration(s: &str) -> Result<i32> {
     let s = s.trim().to_lowercase();
-    if let Some(mins) = s.strip_suffix('m') {
+    if let Some(days) = s.strip_suffix('d') {
+        let d: i32 = days.parse().context("Invalid days value")?;
+        Ok(d * 24 * 60)
+    } else if let Some(mins) = s.strip_suffix('m') {
         mins.parse::<i32>().context("Invalid minutes value")
     } else if let Some(hours) = s.strip_suffix('h') {
         let h: i32 = hours.parse().context("Invalid hours value")?;
@@ -45,12 +65,96 @@ pub fn parse_duration(s: &str) -> Result<i32> {
     }
 }
 
+/// Slot search options shared by `meetd avail` and `meetd config slots`
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct SlotArgs {
+    /// Time between candidate start times (e.g. "15m")
+    #[arg(long)]
+    pub step: Option<String>,
+    /// Free time required before a meeting (e.g. "10m")
+    #[arg(long)]
+    pub buffer_before: Option<String>,
+    /// Free time required after a meeting (e.g. "10m")
+    #[arg(long)]
+    pub buffer_after: Option<String>,
+    /// Minimum notice before a meeting starts (e.g. "4h")
+    #[arg(long)]
+    pub notice: Option<String>,
+    /// How far ahead meetings may be booked (e.g. "14d")
+    #[arg(long)]
+    pub horizon: Option<String>,
+}
+
+impl SlotArgs {
+    pub fn preferences(&self) -> Result<SlotPreferences> {
+        let minutes = |v: &Option<String>| v.as_deref().map(parse_duration).transpose();
+        let preferences = SlotPreferences {
+            step_minutes: minutes(&self.step)?,
+            buffer_before_minutes: minutes(&self.buffer_before)?,
+            buffer_after_minutes: minutes(&self.buffer_after)?,
+            min_notice_minutes: minutes(&self.notice)?,
+            max_horizon_days: self.horizon.as_deref().map(parse_days).transpose()?,
+        };
+        preferences.validate()?;
+        Ok(preferences)
+    }
+}
+
+/// Recurrence options shared by `meetd avail` and `meetd propose`
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct RepeatArgs {
+    /// Make the meeting recurring: daily, weekly, monthly or yearly
+    #[arg(long)]
+    pub repeat: Option<String>,
+    /// How many times the meeting happens (default: no end)
+    #[arg(long, requires = "repeat")]
+    pub count: Option<u32>,
+}
+
+impl RepeatArgs {
+    /// The RRULE value these options describe, e.g. `FREQ=WEEKLY;COUNT=10`
+    pub fn rule(&self) -> Result<Option<String>> {
+        let Some(repeat) = &self.repeat else {
+            return Ok(None);
+        };
+        let freq = match repeat.trim().to_lowercase().as_str() {
+            "daily" => "DAILY",
+            "weekly" => "WEEKLY",
+            "monthly" => "MONTHLY",
+            "yearly" => "YEARLY",
+            other => anyhow::bail!(
+                "Invalid --repeat '{}': use daily, weekly, monthly or yearly",
+                other
+            ),
+        };
+        Ok(Some(match self.count {
+            Some(0) => anyhow::bail!("--count must be at least 1"),
+            Some(count) => format!("FREQ={};COUNT={}", freq, count),
+            None => format!("FREQ={}", freq),
+        }))
+    }
+}
+
+/// Parse a day count like "14d" or "14"
+fn parse_days(s: &str) -> Result<i32> {
+    let s = s.trim().to_lowercase();
+    s.strip_suffix('d')
+        .unwrap_or(&s)
+        .parse()
+        .context("Invalid days value")
+}
+
 /// Query availability for a meeting
+///
+/// Required participants have to be free; optional ones only affect ranking.
+/// With a `recurrence` (an RRULE value) every checked occurrence has to be free.
 pub async fn run_avail(
-    with_email: &str,
+    participants: Vec<Participant>,
     duration: &str,
     window: &str,
     timezone: Option<&str>,
+    preferences: SlotPreferences,
+    recurrence: Option<String>,
     format: OutputFormat,
 ) -> Result<()> {
     let api_key = get_api_key()?;
@@ -60,11 +164,14 @@ pub async fn run_avail(
     let time_window = TimeWindow::parse(window)?;
 
     let request = AvailabilityRequest {
-        with_email: with_email.to_string(),
+        with_email: None,
+        participants,
         duration_minutes,
         window_start: time_window.start,
         window_end: time_window.end,
         timezone: timezone.map(|s| s.to_string()),
+        recurrence,
+        preferences,
     };
 
     let client = get_api_client();
@@ -86,6 +193,72 @@ pub async fn run_avail(
     Ok(())
 }
 
+/// Show someone's busy periods, with as much detail as they share
+pub async fn run_busy(email: &str, window: &str, format: OutputFormat) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+
+    let time_window = TimeWindow::parse(window)?;
+    let request = BusyRequest {
+        email: email.to_string(),
+        window_start: time_window.start,
+        window_end: time_window.end,
+    };
+
+    let client = get_api_client();
+    let resp = client
+        .post(format!("{}/v1/availability/busy", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&request)
+        .send()
+        .await
+        .context("Failed to query busy periods")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to query busy periods: {}", error);
+    }
+
+    let response: BusyListResponse = resp.json().await.context("Failed to parse response")?;
+    format.print(&response);
+    Ok(())
+}
+
+/// Response from busy period query
+#[derive(Debug, Serialize, serde::Deserialize)]
+pub struct BusyListResponse {
+    pub email: String,
+    pub visibility: Visibility,
+    pub busy: Vec<BusyPeriod>,
+}
+
+impl std::fmt::Display for BusyListResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let response = self;
+        if response.busy.is_empty() {
+            return writeln!(
+                f,
+                "{} has nothing scheduled in this window.",
+                response.email
+            );
+        }
+        writeln!(f, "{} is busy ({}):", response.email, response.visibility)?;
+        for period in &response.busy {
+            write!(
+                f,
+                "  {} - {}",
+                period.start.format("%Y-%m-%d %H:%M"),
+                period.end.format("%Y-%m-%d %H:%M"),
+            )?;
+            if let Some(title) = &period.title {
+                write!(f, "  {}", title)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
 /// Parse a slot time like "2026-02-03T10:00" or "2026-02-03T10:00:00Z"
 pub fn parse_slot_time(s: &str) -> Result<DateTime<Utc>> {
     // Try full RFC3339 first
@@ -117,6 +290,7 @@ mod tests {
         assert_eq!(parse_duration("90m").unwrap(), 90);
         assert_eq!(parse_duration("2h").unwrap(), 120);
         assert_eq!(parse_duration("45").unwrap(), 45);
+        assert_eq!(parse_duration("2d").unwrap(), 2880);
     }
 
     #[test]
@@ -127,4 +301,23 @@ mod tests {
         let dt2 = parse_slot_time("2026-02-03T10:00:00Z").unwrap();
         assert_eq!(dt2.format("%Y-%m-%d %H:%M").to_string(), "2026-02-03 10:00");
     }
+
+    #[test]
+    fn test_repeat_rule() {
+        let repeat = |repeat: Option<&str>, count| RepeatArgs {
+            repeat: repeat.map(|s| s.to_string()),
+            count,
+        };
+        assert_eq!(repeat(None, None).rule().unwrap(), None);
+        assert_eq!(
+            repeat(Some("weekly"), Some(10)).rule().unwrap().as_deref(),
+            Some("FREQ=WEEKLY;COUNT=10")
+        );
+        assert_eq!(
+            repeat(Some("Daily"), None).rule().unwrap().as_deref(),
+            Some("FREQ=DAILY")
+        );
+        assert!(repeat(Some("hourly"), None).rule().is_err());
+        assert!(repeat(Some("weekly"), Some(0)).rule().is_err());
+    }
 }
@@ -2,7 +2,10 @@ use anyhow::{Context, Result};
 use serde::Serialize;
 
 use super::{get_api_client, get_api_key, get_server_url, OutputFormat};
-use crate::models::{LocalConfig, Visibility};
+use crate::models::{
+    CalendarAccountInfo, LocalConfig, SharingMode, SharingPolicy, SlotPreferences, TimeRange,
+    Visibility, WorkingHours,
+};
 
 /// Config show response
 #[derive(Debug, Serialize)]
@@ -142,3 +145,455 @@ pub fn run_config_server(url: &str, format: OutputFormat) -> Result<()> {
     format.print(&response);
     Ok(())
 }
+
+/// Calendar accounts and the calendars used in each
+#[derive(Debug, Serialize)]
+pub struct CalendarsShowResponse {
+    pub calendars: Vec<CalendarAccountInfo>,
+}
+
+impl std::fmt::Display for CalendarsShowResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.calendars.is_empty() {
+            return write!(f, "No calendar accounts linked");
+        }
+
+        for (i, account) in self.calendars.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(
+                f,
+                "{} [{}] {}{}",
+                account.id,
+                account.provider,
+                account.label.as_deref().unwrap_or(&account.account),
+                if account.is_primary { " (primary)" } else { "" }
+            )?;
+            let busy = if account.calendars.busy.is_empty() {
+                "default".to_string()
+            } else {
+                account.calendars.busy.join(", ")
+            };
+            writeln!(f, "  Busy from: {}", busy)?;
+            write!(
+                f,
+                "  New events in: {}",
+                account.calendars.write.as_deref().unwrap_or("default")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Show which calendars are used
+pub async fn run_config_calendars_show(format: OutputFormat) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+
+    let client = get_api_client();
+    let resp = client
+        .get(format!("{}/v1/config", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await
+        .context("Failed to fetch config")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to get config: {}", error);
+    }
+
+    #[derive(serde::Deserialize)]
+    struct ServerConfig {
+        #[serde(default)]
+        calendars: Vec<CalendarAccountInfo>,
+    }
+
+    let server_config: ServerConfig = resp.json().await?;
+
+    format.print(&CalendarsShowResponse {
+        calendars: server_config.calendars,
+    });
+    Ok(())
+}
+
+/// Choose busy and write calendars (empty `busy` and no `write` resets to defaults)
+pub async fn run_config_calendars(
+    busy: &[String],
+    write: Option<&str>,
+    account: Option<&str>,
+    format: OutputFormat,
+) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+
+    let client = get_api_client();
+    let resp = client
+        .patch(format!("{}/v1/config", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({
+            "calendars": {
+                "account": account,
+                "busy": busy,
+                "write": write,
+            },
+        }))
+        .send()
+        .await
+        .context("Failed to update config")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to update calendars: {}", error);
+    }
+
+    #[derive(serde::Deserialize)]
+    struct UpdateResponse {
+        calendars: CalendarAccountInfo,
+    }
+
+    let updated: UpdateResponse = resp.json().await?;
+
+    format.print(&CalendarsShowResponse {
+        calendars: vec![updated.calendars],
+    });
+    Ok(())
+}
+
+/// Working hours, as shown by `meetd config hours`
+#[derive(Debug, Serialize)]
+pub struct HoursShowResponse {
+    pub working_hours: WorkingHours,
+    /// Whether these are the built-in defaults rather than the user's own
+    pub is_default: bool,
+}
+
+impl std::fmt::Display for HoursShowResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hours = &self.working_hours;
+        write!(f, "Timezone: {}", hours.timezone)?;
+        if self.is_default {
+            write!(f, " (default)")?;
+        }
+        writeln!(f)?;
+        for day in [
+            chrono::Weekday::Mon,
+            chrono::Weekday::Tue,
+            chrono::Weekday::Wed,
+            chrono::Weekday::Thu,
+            chrono::Weekday::Fri,
+            chrono::Weekday::Sat,
+            chrono::Weekday::Sun,
+        ] {
+            let ranges = hours.days.get(day);
+            let text = if ranges.is_empty() {
+                "off".to_string()
+            } else {
+                ranges
+                    .iter()
+                    .map(|r| r.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            writeln!(f, "  {}: {}", day, text)?;
+        }
+        write!(
+            f,
+            "Lunch: {}",
+            hours
+                .lunch
+                .map(|l| l.to_string())
+                .unwrap_or_else(|| "none".to_string())
+        )
+    }
+}
+
+/// Show or change working hours
+pub async fn run_config_hours(
+    timezone: Option<&str>,
+    days: &[String],
+    lunch: Option<&str>,
+    reset: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+    let client = get_api_client();
+
+    let resp = client
+        .get(format!("{}/v1/config", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await
+        .context("Failed to fetch config")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to get config: {}", error);
+    }
+
+    #[derive(serde::Deserialize)]
+    struct ServerConfig {
+        working_hours: Option<WorkingHours>,
+    }
+
+    let current = resp.json::<ServerConfig>().await?.working_hours;
+
+    if timezone.is_none() && days.is_empty() && lunch.is_none() && !reset {
+        format.print(&HoursShowResponse {
+            is_default: current.is_none(),
+            working_hours: current.unwrap_or_default(),
+        });
+        return Ok(());
+    }
+
+    let updated = if reset {
+        None
+    } else {
+        let mut hours = current.unwrap_or_default();
+        if let Some(tz) = timezone {
+            hours.timezone = tz.to_string();
+        }
+        for spec in days {
+            hours.apply_day_spec(spec)?;
+        }
+        if let Some(lunch) = lunch {
+            hours.lunch = if lunch.eq_ignore_ascii_case("off") {
+                None
+            } else {
+                Some(TimeRange::parse(lunch)?)
+            };
+        }
+        hours.validate()?;
+        Some(hours)
+    };
+
+    let resp = client
+        .patch(format!("{}/v1/config", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({
+            "working_hours": updated,
+        }))
+        .send()
+        .await
+        .context("Failed to update config")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to update working hours: {}", error);
+    }
+
+    format.print(&HoursShowResponse {
+        is_default: updated.is_none(),
+        working_hours: updated.unwrap_or_default(),
+    });
+    Ok(())
+}
+
+/// Saved slot preferences, as shown by `meetd config slots`
+#[derive(Debug, Serialize)]
+pub struct SlotsShowResponse {
+    pub slot_preferences: SlotPreferences,
+}
+
+impl std::fmt::Display for SlotsShowResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let p = &self.slot_preferences;
+        let minutes = |v: Option<i32>, default: &str| match v {
+            Some(m) => format!("{}m", m),
+            None => default.to_string(),
+        };
+        writeln!(f, "Step: {}", minutes(p.step_minutes, "30m (default)"))?;
+        writeln!(
+            f,
+            "Buffer before: {}",
+            minutes(p.buffer_before_minutes, "none")
+        )?;
+        writeln!(
+            f,
+            "Buffer after: {}",
+            minutes(p.buffer_after_minutes, "none")
+        )?;
+        writeln!(
+            f,
+            "Minimum notice: {}",
+            minutes(p.min_notice_minutes, "none")
+        )?;
+        write!(
+            f,
+            "Horizon: {}",
+            p.max_horizon_days
+                .map(|d| format!("{}d", d))
+                .unwrap_or_else(|| "none".to_string())
+        )
+    }
+}
+
+/// Show or change saved slot preferences
+///
+/// Options that aren't given keep their saved value.
+pub async fn run_config_slots(
+    preferences: SlotPreferences,
+    reset: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+    let client = get_api_client();
+
+    let resp = client
+        .get(format!("{}/v1/config", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await
+        .context("Failed to fetch config")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to get config: {}", error);
+    }
+
+    #[derive(serde::Deserialize)]
+    struct ServerConfig {
+        #[serde(default)]
+        slot_preferences: SlotPreferences,
+    }
+
+    let current = resp.json::<ServerConfig>().await?.slot_preferences;
+
+    if preferences.is_empty() && !reset {
+        format.print(&SlotsShowResponse {
+            slot_preferences: current,
+        });
+        return Ok(());
+    }
+
+    let updated = if reset {
+        preferences
+    } else {
+        preferences.or(&current)
+    };
+
+    let resp = client
+        .patch(format!("{}/v1/config", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({
+            "slot_preferences": updated,
+        }))
+        .send()
+        .await
+        .context("Failed to update config")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to update slot preferences: {}", error);
+    }
+
+    format.print(&SlotsShowResponse {
+        slot_preferences: updated,
+    });
+    Ok(())
+}
+
+/// Saved sharing policy, as shown by `meetd config sharing`
+#[derive(Debug, Serialize)]
+pub struct SharingShowResponse {
+    pub sharing: SharingPolicy,
+}
+
+impl std::fmt::Display for SharingShowResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self.sharing.mode {
+            SharingMode::Everyone => "any meetd user",
+            SharingMode::Contacts => "people you've proposed to or accepted a proposal from",
+            SharingMode::Domain => "people at your email domain",
+            SharingMode::Allowlist => "only the allowlist",
+        };
+        writeln!(f, "Sharing: {} ({})", self.sharing.mode, description)?;
+        if self.sharing.allow.is_empty() {
+            write!(f, "Always allowed: none")
+        } else {
+            write!(f, "Always allowed: {}", self.sharing.allow.join(", "))
+        }
+    }
+}
+
+/// Show or change who may see your availability
+pub async fn run_config_sharing(
+    mode: Option<&str>,
+    allow: &[String],
+    disallow: &[String],
+    format: OutputFormat,
+) -> Result<()> {
+    let mode = mode
+        .map(|m| {
+            SharingMode::parse(m).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid sharing mode. Use: everyone, contacts, domain, or allowlist"
+                )
+            })
+        })
+        .transpose()?;
+
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+    let client = get_api_client();
+
+    let resp = client
+        .get(format!("{}/v1/config", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await
+        .context("Failed to fetch config")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to get config: {}", error);
+    }
+
+    #[derive(serde::Deserialize)]
+    struct ServerConfig {
+        #[serde(default)]
+        sharing: SharingPolicy,
+    }
+
+    let current = resp.json::<ServerConfig>().await?.sharing;
+
+    if mode.is_none() && allow.is_empty() && disallow.is_empty() {
+        format.print(&SharingShowResponse { sharing: current });
+        return Ok(());
+    }
+
+    let mut updated = current;
+    if let Some(mode) = mode {
+        updated.mode = mode;
+    }
+    for entry in allow {
+        if !updated.allow.iter().any(|a| a.eq_ignore_ascii_case(entry)) {
+            updated.allow.push(entry.clone());
+        }
+    }
+    updated
+        .allow
+        .retain(|a| !disallow.iter().any(|d| d.eq_ignore_ascii_case(a)));
+    updated.validate()?;
+
+    let resp = client
+        .patch(format!("{}/v1/config", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({
+            "sharing": updated,
+        }))
+        .send()
+        .await
+        .context("Failed to update config")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to update sharing: {}", error);
+    }
+
+    format.print(&SharingShowResponse { sharing: updated });
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{get_api_client, get_api_key, get_server_url, OutputFormat, SuccessResponse};
+use crate::models::{ContactKey, KeySource};
+
+/// Keys pinned for the people who've sent proposals
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContactsResponse {
+    pub contacts: Vec<ContactKey>,
+}
+
+impl std::fmt::Display for ContactsResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.contacts.is_empty() {
+            return write!(f, "No pinned keys yet.");
+        }
+        write!(f, "Pinned keys:")?;
+        for contact in &self.contacts {
+            write!(f, "\n{}", TrustedKey(contact.clone()))?;
+        }
+        Ok(())
+    }
+}
+
+/// A key that was just pinned
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TrustedKey(pub ContactKey);
+
+impl std::fmt::Display for TrustedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let key = &self.0;
+        writeln!(f, "  {}: {}", key.email, key.public_key)?;
+        let source = match (key.source, &key.server) {
+            (KeySource::Server, Some(server)) => format!("looked up on {}", server),
+            (KeySource::Directory, _) => "this server's directory".to_string(),
+            (KeySource::Manual, _) => "trusted manually".to_string(),
+            _ => "first proposal".to_string(),
+        };
+        write!(
+            f,
+            "    Pinned {} ({})",
+            key.pinned_at.format("%Y-%m-%d %H:%M"),
+            source
+        )
+    }
+}
+
+/// List pinned contact keys
+pub async fn run_contacts(format: OutputFormat) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+
+    let client = get_api_client();
+    let resp = client
+        .get(format!("{}/v1/contacts", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await
+        .context("Failed to fetch contacts")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to fetch contacts: {}", error);
+    }
+
+    let response: ContactsResponse = resp.json().await.context("Failed to parse response")?;
+    format.print(&response);
+    Ok(())
+}
+
+/// Pin `public_key` (or the contact's current key) for `email`
+pub async fn run_contacts_trust(
+    email: &str,
+    public_key: Option<&str>,
+    format: OutputFormat,
+) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+
+    let client = get_api_client();
+    let resp = client
+        .put(format!(
+            "{}/v1/contacts/{}/key",
+            server_url,
+            urlencoding::encode(email)
+        ))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({ "public_key": public_key }))
+        .send()
+        .await
+        .context("Failed to trust key")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to trust key: {}", error);
+    }
+
+    let response: TrustedKey = resp.json().await.context("Failed to parse response")?;
+    format.print(&response);
+    Ok(())
+}
+
+/// Forget the key pinned for `email`
+pub async fn run_contacts_untrust(email: &str, format: OutputFormat) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+
+    let client = get_api_client();
+    let resp = client
+        .delete(format!(
+            "{}/v1/contacts/{}/key",
+            server_url,
+            urlencoding::encode(email)
+        ))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await
+        .context("Failed to untrust key")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to untrust key: {}", error);
+    }
+
+    format.print(&SuccessResponse {
+        message: format!(
+            "Forgot {}'s key; the next one they sign with will be pinned",
+            email
+        ),
+    });
+    Ok(())
+}
@@ -26,6 +26,40 @@ impl std::fmt::Display for InboxResponse {
                     p.slot.duration_minutes
                 )?;
                 writeln!(f, "    From: {}", p.from)?;
+                if p.key_changed {
+                    writeln!(
+                        f,
+                        "    Warning: signed with a new key (check with the sender, then `meetd contacts trust {}`)",
+                        p.from
+                    )?;
+                }
+                if let Some(rule) = &p.recurrence {
+                    writeln!(f, "    Repeats: {}", rule)?;
+                }
+                if let Some(location) = &p.location {
+                    writeln!(f, "    Location: {}", location)?;
+                }
+                if let Some(conference) = &p.conference {
+                    writeln!(f, "    Join: {}", conference)?;
+                }
+                if let Some(original) = &p.counter_of {
+                    writeln!(f, "    Counters: {}", original)?;
+                }
+                if let Some(message) = &p.message {
+                    writeln!(f, "    Message: {}", message)?;
+                }
+                if !p.options.is_empty() {
+                    writeln!(f, "    Options (accept with --option N):")?;
+                    for (i, slot) in p.options.iter().enumerate() {
+                        writeln!(
+                            f,
+                            "      [{}] {} ({} min)",
+                            i,
+                            slot.start.format("%Y-%m-%d %H:%M"),
+                            slot.duration_minutes
+                        )?;
+                    }
+                }
                 writeln!(f, "    Expires: {}", p.expires_at.format("%Y-%m-%d %H:%M"))?;
             }
         }
@@ -109,10 +143,15 @@ pub async fn run_inbox_watch(format: OutputFormat) -> Result<()> {
                                 }
                                 OutputFormat::Human => {
                                     println!(
-                                        "New proposal: {} from {} at {}",
+                                        "New proposal: {} from {} at {}{}",
                                         proposal.title.as_deref().unwrap_or("(no title)"),
                                         proposal.from,
-                                        proposal.slot.start.format("%Y-%m-%d %H:%M")
+                                        proposal.slot.start.format("%Y-%m-%d %H:%M"),
+                                        if proposal.key_changed {
+                                            " (signed with a new key!)"
+                                        } else {
+                                            ""
+                                        }
                                     );
                                 }
                             }
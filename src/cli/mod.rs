@@ -1,14 +1,18 @@
 mod accept;
+mod admin;
 mod auth;
 mod availability;
 mod config;
+mod contacts;
 mod inbox;
 mod propose;
 
 pub use accept::*;
+pub use admin::*;
 pub use auth::*;
 pub use availability::*;
 pub use config::*;
+pub use contacts::*;
 pub use inbox::*;
 pub use propose::*;
 
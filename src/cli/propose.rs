@@ -2,8 +2,12 @@ use anyhow::{Context, Result};
 use serde::Serialize;
 
 use super::{get_api_client, get_api_key, get_server_url, OutputFormat};
-use crate::cli::availability::parse_slot_time;
-use crate::models::CreateProposalRequest;
+use crate::cli::availability::{parse_slot_time, RepeatArgs};
+use crate::crypto::Keypair;
+use crate::models::{
+    Conference, CounterProposalRequest, CreateProposalRequest, InboxProposal, LocalConfig,
+    SubmitSignedProposalRequest,
+};
 
 /// Response from creating a proposal
 #[derive(Debug, Serialize, serde::Deserialize)]
@@ -11,6 +15,9 @@ pub struct ProposeResponse {
     pub proposal_id: String,
     pub signed_proposal: String,
     pub accept_link: String,
+    /// Calendar invitation for recipients who don't use meetd
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub itip: Option<String>,
 }
 
 impl std::fmt::Display for ProposeResponse {
@@ -18,35 +25,104 @@ impl std::fmt::Display for ProposeResponse {
         writeln!(f, "Proposal created!")?;
         writeln!(f, "Proposal ID: {}", self.proposal_id)?;
         writeln!(f, "Accept link: {}", self.accept_link)?;
+        if self.itip.is_some() {
+            writeln!(
+                f,
+                "The recipient doesn't use meetd; they get a calendar invitation instead (see --json)"
+            )?;
+        }
         writeln!(f)?;
         writeln!(f, "Signed proposal (for agent-to-agent):")?;
         writeln!(f, "{}", self.signed_proposal)
     }
 }
 
+/// Parse `--slot` values into the preferred start and the alternatives
+fn parse_slots(
+    slots: &[String],
+) -> Result<(
+    chrono::DateTime<chrono::Utc>,
+    Vec<chrono::DateTime<chrono::Utc>>,
+)> {
+    let mut starts = slots
+        .iter()
+        .map(|s| parse_slot_time(s))
+        .collect::<Result<Vec<_>>>()?;
+    if starts.is_empty() {
+        anyhow::bail!("At least one --slot is required");
+    }
+    let first = starts.remove(0);
+    Ok((first, starts))
+}
+
+/// What a proposed meeting is about and where it happens
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct MeetingArgs {
+    /// Meeting title
+    #[arg(long)]
+    pub title: Option<String>,
+    /// Meeting description
+    #[arg(long)]
+    pub description: Option<String>,
+    /// Where the meeting takes place (e.g. "Room 4")
+    #[arg(long)]
+    pub location: Option<String>,
+    /// Video call: a join URL, or "meet" to have Google Calendar create a Meet link
+    #[arg(long)]
+    pub conference: Option<String>,
+    #[command(flatten)]
+    pub repeat: RepeatArgs,
+}
+
+impl MeetingArgs {
+    /// The conference these options describe
+    pub fn conference(&self) -> Result<Option<Conference>> {
+        let Some(value) = self.conference.as_deref().map(str::trim) else {
+            return Ok(None);
+        };
+        let conference = match value {
+            "meet" | "google_meet" => Conference::google_meet(),
+            url => Conference::Url(url.to_string()),
+        };
+        conference.validate().map_err(anyhow::Error::msg)?;
+        Ok(Some(conference))
+    }
+}
+
 /// Create a meeting proposal
+///
+/// With several `slots` the recipient picks one; the first is preferred.
 pub async fn run_propose(
     to_email: &str,
-    slot: &str,
+    slots: &[String],
     duration: &str,
-    title: Option<&str>,
-    description: Option<&str>,
+    meeting: &MeetingArgs,
     format: OutputFormat,
 ) -> Result<()> {
     let api_key = get_api_key()?;
     let server_url = get_server_url();
 
-    let slot_start = parse_slot_time(slot)?;
+    let (slot_start, starts) = parse_slots(slots)?;
     let duration_minutes = super::availability::parse_duration(duration)?;
 
     let request = CreateProposalRequest {
         to_email: to_email.to_string(),
         slot_start,
+        alternative_starts: starts,
         duration_minutes,
-        title: title.map(|s| s.to_string()),
-        description: description.map(|s| s.to_string()),
+        title: meeting.title.clone(),
+        description: meeting.description.clone(),
+        recurrence: meeting.repeat.rule()?,
+        location: meeting.location.clone(),
+        conference: meeting.conference()?,
     };
 
+    if let Some(keypair) = LocalConfig::load_signing_key().context("Failed to load signing key")? {
+        let response = submit_signed(&keypair, &request, None, None).await?;
+        format.print(&response);
+        return Ok(());
+    }
+
     let client = get_api_client();
     let resp = client
         .post(format!("{}/v1/proposals", server_url))
@@ -65,3 +141,119 @@ pub async fn run_propose(
     format.print(&response);
     Ok(())
 }
+
+/// Answer a proposal with a different time
+pub async fn run_counter(
+    proposal_id: &str,
+    slots: &[String],
+    duration: Option<&str>,
+    message: Option<&str>,
+    format: OutputFormat,
+) -> Result<()> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+
+    let (slot_start, alternative_starts) = parse_slots(slots)?;
+    let duration_minutes = duration
+        .map(super::availability::parse_duration)
+        .transpose()?;
+
+    if let Some(keypair) = LocalConfig::load_signing_key().context("Failed to load signing key")? {
+        // Sign the counter here: it keeps the original's details but goes back to its sender
+        let client = get_api_client();
+        let resp = client
+            .get(format!("{}/v1/proposals/{}", server_url, proposal_id))
+            .header("Authorization", format!("Bearer {}", api_key))
+            .send()
+            .await
+            .context("Failed to get proposal")?;
+        if !resp.status().is_success() {
+            let error = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Failed to get proposal: {}", error);
+        }
+        let original: InboxProposal = resp.json().await.context("Failed to parse response")?;
+
+        let request = CreateProposalRequest {
+            to_email: original.from,
+            slot_start,
+            alternative_starts,
+            duration_minutes: duration_minutes.unwrap_or(original.slot.duration_minutes),
+            title: original.title,
+            description: original.description,
+            recurrence: original.recurrence,
+            location: original.location,
+            conference: original.conference,
+        };
+        let response = submit_signed(&keypair, &request, Some(proposal_id), message).await?;
+        format.print(&response);
+        return Ok(());
+    }
+
+    let request = CounterProposalRequest {
+        slot_start,
+        alternative_starts,
+        duration_minutes,
+        message: message.map(|s| s.to_string()),
+    };
+
+    let client = get_api_client();
+    let resp = client
+        .post(format!(
+            "{}/v1/proposals/{}/counter",
+            server_url, proposal_id
+        ))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&request)
+        .send()
+        .await
+        .context("Failed to counter proposal")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to counter proposal: {}", error);
+    }
+
+    let response: ProposeResponse = resp.json().await.context("Failed to parse response")?;
+    format.print(&response);
+    Ok(())
+}
+
+/// Sign a proposal with the local key and submit it to the server
+async fn submit_signed(
+    keypair: &Keypair,
+    request: &CreateProposalRequest,
+    counter_of: Option<&str>,
+    message: Option<&str>,
+) -> Result<ProposeResponse> {
+    let api_key = get_api_key()?;
+    let server_url = get_server_url();
+    let email = LocalConfig::load()?
+        .email
+        .ok_or_else(|| anyhow::anyhow!("Not logged in. Run 'meetd login' first."))?;
+
+    let mut signed = request.unsigned_proposal(&email, &keypair.public_key_base64());
+    signed.validate().map_err(anyhow::Error::msg)?;
+    keypair.sign_proposal(&mut signed);
+
+    let body = SubmitSignedProposalRequest {
+        signed_proposal: signed.to_base64(),
+        counter_of: counter_of.map(|s| s.to_string()),
+        message: message.map(|s| s.to_string()),
+    };
+
+    let client = get_api_client();
+    let resp = client
+        .post(format!("{}/v1/proposals/signed", server_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&body)
+        .send()
+        .await
+        .context("Failed to submit proposal")?;
+
+    if !resp.status().is_success() {
+        let error = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to submit proposal: {}", error);
+    }
+
+    resp.json().await.context("Failed to parse response")
+}
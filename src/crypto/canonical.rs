@@ -0,0 +1,89 @@
+//! Canonical JSON (RFC 8785, JSON Canonicalization Scheme)
+//!
+//! Object members are sorted by their UTF-16 code units and written without
+//! whitespace; strings use the minimal JSON escaping. Non-integral numbers
+//! fall back to serde_json's formatting, which matches JCS for the values
+//! meetd signs (it only signs integers).
+
+use serde_json::Value;
+
+/// Serialize `value` in canonical form
+pub fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(value, &mut out);
+    out
+}
+
+fn write_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null | Value::Bool(_) => out.push_str(&value.to_string()),
+        Value::Number(n) => match n.as_f64() {
+            // Integral floats are written like integers (1.0 -> 1)
+            Some(f) if n.is_f64() && f.fract() == 0.0 && f.abs() < 1e21 => {
+                out.push_str(&format!("{}", f as i128))
+            }
+            _ => out.push_str(&n.to_string()),
+        },
+        Value::String(s) => out.push_str(&Value::String(s.clone()).to_string()),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(members) => {
+            let mut keys: Vec<&String> = members.keys().collect();
+            keys.sort_by(|a, b| a.encode_utf16().cmp(b.encode_utf16()));
+
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_value(&members[key], out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_canonical_json() {
+        let value = json!({
+            "to": "bob@example.com",
+            "slot": {"start": "2026-02-03T10:00:00Z", "duration_minutes": 30},
+            "version": 2,
+            "title": "Line\nbreak \"quoted\" é",
+            "slots": [],
+            "expires": null,
+            "flag": true,
+            "ratio": 2.0
+        });
+
+        assert_eq!(
+            canonical_json(&value),
+            r#"{"expires":null,"flag":true,"ratio":2,"slot":{"duration_minutes":30,"start":"2026-02-03T10:00:00Z"},"slots":[],"title":"Line\nbreak \"quoted\" é","to":"bob@example.com","version":2}"#
+        );
+    }
+
+    #[test]
+    fn test_canonical_json_sorts_by_utf16() {
+        // U+1F600 sorts before U+E000 in UTF-16 (its surrogates start at 0xD83D) but after it in UTF-8
+        let value = json!({"\u{1F600}": 1, "\u{E000}": 2, "a": 3});
+        assert_eq!(
+            canonical_json(&value),
+            "{\"a\":3,\"\u{1F600}\":1,\"\u{E000}\":2}"
+        );
+    }
+}
@@ -1,3 +1,7 @@
+mod canonical;
+mod secrets;
 mod signing;
 
+pub use canonical::*;
+pub use secrets::*;
 pub use signing::*;
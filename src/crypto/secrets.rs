@@ -0,0 +1,226 @@
+//! Envelope encryption for secrets stored in the database
+//!
+//! Every value gets its own random data key. The data key encrypts the value
+//! and is itself encrypted ("wrapped") with the server's master key, both with
+//! XChaCha20-Poly1305. A context string naming the row (e.g.
+//! `users.private_key:<id>`) is bound in as associated data, so sealed values
+//! can't be moved between rows.
+//!
+//! Sealed values look like `enc1:<key id>:<wrapped data key>:<ciphertext>`.
+//! Anything without the `enc1:` prefix is a plaintext value from before
+//! encryption was turned on.
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+const PREFIX: &str = "enc1";
+const NONCE_LEN: usize = 24;
+
+/// Environment variable holding a base64 master key
+pub const MASTER_KEY_ENV: &str = "MEETD_MASTER_KEY";
+/// Environment variable pointing at a file that holds a base64 master key
+pub const MASTER_KEY_FILE_ENV: &str = "MEETD_MASTER_KEY_FILE";
+
+/// The server's key-encryption key
+#[derive(Clone)]
+pub struct MasterKey {
+    key: [u8; 32],
+    id: String,
+}
+
+impl MasterKey {
+    /// Generate a new random master key
+    pub fn generate() -> Self {
+        Self::from_bytes(rand::random())
+    }
+
+    fn from_bytes(key: [u8; 32]) -> Self {
+        // Short fingerprint so sealed values say which key they need
+        let id = hex::encode(&Sha256::digest(key)[..4]);
+        Self { key, id }
+    }
+
+    /// Create from a base64-encoded 32-byte key
+    pub fn from_base64(key: &str) -> Result<Self> {
+        let bytes = BASE64
+            .decode(key.trim())
+            .context("Invalid base64 master key")?;
+        let key: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Master key must be 32 bytes"))?;
+        Ok(Self::from_bytes(key))
+    }
+
+    /// Get the key as base64 (for storing in a key file)
+    pub fn to_base64(&self) -> String {
+        BASE64.encode(self.key)
+    }
+
+    /// Read a key file written by [`MasterKey::write_file`]
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read master key file {}", path.display()))?;
+        Self::from_base64(&text)
+    }
+
+    /// Write the key to a new file readable only by its owner
+    pub fn write_file(&self, path: &Path) -> Result<()> {
+        use std::io::Write;
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("Failed to create master key file {}", path.display()))?;
+        writeln!(file, "{}", self.to_base64())?;
+        Ok(())
+    }
+
+    /// The key configured through `MEETD_MASTER_KEY` or `MEETD_MASTER_KEY_FILE`, if any
+    pub fn from_env() -> Result<Option<Self>> {
+        if let Ok(key) = std::env::var(MASTER_KEY_ENV) {
+            return Self::from_base64(&key)
+                .with_context(|| format!("Invalid {}", MASTER_KEY_ENV))
+                .map(Some);
+        }
+        match std::env::var(MASTER_KEY_FILE_ENV) {
+            Ok(path) => Self::load_file(Path::new(&path)).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Short fingerprint of the key
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Encrypt `plaintext` for the row described by `context`
+    pub fn seal(&self, plaintext: &str, context: &str) -> Result<String> {
+        let data_key: [u8; 32] = rand::random();
+        let wrapped = encrypt(&self.key, &data_key, context)?;
+        let ciphertext = encrypt(&data_key, plaintext.as_bytes(), context)?;
+        Ok(format!(
+            "{}:{}:{}:{}",
+            PREFIX,
+            self.id,
+            BASE64.encode(wrapped),
+            BASE64.encode(ciphertext)
+        ))
+    }
+
+    /// Decrypt a value made by [`MasterKey::seal`] with the same `context`
+    pub fn open(&self, sealed: &str, context: &str) -> Result<String> {
+        let mut parts = sealed.splitn(4, ':');
+        let (Some(PREFIX), Some(key_id), Some(wrapped), Some(ciphertext)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("Not an encrypted value");
+        };
+        if key_id != self.id {
+            anyhow::bail!(
+                "Value was encrypted with master key {}, but the configured key is {}",
+                key_id,
+                self.id
+            );
+        }
+
+        let wrapped = BASE64.decode(wrapped).context("Invalid wrapped key")?;
+        let data_key: [u8; 32] = decrypt(&self.key, &wrapped, context)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid data key length"))?;
+        let ciphertext = BASE64.decode(ciphertext).context("Invalid ciphertext")?;
+        let plaintext = decrypt(&data_key, &ciphertext, context)?;
+        String::from_utf8(plaintext).context("Decrypted value isn't UTF-8")
+    }
+}
+
+/// Whether a stored value is encrypted (rather than legacy plaintext)
+pub fn is_sealed(value: &str) -> bool {
+    value.starts_with("enc1:")
+}
+
+/// Nonce followed by ciphertext
+fn encrypt(key: &[u8; 32], plaintext: &[u8], context: &str) -> Result<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let ciphertext = cipher
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: context.as_bytes(),
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("Encryption failed"))?;
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+fn decrypt(key: &[u8; 32], data: &[u8], context: &str) -> Result<Vec<u8>> {
+    if data.len() < NONCE_LEN {
+        anyhow::bail!("Encrypted value is truncated");
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let cipher = XChaCha20Poly1305::new(key.into());
+    cipher
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: context.as_bytes(),
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("Decryption failed (wrong key or tampered value)"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = MasterKey::generate();
+        let sealed = key
+            .seal("refresh-token", "calendar_accounts.config:cal_1")
+            .unwrap();
+
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("refresh-token"));
+        assert_eq!(
+            key.open(&sealed, "calendar_accounts.config:cal_1").unwrap(),
+            "refresh-token"
+        );
+
+        // Bound to its row and its key
+        assert!(key.open(&sealed, "calendar_accounts.config:cal_2").is_err());
+        assert!(MasterKey::generate()
+            .open(&sealed, "calendar_accounts.config:cal_1")
+            .is_err());
+
+        // Same plaintext, different data key and nonce
+        let again = key
+            .seal("refresh-token", "calendar_accounts.config:cal_1")
+            .unwrap();
+        assert_ne!(sealed, again);
+    }
+
+    #[test]
+    fn test_key_file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("master.key");
+        let key = MasterKey::generate();
+        key.write_file(&path).unwrap();
+
+        let loaded = MasterKey::load_file(&path).unwrap();
+        assert_eq!(loaded.id(), key.id());
+        assert!(key.write_file(&path).is_err());
+    }
+}
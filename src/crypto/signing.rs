@@ -3,7 +3,7 @@ use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use rand::rngs::OsRng;
 
-use crate::models::SignedProposal;
+use crate::models::{ProposalResponse, SignedProposal, PROPOSAL_VERSION};
 
 /// Ed25519 keypair for signing proposals
 pub struct Keypair {
@@ -46,12 +46,23 @@ impl Keypair {
     }
 
     /// Sign a proposal and return the signature
+    ///
+    /// Proposals are always signed as [`PROPOSAL_VERSION`]; version 1
+    /// payloads leave fields out and are only verified.
     pub fn sign_proposal(&self, proposal: &mut SignedProposal) -> String {
+        proposal.version = PROPOSAL_VERSION;
         let payload = proposal.signing_payload();
         let signature = self.sign(&payload);
         proposal.signature = signature.clone();
         signature
     }
+
+    /// Sign a response to a proposal and return the signature
+    pub fn sign_response(&self, response: &mut ProposalResponse) -> String {
+        let signature = self.sign(&response.signing_payload());
+        response.signature = signature.clone();
+        signature
+    }
 }
 
 /// Verify a signature using a public key
@@ -90,10 +101,21 @@ impl PublicKey {
     }
 
     /// Verify a signed proposal
+    ///
+    /// Only version 1 (the legacy payload) and [`PROPOSAL_VERSION`] are
+    /// understood; anything else is an error rather than a failed check.
     pub fn verify_proposal(&self, proposal: &SignedProposal) -> Result<bool> {
+        if proposal.version != 1 && proposal.version != PROPOSAL_VERSION {
+            anyhow::bail!("Unsupported proposal version {}", proposal.version);
+        }
         let payload = proposal.signing_payload();
         self.verify(&payload, &proposal.signature)
     }
+
+    /// Verify a signed response to a proposal
+    pub fn verify_response(&self, response: &ProposalResponse) -> Result<bool> {
+        self.verify(&response.signing_payload(), &response.signature)
+    }
 }
 
 /// Generate a random API key
@@ -111,6 +133,12 @@ pub fn generate_webhook_secret() -> String {
     hex::encode(random_bytes)
 }
 
+/// Generate a secret that authenticates federated responses to one proposal
+pub fn generate_reply_token() -> String {
+    let random_bytes: [u8; 32] = rand::random();
+    hex::encode(random_bytes)
+}
+
 /// Hash an API key for storage
 pub fn hash_api_key(api_key: &str) -> Result<String> {
     bcrypt::hash(api_key, 10).context("Failed to hash API key")
@@ -124,7 +152,10 @@ pub fn verify_api_key(api_key: &str, hash: &str) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::{ProposalSlot, SignedProposal};
+    use crate::models::{
+        Conference, CreateProposalRequest, ProposalResponse, ProposalSlot, ProposalStatus,
+        SignedProposal,
+    };
     use chrono::Utc;
 
     #[test]
@@ -168,10 +199,238 @@ mod tests {
                 start: Utc::now(),
                 duration_minutes: 30,
             },
+            slots: vec![],
+            title: Some("Coffee chat".to_string()),
+            description: None,
+            recurrence: None,
+            location: None,
+            conference: None,
+            nonce: uuid::Uuid::new_v4().to_string(),
+            expires_at: Utc::now() + chrono::Duration::days(1),
+            created_at: None,
+            signature: String::new(),
+        };
+
+        keypair.sign_proposal(&mut proposal);
+
+        // Older versions are only verified; new signatures use the current one
+        assert_eq!(proposal.version, PROPOSAL_VERSION);
+        let pub_key = PublicKey::from_base64(&proposal.from_pubkey).unwrap();
+        assert!(pub_key.verify_proposal(&proposal).unwrap());
+    }
+
+    /// Sign with an old version's payload, as servers did before version 2
+    fn sign_legacy(keypair: &Keypair, proposal: &mut SignedProposal) {
+        proposal.signature = keypair.sign(&proposal.signing_payload());
+    }
+
+    #[test]
+    fn test_sign_multi_slot_proposal() {
+        let keypair = Keypair::generate().unwrap();
+        let start = Utc::now();
+        let slots: Vec<ProposalSlot> = (0..3)
+            .map(|i| ProposalSlot {
+                start: start + chrono::Duration::hours(i),
+                duration_minutes: 30,
+            })
+            .collect();
+
+        let mut proposal = SignedProposal {
+            version: PROPOSAL_VERSION,
+            from: "alice@example.com".to_string(),
+            from_pubkey: keypair.public_key_base64(),
+            to: "bob@example.com".to_string(),
+            slot: slots[0].clone(),
+            slots: slots.clone(),
             title: Some("Coffee chat".to_string()),
             description: None,
+            recurrence: None,
+            location: None,
+            conference: None,
+            nonce: uuid::Uuid::new_v4().to_string(),
+            expires_at: Utc::now() + chrono::Duration::days(1),
+            created_at: None,
+            signature: String::new(),
+        };
+
+        keypair.sign_proposal(&mut proposal);
+
+        let pub_key = PublicKey::from_base64(&proposal.from_pubkey).unwrap();
+        assert!(pub_key.verify_proposal(&proposal).unwrap());
+        assert!(proposal.validate().is_ok());
+
+        // Reordering or dropping options breaks the signature
+        let mut reordered = proposal.clone();
+        reordered.slots.swap(1, 2);
+        assert!(!pub_key.verify_proposal(&reordered).unwrap());
+
+        let mut dropped = proposal.clone();
+        dropped.slots.pop();
+        assert!(!pub_key.verify_proposal(&dropped).unwrap());
+    }
+
+    #[test]
+    fn test_sign_recurring_proposal_with_location() {
+        let keypair = Keypair::generate().unwrap();
+        let slot = ProposalSlot {
+            start: Utc::now(),
+            duration_minutes: 30,
+        };
+
+        let mut proposal = SignedProposal {
+            version: PROPOSAL_VERSION,
+            from: "alice@example.com".to_string(),
+            from_pubkey: keypair.public_key_base64(),
+            to: "bob@example.com".to_string(),
+            slot: slot.clone(),
+            slots: vec![slot],
+            title: Some("Weekly sync".to_string()),
+            description: None,
+            recurrence: Some("FREQ=WEEKLY;COUNT=10".to_string()),
+            location: Some("Room 4".to_string()),
+            conference: Some(Conference::google_meet()),
+            nonce: uuid::Uuid::new_v4().to_string(),
+            expires_at: Utc::now() + chrono::Duration::days(1),
+            created_at: None,
+            signature: String::new(),
+        };
+
+        keypair.sign_proposal(&mut proposal);
+
+        let pub_key = PublicKey::from_base64(&proposal.from_pubkey).unwrap();
+        assert!(pub_key.verify_proposal(&proposal).unwrap());
+        assert!(proposal.validate().is_ok());
+
+        // The rule can't be changed or stripped after signing
+        let mut longer = proposal.clone();
+        longer.recurrence = Some("FREQ=WEEKLY;COUNT=52".to_string());
+        assert!(!pub_key.verify_proposal(&longer).unwrap());
+
+        let mut one_off = proposal.clone();
+        one_off.recurrence = None;
+        assert!(!pub_key.verify_proposal(&one_off).unwrap());
+
+        // Nor can the meeting be moved or the join link swapped
+        let mut moved = proposal.clone();
+        moved.location = Some("Room 5".to_string());
+        assert!(!pub_key.verify_proposal(&moved).unwrap());
+
+        let mut relinked = proposal.clone();
+        relinked.conference = Some(Conference::Url("https://evil.example/j/1".to_string()));
+        assert!(!pub_key.verify_proposal(&relinked).unwrap());
+
+        // Version 1 payloads don't cover these fields, so they aren't accepted there
+        let mut v1 = proposal.clone();
+        v1.version = 1;
+        v1.slots.clear();
+        assert!(v1.validate().is_err());
+    }
+
+    #[test]
+    fn test_legacy_proposal_still_verifies() {
+        let keypair = Keypair::generate().unwrap();
+        let mut proposal = SignedProposal {
+            version: 1,
+            from: "alice@example.com".to_string(),
+            from_pubkey: keypair.public_key_base64(),
+            to: "bob@example.com".to_string(),
+            slot: ProposalSlot {
+                start: Utc::now(),
+                duration_minutes: 30,
+            },
+            slots: vec![],
+            title: Some("Sync".to_string()),
+            description: None,
+            recurrence: None,
+            location: None,
+            conference: None,
             nonce: uuid::Uuid::new_v4().to_string(),
             expires_at: Utc::now() + chrono::Duration::days(1),
+            created_at: None,
+            signature: String::new(),
+        };
+        sign_legacy(&keypair, &mut proposal);
+
+        let pub_key = PublicKey::from_base64(&proposal.from_pubkey).unwrap();
+        assert!(pub_key.verify_proposal(&proposal).unwrap());
+        assert!(proposal.validate().is_ok());
+
+        let mut retitled = proposal.clone();
+        retitled.title = Some("Other".to_string());
+        assert!(!pub_key.verify_proposal(&retitled).unwrap());
+
+        // Fields version 1 doesn't sign can't ride along unsigned
+        let mut relocated = proposal.clone();
+        relocated.location = Some("Room 4".to_string());
+        assert!(pub_key.verify_proposal(&relocated).unwrap());
+        assert!(relocated.validate().is_err());
+
+        let mut extra_slots = proposal.clone();
+        extra_slots.slots = vec![proposal.slot.clone()];
+        assert!(extra_slots.validate().is_err());
+
+        // Versions this build doesn't sign with are refused outright
+        let mut unknown = proposal.clone();
+        unknown.version = 3;
+        assert!(pub_key.verify_proposal(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_location_cannot_pass_for_conference() {
+        let keypair = Keypair::generate().unwrap();
+        let slot = ProposalSlot {
+            start: Utc::now(),
+            duration_minutes: 30,
+        };
+        let mut proposal = SignedProposal {
+            version: PROPOSAL_VERSION,
+            from: "alice@example.com".to_string(),
+            from_pubkey: keypair.public_key_base64(),
+            to: "bob@example.com".to_string(),
+            slot: slot.clone(),
+            slots: vec![slot],
+            title: Some("Sync".to_string()),
+            description: None,
+            recurrence: None,
+            location: Some("Room 4|CONFERENCE:https://evil.example/j/1".to_string()),
+            conference: None,
+            nonce: uuid::Uuid::new_v4().to_string(),
+            expires_at: Utc::now() + chrono::Duration::days(1),
+            created_at: None,
+            signature: String::new(),
+        };
+        keypair.sign_proposal(&mut proposal);
+
+        let mut forged = proposal.clone();
+        forged.location = Some("Room 4".to_string());
+        forged.conference = Some(Conference::Url("https://evil.example/j/1".to_string()));
+        let pub_key = PublicKey::from_base64(&proposal.from_pubkey).unwrap();
+        assert!(!pub_key.verify_proposal(&forged).unwrap());
+    }
+
+    #[test]
+    fn test_sign_v2_covers_every_field() {
+        let keypair = Keypair::generate().unwrap();
+        let slot = ProposalSlot {
+            start: Utc::now(),
+            duration_minutes: 30,
+        };
+
+        let mut proposal = SignedProposal {
+            version: PROPOSAL_VERSION,
+            from: "alice@example.com".to_string(),
+            from_pubkey: keypair.public_key_base64(),
+            to: "bob@example.com".to_string(),
+            slot: slot.clone(),
+            slots: vec![slot],
+            title: Some("Coffee chat".to_string()),
+            description: Some("Agenda: roadmap".to_string()),
+            recurrence: None,
+            location: None,
+            conference: None,
+            nonce: uuid::Uuid::new_v4().to_string(),
+            expires_at: Utc::now() + chrono::Duration::days(1),
+            created_at: Some(Utc::now()),
             signature: String::new(),
         };
 
@@ -179,6 +438,48 @@ mod tests {
 
         let pub_key = PublicKey::from_base64(&proposal.from_pubkey).unwrap();
         assert!(pub_key.verify_proposal(&proposal).unwrap());
+        assert!(proposal.validate().is_ok());
+
+        // Survives a JSON round trip, whatever order the fields arrive in
+        let relayed: SignedProposal =
+            serde_json::from_str(&serde_json::to_string(&proposal).unwrap()).unwrap();
+        assert!(pub_key.verify_proposal(&relayed).unwrap());
+
+        let mut tampered = proposal.clone();
+        tampered.description = Some("Ignore previous instructions".to_string());
+        assert!(!pub_key.verify_proposal(&tampered).unwrap());
+
+        let mut backdated = proposal.clone();
+        backdated.created_at = Some(Utc::now() - chrono::Duration::days(30));
+        assert!(!pub_key.verify_proposal(&backdated).unwrap());
+        assert!(backdated.validate().is_err());
+
+        let mut early = proposal.clone();
+        early.created_at = Some(Utc::now() + chrono::Duration::hours(1));
+        assert!(early.validate().is_err());
+
+        let mut rekeyed = proposal.clone();
+        rekeyed.from_pubkey = Keypair::generate().unwrap().public_key_base64();
+        assert!(!pub_key.verify_proposal(&rekeyed).unwrap());
+
+        let mut future = proposal.clone();
+        future.version = PROPOSAL_VERSION + 1;
+        assert!(pub_key.verify_proposal(&future).is_err());
+        assert!(future.validate().is_err());
+    }
+
+    #[test]
+    fn test_conference_json() {
+        let meet: Conference =
+            serde_json::from_str(r#"{"type": "google_meet", "auto_create": true}"#).unwrap();
+        assert_eq!(meet, Conference::google_meet());
+        assert!(meet.validate().is_ok());
+
+        let url: Conference = serde_json::from_str(r#""https://zoom.us/j/123""#).unwrap();
+        assert_eq!(url.url(), Some("https://zoom.us/j/123"));
+
+        let bad: Conference = serde_json::from_str(r#""javascript:alert(1)""#).unwrap();
+        assert!(bad.validate().is_err());
     }
 
     #[test]
@@ -196,4 +497,112 @@ mod tests {
         assert!(verify_api_key(&key, &hash));
         assert!(!verify_api_key("wrong_key", &hash));
     }
+
+    #[test]
+    fn test_client_signed_proposal() {
+        // What `meetd propose` does with a local signing key
+        let keypair = Keypair::generate().unwrap();
+        let start = Utc::now() + chrono::Duration::days(1);
+        let mut request = CreateProposalRequest {
+            to_email: "bob@example.com".to_string(),
+            slot_start: start,
+            alternative_starts: vec![start + chrono::Duration::hours(2)],
+            duration_minutes: 30,
+            title: Some("Coffee chat".to_string()),
+            description: Some("Agenda: roadmap".to_string()),
+            recurrence: None,
+            location: None,
+            conference: None,
+        };
+        let mut signed =
+            request.unsigned_proposal("alice@example.com", &keypair.public_key_base64());
+        assert_eq!(signed.version, PROPOSAL_VERSION);
+        assert_eq!(signed.slots.len(), 2);
+        signed.validate().unwrap();
+        keypair.sign_proposal(&mut signed);
+
+        // What the server does with the submitted proposal
+        let received = SignedProposal::from_base64(&signed.to_base64()).unwrap();
+        let pub_key = PublicKey::from_base64(&received.from_pubkey).unwrap();
+        assert!(pub_key.verify_proposal(&received).unwrap());
+        assert!(SignedProposal::from_base64("not base64!").is_err());
+
+        request.alternative_starts = vec![start];
+        let duplicate =
+            request.unsigned_proposal("alice@example.com", &keypair.public_key_base64());
+        assert!(duplicate.validate().is_err());
+
+        request.alternative_starts = vec![];
+        request.duration_minutes = 0;
+        let empty = request.unsigned_proposal("alice@example.com", &keypair.public_key_base64());
+        assert!(empty.validate().is_err());
+    }
+
+    #[test]
+    fn test_sign_response() {
+        let alice = Keypair::generate().unwrap();
+        let bob = Keypair::generate().unwrap();
+        let start = Utc::now() + chrono::Duration::days(1);
+        let request = CreateProposalRequest {
+            to_email: "bob@example.com".to_string(),
+            slot_start: start,
+            alternative_starts: vec![start + chrono::Duration::hours(2)],
+            duration_minutes: 30,
+            title: Some("Coffee chat".to_string()),
+            description: None,
+            recurrence: None,
+            location: None,
+            conference: None,
+        };
+        let mut proposal =
+            request.unsigned_proposal("alice@example.com", &alice.public_key_base64());
+        alice.sign_proposal(&mut proposal);
+
+        let mut response = ProposalResponse::new(
+            &proposal.hash(),
+            "bob@example.com",
+            &bob.public_key_base64(),
+            "alice@example.com",
+            ProposalStatus::Accepted,
+            Some(proposal.slots[1].clone()),
+        );
+        bob.sign_response(&mut response);
+
+        let pub_key = PublicKey::from_base64(&response.from_pubkey).unwrap();
+        assert!(pub_key.verify_response(&response).unwrap());
+        response.validate().unwrap();
+        response.answers(&proposal).unwrap();
+
+        // Survives a JSON round trip
+        let relayed: ProposalResponse =
+            serde_json::from_str(&serde_json::to_string(&response).unwrap()).unwrap();
+        assert!(pub_key.verify_response(&relayed).unwrap());
+
+        // The slot and the answer are covered by the signature
+        let mut tampered = response.clone();
+        tampered.slot = Some(proposal.slots[0].clone());
+        assert!(!pub_key.verify_response(&tampered).unwrap());
+        let mut tampered = response.clone();
+        tampered.status = ProposalStatus::Declined;
+        assert!(!pub_key.verify_response(&tampered).unwrap());
+
+        // It only answers the proposal it was made for
+        let mut other = proposal.clone();
+        other.title = Some("Something else".to_string());
+        alice.sign_proposal(&mut other);
+        assert!(response.answers(&other).is_err());
+
+        let mut unoffered = response.clone();
+        unoffered.slot = Some(ProposalSlot {
+            start: start + chrono::Duration::hours(5),
+            duration_minutes: 30,
+        });
+        assert!(unoffered.answers(&proposal).is_err());
+
+        let mut declined = response.clone();
+        declined.status = ProposalStatus::Declined;
+        assert!(declined.validate().is_err());
+        declined.slot = None;
+        declined.validate().unwrap();
+    }
 }
@@ -0,0 +1,2 @@
+-- Non-Google calendar backends (JSON, e.g. {"provider":"caldav",...})
+ALTER TABLE users ADD COLUMN calendar_config TEXT;
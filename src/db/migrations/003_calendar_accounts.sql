@@ -0,0 +1,28 @@
+-- Calendar accounts (a user can link several; the primary one receives new events)
+CREATE TABLE IF NOT EXISTS calendar_accounts (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    provider TEXT NOT NULL,        -- google | caldav | ics
+    config TEXT NOT NULL,          -- Provider credentials (JSON)
+    label TEXT,
+    is_primary INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_calendar_accounts_user ON calendar_accounts(user_id);
+
+-- Move existing credentials off the users table
+INSERT INTO calendar_accounts (id, user_id, provider, config, is_primary, created_at)
+SELECT 'cal_' || lower(hex(randomblob(6))), id, json_extract(calendar_config, '$.provider'),
+       calendar_config, 1, created_at
+FROM users WHERE calendar_config IS NOT NULL;
+
+INSERT INTO calendar_accounts (id, user_id, provider, config, is_primary, created_at)
+SELECT 'cal_' || lower(hex(randomblob(6))), id, 'google',
+       json_object('provider', 'google', 'refresh_token', google_refresh_token, 'email', email),
+       calendar_config IS NULL, created_at
+FROM users WHERE google_refresh_token IS NOT NULL AND google_refresh_token != '';
+
+ALTER TABLE users DROP COLUMN calendar_config;
+ALTER TABLE users DROP COLUMN google_refresh_token;
@@ -0,0 +1,2 @@
+-- Which calendars within an account count as busy / receive events (JSON, NULL = defaults)
+ALTER TABLE calendar_accounts ADD COLUMN selection TEXT;
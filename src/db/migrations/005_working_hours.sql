@@ -0,0 +1,2 @@
+-- Per-user working hours profile (JSON: timezone, per-weekday ranges, lunch)
+ALTER TABLE users ADD COLUMN working_hours TEXT;
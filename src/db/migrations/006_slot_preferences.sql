@@ -0,0 +1,2 @@
+-- Per-user slot search defaults (JSON: step, buffers, notice, horizon)
+ALTER TABLE users ADD COLUMN slot_preferences TEXT;
@@ -0,0 +1,2 @@
+-- Who may see a user's availability (JSON: mode, allow); NULL = contacts only
+ALTER TABLE users ADD COLUMN sharing TEXT;
@@ -0,0 +1,3 @@
+-- Multi-slot proposals: every offered slot (JSON), and which one the recipient picked
+ALTER TABLE proposals ADD COLUMN options TEXT;
+ALTER TABLE proposals ADD COLUMN chosen_option INTEGER;
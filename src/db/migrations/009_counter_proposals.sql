@@ -0,0 +1,3 @@
+-- Counter-proposals: link back to the proposal being answered, plus an optional note
+ALTER TABLE proposals ADD COLUMN counter_of TEXT;
+ALTER TABLE proposals ADD COLUMN message TEXT;
@@ -0,0 +1,2 @@
+-- Calendar event created when a proposal is accepted, so it can be moved or cancelled later
+ALTER TABLE proposals ADD COLUMN event_id TEXT;
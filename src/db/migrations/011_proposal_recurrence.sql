@@ -0,0 +1,2 @@
+-- RFC 5545 RRULE for recurring meetings (NULL for one-off meetings)
+ALTER TABLE proposals ADD COLUMN recurrence TEXT;
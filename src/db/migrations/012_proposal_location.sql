@@ -0,0 +1,3 @@
+-- Where a meeting happens and how to join it (conference is JSON: a URL or a generated-conference request)
+ALTER TABLE proposals ADD COLUMN location TEXT;
+ALTER TABLE proposals ADD COLUMN conference TEXT;
@@ -0,0 +1,17 @@
+-- Every signing key a user has had, and when it was their current key
+CREATE TABLE IF NOT EXISTS user_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL REFERENCES users(id),
+    public_key TEXT NOT NULL,
+    valid_from INTEGER NOT NULL,
+    -- NULL while the key is current
+    valid_until INTEGER,
+    -- 'superseded' or 'compromised', once the key is replaced
+    revocation_reason TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_keys_user ON user_keys(user_id);
+
+-- Existing keys have been current since the account was created
+INSERT INTO user_keys (user_id, public_key, valid_from)
+SELECT id, public_key, created_at FROM users;
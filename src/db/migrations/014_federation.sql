@@ -0,0 +1,2 @@
+-- Proposals exchanged with another meetd server (JSON: server URL, the proposal's ID there, reply token)
+ALTER TABLE proposals ADD COLUMN remote TEXT;
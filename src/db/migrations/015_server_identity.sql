@@ -0,0 +1,7 @@
+-- This server's own Ed25519 key, published in /.well-known/meetd (a single row)
+CREATE TABLE IF NOT EXISTS server_identity (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    public_key TEXT NOT NULL,
+    private_key TEXT NOT NULL,     -- Sealed like users.private_key
+    created_at INTEGER NOT NULL
+);
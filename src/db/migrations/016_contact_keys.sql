@@ -0,0 +1,16 @@
+-- The signing key each user has pinned for the people who send them proposals
+CREATE TABLE IF NOT EXISTS contact_keys (
+    user_id TEXT NOT NULL REFERENCES users(id),
+    -- Lowercased sender email
+    email TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    -- 'directory', 'server', 'proposal' or 'manual'
+    source TEXT NOT NULL,
+    -- Server the key was looked up on, for 'server'
+    server TEXT,
+    pinned_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id, email)
+);
+
+-- Set when a proposal was signed with a different key than the one pinned for its sender
+ALTER TABLE proposals ADD COLUMN key_changed INTEGER NOT NULL DEFAULT 0;
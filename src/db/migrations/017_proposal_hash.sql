@@ -0,0 +1,2 @@
+-- Hash of the signed proposal, which signed responses refer to (NULL for older proposals)
+ALTER TABLE proposals ADD COLUMN proposal_hash TEXT;
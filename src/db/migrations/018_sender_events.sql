@@ -0,0 +1,2 @@
+-- Calendar event made in the sender's own calendar, next to the recipient's event_id
+ALTER TABLE proposals ADD COLUMN sender_event_id TEXT;
@@ -0,0 +1,6 @@
+-- Which calendar account (and calendar within it) each meeting event was made in,
+-- so moves and cancellations reach it after the user changes accounts
+ALTER TABLE proposals ADD COLUMN event_account_id TEXT;
+ALTER TABLE proposals ADD COLUMN event_calendar_id TEXT;
+ALTER TABLE proposals ADD COLUMN sender_event_account_id TEXT;
+ALTER TABLE proposals ADD COLUMN sender_event_calendar_id TEXT;
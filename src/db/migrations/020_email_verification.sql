@@ -0,0 +1,12 @@
+-- Whether the user has proven they own their email address (Google sign-in or a mailed link).
+-- Until then the address doesn't count for anyone's contacts, domain or allowlist sharing.
+ALTER TABLE users ADD COLUMN email_verified INTEGER NOT NULL DEFAULT 0;
+
+-- Outstanding verification links; only a hash of the token is kept
+CREATE TABLE IF NOT EXISTS email_verifications (
+    token_hash TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users(id),
+    expires_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_email_verifications_user ON email_verifications(user_id);
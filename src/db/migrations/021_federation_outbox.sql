@@ -0,0 +1,14 @@
+-- Federated messages the other server couldn't be reached for, retried until it takes them
+CREATE TABLE IF NOT EXISTS federation_outbox (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- Base URL of the server the message is for
+    server TEXT NOT NULL,
+    -- The FederationMessage, as JSON
+    message TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    last_error TEXT,
+    next_attempt_at INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_federation_outbox_due ON federation_outbox(next_attempt_at);
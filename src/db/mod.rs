@@ -4,14 +4,255 @@ use rusqlite::{params, Connection, OptionalExtension};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
-use crate::models::{Proposal, ProposalStatus, User, Visibility};
+use crate::calendar::EventRef;
+use crate::crypto::{is_sealed, Keypair, MasterKey};
+use crate::federation::{FederationMessage, QueuedMessage};
+use crate::models::{
+    CalendarAccount, CalendarConfig, CalendarSelection, ContactKey, KeySource, Proposal,
+    ProposalSlot, ProposalStatus, RemoteProposal, RevocationReason, SharingPolicy, SlotPreferences,
+    User, UserKey, Visibility, WorkingHours,
+};
 
 const MIGRATION_001: &str = include_str!("migrations/001_initial.sql");
+const MIGRATION_002: &str = include_str!("migrations/002_calendar_config.sql");
+const MIGRATION_003: &str = include_str!("migrations/003_calendar_accounts.sql");
+const MIGRATION_004: &str = include_str!("migrations/004_calendar_selection.sql");
+const MIGRATION_005: &str = include_str!("migrations/005_working_hours.sql");
+const MIGRATION_006: &str = include_str!("migrations/006_slot_preferences.sql");
+const MIGRATION_007: &str = include_str!("migrations/007_sharing.sql");
+const MIGRATION_008: &str = include_str!("migrations/008_proposal_options.sql");
+const MIGRATION_009: &str = include_str!("migrations/009_counter_proposals.sql");
+const MIGRATION_010: &str = include_str!("migrations/010_proposal_events.sql");
+const MIGRATION_011: &str = include_str!("migrations/011_proposal_recurrence.sql");
+const MIGRATION_012: &str = include_str!("migrations/012_proposal_location.sql");
+const MIGRATION_013: &str = include_str!("migrations/013_user_keys.sql");
+const MIGRATION_014: &str = include_str!("migrations/014_federation.sql");
+const MIGRATION_015: &str = include_str!("migrations/015_server_identity.sql");
+const MIGRATION_016: &str = include_str!("migrations/016_contact_keys.sql");
+const MIGRATION_017: &str = include_str!("migrations/017_proposal_hash.sql");
+const MIGRATION_018: &str = include_str!("migrations/018_sender_events.sql");
+const MIGRATION_019: &str = include_str!("migrations/019_event_accounts.sql");
+const MIGRATION_020: &str = include_str!("migrations/020_email_verification.sql");
+const MIGRATION_021: &str = include_str!("migrations/021_federation_outbox.sql");
+
+/// Migrations in order; `PRAGMA user_version` records how many have been applied
+const MIGRATIONS: &[&str] = &[
+    MIGRATION_001,
+    MIGRATION_002,
+    MIGRATION_003,
+    MIGRATION_004,
+    MIGRATION_005,
+    MIGRATION_006,
+    MIGRATION_007,
+    MIGRATION_008,
+    MIGRATION_009,
+    MIGRATION_010,
+    MIGRATION_011,
+    MIGRATION_012,
+    MIGRATION_013,
+    MIGRATION_014,
+    MIGRATION_015,
+    MIGRATION_016,
+    MIGRATION_017,
+    MIGRATION_018,
+    MIGRATION_019,
+    MIGRATION_020,
+    MIGRATION_021,
+];
+
+const USER_COLUMNS: &str = "id, email, public_key, private_key, api_key_hash, visibility,
+                    webhook_url, webhook_secret, working_hours, slot_preferences,
+                    sharing, created_at, email_verified";
+
+fn user_from_row(row: &rusqlite::Row, secrets: Option<&MasterKey>) -> rusqlite::Result<User> {
+    let id: String = row.get(0)?;
+    let private_key = open_secret(secrets, row.get(3)?, &user_key_context(&id)).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, e.into())
+    })?;
+    Ok(User {
+        id,
+        email: row.get(1)?,
+        public_key: row.get(2)?,
+        private_key,
+        api_key_hash: row.get(4)?,
+        visibility: Visibility::parse(&row.get::<_, String>(5)?).unwrap_or_default(),
+        webhook_url: row.get(6)?,
+        webhook_secret: row.get(7)?,
+        working_hours: json_column(row, 8)?,
+        slot_preferences: json_column(row, 9)?.unwrap_or_default(),
+        sharing: json_column(row, 10)?.unwrap_or_default(),
+        created_at: row.get(11)?,
+        email_verified: row.get(12)?,
+    })
+}
+
+/// Apply the migrations `PRAGMA user_version` says haven't run yet
+///
+/// Each migration commits together with its version bump, so one that fails
+/// halfway leaves nothing behind and runs again from the start next time.
+fn apply_migrations(conn: &mut Connection, migrations: &[&str]) -> Result<()> {
+    let applied: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (i, migration) in migrations.iter().enumerate().skip(applied) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)
+            .with_context(|| format!("Failed to run migration {:03}", i + 1))?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Read an optional JSON column
+fn json_column<T: serde::de::DeserializeOwned>(
+    row: &rusqlite::Row,
+    idx: usize,
+) -> rusqlite::Result<Option<T>> {
+    let value: Option<String> = row.get(idx)?;
+    value
+        .map(|v| {
+            serde_json::from_str(&v).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    idx,
+                    rusqlite::types::Type::Text,
+                    Box::new(e),
+                )
+            })
+        })
+        .transpose()
+}
+
+const PROPOSAL_COLUMNS: &str = "id, from_user_id, to_email, slot_start, duration_minutes, title,
+                    description, nonce, expires_at, signature, status, created_at, options,
+                    chosen_option, counter_of, message, event_id, recurrence, location,
+                    conference, remote, key_changed, proposal_hash, sender_event_id,
+                    event_account_id, event_calendar_id, sender_event_account_id,
+                    sender_event_calendar_id";
+
+fn proposal_from_row(row: &rusqlite::Row) -> rusqlite::Result<Proposal> {
+    Ok(Proposal {
+        id: row.get(0)?,
+        from_user_id: row.get(1)?,
+        to_email: row.get(2)?,
+        slot_start: DateTime::from_timestamp(row.get(3)?, 0).unwrap_or_else(Utc::now),
+        duration_minutes: row.get(4)?,
+        title: row.get(5)?,
+        description: row.get(6)?,
+        nonce: row.get(7)?,
+        expires_at: DateTime::from_timestamp(row.get(8)?, 0).unwrap_or_else(Utc::now),
+        signature: row.get(9)?,
+        status: ProposalStatus::parse(&row.get::<_, String>(10)?).unwrap_or_default(),
+        created_at: row.get(11)?,
+        options: json_column(row, 12)?.unwrap_or_default(),
+        chosen_option: row.get::<_, Option<i64>>(13)?.map(|i| i as usize),
+        counter_of: row.get(14)?,
+        message: row.get(15)?,
+        event_id: row.get(16)?,
+        recurrence: row.get(17)?,
+        location: row.get(18)?,
+        conference: json_column(row, 19)?,
+        remote: json_column(row, 20)?,
+        key_changed: row.get(21)?,
+        proposal_hash: row.get(22)?,
+        sender_event_id: row.get(23)?,
+        event_account_id: row.get(24)?,
+        event_calendar_id: row.get(25)?,
+        sender_event_account_id: row.get(26)?,
+        sender_event_calendar_id: row.get(27)?,
+    })
+}
+
+const CONTACT_KEY_COLUMNS: &str = "email, public_key, source, server, pinned_at";
+
+fn contact_key_from_row(row: &rusqlite::Row) -> rusqlite::Result<ContactKey> {
+    Ok(ContactKey {
+        email: row.get(0)?,
+        public_key: row.get(1)?,
+        source: KeySource::parse(&row.get::<_, String>(2)?).unwrap_or(KeySource::Proposal),
+        server: row.get(3)?,
+        pinned_at: DateTime::from_timestamp(row.get(4)?, 0).unwrap_or_else(Utc::now),
+    })
+}
+
+const USER_KEY_COLUMNS: &str = "public_key, valid_from, valid_until, revocation_reason";
+
+fn user_key_from_row(row: &rusqlite::Row) -> rusqlite::Result<UserKey> {
+    Ok(UserKey {
+        public_key: row.get(0)?,
+        valid_from: DateTime::from_timestamp(row.get(1)?, 0).unwrap_or_else(Utc::now),
+        valid_until: row
+            .get::<_, Option<i64>>(2)?
+            .and_then(|t| DateTime::from_timestamp(t, 0)),
+        revocation_reason: row
+            .get::<_, Option<String>>(3)?
+            .and_then(|r| RevocationReason::parse(&r)),
+    })
+}
+
+const CALENDAR_ACCOUNT_COLUMNS: &str =
+    "id, user_id, config, label, is_primary, selection, created_at";
+
+/// Default selections are stored as NULL
+fn selection_json(selection: &CalendarSelection) -> Result<Option<String>> {
+    if selection.is_default() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::to_string(selection)?))
+}
+
+fn calendar_account_from_row(
+    row: &rusqlite::Row,
+    secrets: Option<&MasterKey>,
+) -> rusqlite::Result<CalendarAccount> {
+    let id: String = row.get(0)?;
+    let config = open_secret(secrets, row.get(2)?, &calendar_config_context(&id)).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, e.into())
+    })?;
+    let selection: Option<String> = row.get(5)?;
+    Ok(CalendarAccount {
+        id,
+        user_id: row.get(1)?,
+        config: serde_json::from_str(&config).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        label: row.get(3)?,
+        is_primary: row.get(4)?,
+        selection: selection
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default(),
+        created_at: row.get(6)?,
+    })
+}
+
+/// Associated data for a user's sealed private key
+fn user_key_context(user_id: &str) -> String {
+    format!("users.private_key:{}", user_id)
+}
+
+/// Associated data for the server's sealed private key
+const SERVER_KEY_CONTEXT: &str = "server_identity.private_key";
+
+/// Associated data for a calendar account's sealed credentials
+fn calendar_config_context(account_id: &str) -> String {
+    format!("calendar_accounts.config:{}", account_id)
+}
+
+/// Decrypt a secret column; values written before encryption was enabled are plaintext
+fn open_secret(secrets: Option<&MasterKey>, value: String, context: &str) -> Result<String> {
+    if !is_sealed(&value) {
+        return Ok(value);
+    }
+    match secrets {
+        Some(key) => key.open(&value, context),
+        None => anyhow::bail!("{} is encrypted, but no master key is configured", context),
+    }
+}
 
 /// Database connection wrapper
 #[derive(Clone)]
 pub struct Database {
     conn: Arc<Mutex<Connection>>,
+    /// Encrypts private keys and calendar credentials at rest
+    secrets: Option<Arc<MasterKey>>,
 }
 
 impl Database {
@@ -21,6 +262,7 @@ impl Database {
         conn.execute_batch("PRAGMA foreign_keys = ON;")?;
         let db = Self {
             conn: Arc::new(Mutex::new(conn)),
+            secrets: None,
         };
         db.run_migrations()?;
         Ok(db)
@@ -32,16 +274,115 @@ impl Database {
         conn.execute_batch("PRAGMA foreign_keys = ON;")?;
         let db = Self {
             conn: Arc::new(Mutex::new(conn)),
+            secrets: None,
         };
         db.run_migrations()?;
         Ok(db)
     }
 
-    fn run_migrations(&self) -> Result<()> {
+    /// Encrypt secrets with `key` from now on, and decrypt existing ones with it
+    pub fn with_master_key(mut self, key: MasterKey) -> Self {
+        self.secrets = Some(Arc::new(key));
+        self
+    }
+
+    /// Seal a secret column value, or leave it as-is without a master key
+    fn seal(&self, value: &str, context: &str) -> Result<String> {
+        match &self.secrets {
+            Some(key) => key.seal(value, context),
+            None => Ok(value.to_string()),
+        }
+    }
+
+    /// Re-encrypt every private key and calendar credential with `new_key`
+    ///
+    /// Values are decrypted with the current master key, so plaintext rows
+    /// from before encryption was enabled get encrypted too. Everything
+    /// happens in one transaction. Returns how many values were re-encrypted.
+    pub fn rotate_master_key(&self, new_key: &MasterKey) -> Result<usize> {
+        let current = self.secrets.as_deref();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut rotated = 0;
+
+        let users: Vec<(String, String)> = tx
+            .prepare("SELECT id, private_key FROM users")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        for (id, private_key) in users {
+            let context = user_key_context(&id);
+            let private_key = open_secret(current, private_key, &context)?;
+            tx.execute(
+                "UPDATE users SET private_key = ?1 WHERE id = ?2",
+                params![new_key.seal(&private_key, &context)?, id],
+            )?;
+            rotated += 1;
+        }
+
+        let accounts: Vec<(String, String)> = tx
+            .prepare("SELECT id, config FROM calendar_accounts")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        for (id, config) in accounts {
+            let context = calendar_config_context(&id);
+            let config = open_secret(current, config, &context)?;
+            tx.execute(
+                "UPDATE calendar_accounts SET config = ?1 WHERE id = ?2",
+                params![new_key.seal(&config, &context)?, id],
+            )?;
+            rotated += 1;
+        }
+
+        let server_key: Option<String> = tx
+            .query_row("SELECT private_key FROM server_identity", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        if let Some(private_key) = server_key {
+            let private_key = open_secret(current, private_key, SERVER_KEY_CONTEXT)?;
+            tx.execute(
+                "UPDATE server_identity SET private_key = ?1",
+                params![new_key.seal(&private_key, SERVER_KEY_CONTEXT)?],
+            )?;
+            rotated += 1;
+        }
+
+        tx.commit()?;
+        Ok(rotated)
+    }
+
+    /// The server's own signing key, generated the first time it's needed
+    pub fn server_keypair(&self) -> Result<Keypair> {
         let conn = self.conn.lock().unwrap();
-        conn.execute_batch(MIGRATION_001)
-            .context("Failed to run migration 001")?;
-        Ok(())
+        let stored: Option<String> = conn
+            .query_row("SELECT private_key FROM server_identity", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        if let Some(private_key) = stored {
+            let private_key =
+                open_secret(self.secrets.as_deref(), private_key, SERVER_KEY_CONTEXT)?;
+            return Keypair::from_private_key_base64(&private_key);
+        }
+
+        let keypair = Keypair::generate()?;
+        conn.execute(
+            r#"
+            INSERT INTO server_identity (id, public_key, private_key, created_at)
+            VALUES (1, ?1, ?2, ?3)
+            "#,
+            params![
+                keypair.public_key_base64(),
+                self.seal(&keypair.private_key_base64(), SERVER_KEY_CONTEXT)?,
+                Utc::now().timestamp(),
+            ],
+        )?;
+        Ok(keypair)
+    }
+
+    fn run_migrations(&self) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        apply_migrations(&mut conn, MIGRATIONS)
     }
 
     // ==================== User Operations ====================
@@ -51,48 +392,45 @@ impl Database {
         let conn = self.conn.lock().unwrap();
         conn.execute(
             r#"
-            INSERT INTO users (id, email, google_refresh_token, public_key, private_key,
-                              api_key_hash, visibility, webhook_url, webhook_secret, created_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            INSERT INTO users (id, email, public_key, private_key, api_key_hash,
+                              visibility, webhook_url, webhook_secret, working_hours,
+                              slot_preferences, sharing, created_at, email_verified)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
             "#,
             params![
                 user.id,
                 user.email,
-                user.google_refresh_token,
                 user.public_key,
-                user.private_key,
+                self.seal(&user.private_key, &user_key_context(&user.id))?,
                 user.api_key_hash,
                 user.visibility.as_str(),
                 user.webhook_url,
                 user.webhook_secret,
+                user.working_hours
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?,
+                serde_json::to_string(&user.slot_preferences)?,
+                serde_json::to_string(&user.sharing)?,
                 user.created_at,
+                user.email_verified,
             ],
         )?;
+        conn.execute(
+            "INSERT INTO user_keys (user_id, public_key, valid_from) VALUES (?1, ?2, ?3)",
+            params![user.id, user.public_key, user.created_at],
+        )?;
         Ok(())
     }
 
     /// Get a user by ID
     pub fn get_user(&self, id: &str) -> Result<Option<User>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, email, google_refresh_token, public_key, private_key,
-                    api_key_hash, visibility, webhook_url, webhook_secret, created_at
-             FROM users WHERE id = ?1",
-        )?;
+        let mut stmt =
+            conn.prepare(&format!("SELECT {} FROM users WHERE id = ?1", USER_COLUMNS))?;
 
         stmt.query_row(params![id], |row| {
-            Ok(User {
-                id: row.get(0)?,
-                email: row.get(1)?,
-                google_refresh_token: row.get(2)?,
-                public_key: row.get(3)?,
-                private_key: row.get(4)?,
-                api_key_hash: row.get(5)?,
-                visibility: Visibility::parse(&row.get::<_, String>(6)?).unwrap_or_default(),
-                webhook_url: row.get(7)?,
-                webhook_secret: row.get(8)?,
-                created_at: row.get(9)?,
-            })
+            user_from_row(row, self.secrets.as_deref())
         })
         .optional()
         .context("Failed to get user")
@@ -101,50 +439,93 @@ impl Database {
     /// Get a user by email
     pub fn get_user_by_email(&self, email: &str) -> Result<Option<User>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, email, google_refresh_token, public_key, private_key,
-                    api_key_hash, visibility, webhook_url, webhook_secret, created_at
-             FROM users WHERE email = ?1",
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM users WHERE email = ?1",
+            USER_COLUMNS
+        ))?;
 
         stmt.query_row(params![email], |row| {
-            Ok(User {
-                id: row.get(0)?,
-                email: row.get(1)?,
-                google_refresh_token: row.get(2)?,
-                public_key: row.get(3)?,
-                private_key: row.get(4)?,
-                api_key_hash: row.get(5)?,
-                visibility: Visibility::parse(&row.get::<_, String>(6)?).unwrap_or_default(),
-                webhook_url: row.get(7)?,
-                webhook_secret: row.get(8)?,
-                created_at: row.get(9)?,
-            })
+            user_from_row(row, self.secrets.as_deref())
         })
         .optional()
         .context("Failed to get user by email")
     }
 
-    /// Update user's refresh token
-    pub fn update_user_refresh_token(&self, user_id: &str, token: Option<&str>) -> Result<()> {
+    /// Update user's visibility setting
+    pub fn update_user_visibility(&self, user_id: &str, visibility: Visibility) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "UPDATE users SET google_refresh_token = ?1 WHERE id = ?2",
-            params![token, user_id],
+            "UPDATE users SET visibility = ?1 WHERE id = ?2",
+            params![visibility.as_str(), user_id],
         )?;
         Ok(())
     }
 
-    /// Update user's visibility setting
-    pub fn update_user_visibility(&self, user_id: &str, visibility: Visibility) -> Result<()> {
+    /// Update user's working hours (None = back to the default schedule)
+    pub fn update_user_working_hours(
+        &self,
+        user_id: &str,
+        working_hours: Option<&WorkingHours>,
+    ) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "UPDATE users SET visibility = ?1 WHERE id = ?2",
-            params![visibility.as_str(), user_id],
+            "UPDATE users SET working_hours = ?1 WHERE id = ?2",
+            params![
+                working_hours.map(serde_json::to_string).transpose()?,
+                user_id
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Update user's saved slot search preferences
+    pub fn update_user_slot_preferences(
+        &self,
+        user_id: &str,
+        preferences: &SlotPreferences,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE users SET slot_preferences = ?1 WHERE id = ?2",
+            params![serde_json::to_string(preferences)?, user_id],
+        )?;
+        Ok(())
+    }
+
+    /// Update who may see the user's availability
+    pub fn update_user_sharing(&self, user_id: &str, sharing: &SharingPolicy) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE users SET sharing = ?1 WHERE id = ?2",
+            params![serde_json::to_string(sharing)?, user_id],
         )?;
         Ok(())
     }
 
+    /// Whether `other_email` is one of the user's contacts
+    ///
+    /// Contacts are people the user has sent a proposal to, or accepted a
+    /// proposal from. Unsolicited proposals don't count.
+    pub fn is_contact(&self, user: &User, other_email: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let found = conn
+            .query_row(
+                r#"
+                SELECT 1 FROM proposals
+                WHERE from_user_id = ?1 AND to_email = ?2 COLLATE NOCASE
+                UNION ALL
+                SELECT 1 FROM proposals p JOIN users u ON u.id = p.from_user_id
+                WHERE u.email = ?2 COLLATE NOCASE AND p.to_email = ?3 COLLATE NOCASE
+                      AND p.status = 'accepted'
+                LIMIT 1
+                "#,
+                params![user.id, other_email, user.email],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(found.is_some())
+    }
+
     /// Update user's webhook configuration
     pub fn update_user_webhook(
         &self,
@@ -160,6 +541,177 @@ impl Database {
         Ok(())
     }
 
+    /// Replace a user's signing key, recording why the current one was retired
+    ///
+    /// `private_key` is empty when the client holds the new key. The old
+    /// key's validity window ends now; the new key's starts now.
+    pub fn rotate_user_key(
+        &self,
+        user_id: &str,
+        public_key: &str,
+        private_key: &str,
+        reason: RevocationReason,
+    ) -> Result<()> {
+        let now = Utc::now().timestamp();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE users SET public_key = ?1, private_key = ?2 WHERE id = ?3",
+            params![
+                public_key,
+                self.seal(private_key, &user_key_context(user_id))?,
+                user_id
+            ],
+        )?;
+        tx.execute(
+            r#"
+            UPDATE user_keys SET valid_until = ?1, revocation_reason = ?2
+            WHERE user_id = ?3 AND valid_until IS NULL
+            "#,
+            params![now, reason.as_str(), user_id],
+        )?;
+        tx.execute(
+            "INSERT INTO user_keys (user_id, public_key, valid_from) VALUES (?1, ?2, ?3)",
+            params![user_id, public_key, now],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Every signing key a user has had, oldest first
+    pub fn get_user_keys(&self, user_id: &str) -> Result<Vec<UserKey>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM user_keys WHERE user_id = ?1 ORDER BY id",
+            USER_KEY_COLUMNS
+        ))?;
+
+        let keys = stmt
+            .query_map(params![user_id], user_key_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(keys)
+    }
+
+    /// Whether `public_key` was one of the user's trusted keys at `at`
+    pub fn is_signing_key_valid(
+        &self,
+        user_id: &str,
+        public_key: &str,
+        at: DateTime<Utc>,
+    ) -> Result<bool> {
+        Ok(self
+            .get_user_keys(user_id)?
+            .iter()
+            .any(|key| key.public_key == public_key && key.valid_at(at)))
+    }
+
+    /// The key `user_id` has pinned for proposals from `email`
+    pub fn get_contact_key(&self, user_id: &str, email: &str) -> Result<Option<ContactKey>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM contact_keys WHERE user_id = ?1 AND email = ?2",
+            CONTACT_KEY_COLUMNS
+        ))?;
+
+        stmt.query_row(params![user_id, email.to_lowercase()], contact_key_from_row)
+            .optional()
+            .context("Failed to get contact key")
+    }
+
+    /// Every key `user_id` has pinned, by email
+    pub fn get_contact_keys(&self, user_id: &str) -> Result<Vec<ContactKey>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM contact_keys WHERE user_id = ?1 ORDER BY email",
+            CONTACT_KEY_COLUMNS
+        ))?;
+
+        let keys = stmt
+            .query_map(params![user_id], contact_key_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(keys)
+    }
+
+    /// Pin `key` for its email, replacing any key pinned before
+    pub fn pin_contact_key(&self, user_id: &str, key: &ContactKey) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            r#"
+            INSERT OR REPLACE INTO contact_keys (user_id, email, public_key, source, server, pinned_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            "#,
+            params![
+                user_id,
+                key.email.to_lowercase(),
+                key.public_key,
+                key.source.as_str(),
+                key.server,
+                key.pinned_at.timestamp()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Forget the key pinned for `email`; returns whether there was one
+    pub fn unpin_contact_key(&self, user_id: &str, email: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let rows = conn.execute(
+            "DELETE FROM contact_keys WHERE user_id = ?1 AND email = ?2",
+            params![user_id, email.to_lowercase()],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Record that the user has proven they own their email address
+    pub fn mark_email_verified(&self, user_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE users SET email_verified = 1 WHERE id = ?1",
+            params![user_id],
+        )?;
+        conn.execute(
+            "DELETE FROM email_verifications WHERE user_id = ?1",
+            params![user_id],
+        )?;
+        Ok(())
+    }
+
+    /// Store a pending email verification token (hashed)
+    pub fn create_email_verification(
+        &self,
+        user_id: &str,
+        token_hash: &str,
+        expires_at: i64,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO email_verifications (token_hash, user_id, expires_at) VALUES (?1, ?2, ?3)",
+            params![token_hash, user_id, expires_at],
+        )?;
+        Ok(())
+    }
+
+    /// Use up a verification token, returning its user if it hadn't expired
+    ///
+    /// The token is deleted either way, so each link works once.
+    pub fn take_email_verification(&self, token_hash: &str, now: i64) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let found: Option<(String, i64)> = conn
+            .query_row(
+                "SELECT user_id, expires_at FROM email_verifications WHERE token_hash = ?1",
+                params![token_hash],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        conn.execute(
+            "DELETE FROM email_verifications WHERE token_hash = ?1",
+            params![token_hash],
+        )?;
+        Ok(found
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(user_id, _)| user_id))
+    }
+
     /// Update user's API key hash
     pub fn update_user_api_key_hash(&self, user_id: &str, api_key_hash: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
@@ -174,26 +726,9 @@ impl Database {
     /// Returns the user if the API key matches
     pub fn find_user_by_api_key(&self, api_key: &str) -> Result<Option<User>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, email, google_refresh_token, public_key, private_key,
-                    api_key_hash, visibility, webhook_url, webhook_secret, created_at
-             FROM users",
-        )?;
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM users", USER_COLUMNS))?;
 
-        let users = stmt.query_map([], |row| {
-            Ok(User {
-                id: row.get(0)?,
-                email: row.get(1)?,
-                google_refresh_token: row.get(2)?,
-                public_key: row.get(3)?,
-                private_key: row.get(4)?,
-                api_key_hash: row.get(5)?,
-                visibility: Visibility::parse(&row.get::<_, String>(6)?).unwrap_or_default(),
-                webhook_url: row.get(7)?,
-                webhook_secret: row.get(8)?,
-                created_at: row.get(9)?,
-            })
-        })?;
+        let users = stmt.query_map([], |row| user_from_row(row, self.secrets.as_deref()))?;
 
         for user_result in users {
             let user = user_result?;
@@ -205,6 +740,120 @@ impl Database {
         Ok(None)
     }
 
+    // ==================== Calendar Account Operations ====================
+
+    /// Link a calendar account to a user
+    pub fn create_calendar_account(&self, account: &CalendarAccount) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            r#"
+            INSERT INTO calendar_accounts (id, user_id, provider, config, label, is_primary,
+                                           selection, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            "#,
+            params![
+                account.id,
+                account.user_id,
+                account.config.provider(),
+                self.seal(
+                    &serde_json::to_string(&account.config)?,
+                    &calendar_config_context(&account.id)
+                )?,
+                account.label,
+                account.is_primary,
+                selection_json(&account.selection)?,
+                account.created_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Get a user's calendar accounts, primary first
+    pub fn get_calendar_accounts(&self, user_id: &str) -> Result<Vec<CalendarAccount>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM calendar_accounts WHERE user_id = ?1
+             ORDER BY is_primary DESC, created_at ASC",
+            CALENDAR_ACCOUNT_COLUMNS
+        ))?;
+
+        let accounts = stmt.query_map(params![user_id], |row| {
+            calendar_account_from_row(row, self.secrets.as_deref())
+        })?;
+
+        accounts
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to get calendar accounts")
+    }
+
+    /// Replace the credentials of a calendar account
+    pub fn update_calendar_account_config(&self, id: &str, config: &CalendarConfig) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE calendar_accounts SET provider = ?1, config = ?2 WHERE id = ?3",
+            params![
+                config.provider(),
+                self.seal(
+                    &serde_json::to_string(config)?,
+                    &calendar_config_context(id)
+                )?,
+                id
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Choose which calendars within an account are used
+    pub fn update_calendar_selection(&self, id: &str, selection: &CalendarSelection) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE calendar_accounts SET selection = ?1 WHERE id = ?2",
+            params![selection_json(selection)?, id],
+        )?;
+        Ok(())
+    }
+
+    /// Make one of a user's calendar accounts the primary one
+    /// Returns false if the account doesn't belong to the user
+    pub fn set_primary_calendar_account(&self, user_id: &str, id: &str) -> Result<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let exists = tx
+            .prepare("SELECT 1 FROM calendar_accounts WHERE id = ?1 AND user_id = ?2")?
+            .exists(params![id, user_id])?;
+        if exists {
+            tx.execute(
+                "UPDATE calendar_accounts SET is_primary = (id = ?1) WHERE user_id = ?2",
+                params![id, user_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(exists)
+    }
+
+    /// Unlink a calendar account; the oldest remaining account becomes primary if needed
+    /// Returns false if the account doesn't belong to the user
+    pub fn delete_calendar_account(&self, user_id: &str, id: &str) -> Result<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let deleted = tx.execute(
+            "DELETE FROM calendar_accounts WHERE id = ?1 AND user_id = ?2",
+            params![id, user_id],
+        )?;
+        tx.execute(
+            r#"
+            UPDATE calendar_accounts SET is_primary = 1
+            WHERE id = (SELECT id FROM calendar_accounts WHERE user_id = ?1
+                        ORDER BY created_at ASC LIMIT 1)
+              AND NOT EXISTS (SELECT 1 FROM calendar_accounts
+                              WHERE user_id = ?1 AND is_primary = 1)
+            "#,
+            params![user_id],
+        )?;
+        tx.commit()?;
+        Ok(deleted > 0)
+    }
+
     // ==================== Proposal Operations ====================
 
     /// Create a new proposal
@@ -213,8 +862,14 @@ impl Database {
         conn.execute(
             r#"
             INSERT INTO proposals (id, from_user_id, to_email, slot_start, duration_minutes,
-                                   title, description, nonce, expires_at, signature, status, created_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+                                   title, description, nonce, expires_at, signature, status, created_at,
+                                   options, chosen_option, counter_of, message, event_id,
+                                   recurrence, location, conference, remote, key_changed,
+                                   proposal_hash, sender_event_id, event_account_id,
+                                   event_calendar_id, sender_event_account_id,
+                                   sender_event_calendar_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+                    ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28)
             "#,
             params![
                 proposal.id,
@@ -229,6 +884,31 @@ impl Database {
                 proposal.signature,
                 proposal.status.as_str(),
                 proposal.created_at,
+                Some(&proposal.options)
+                    .filter(|o| !o.is_empty())
+                    .map(serde_json::to_string)
+                    .transpose()?,
+                proposal.chosen_option.map(|i| i as i64),
+                proposal.counter_of,
+                proposal.message,
+                proposal.event_id,
+                proposal.recurrence,
+                proposal.location,
+                proposal.conference
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?,
+                proposal.remote
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?,
+                proposal.key_changed,
+                proposal.proposal_hash,
+                proposal.sender_event_id,
+                proposal.event_account_id,
+                proposal.event_calendar_id,
+                proposal.sender_event_account_id,
+                proposal.sender_event_calendar_id,
             ],
         )?;
         Ok(())
@@ -237,30 +917,14 @@ impl Database {
     /// Get a proposal by ID
     pub fn get_proposal(&self, id: &str) -> Result<Option<Proposal>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, from_user_id, to_email, slot_start, duration_minutes, title, description,
-                    nonce, expires_at, signature, status, created_at
-             FROM proposals WHERE id = ?1",
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM proposals WHERE id = ?1",
+            PROPOSAL_COLUMNS
+        ))?;
 
-        stmt.query_row(params![id], |row| {
-            Ok(Proposal {
-                id: row.get(0)?,
-                from_user_id: row.get(1)?,
-                to_email: row.get(2)?,
-                slot_start: DateTime::from_timestamp(row.get(3)?, 0).unwrap_or_else(Utc::now),
-                duration_minutes: row.get(4)?,
-                title: row.get(5)?,
-                description: row.get(6)?,
-                nonce: row.get(7)?,
-                expires_at: DateTime::from_timestamp(row.get(8)?, 0).unwrap_or_else(Utc::now),
-                signature: row.get(9)?,
-                status: ProposalStatus::parse(&row.get::<_, String>(10)?).unwrap_or_default(),
-                created_at: row.get(11)?,
-            })
-        })
-        .optional()
-        .context("Failed to get proposal")
+        stmt.query_row(params![id], proposal_from_row)
+            .optional()
+            .context("Failed to get proposal")
     }
 
     /// Get proposals for a recipient
@@ -271,43 +935,25 @@ impl Database {
     ) -> Result<Vec<Proposal>> {
         let conn = self.conn.lock().unwrap();
 
-        let (sql, params_vec): (&str, Vec<Box<dyn rusqlite::ToSql>>) = match status {
+        let (filter, params_vec): (&str, Vec<Box<dyn rusqlite::ToSql>>) = match status {
             Some(s) => (
-                "SELECT id, from_user_id, to_email, slot_start, duration_minutes, title, description,
-                        nonce, expires_at, signature, status, created_at
-                 FROM proposals WHERE to_email = ?1 AND status = ?2
-                 ORDER BY slot_start ASC",
-                vec![Box::new(email.to_string()), Box::new(s.as_str().to_string())],
-            ),
-            None => (
-                "SELECT id, from_user_id, to_email, slot_start, duration_minutes, title, description,
-                        nonce, expires_at, signature, status, created_at
-                 FROM proposals WHERE to_email = ?1
-                 ORDER BY slot_start ASC",
-                vec![Box::new(email.to_string())],
+                "to_email = ?1 AND status = ?2",
+                vec![
+                    Box::new(email.to_string()),
+                    Box::new(s.as_str().to_string()),
+                ],
             ),
+            None => ("to_email = ?1", vec![Box::new(email.to_string())]),
         };
 
-        let mut stmt = conn.prepare(sql)?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM proposals WHERE {} ORDER BY slot_start ASC",
+            PROPOSAL_COLUMNS, filter
+        ))?;
         let params_refs: Vec<&dyn rusqlite::ToSql> =
             params_vec.iter().map(|p| p.as_ref()).collect();
 
-        let proposals = stmt.query_map(params_refs.as_slice(), |row| {
-            Ok(Proposal {
-                id: row.get(0)?,
-                from_user_id: row.get(1)?,
-                to_email: row.get(2)?,
-                slot_start: DateTime::from_timestamp(row.get(3)?, 0).unwrap_or_else(Utc::now),
-                duration_minutes: row.get(4)?,
-                title: row.get(5)?,
-                description: row.get(6)?,
-                nonce: row.get(7)?,
-                expires_at: DateTime::from_timestamp(row.get(8)?, 0).unwrap_or_else(Utc::now),
-                signature: row.get(9)?,
-                status: ProposalStatus::parse(&row.get::<_, String>(10)?).unwrap_or_default(),
-                created_at: row.get(11)?,
-            })
-        })?;
+        let proposals = stmt.query_map(params_refs.as_slice(), proposal_from_row)?;
 
         proposals
             .collect::<Result<Vec<_>, _>>()
@@ -317,45 +963,121 @@ impl Database {
     /// Get proposals sent by a user
     pub fn get_proposals_from_user(&self, user_id: &str) -> Result<Vec<Proposal>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, from_user_id, to_email, slot_start, duration_minutes, title, description,
-                    nonce, expires_at, signature, status, created_at
-             FROM proposals WHERE from_user_id = ?1
-             ORDER BY created_at DESC",
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM proposals WHERE from_user_id = ?1 ORDER BY created_at DESC",
+            PROPOSAL_COLUMNS
+        ))?;
 
-        let proposals = stmt.query_map(params![user_id], |row| {
-            Ok(Proposal {
-                id: row.get(0)?,
-                from_user_id: row.get(1)?,
-                to_email: row.get(2)?,
-                slot_start: DateTime::from_timestamp(row.get(3)?, 0).unwrap_or_else(Utc::now),
-                duration_minutes: row.get(4)?,
-                title: row.get(5)?,
-                description: row.get(6)?,
-                nonce: row.get(7)?,
-                expires_at: DateTime::from_timestamp(row.get(8)?, 0).unwrap_or_else(Utc::now),
-                signature: row.get(9)?,
-                status: ProposalStatus::parse(&row.get::<_, String>(10)?).unwrap_or_default(),
-                created_at: row.get(11)?,
-            })
-        })?;
+        let proposals = stmt.query_map(params![user_id], proposal_from_row)?;
 
         proposals
             .collect::<Result<Vec<_>, _>>()
             .context("Failed to get proposals from user")
     }
 
-    /// Update proposal status
-    pub fn update_proposal_status(&self, id: &str, status: ProposalStatus) -> Result<()> {
+    /// Record which offered slot the recipient picked, making it the proposal's slot
+    pub fn choose_proposal_option(
+        &self,
+        id: &str,
+        index: usize,
+        slot: &ProposalSlot,
+    ) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "UPDATE proposals SET status = ?1 WHERE id = ?2",
-            params![status.as_str(), id],
+            "UPDATE proposals SET chosen_option = ?1, slot_start = ?2, duration_minutes = ?3
+             WHERE id = ?4",
+            params![
+                index as i64,
+                slot.start.timestamp(),
+                slot.duration_minutes,
+                id
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Remember (or forget) the calendar event made for an accepted proposal
+    pub fn set_proposal_event(&self, id: &str, event: Option<&EventRef>) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE proposals SET event_id = ?1, event_account_id = ?2, event_calendar_id = ?3
+             WHERE id = ?4",
+            params![
+                event.map(|e| &e.id),
+                event.and_then(|e| e.account_id.as_ref()),
+                event.and_then(|e| e.calendar_id.as_ref()),
+                id
+            ],
         )?;
         Ok(())
     }
 
+    /// Remember (or forget) the event made in the sender's own calendar
+    pub fn set_proposal_sender_event(&self, id: &str, event: Option<&EventRef>) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE proposals SET sender_event_id = ?1, sender_event_account_id = ?2,
+                                  sender_event_calendar_id = ?3
+             WHERE id = ?4",
+            params![
+                event.map(|e| &e.id),
+                event.and_then(|e| e.account_id.as_ref()),
+                event.and_then(|e| e.calendar_id.as_ref()),
+                id
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Move an accepted meeting to a new slot
+    ///
+    /// Returns the number of proposals changed, which is 0 when the meeting
+    /// was cancelled in the meantime.
+    pub fn update_proposal_slot(&self, id: &str, slot: &ProposalSlot) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let rows = conn.execute(
+            "UPDATE proposals SET slot_start = ?1, duration_minutes = ?2
+             WHERE id = ?3 AND status = 'accepted'",
+            params![slot.start.timestamp(), slot.duration_minutes, id],
+        )?;
+        Ok(rows)
+    }
+
+    /// Link a proposal to its copy on another meetd server
+    pub fn set_proposal_remote(&self, id: &str, remote: &RemoteProposal) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE proposals SET remote = ?1 WHERE id = ?2",
+            params![serde_json::to_string(remote)?, id],
+        )?;
+        Ok(())
+    }
+
+    /// Answer a pending proposal with `status`
+    ///
+    /// Returns the number of proposals changed, which is 0 when the proposal
+    /// was answered (or withdrawn) in the meantime.
+    pub fn update_proposal_status(&self, id: &str, status: ProposalStatus) -> Result<usize> {
+        self.transition_proposal_status(id, ProposalStatus::Pending, status)
+    }
+
+    /// Move a proposal from status `from` to `to`, if it's still `from`
+    ///
+    /// Returns the number of proposals changed.
+    pub fn transition_proposal_status(
+        &self,
+        id: &str,
+        from: ProposalStatus,
+        to: ProposalStatus,
+    ) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let count = conn.execute(
+            "UPDATE proposals SET status = ?1 WHERE id = ?2 AND status = ?3",
+            params![to.as_str(), id, from.as_str()],
+        )?;
+        Ok(count)
+    }
+
     /// Expire old pending proposals
     pub fn expire_old_proposals(&self) -> Result<usize> {
         let conn = self.conn.lock().unwrap();
@@ -398,25 +1120,110 @@ impl Database {
         )?;
         Ok(count)
     }
+
+    // ==================== Federation Outbox Operations ====================
+
+    /// Queue a message its server couldn't be reached for
+    pub fn queue_federation_message(
+        &self,
+        server: &str,
+        message: &FederationMessage,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            r#"
+            INSERT INTO federation_outbox (server, message, last_error, next_attempt_at, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            "#,
+            params![
+                server,
+                serde_json::to_string(message)?,
+                error,
+                next_attempt_at.timestamp(),
+                Utc::now().timestamp(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Queued messages whose next attempt is due, oldest first
+    pub fn due_federation_messages(&self, now: DateTime<Utc>) -> Result<Vec<QueuedMessage>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT id, server, message, attempts FROM federation_outbox
+            WHERE next_attempt_at <= ?1
+            ORDER BY id
+            "#,
+        )?;
+        let messages = stmt.query_map(params![now.timestamp()], |row| {
+            Ok(QueuedMessage {
+                id: row.get(0)?,
+                server: row.get(1)?,
+                message: serde_json::from_str(&row.get::<_, String>(2)?).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        2,
+                        rusqlite::types::Type::Text,
+                        Box::new(e),
+                    )
+                })?,
+                attempts: row.get(3)?,
+            })
+        })?;
+        messages
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to read the federation outbox")
+    }
+
+    /// Record another failed attempt at a queued message
+    pub fn retry_federation_message(
+        &self,
+        id: i64,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            r#"
+            UPDATE federation_outbox
+            SET attempts = attempts + 1, last_error = ?1, next_attempt_at = ?2
+            WHERE id = ?3
+            "#,
+            params![error, next_attempt_at.timestamp(), id],
+        )?;
+        Ok(())
+    }
+
+    /// Drop a queued message, once delivered or given up on
+    pub fn remove_federation_message(&self, id: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM federation_outbox WHERE id = ?1", params![id])?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::Visibility;
+    use crate::models::{Conference, Visibility};
 
     fn create_test_user() -> User {
         User {
             id: uuid::Uuid::new_v4().to_string(),
             email: "test@example.com".to_string(),
-            google_refresh_token: Some("token123".to_string()),
             public_key: "pubkey".to_string(),
             private_key: "privkey".to_string(),
             api_key_hash: bcrypt::hash("test_api_key", 4).unwrap(),
             visibility: Visibility::BusyOnly,
             webhook_url: None,
             webhook_secret: None,
+            working_hours: None,
+            slot_preferences: SlotPreferences::default(),
+            sharing: SharingPolicy::default(),
             created_at: Utc::now().timestamp(),
+            email_verified: false,
         }
     }
 
@@ -447,6 +1254,448 @@ mod tests {
         assert!(not_found.is_none());
     }
 
+    fn create_test_account(user_id: &str, config: CalendarConfig) -> CalendarAccount {
+        CalendarAccount {
+            id: format!("cal_{}", uuid::Uuid::new_v4().simple()),
+            user_id: user_id.to_string(),
+            config,
+            label: None,
+            is_primary: false,
+            selection: CalendarSelection::default(),
+            created_at: Utc::now().timestamp(),
+        }
+    }
+
+    #[test]
+    fn test_calendar_accounts() {
+        let db = Database::open_in_memory().unwrap();
+        let user = create_test_user();
+        db.create_user(&user).unwrap();
+
+        let mut google = create_test_account(
+            &user.id,
+            CalendarConfig::Google {
+                refresh_token: "token123".to_string(),
+                email: Some(user.email.clone()),
+            },
+        );
+        google.is_primary = true;
+        let caldav = create_test_account(
+            &user.id,
+            CalendarConfig::Caldav {
+                url: "http://localhost:5232/test/calendar/".to_string(),
+                username: "test".to_string(),
+                password: "secret".to_string(),
+            },
+        );
+        db.create_calendar_account(&google).unwrap();
+        db.create_calendar_account(&caldav).unwrap();
+
+        let accounts = db.get_calendar_accounts(&user.id).unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].id, google.id);
+        assert_eq!(accounts[1].config, caldav.config);
+
+        assert!(db
+            .set_primary_calendar_account(&user.id, &caldav.id)
+            .unwrap());
+        assert!(!db
+            .set_primary_calendar_account("someone-else", &google.id)
+            .unwrap());
+        let accounts = db.get_calendar_accounts(&user.id).unwrap();
+        assert_eq!(accounts[0].id, caldav.id);
+        assert!(!accounts[1].is_primary);
+
+        // Removing the primary promotes the remaining account
+        assert!(db.delete_calendar_account(&user.id, &caldav.id).unwrap());
+        let accounts = db.get_calendar_accounts(&user.id).unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert!(accounts[0].is_primary);
+    }
+
+    #[test]
+    fn test_migrate_credentials_to_calendar_accounts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("meetd.db");
+
+        // A database created before calendar accounts existed
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(MIGRATION_001).unwrap();
+            conn.execute(
+                "INSERT INTO users (id, email, google_refresh_token, public_key, private_key,
+                                    api_key_hash, created_at)
+                 VALUES ('u1', 'old@example.com', 'rt', 'pk', 'sk', 'hash', 0)",
+                [],
+            )
+            .unwrap();
+        }
+
+        let db = Database::open(&path).unwrap();
+        let accounts = db.get_calendar_accounts("u1").unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert!(accounts[0].is_primary);
+        assert_eq!(
+            accounts[0].config,
+            CalendarConfig::Google {
+                refresh_token: "rt".to_string(),
+                email: Some("old@example.com".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_secrets_encrypted_at_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("meetd.db");
+        let user = create_test_user();
+        let config = CalendarConfig::Google {
+            refresh_token: "token123".to_string(),
+            email: None,
+        };
+        let account = create_test_account(&user.id, config.clone());
+
+        // Written before encryption was enabled
+        let db = Database::open(&path).unwrap();
+        db.create_user(&user).unwrap();
+        db.create_calendar_account(&account).unwrap();
+        let server_key = db.server_keypair().unwrap().public_key_base64();
+
+        let old_key = MasterKey::generate();
+        assert_eq!(db.rotate_master_key(&old_key).unwrap(), 3);
+        let db = Database::open(&path)
+            .unwrap()
+            .with_master_key(old_key.clone());
+        let stored: String = db
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT config FROM calendar_accounts", [], |row| row.get(0))
+            .unwrap();
+        assert!(is_sealed(&stored));
+        assert!(!stored.contains("token123"));
+        assert_eq!(
+            db.get_user(&user.id).unwrap().unwrap().private_key,
+            "privkey"
+        );
+        assert_eq!(
+            db.get_calendar_accounts(&user.id).unwrap()[0].config,
+            config
+        );
+
+        // New rows are sealed too
+        let other = create_test_account(&user.id, config.clone());
+        db.create_calendar_account(&other).unwrap();
+        assert!(Database::open(&path)
+            .unwrap()
+            .get_calendar_accounts(&user.id)
+            .is_err());
+
+        let new_key = MasterKey::generate();
+        assert_eq!(db.rotate_master_key(&new_key).unwrap(), 4);
+        assert!(Database::open(&path)
+            .unwrap()
+            .with_master_key(old_key)
+            .get_user(&user.id)
+            .is_err());
+        let db = Database::open(&path).unwrap().with_master_key(new_key);
+        assert_eq!(
+            db.get_user(&user.id).unwrap().unwrap().private_key,
+            "privkey"
+        );
+        assert_eq!(db.get_calendar_accounts(&user.id).unwrap().len(), 2);
+        assert_eq!(db.server_keypair().unwrap().public_key_base64(), server_key);
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            "CREATE TABLE a (id INTEGER);",
+            "CREATE TABLE b (id INTEGER); INSERT INTO missing VALUES (1);",
+        ];
+        assert!(apply_migrations(&mut conn, &migrations).is_err());
+
+        let version: usize = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, 1);
+        let table_exists = |name: &str| {
+            conn.query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![name],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap()
+                > 0
+        };
+        assert!(table_exists("a"));
+        assert!(!table_exists("b"));
+    }
+
+    #[test]
+    fn test_migrations_are_idempotent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("meetd.db");
+
+        Database::open(&path).unwrap();
+        let db = Database::open(&path).unwrap();
+        db.create_user(&create_test_user()).unwrap();
+    }
+
+    #[test]
+    fn test_contacts() {
+        let db = Database::open_in_memory().unwrap();
+        let alice = User {
+            email: "alice@example.com".to_string(),
+            ..create_test_user()
+        };
+        let bob = User {
+            email: "bob@example.com".to_string(),
+            ..create_test_user()
+        };
+        db.create_user(&alice).unwrap();
+        db.create_user(&bob).unwrap();
+
+        let proposal = Proposal {
+            id: "prop_1".to_string(),
+            from_user_id: bob.id.clone(),
+            to_email: "Alice@example.com".to_string(),
+            slot_start: Utc::now(),
+            duration_minutes: 30,
+            title: None,
+            description: None,
+            nonce: "n1".to_string(),
+            expires_at: Utc::now(),
+            signature: "sig".to_string(),
+            status: ProposalStatus::Pending,
+            created_at: Utc::now().timestamp(),
+            options: vec![],
+            chosen_option: None,
+            counter_of: None,
+            message: None,
+            event_id: None,
+            recurrence: None,
+            location: None,
+            conference: None,
+            remote: None,
+            key_changed: false,
+            proposal_hash: None,
+            sender_event_id: None,
+            event_account_id: None,
+            event_calendar_id: None,
+            sender_event_account_id: None,
+            sender_event_calendar_id: None,
+        };
+        db.create_proposal(&proposal).unwrap();
+
+        // Bob proposed to Alice, so Alice is Bob's contact but not yet the other way round
+        assert!(db.is_contact(&bob, "alice@example.com").unwrap());
+        assert!(!db.is_contact(&alice, "bob@example.com").unwrap());
+
+        assert_eq!(
+            db.update_proposal_status("prop_1", ProposalStatus::Accepted)
+                .unwrap(),
+            1
+        );
+        assert!(db.is_contact(&alice, "bob@example.com").unwrap());
+
+        // Only a pending proposal can be answered
+        assert_eq!(
+            db.update_proposal_status("prop_1", ProposalStatus::Declined)
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            db.get_proposal("prop_1").unwrap().unwrap().status,
+            ProposalStatus::Accepted
+        );
+    }
+
+    #[test]
+    fn test_counter_proposal_round_trip() {
+        let db = Database::open_in_memory().unwrap();
+        let bob = create_test_user();
+        db.create_user(&bob).unwrap();
+
+        let start = DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap();
+        let options: Vec<ProposalSlot> = (0..2)
+            .map(|i| ProposalSlot {
+                start: start + chrono::Duration::hours(i),
+                duration_minutes: 30,
+            })
+            .collect();
+        let counter = Proposal {
+            id: "prop_2".to_string(),
+            from_user_id: bob.id.clone(),
+            to_email: "alice@example.com".to_string(),
+            slot_start: start,
+            duration_minutes: 30,
+            title: None,
+            description: None,
+            nonce: "n2".to_string(),
+            expires_at: start,
+            signature: "sig".to_string(),
+            status: ProposalStatus::Pending,
+            created_at: start.timestamp(),
+            options: options.clone(),
+            chosen_option: None,
+            counter_of: Some("prop_1".to_string()),
+            message: Some("Mornings are better".to_string()),
+            event_id: None,
+            recurrence: Some("FREQ=WEEKLY;COUNT=4".to_string()),
+            location: Some("Room 4".to_string()),
+            conference: Some(Conference::google_meet()),
+            remote: None,
+            key_changed: true,
+            proposal_hash: Some("abc123".to_string()),
+            sender_event_id: Some("evt_1".to_string()),
+            event_account_id: None,
+            event_calendar_id: None,
+            sender_event_account_id: None,
+            sender_event_calendar_id: None,
+        };
+        db.create_proposal(&counter).unwrap();
+
+        let stored = db.get_proposal("prop_2").unwrap().unwrap();
+        assert_eq!(stored.counter_of.as_deref(), Some("prop_1"));
+        assert_eq!(stored.message.as_deref(), Some("Mornings are better"));
+        assert_eq!(stored.options, options);
+        assert_eq!(stored.recurrence.as_deref(), Some("FREQ=WEEKLY;COUNT=4"));
+        assert_eq!(stored.location.as_deref(), Some("Room 4"));
+        assert_eq!(stored.conference, Some(Conference::google_meet()));
+        assert!(stored.key_changed);
+        assert_eq!(stored.proposal_hash.as_deref(), Some("abc123"));
+        assert_eq!(stored.sender_event_id.as_deref(), Some("evt_1"));
+
+        let event = EventRef {
+            id: "evt_2".to_string(),
+            account_id: Some("cal_work".to_string()),
+            calendar_id: Some("team@group.calendar.google.com".to_string()),
+        };
+        db.set_proposal_event("prop_2", Some(&event)).unwrap();
+        let stored = db.get_proposal("prop_2").unwrap().unwrap();
+        assert_eq!(stored.event_id.as_deref(), Some("evt_2"));
+        assert_eq!(stored.event_account_id.as_deref(), Some("cal_work"));
+        assert_eq!(
+            stored.event_calendar_id,
+            Some("team@group.calendar.google.com".to_string())
+        );
+
+        db.choose_proposal_option("prop_2", 1, &options[1]).unwrap();
+        let stored = db.get_proposal("prop_2").unwrap().unwrap();
+        assert_eq!(stored.chosen_option, Some(1));
+        assert_eq!(stored.slot(), options[1]);
+        assert_eq!(stored.open_options(), vec![options[1].clone()]);
+
+        let remote = RemoteProposal {
+            server: "https://meetd.partner.com".to_string(),
+            id: "prop_9".to_string(),
+            reply_token: "token".to_string(),
+        };
+        db.set_proposal_remote("prop_2", &remote).unwrap();
+        let stored = db.get_proposal("prop_2").unwrap().unwrap();
+        assert_eq!(stored.remote, Some(remote));
+    }
+
+    #[test]
+    fn test_signing_key_rotation() {
+        let db = Database::open_in_memory().unwrap();
+        let user = User {
+            created_at: Utc::now().timestamp() - 3600,
+            ..create_test_user()
+        };
+        db.create_user(&user).unwrap();
+        let before = Utc::now() - chrono::Duration::minutes(30);
+
+        db.rotate_user_key(
+            &user.id,
+            "pubkey2",
+            "privkey2",
+            RevocationReason::Superseded,
+        )
+        .unwrap();
+        let stored = db.get_user(&user.id).unwrap().unwrap();
+        assert_eq!(stored.public_key, "pubkey2");
+        assert_eq!(stored.private_key, "privkey2");
+
+        let keys = db.get_user_keys(&user.id).unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].public_key, "pubkey");
+        assert_eq!(
+            keys[0].revocation_reason,
+            Some(RevocationReason::Superseded)
+        );
+        assert!(keys[1].valid_until.is_none());
+
+        // The old key still covers proposals made while it was current
+        assert!(db.is_signing_key_valid(&user.id, "pubkey", before).unwrap());
+        assert!(!db
+            .is_signing_key_valid(
+                &user.id,
+                "pubkey",
+                Utc::now() + chrono::Duration::minutes(1)
+            )
+            .unwrap());
+        assert!(!db
+            .is_signing_key_valid(&user.id, "pubkey2", before)
+            .unwrap());
+        assert!(db
+            .is_signing_key_valid(&user.id, "pubkey2", Utc::now())
+            .unwrap());
+
+        // A compromised key is never trusted again, and a client-held key leaves no private key
+        db.rotate_user_key(&user.id, "pubkey3", "", RevocationReason::Compromised)
+            .unwrap();
+        assert!(!db
+            .is_signing_key_valid(&user.id, "pubkey2", Utc::now())
+            .unwrap());
+        assert!(!db.get_user(&user.id).unwrap().unwrap().has_server_key());
+        assert_eq!(db.get_user_keys(&user.id).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_contact_keys() {
+        let db = Database::open_in_memory().unwrap();
+        let user = create_test_user();
+        db.create_user(&user).unwrap();
+
+        assert!(db
+            .get_contact_key(&user.id, "bob@partner.com")
+            .unwrap()
+            .is_none());
+
+        let key = ContactKey::new(
+            "Bob@Partner.com",
+            "bobkey",
+            KeySource::Server,
+            Some("https://meetd.partner.com".to_string()),
+        );
+        db.pin_contact_key(&user.id, &key).unwrap();
+        let pinned = db
+            .get_contact_key(&user.id, "bob@PARTNER.com")
+            .unwrap()
+            .unwrap();
+        assert_eq!(pinned.email, "bob@partner.com");
+        assert_eq!(pinned.source, KeySource::Server);
+        assert_eq!(pinned.server.as_deref(), Some("https://meetd.partner.com"));
+
+        // Trusting another key replaces the pin
+        db.pin_contact_key(
+            &user.id,
+            &ContactKey::new("bob@partner.com", "bobkey2", KeySource::Manual, None),
+        )
+        .unwrap();
+        let keys = db.get_contact_keys(&user.id).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].public_key, "bobkey2");
+        assert_eq!(keys[0].source, KeySource::Manual);
+
+        assert!(db.unpin_contact_key(&user.id, "bob@partner.com").unwrap());
+        assert!(!db.unpin_contact_key(&user.id, "bob@partner.com").unwrap());
+        assert!(db.get_contact_keys(&user.id).unwrap().is_empty());
+    }
+
     #[test]
     fn test_nonce_tracking() {
         let db = Database::open_in_memory().unwrap();
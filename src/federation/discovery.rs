@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::{Peers, FEDERATION_INBOX_PATH};
+use crate::models::{PubkeyHistoryResponse, PROPOSAL_VERSION};
+
+/// Where every meetd server describes itself
+pub const WELL_KNOWN_PATH: &str = "/.well-known/meetd";
+
+/// How long a discovered server description is reused
+const DISCOVERY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How long to remember that a domain has no meetd server
+const NEGATIVE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// The document served at `/.well-known/meetd`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerMetadata {
+    /// Always `meetd`
+    pub software: String,
+    pub version: String,
+    /// Base URL of the REST API
+    pub api_base: String,
+    /// Where other servers post proposals and responses
+    pub federation_inbox: String,
+    /// Where to look up a user's signing keys (`{email}` is replaced)
+    pub key_lookup: String,
+    /// Base64 Ed25519 key that signs this server's federation messages
+    pub server_key: String,
+    /// Signed proposal versions this server accepts
+    pub proposal_versions: Vec<u32>,
+}
+
+impl ServerMetadata {
+    /// Describe the server at `server_url`
+    pub fn new(server_url: &str, server_key: String) -> Self {
+        let api_base = server_url.trim_end_matches('/').to_string();
+        Self {
+            software: "meetd".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            federation_inbox: format!("{}{}", api_base, FEDERATION_INBOX_PATH),
+            key_lookup: format!("{}/v1/agent/pubkey/{{email}}/history", api_base),
+            api_base,
+            server_key,
+            proposal_versions: vec![PROPOSAL_VERSION],
+        }
+    }
+
+    /// Where to look up `email`'s signing keys
+    pub fn key_url(&self, email: &str) -> String {
+        self.key_lookup
+            .replace("{email}", &urlencoding::encode(email))
+    }
+}
+
+/// Finds the meetd server for an email domain
+///
+/// Domains listed in the overrides are looked up on the configured server;
+/// any other domain on `https://<domain>/.well-known/meetd`. Answers,
+/// including "no server", are cached.
+pub struct Resolver {
+    overrides: Peers,
+    http_client: reqwest::Client,
+    cache: Mutex<HashMap<String, (Instant, Option<ServerMetadata>)>>,
+}
+
+impl Resolver {
+    pub fn new(overrides: Peers) -> Self {
+        Self {
+            overrides,
+            http_client: reqwest::Client::new(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The meetd server hosting `email`, if there is one
+    pub async fn home_server(&self, email: &str) -> Result<Option<ServerMetadata>> {
+        match email.rsplit_once('@') {
+            Some((_, domain)) => self.resolve(domain).await,
+            None => Ok(None),
+        }
+    }
+
+    /// The meetd server for `domain`, if there is one
+    ///
+    /// Failing to reach an overridden server is an error; for other domains
+    /// it just means they have no meetd server.
+    pub async fn resolve(&self, domain: &str) -> Result<Option<ServerMetadata>> {
+        let domain = domain.to_lowercase();
+        if let Some((fetched_at, metadata)) = self.cache.lock().unwrap().get(&domain) {
+            let ttl = if metadata.is_some() {
+                DISCOVERY_TTL
+            } else {
+                NEGATIVE_TTL
+            };
+            if fetched_at.elapsed() < ttl {
+                return Ok(metadata.clone());
+            }
+        }
+
+        let metadata = match self.overrides.server(&domain) {
+            Some(server_url) => Some(self.fetch(server_url).await?),
+            None => {
+                let base = url::Url::parse(&format!("https://{}", domain))
+                    .ok()
+                    .filter(|url| url.host_str() == Some(domain.as_str()));
+                match base {
+                    Some(base) => match self.fetch(base.as_str()).await {
+                        Ok(metadata) => Some(metadata),
+                        Err(e) => {
+                            tracing::debug!("No meetd server found for {}: {:#}", domain, e);
+                            None
+                        }
+                    },
+                    None => None,
+                }
+            }
+        };
+
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(domain, (Instant::now(), metadata.clone()));
+        Ok(metadata)
+    }
+
+    /// Look up `email`'s signing keys on their home server
+    pub async fn signing_keys(
+        &self,
+        server: &ServerMetadata,
+        email: &str,
+    ) -> Result<PubkeyHistoryResponse> {
+        let response = self
+            .http_client
+            .get(server.key_url(email))
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", server.api_base))?;
+
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "{} answered {} for {}'s keys",
+                server.api_base,
+                response.status(),
+                email
+            );
+        }
+
+        response
+            .json()
+            .await
+            .with_context(|| format!("{} sent invalid keys for {}", server.api_base, email))
+    }
+
+    /// Fetch the description of the server at `server_url`
+    pub async fn fetch(&self, server_url: &str) -> Result<ServerMetadata> {
+        let response = self
+            .http_client
+            .get(format!(
+                "{}{}",
+                server_url.trim_end_matches('/'),
+                WELL_KNOWN_PATH
+            ))
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", server_url))?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("{} answered {}", server_url, response.status());
+        }
+
+        let metadata: ServerMetadata = response
+            .json()
+            .await
+            .with_context(|| format!("{} sent an invalid server description", server_url))?;
+        if metadata.software != "meetd" {
+            anyhow::bail!("{} isn't a meetd server", server_url);
+        }
+        Ok(metadata)
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new(Peers::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_resolve_override_and_cache() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_url = format!("http://{}", listener.local_addr().unwrap());
+        let metadata = ServerMetadata::new(&server_url, "serverkey".to_string());
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let served = metadata.clone();
+        let counter = hits.clone();
+        let app = axum::Router::new().route(
+            WELL_KNOWN_PATH,
+            axum::routing::get(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let served = served.clone();
+                async move { axum::Json(served) }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let resolver = Resolver::new(Peers::parse(&format!("partner.com={}", server_url)).unwrap());
+        let found = resolver.home_server("bob@Partner.com").await.unwrap();
+        assert_eq!(found, Some(metadata.clone()));
+        assert_eq!(
+            found.unwrap().key_url("bob@partner.com"),
+            format!("{}/v1/agent/pubkey/bob%40partner.com/history", server_url)
+        );
+        assert_eq!(
+            resolver.resolve("partner.com").await.unwrap(),
+            Some(metadata)
+        );
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        assert!(resolver
+            .home_server("not-an-email")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(resolver.resolve("bad domain").await.unwrap().is_none());
+    }
+}
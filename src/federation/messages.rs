@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::{ProposalResponse, ProposalSlot, ProposalStatus};
+
+/// Message one meetd server posts to another's federated inbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FederationMessage {
+    /// A proposal for a user on the receiving server
+    Proposal {
+        /// Base64 signed proposal
+        signed_proposal: String,
+        /// The proposal's ID on the sending server
+        proposal_id: String,
+        /// Base URL of the sending server, where responses go
+        reply_to: String,
+        /// Secret both servers send with later messages about the proposal
+        reply_token: String,
+        /// The receiving server's ID of the proposal this one counters
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        counter_of: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
+    /// The recipient's answer to a proposal the receiving server sent
+    Response {
+        /// The proposal's ID on the receiving server
+        proposal_id: String,
+        reply_token: String,
+        /// `accepted` or `declined`
+        status: ProposalStatus,
+        /// Which offered slot was accepted, for multi-slot proposals
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        option: Option<usize>,
+        /// The recipient's signed answer; required, and checked against the key
+        /// history their server publishes
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        response: Option<ProposalResponse>,
+    },
+    /// The sender took back a pending proposal
+    Withdraw {
+        /// The proposal's ID on the receiving server
+        proposal_id: String,
+        reply_token: String,
+    },
+    /// Either participant called off an accepted meeting
+    Cancel {
+        /// The proposal's ID on the receiving server
+        proposal_id: String,
+        reply_token: String,
+    },
+    /// Either participant moved an accepted meeting
+    Reschedule {
+        /// The proposal's ID on the receiving server
+        proposal_id: String,
+        reply_token: String,
+        slot: ProposalSlot,
+        /// Move it even if the new slot clashes with the receiving user's calendar
+        #[serde(default)]
+        force: bool,
+    },
+}
+
+/// What the receiving server answers a delivered message with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FederationReceipt {
+    /// The proposal's ID on the receiving server
+    pub proposal_id: String,
+}
+
+/// A message waiting in the outbox for its server to come back
+#[derive(Debug, Clone)]
+pub struct QueuedMessage {
+    pub id: i64,
+    /// Base URL of the server the message is for
+    pub server: String,
+    pub message: FederationMessage,
+    /// Delivery attempts so far
+    pub attempts: u32,
+}
@@ -0,0 +1,241 @@
+mod discovery;
+mod messages;
+
+pub use discovery::*;
+pub use messages::*;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::collections::HashMap;
+
+use crate::crypto::{Keypair, PublicKey};
+
+/// Path of the federated inbox on every meetd server
+pub const FEDERATION_INBOX_PATH: &str = "/federation/inbox";
+
+/// Which meetd server hosts each email domain, overriding discovery
+#[derive(Debug, Clone, Default)]
+pub struct Peers {
+    servers: HashMap<String, String>,
+}
+
+impl Peers {
+    /// Parse `domain=url` pairs separated by commas
+    /// (e.g. `partner.com=https://meetd.partner.com,gmail.com=https://meetd.fly.dev`)
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut servers = HashMap::new();
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (domain, url) = pair
+                .split_once('=')
+                .with_context(|| format!("Expected domain=url, got {:?}", pair))?;
+            let url = url::Url::parse(url.trim())
+                .with_context(|| format!("Invalid server URL for {}", domain))?;
+            servers.insert(
+                domain.trim().to_lowercase(),
+                url.as_str().trim_end_matches('/').to_string(),
+            );
+        }
+        Ok(Self { servers })
+    }
+
+    /// Read peers from `MEETD_PEERS`
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("MEETD_PEERS") {
+            Ok(value) => Self::parse(&value).context("Invalid MEETD_PEERS"),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Base URL of the server configured for `domain`
+    pub fn server(&self, domain: &str) -> Option<&str> {
+        self.servers.get(&domain.to_lowercase()).map(String::as_str)
+    }
+
+    /// Base URL of the server configured for `email`'s domain
+    pub fn home_server(&self, email: &str) -> Option<&str> {
+        let (_, domain) = email.rsplit_once('@')?;
+        self.server(domain)
+    }
+}
+
+/// Another server answered a federated message with an error status
+#[derive(Debug)]
+pub struct DeliveryRefused {
+    pub status: reqwest::StatusCode,
+    pub body: String,
+}
+
+impl std::fmt::Display for DeliveryRefused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Federation delivery failed: {} - {}",
+            self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for DeliveryRefused {}
+
+/// Client for other meetd servers' federated inboxes
+///
+/// Every message is signed with this server's key, which other servers find
+/// in our `/.well-known/meetd`.
+pub struct FederationClient {
+    http_client: reqwest::Client,
+    server_url: String,
+    server_key: Keypair,
+}
+
+impl FederationClient {
+    pub fn new(server_url: &str, server_key: Keypair) -> Self {
+        Self {
+            http_client: reqwest::Client::new(),
+            server_url: server_url.trim_end_matches('/').to_string(),
+            server_key,
+        }
+    }
+
+    /// Post a message to a federated inbox
+    pub async fn deliver(
+        &self,
+        inbox_url: &str,
+        message: &FederationMessage,
+    ) -> Result<FederationReceipt> {
+        let payload =
+            serde_json::to_string(message).context("Failed to serialize federation message")?;
+        let timestamp = Utc::now().timestamp().to_string();
+        let signature = self.server_key.sign(&format!("{}.{}", timestamp, payload));
+
+        let response = self
+            .http_client
+            .post(inbox_url)
+            .header("Content-Type", "application/json")
+            .header("X-Meetd-Server", &self.server_url)
+            .header("X-Meetd-Timestamp", &timestamp)
+            .header("X-Meetd-Signature", signature)
+            .body(payload)
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await
+            .context("Failed to reach server")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(DeliveryRefused { status, body }.into());
+        }
+
+        response
+            .json()
+            .await
+            .context("Invalid response from server")
+    }
+
+    /// Whether a failed delivery is worth retrying
+    ///
+    /// Messages the other server turned down (a 4xx) would only be turned
+    /// down again; anything else (unreachable, a 5xx) may go through later.
+    pub fn is_retryable(error: &anyhow::Error) -> bool {
+        error
+            .downcast_ref::<DeliveryRefused>()
+            .map_or(true, |refused| refused.status.is_server_error())
+    }
+
+    /// Verify the server signature on an incoming message
+    pub fn verify_signature(
+        payload: &str,
+        timestamp: &str,
+        signature: &str,
+        server_key: &str,
+    ) -> Result<bool> {
+        // Check timestamp is not too old (5 minutes)
+        let ts: i64 = timestamp.parse().context("Invalid timestamp")?;
+        if (Utc::now().timestamp() - ts).abs() > 300 {
+            return Ok(false);
+        }
+
+        PublicKey::from_base64(server_key)?.verify(&format!("{}.{}", timestamp, payload), signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peers() {
+        let peers = Peers::parse(
+            "partner.com=https://meetd.partner.com/, Example.org=http://localhost:9090",
+        )
+        .unwrap();
+        assert_eq!(
+            peers.home_server("bob@partner.com"),
+            Some("https://meetd.partner.com")
+        );
+        assert_eq!(
+            peers.home_server("alice@EXAMPLE.org"),
+            Some("http://localhost:9090")
+        );
+        assert_eq!(peers.home_server("carol@elsewhere.com"), None);
+        assert_eq!(peers.home_server("not-an-email"), None);
+
+        assert!(Peers::parse("partner.com").is_err());
+        assert!(Peers::parse("partner.com=not a url").is_err());
+        assert!(Peers::parse("")
+            .unwrap()
+            .home_server("bob@partner.com")
+            .is_none());
+    }
+
+    #[test]
+    fn test_signature_verification() {
+        let key = Keypair::generate().unwrap();
+        let payload = r#"{"type":"response"}"#;
+        let timestamp = Utc::now().timestamp().to_string();
+        let signature = key.sign(&format!("{}.{}", timestamp, payload));
+        let server_key = key.public_key_base64();
+
+        assert!(
+            FederationClient::verify_signature(payload, &timestamp, &signature, &server_key)
+                .unwrap()
+        );
+        assert!(!FederationClient::verify_signature(
+            r#"{"type":"proposal"}"#,
+            &timestamp,
+            &signature,
+            &server_key
+        )
+        .unwrap());
+
+        let stale = (Utc::now().timestamp() - 600).to_string();
+        let signature = key.sign(&format!("{}.{}", stale, payload));
+        assert!(
+            !FederationClient::verify_signature(payload, &stale, &signature, &server_key).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_message_json() {
+        let message: FederationMessage = serde_json::from_str(
+            r#"{"type": "response", "proposal_id": "prop_1", "reply_token": "t", "status": "accepted", "option": 1}"#,
+        )
+        .unwrap();
+        match message {
+            FederationMessage::Response { status, option, .. } => {
+                assert_eq!(status, crate::models::ProposalStatus::Accepted);
+                assert_eq!(option, Some(1));
+            }
+            _ => panic!("expected a response"),
+        }
+
+        let message: FederationMessage = serde_json::from_str(
+            r#"{"type": "reschedule", "proposal_id": "prop_1", "reply_token": "t", "slot": {"start": "2030-01-02T15:00:00Z", "duration_minutes": 45}}"#,
+        )
+        .unwrap();
+        match message {
+            FederationMessage::Reschedule { slot, .. } => assert_eq!(slot.duration_minutes, 45),
+            _ => panic!("expected a reschedule"),
+        }
+    }
+}
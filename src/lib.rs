@@ -2,6 +2,8 @@ pub mod calendar;
 pub mod cli;
 pub mod crypto;
 pub mod db;
+pub mod federation;
+pub mod mail;
 pub mod models;
 pub mod server;
 pub mod webhook;
@@ -0,0 +1,185 @@
+mod smtp;
+
+pub use smtp::*;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::Utc;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// An email, optionally carrying an iMIP (RFC 6047) calendar part
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub from: String,
+    pub to: String,
+    pub reply_to: Option<String>,
+    pub subject: String,
+    pub text: String,
+    /// iTIP method (e.g. `REQUEST`) and the iCalendar object to send with it
+    pub calendar: Option<(String, String)>,
+}
+
+impl Mail {
+    pub fn new(from: &str, to: &str, subject: &str, text: &str) -> Self {
+        Self {
+            from: from.to_string(),
+            to: to.to_string(),
+            reply_to: None,
+            subject: subject.to_string(),
+            text: text.to_string(),
+            calendar: None,
+        }
+    }
+
+    /// Set where human replies go (builder style)
+    pub fn with_reply_to(mut self, reply_to: &str) -> Self {
+        self.reply_to = Some(reply_to.to_string());
+        self
+    }
+
+    /// Attach an iTIP message as a `text/calendar` part (builder style)
+    pub fn with_calendar(mut self, method: &str, calendar: &str) -> Self {
+        self.calendar = Some((method.to_ascii_uppercase(), calendar.to_string()));
+        self
+    }
+
+    /// Render as an RFC 5322 message with CRLF line endings
+    ///
+    /// Bodies are base64-encoded so the message is 7-bit clean whatever the
+    /// relay supports.
+    pub fn to_message(&self) -> String {
+        let domain = self.from.rsplit_once('@').map_or("meetd", |(_, d)| d);
+        let mut headers = vec![
+            format!("From: {}", self.from),
+            format!("To: {}", self.to),
+            format!("Subject: {}", encode_header(&self.subject)),
+            format!("Date: {}", Utc::now().to_rfc2822()),
+            format!("Message-ID: <{}@{}>", Uuid::new_v4(), domain),
+            "MIME-Version: 1.0".to_string(),
+        ];
+        if let Some(reply_to) = &self.reply_to {
+            headers.push(format!("Reply-To: {}", reply_to));
+        }
+
+        let text = body_part("text/plain; charset=UTF-8", &self.text);
+        let body = match &self.calendar {
+            None => text,
+            Some((method, calendar)) => {
+                let boundary = format!("meetd-{}", Uuid::new_v4().simple());
+                let calendar = body_part(
+                    &format!("text/calendar; charset=UTF-8; method={}", method),
+                    calendar,
+                );
+                format!(
+                    "Content-Type: multipart/alternative; boundary=\"{b}\"\r\n\r\n--{b}\r\n{}\r\n--{b}\r\n{}\r\n--{b}--\r\n",
+                    text,
+                    calendar,
+                    b = boundary
+                )
+            }
+        };
+        format!("{}\r\n{}", headers.join("\r\n"), body)
+    }
+}
+
+/// A MIME entity with a base64 body, wrapped at 76 columns
+fn body_part(content_type: &str, body: &str) -> String {
+    let encoded = BASE64.encode(body);
+    let lines: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|chunk| std::str::from_utf8(chunk).unwrap_or_default())
+        .collect();
+    format!(
+        "Content-Type: {}\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+        content_type,
+        lines.join("\r\n")
+    )
+}
+
+/// RFC 2047-encode a header value that isn't plain ASCII
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() && !value.contains(['\r', '\n']) {
+        value.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", BASE64.encode(value))
+    }
+}
+
+/// Something that can deliver mail, e.g. an SMTP relay
+#[async_trait]
+pub trait MailTransport: Send + Sync {
+    async fn send(&self, mail: &Mail) -> Result<()>;
+}
+
+/// Sends this server's mail from one address through a transport
+#[derive(Clone)]
+pub struct Mailer {
+    from: String,
+    transport: Arc<dyn MailTransport>,
+}
+
+impl Mailer {
+    pub fn new(from: &str, transport: Arc<dyn MailTransport>) -> Self {
+        Self {
+            from: from.to_string(),
+            transport,
+        }
+    }
+
+    /// Configure from `MEETD_SMTP_URL` and `MEETD_MAIL_FROM`
+    ///
+    /// `None` when no SMTP relay is configured.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(url) = std::env::var("MEETD_SMTP_URL") else {
+            return Ok(None);
+        };
+        let transport = SmtpTransport::parse(&url).context("Invalid MEETD_SMTP_URL")?;
+        let from = std::env::var("MEETD_MAIL_FROM")
+            .context("MEETD_MAIL_FROM must be set along with MEETD_SMTP_URL")?;
+        Ok(Some(Self::new(&from, Arc::new(transport))))
+    }
+
+    /// Address mail is sent from
+    pub fn from(&self) -> &str {
+        &self.from
+    }
+
+    /// A message from this server's address
+    pub fn mail(&self, to: &str, subject: &str, text: &str) -> Mail {
+        Mail::new(&self.from, to, subject, text)
+    }
+
+    pub async fn send(&self, mail: &Mail) -> Result<()> {
+        self.transport.send(mail).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_with_calendar() {
+        let calendar = "BEGIN:VCALENDAR\r\nMETHOD:REQUEST\r\nEND:VCALENDAR\r\n";
+        let message = Mail::new(
+            "invites@meetd.example.com",
+            "carol@elsewhere.org",
+            "Café?",
+            "Alice invited you",
+        )
+        .with_reply_to("alice@example.com")
+        .with_calendar("request", calendar)
+        .to_message();
+
+        assert!(message.contains("To: carol@elsewhere.org\r\n"));
+        assert!(message.contains("Reply-To: alice@example.com\r\n"));
+        assert!(message.contains(&format!("Subject: =?UTF-8?B?{}?=", BASE64.encode("Café?"))));
+        assert!(message.contains("Content-Type: multipart/alternative"));
+        assert!(message.contains("Content-Type: text/calendar; charset=UTF-8; method=REQUEST\r\n"));
+        assert!(message.contains(&BASE64.encode(calendar)));
+        assert!(message.is_ascii());
+    }
+}
@@ -0,0 +1,245 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use super::{Mail, MailTransport};
+
+/// Plain SMTP (RFC 5321) to a relay, e.g. a local MTA
+///
+/// There's no TLS or authentication; relay through an MTA on the same host
+/// or network when the outside world needs either.
+#[derive(Debug, Clone)]
+pub struct SmtpTransport {
+    host: String,
+    port: u16,
+    hello_name: String,
+}
+
+impl SmtpTransport {
+    pub fn new(host: &str, port: u16) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            hello_name: "meetd".to_string(),
+        }
+    }
+
+    /// Parse `smtp://host[:port]` (port 25 by default)
+    pub fn parse(url: &str) -> Result<Self> {
+        let url = url::Url::parse(url).with_context(|| format!("Invalid SMTP URL: {}", url))?;
+        if url.scheme() != "smtp" {
+            anyhow::bail!("Only smtp:// relays are supported, got {}://", url.scheme());
+        }
+        let host = url.host_str().context("SMTP URL has no host")?;
+        Ok(Self::new(host, url.port().unwrap_or(25)))
+    }
+
+    async fn deliver(&self, mail: &Mail) -> Result<()> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .with_context(|| format!("Couldn't connect to {}:{}", self.host, self.port))?;
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        expect_reply(&mut reader, 220).await?;
+        command(
+            &mut reader,
+            &mut writer,
+            &format!("EHLO {}", self.hello_name),
+            250,
+        )
+        .await?;
+        command(
+            &mut reader,
+            &mut writer,
+            &format!("MAIL FROM:<{}>", mail.from),
+            250,
+        )
+        .await?;
+        command(
+            &mut reader,
+            &mut writer,
+            &format!("RCPT TO:<{}>", mail.to),
+            250,
+        )
+        .await?;
+        command(&mut reader, &mut writer, "DATA", 354).await?;
+
+        writer
+            .write_all(dot_stuff(&mail.to_message()).as_bytes())
+            .await?;
+        writer.write_all(b".\r\n").await?;
+        expect_reply(&mut reader, 250).await?;
+
+        command(&mut reader, &mut writer, "QUIT", 221).await
+    }
+}
+
+#[async_trait]
+impl MailTransport for SmtpTransport {
+    async fn send(&self, mail: &Mail) -> Result<()> {
+        tokio::time::timeout(Duration::from_secs(30), self.deliver(mail))
+            .await
+            .context("SMTP relay timed out")?
+            .with_context(|| format!("Failed to send mail to {}", mail.to))
+    }
+}
+
+/// Send one command and check the reply code
+async fn command<R, W>(reader: &mut R, writer: &mut W, line: &str, expected: u16) -> Result<()>
+where
+    R: AsyncBufReadExt + Unpin,
+    W: AsyncWriteExt + Unpin,
+{
+    writer.write_all(format!("{}\r\n", line).as_bytes()).await?;
+    expect_reply(reader, expected)
+        .await
+        .with_context(|| format!("Relay rejected {}", line))
+}
+
+/// Read a (possibly multi-line) reply and check its code
+async fn expect_reply<R: AsyncBufReadExt + Unpin>(reader: &mut R, expected: u16) -> Result<()> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            anyhow::bail!("Relay closed the connection");
+        }
+        let code: u16 = line
+            .get(..3)
+            .and_then(|c| c.parse().ok())
+            .with_context(|| format!("Invalid SMTP reply: {}", line.trim_end()))?;
+        // "250-..." continues the reply, "250 ..." ends it
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        if code != expected && !(expected == 250 && code == 251) {
+            anyhow::bail!("{}", line.trim_end());
+        }
+        return Ok(());
+    }
+}
+
+/// Escape lines starting with `.` so they don't end the DATA section
+fn dot_stuff(message: &str) -> String {
+    let mut out = String::with_capacity(message.len() + 2);
+    for line in message.split_inclusive("\r\n") {
+        if line.starts_with('.') {
+            out.push('.');
+        }
+        out.push_str(line);
+    }
+    if !out.ends_with("\r\n") {
+        out.push_str("\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Accept one SMTP session and return its commands and message
+    async fn smtp_sink(listener: TcpListener) -> (Vec<String>, String) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        writer.write_all(b"220 sink ready\r\n").await.unwrap();
+
+        let mut commands = Vec::new();
+        let mut message = String::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            let line = line.trim_end().to_string();
+            let reply: &[u8] = match line.as_str() {
+                l if l.starts_with("EHLO") => b"250-sink\r\n250 8BITMIME\r\n",
+                "DATA" => {
+                    writer.write_all(b"354 go ahead\r\n").await.unwrap();
+                    loop {
+                        let mut data = String::new();
+                        reader.read_line(&mut data).await.unwrap();
+                        if data == ".\r\n" {
+                            break;
+                        }
+                        message.push_str(data.strip_prefix('.').unwrap_or(&data));
+                    }
+                    b"250 queued\r\n"
+                }
+                "QUIT" => b"221 bye\r\n",
+                _ => b"250 ok\r\n",
+            };
+            commands.push(line.clone());
+            writer.write_all(reply).await.unwrap();
+            if line == "QUIT" {
+                break;
+            }
+        }
+        (commands, message)
+    }
+
+    #[tokio::test]
+    async fn test_send_to_sink() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sink = tokio::spawn(smtp_sink(listener));
+
+        let mail = Mail::new(
+            "invites@meetd.example.com",
+            "carol@elsewhere.org",
+            "Sync",
+            "Alice invited you",
+        )
+        .with_calendar("REQUEST", "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n");
+        let transport = SmtpTransport::parse(&format!("smtp://127.0.0.1:{}", port)).unwrap();
+        transport.send(&mail).await.unwrap();
+
+        let (commands, message) = sink.await.unwrap();
+        assert_eq!(
+            commands,
+            vec![
+                "EHLO meetd",
+                "MAIL FROM:<invites@meetd.example.com>",
+                "RCPT TO:<carol@elsewhere.org>",
+                "DATA",
+                "QUIT",
+            ]
+        );
+        assert!(message.contains("To: carol@elsewhere.org\r\n"));
+        assert!(message.contains("method=REQUEST"));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_recipient() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.split();
+            let mut reader = BufReader::new(reader);
+            writer.write_all(b"220 sink\r\n").await.unwrap();
+            for reply in [&b"250 hi\r\n"[..], b"250 ok\r\n", b"550 no such user\r\n"] {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                writer.write_all(reply).await.unwrap();
+            }
+        });
+
+        let mail = Mail::new("a@example.com", "nobody@example.com", "Hi", "Hi");
+        let err = SmtpTransport::new("127.0.0.1", port)
+            .send(&mail)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("550 no such user"));
+    }
+
+    #[test]
+    fn test_dot_stuff() {
+        assert_eq!(dot_stuff("a\r\n.b\r\n"), "a\r\n..b\r\n");
+        assert_eq!(dot_stuff("a"), "a\r\n");
+    }
+}
@@ -1,9 +1,10 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
 use meetd::cli::{self, OutputFormat};
-use meetd::DEFAULT_SERVER_URL;
+use meetd::{Participant, DEFAULT_SERVER_URL};
 
 #[derive(Parser)]
 #[command(name = "meetd")]
@@ -19,14 +20,40 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Authenticate with Google Calendar
+    /// Authenticate with Google Calendar (or CalDAV / ICS with --caldav / --ics)
     Login {
         /// Server URL (default: https://meetd.example.com)
         #[arg(long, default_value = DEFAULT_SERVER_URL)]
         server: String,
+        /// CalDAV calendar collection URL (use instead of Google)
+        #[arg(long, requires_all = ["email", "caldav_user"], conflicts_with = "ics")]
+        caldav: Option<String>,
+        /// CalDAV username
+        #[arg(long)]
+        caldav_user: Option<String>,
+        /// ICS feed URL or server-side file path (use instead of Google)
+        #[arg(long, requires = "email")]
+        ics: Option<String>,
+        /// Server-side ICS file that accepted meetings are appended to
+        #[arg(long, requires = "ics")]
+        ics_write: Option<String>,
+        /// Your email address (required with --caldav and --ics)
+        #[arg(long)]
+        email: Option<String>,
+        /// Sign proposals with a key kept on this machine; only its public key is uploaded
+        #[arg(long)]
+        local_key: bool,
     },
     /// Log out and remove local credentials
     Logout,
+    /// Replace your signing key; proposals signed with the old one stay valid
+    RotateSigningKey {
+        /// The old key leaked: stop trusting anything signed with it
+        #[arg(long)]
+        compromised: bool,
+    },
+    /// Confirm your email address so others' sharing settings recognise you
+    VerifyEmail,
     /// Configure settings
     Config {
         #[command(subcommand)]
@@ -34,9 +61,12 @@ enum Commands {
     },
     /// Query availability for a meeting
     Avail {
-        /// Email of the person to meet with
-        #[arg(long)]
-        with: String,
+        /// Emails of the people who have to attend (comma-separated)
+        #[arg(long, required = true, value_delimiter = ',')]
+        with: Vec<String>,
+        /// Emails of people who may attend if they're free (comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        optional: Vec<String>,
         /// Duration (e.g., "30m", "1h")
         #[arg(long)]
         duration: String,
@@ -46,30 +76,61 @@ enum Commands {
         /// Timezone (e.g., "America/New_York")
         #[arg(long)]
         timezone: Option<String>,
+        #[command(flatten)]
+        slots: cli::SlotArgs,
+        #[command(flatten)]
+        repeat: cli::RepeatArgs,
+    },
+    /// Show someone's busy periods (as much detail as they share)
+    Busy {
+        /// Email of the person whose calendar to show
+        #[arg(long)]
+        with: String,
+        /// Time window (e.g., "2026-02-01..2026-02-07")
+        #[arg(long)]
+        window: String,
     },
     /// Create a meeting proposal
     Propose {
         /// Email to send proposal to
         #[arg(long)]
         to: String,
-        /// Proposed time slot (e.g., "2026-02-03T10:00")
-        #[arg(long)]
-        slot: String,
+        /// Proposed time slot (e.g., "2026-02-03T10:00"); repeat to let the
+        /// recipient pick one, most preferred first
+        #[arg(long, required = true)]
+        slot: Vec<String>,
         /// Duration (e.g., "30m", "1h")
         #[arg(long)]
         duration: String,
-        /// Meeting title
-        #[arg(long)]
-        title: Option<String>,
-        /// Meeting description
-        #[arg(long)]
-        description: Option<String>,
+        #[command(flatten)]
+        meeting: cli::MeetingArgs,
     },
     /// Accept a proposal
     Accept {
         /// Proposal ID to accept
         #[arg(long)]
         proposal: String,
+        /// Which offered slot to accept (0-based, as listed in the inbox)
+        #[arg(long)]
+        option: Option<usize>,
+        /// Accept even if the slot now clashes with a calendar event
+        #[arg(long)]
+        force: bool,
+    },
+    /// Answer a proposal with a different time
+    Counter {
+        /// Proposal ID to counter
+        #[arg(long)]
+        proposal: String,
+        /// Time slot to offer instead (e.g., "2026-02-03T10:00"); repeatable
+        #[arg(long, required = true)]
+        slot: Vec<String>,
+        /// Duration (default: same as the original proposal)
+        #[arg(long)]
+        duration: Option<String>,
+        /// Note for the original sender
+        #[arg(long)]
+        message: Option<String>,
     },
     /// Decline a proposal
     Decline {
@@ -77,12 +138,39 @@ enum Commands {
         #[arg(long)]
         proposal: String,
     },
+    /// Withdraw a proposal you sent that hasn't been answered yet
+    Withdraw {
+        /// Proposal ID to withdraw
+        #[arg(long)]
+        proposal: String,
+    },
+    /// Cancel an accepted meeting
+    Cancel {
+        /// Proposal ID of the meeting
+        #[arg(long)]
+        proposal: String,
+    },
+    /// Move an accepted meeting to a new time
+    Reschedule {
+        /// Proposal ID of the meeting
+        #[arg(long)]
+        proposal: String,
+        /// New time slot (e.g., "2026-02-03T10:00")
+        #[arg(long)]
+        slot: String,
+        /// New duration (default: unchanged)
+        #[arg(long)]
+        duration: Option<String>,
+        /// Move it even if the new slot clashes with a calendar event
+        #[arg(long)]
+        force: bool,
+    },
     /// View and manage inbox
     Inbox {
         /// Watch for new proposals (streaming)
         #[arg(long)]
         watch: bool,
-        /// Filter by status (pending, accepted, declined, expired)
+        /// Filter by status (pending, accepted, declined, expired, countered, cancelled, withdrawn)
         #[arg(long)]
         status: Option<String>,
     },
@@ -93,6 +181,17 @@ enum Commands {
         /// Base64-encoded signed proposal
         #[arg(long)]
         signed: String,
+        /// Which offered slot to accept (0-based)
+        #[arg(long)]
+        option: Option<usize>,
+        /// Accept even if the slot now clashes with a calendar event
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show the signing keys pinned for people who've sent you proposals
+    Contacts {
+        #[command(subcommand)]
+        action: Option<ContactsAction>,
     },
     /// Run the API server
     Serve {
@@ -106,6 +205,42 @@ enum Commands {
         #[arg(long, default_value = "http://localhost:8080")]
         url: String,
     },
+    /// Server maintenance
+    Admin {
+        #[command(subcommand)]
+        action: AdminAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum AdminAction {
+    /// Re-encrypt stored private keys and calendar credentials with a new master key
+    /// (the current key is read from MEETD_MASTER_KEY or MEETD_MASTER_KEY_FILE)
+    RotateMasterKey {
+        /// Database file path
+        #[arg(long, default_value = "./meetd.db")]
+        db: String,
+        /// File holding the new base64 master key (created with a fresh key if missing)
+        #[arg(long)]
+        new_key_file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ContactsAction {
+    /// Pin a contact's key, e.g. after confirming they changed it
+    Trust {
+        /// Email of the contact
+        email: String,
+        /// Base64 public key to pin (default: the key their server publishes)
+        #[arg(long)]
+        key: Option<String>,
+    },
+    /// Forget a contact's pinned key; the next key they sign with is pinned
+    Untrust {
+        /// Email of the contact
+        email: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -127,6 +262,56 @@ enum ConfigAction {
         /// Server URL
         url: String,
     },
+    /// Choose which calendars count as busy and which receive new events
+    /// (shows the current choice when no options are given)
+    Calendars {
+        /// Calendar IDs whose events count as busy time (e.g. "primary,team@group.calendar.google.com")
+        #[arg(long, value_delimiter = ',')]
+        busy: Vec<String>,
+        /// Calendar ID that new events are created in
+        #[arg(long)]
+        write: Option<String>,
+        /// Calendar account to configure (default: your primary account)
+        #[arg(long)]
+        account: Option<String>,
+        /// Go back to the account's default calendars
+        #[arg(long, conflicts_with_all = ["busy", "write"])]
+        reset: bool,
+    },
+    /// Set working hours (shows the current hours when no options are given)
+    Hours {
+        /// IANA timezone (e.g. "Europe/Berlin")
+        #[arg(long)]
+        timezone: Option<String>,
+        /// Hours for some days, e.g. "mon-fri=09:00-17:00", "sat=10:00-12:00" or "fri=off" (repeatable)
+        #[arg(long = "days")]
+        days: Vec<String>,
+        /// Daily lunch break, e.g. "12:00-13:00" (or "off")
+        #[arg(long)]
+        lunch: Option<String>,
+        /// Go back to the default (Monday to Friday, 09:00-17:00 UTC)
+        #[arg(long, conflicts_with_all = ["timezone", "days", "lunch"])]
+        reset: bool,
+    },
+    /// Set default slot step, buffers, notice and horizon (shows them when no options are given)
+    Slots {
+        #[command(flatten)]
+        slots: cli::SlotArgs,
+        /// Clear all saved slot preferences
+        #[arg(long)]
+        reset: bool,
+    },
+    /// Choose who may see your availability (shows the current policy when no options are given)
+    Sharing {
+        /// Sharing mode: everyone, contacts, domain, or allowlist
+        mode: Option<String>,
+        /// Emails or "@domain"s that are always allowed (comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        allow: Vec<String>,
+        /// Remove emails or "@domain"s from the always-allowed list (comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        disallow: Vec<String>,
+    },
 }
 
 #[tokio::main]
@@ -147,12 +332,43 @@ async fn main() -> Result<()> {
     };
 
     match cli.command {
-        Commands::Login { server } => {
-            cli::run_login(&server, format).await?;
-        }
+        Commands::Login {
+            server,
+            caldav,
+            caldav_user,
+            ics,
+            ics_write,
+            email,
+            local_key,
+        } => match (caldav, caldav_user, ics, email) {
+            (Some(caldav), Some(caldav_user), _, Some(email)) => {
+                cli::run_login_caldav(&server, &email, &caldav, &caldav_user, local_key, format)
+                    .await?;
+            }
+            (_, _, Some(ics), Some(email)) => {
+                cli::run_login_ics(
+                    &server,
+                    &email,
+                    &ics,
+                    ics_write.as_deref(),
+                    local_key,
+                    format,
+                )
+                .await?;
+            }
+            _ => {
+                cli::run_login(&server, local_key, format).await?;
+            }
+        },
         Commands::Logout => {
             cli::run_logout(format)?;
         }
+        Commands::RotateSigningKey { compromised } => {
+            cli::run_rotate_signing_key(compromised, format).await?;
+        }
+        Commands::VerifyEmail => {
+            cli::run_verify_email(format).await?;
+        }
         Commands::Config { action } => match action {
             ConfigAction::Show => {
                 cli::run_config_show(format).await?;
@@ -166,38 +382,114 @@ async fn main() -> Result<()> {
             ConfigAction::Server { url } => {
                 cli::run_config_server(&url, format)?;
             }
+            ConfigAction::Calendars {
+                busy,
+                write,
+                account,
+                reset,
+            } => {
+                if busy.is_empty() && write.is_none() && !reset {
+                    cli::run_config_calendars_show(format).await?;
+                } else {
+                    cli::run_config_calendars(&busy, write.as_deref(), account.as_deref(), format)
+                        .await?;
+                }
+            }
+            ConfigAction::Hours {
+                timezone,
+                days,
+                lunch,
+                reset,
+            } => {
+                cli::run_config_hours(timezone.as_deref(), &days, lunch.as_deref(), reset, format)
+                    .await?;
+            }
+            ConfigAction::Slots { slots, reset } => {
+                cli::run_config_slots(slots.preferences()?, reset, format).await?;
+            }
+            ConfigAction::Sharing {
+                mode,
+                allow,
+                disallow,
+            } => {
+                cli::run_config_sharing(mode.as_deref(), &allow, &disallow, format).await?;
+            }
         },
         Commands::Avail {
             with,
+            optional,
             duration,
             window,
             timezone,
+            slots,
+            repeat,
         } => {
-            cli::run_avail(&with, &duration, &window, timezone.as_deref(), format).await?;
+            let participants = with
+                .iter()
+                .map(Participant::required)
+                .chain(optional.iter().map(Participant::optional))
+                .collect();
+            cli::run_avail(
+                participants,
+                &duration,
+                &window,
+                timezone.as_deref(),
+                slots.preferences()?,
+                repeat.rule()?,
+                format,
+            )
+            .await?;
+        }
+        Commands::Busy { with, window } => {
+            cli::run_busy(&with, &window, format).await?;
         }
         Commands::Propose {
             to,
             slot,
             duration,
-            title,
-            description,
+            meeting,
+        } => {
+            cli::run_propose(&to, &slot, &duration, &meeting, format).await?;
+        }
+        Commands::Accept {
+            proposal,
+            option,
+            force,
         } => {
-            cli::run_propose(
-                &to,
+            cli::run_accept(&proposal, option, force, format).await?;
+        }
+        Commands::Counter {
+            proposal,
+            slot,
+            duration,
+            message,
+        } => {
+            cli::run_counter(
+                &proposal,
                 &slot,
-                &duration,
-                title.as_deref(),
-                description.as_deref(),
+                duration.as_deref(),
+                message.as_deref(),
                 format,
             )
             .await?;
         }
-        Commands::Accept { proposal } => {
-            cli::run_accept(&proposal, format).await?;
-        }
         Commands::Decline { proposal } => {
             cli::run_decline(&proposal, format).await?;
         }
+        Commands::Withdraw { proposal } => {
+            cli::run_withdraw(&proposal, format).await?;
+        }
+        Commands::Cancel { proposal } => {
+            cli::run_cancel(&proposal, format).await?;
+        }
+        Commands::Reschedule {
+            proposal,
+            slot,
+            duration,
+            force,
+        } => {
+            cli::run_reschedule(&proposal, &slot, duration.as_deref(), force, format).await?;
+        }
         Commands::Inbox { watch, status } => {
             if watch {
                 cli::run_inbox_watch(format).await?;
@@ -208,13 +500,33 @@ async fn main() -> Result<()> {
         Commands::Sent => {
             cli::run_sent(format).await?;
         }
-        Commands::AcceptSigned { signed } => {
-            cli::run_accept_signed(&signed, format).await?;
+        Commands::AcceptSigned {
+            signed,
+            option,
+            force,
+        } => {
+            cli::run_accept_signed(&signed, option, force, format).await?;
         }
         Commands::Serve { port, db, url } => {
             let addr: SocketAddr = format!("0.0.0.0:{}", port).parse()?;
             meetd::server::run_server(addr, &db, &url).await?;
         }
+        Commands::Contacts { action } => match action {
+            None => {
+                cli::run_contacts(format).await?;
+            }
+            Some(ContactsAction::Trust { email, key }) => {
+                cli::run_contacts_trust(&email, key.as_deref(), format).await?;
+            }
+            Some(ContactsAction::Untrust { email }) => {
+                cli::run_contacts_untrust(&email, format).await?;
+            }
+        },
+        Commands::Admin { action } => match action {
+            AdminAction::RotateMasterKey { db, new_key_file } => {
+                cli::run_rotate_master_key(&db, &new_key_file, format)?;
+            }
+        },
     }
 
     Ok(())
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+
+/// Connection settings for a calendar backend
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum CalendarConfig {
+    /// A Google account, authorized through OAuth
+    Google {
+        refresh_token: String,
+        /// Google account email, used to recognise re-logins
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        email: Option<String>,
+    },
+    /// A CalDAV calendar collection (Fastmail, Nextcloud, Radicale, ...)
+    Caldav {
+        url: String,
+        username: String,
+        password: String,
+    },
+    /// An iCalendar feed (local file or URL), with an optional local file for new events
+    Ics {
+        source: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        write_path: Option<String>,
+    },
+}
+
+impl CalendarConfig {
+    /// Provider name, as used in the `provider` tag
+    pub fn provider(&self) -> &'static str {
+        match self {
+            CalendarConfig::Google { .. } => "google",
+            CalendarConfig::Caldav { .. } => "caldav",
+            CalendarConfig::Ics { .. } => "ics",
+        }
+    }
+
+    /// Human-readable description without any secrets
+    pub fn describe(&self) -> String {
+        match self {
+            CalendarConfig::Google { email, .. } => email
+                .clone()
+                .unwrap_or_else(|| "Google account".to_string()),
+            CalendarConfig::Caldav { url, username, .. } => format!("{} ({})", url, username),
+            CalendarConfig::Ics { source, .. } => source.clone(),
+        }
+    }
+
+    /// Whether two configs point at the same external account (credentials may differ)
+    pub fn same_account(&self, other: &CalendarConfig) -> bool {
+        match (self, other) {
+            (CalendarConfig::Google { email: a, .. }, CalendarConfig::Google { email: b, .. }) => {
+                a.is_some() && a == b
+            }
+            (
+                CalendarConfig::Caldav {
+                    url: url_a,
+                    username: user_a,
+                    ..
+                },
+                CalendarConfig::Caldav {
+                    url: url_b,
+                    username: user_b,
+                    ..
+                },
+            ) => url_a == url_b && user_a == user_b,
+            (CalendarConfig::Ics { source: a, .. }, CalendarConfig::Ics { source: b, .. }) => {
+                a == b
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Which calendars within an account are used
+///
+/// Calendar IDs are provider-specific (for Google, `primary` or an address
+/// like `team@group.calendar.google.com`). Empty means the provider default.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarSelection {
+    /// Calendars whose events count as busy time
+    #[serde(default)]
+    pub busy: Vec<String>,
+    /// Calendar that new events are created in
+    #[serde(default)]
+    pub write: Option<String>,
+}
+
+impl CalendarSelection {
+    pub fn is_default(&self) -> bool {
+        self.busy.is_empty() && self.write.is_none()
+    }
+}
+
+/// A calendar account linked to a user
+#[derive(Debug, Clone)]
+pub struct CalendarAccount {
+    pub id: String,
+    pub user_id: String,
+    pub config: CalendarConfig,
+    pub label: Option<String>,
+    /// The primary account receives new events
+    pub is_primary: bool,
+    pub selection: CalendarSelection,
+    pub created_at: i64,
+}
+
+/// Calendar account info returned by API (excludes credentials)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalendarAccountInfo {
+    pub id: String,
+    pub provider: String,
+    pub account: String,
+    pub label: Option<String>,
+    pub is_primary: bool,
+    #[serde(default, skip_serializing_if = "CalendarSelection::is_default")]
+    pub calendars: CalendarSelection,
+    pub created_at: i64,
+}
+
+impl From<CalendarAccount> for CalendarAccountInfo {
+    fn from(account: CalendarAccount) -> Self {
+        Self {
+            id: account.id,
+            provider: account.config.provider().to_string(),
+            account: account.config.describe(),
+            label: account.label,
+            is_primary: account.is_primary,
+            calendars: account.selection,
+            created_at: account.created_at,
+        }
+    }
+}
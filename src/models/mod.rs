@@ -1,7 +1,11 @@
+mod calendar;
 mod proposal;
 mod timeslot;
 mod user;
+mod working_hours;
 
+pub use calendar::*;
 pub use proposal::*;
 pub use timeslot::*;
 pub use user::*;
+pub use working_hours::*;
@@ -1,6 +1,11 @@
-use chrono::{DateTime, Utc};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::calendar::rrule::RecurrenceRule;
+use crate::crypto::canonical_json;
+use sha2::{Digest, Sha256};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum ProposalStatus {
@@ -9,6 +14,12 @@ pub enum ProposalStatus {
     Accepted,
     Declined,
     Expired,
+    /// The recipient answered with a counter-proposal
+    Countered,
+    /// The meeting was called off after being accepted
+    Cancelled,
+    /// The sender took the proposal back before it was answered
+    Withdrawn,
 }
 
 impl ProposalStatus {
@@ -18,6 +29,9 @@ impl ProposalStatus {
             ProposalStatus::Accepted => "accepted",
             ProposalStatus::Declined => "declined",
             ProposalStatus::Expired => "expired",
+            ProposalStatus::Countered => "countered",
+            ProposalStatus::Cancelled => "cancelled",
+            ProposalStatus::Withdrawn => "withdrawn",
         }
     }
 
@@ -27,6 +41,9 @@ impl ProposalStatus {
             "accepted" => Some(ProposalStatus::Accepted),
             "declined" => Some(ProposalStatus::Declined),
             "expired" => Some(ProposalStatus::Expired),
+            "countered" => Some(ProposalStatus::Countered),
+            "cancelled" => Some(ProposalStatus::Cancelled),
+            "withdrawn" => Some(ProposalStatus::Withdrawn),
             _ => None,
         }
     }
@@ -53,6 +70,127 @@ pub struct Proposal {
     pub signature: String,
     pub status: ProposalStatus,
     pub created_at: i64,
+    /// Every offered slot in order of preference, when there's more than one
+    pub options: Vec<ProposalSlot>,
+    /// Which of `options` the recipient picked
+    pub chosen_option: Option<usize>,
+    /// The proposal this one counters, if it's a counter-proposal
+    pub counter_of: Option<String>,
+    /// Note from the sender (e.g. why they countered)
+    pub message: Option<String>,
+    /// Provider ID of the calendar event created on acceptance, in the recipient's calendar
+    pub event_id: Option<String>,
+    /// RRULE value (e.g. `FREQ=WEEKLY;COUNT=10`) for recurring meetings
+    pub recurrence: Option<String>,
+    /// Where the meeting takes place
+    pub location: Option<String>,
+    /// How to join remotely
+    pub conference: Option<Conference>,
+    /// The other server's side, for proposals exchanged with another meetd server
+    pub remote: Option<RemoteProposal>,
+    /// Signed with a different key than the one the recipient pinned for the sender
+    pub key_changed: bool,
+    /// [`SignedProposal::hash`] of the signed proposal, for proposals made since it's kept
+    pub proposal_hash: Option<String>,
+    /// Provider ID of the calendar event created on acceptance, in the sender's calendar
+    pub sender_event_id: Option<String>,
+    /// Calendar account (and calendar within it) `event_id` was made in
+    pub event_account_id: Option<String>,
+    pub event_calendar_id: Option<String>,
+    /// Calendar account (and calendar within it) `sender_event_id` was made in
+    pub sender_event_account_id: Option<String>,
+    pub sender_event_calendar_id: Option<String>,
+}
+
+/// Where a proposal lives on the other meetd server it was exchanged with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteProposal {
+    /// Base URL of the other server
+    pub server: String,
+    /// The proposal's ID on that server
+    pub id: String,
+    /// Shared secret that authenticates responses about this proposal
+    pub reply_token: String,
+}
+
+impl Proposal {
+    /// A new pending proposal as described by `signed`
+    pub fn from_signed(id: String, from_user_id: String, signed: &SignedProposal) -> Self {
+        Proposal {
+            id,
+            from_user_id,
+            to_email: signed.to.clone(),
+            slot_start: signed.slot.start,
+            duration_minutes: signed.slot.duration_minutes,
+            title: signed.title.clone(),
+            description: signed.description.clone(),
+            nonce: signed.nonce.clone(),
+            expires_at: signed.expires_at,
+            signature: signed.signature.clone(),
+            status: ProposalStatus::Pending,
+            created_at: Utc::now().timestamp(),
+            options: if signed.slots.len() > 1 {
+                signed.slots.clone()
+            } else {
+                vec![]
+            },
+            chosen_option: None,
+            counter_of: None,
+            message: None,
+            event_id: None,
+            recurrence: signed.recurrence.clone(),
+            location: signed.location.clone(),
+            conference: signed.conference.clone(),
+            remote: None,
+            key_changed: false,
+            proposal_hash: Some(signed.hash()),
+            sender_event_id: None,
+            event_account_id: None,
+            event_calendar_id: None,
+            sender_event_account_id: None,
+            sender_event_calendar_id: None,
+        }
+    }
+
+    /// The slot the proposal is currently for (the chosen one, once accepted)
+    pub fn slot(&self) -> ProposalSlot {
+        ProposalSlot {
+            start: self.slot_start,
+            duration_minutes: self.duration_minutes,
+        }
+    }
+
+    /// Slots the recipient can still pick from
+    ///
+    /// Once an option has been chosen the others are released and only the
+    /// chosen slot is returned.
+    pub fn open_options(&self) -> Vec<ProposalSlot> {
+        if self.options.is_empty() || self.chosen_option.is_some() {
+            vec![self.slot()]
+        } else {
+            self.options.clone()
+        }
+    }
+
+    /// Pick the slot to accept; `index` is required when several are offered
+    pub fn pick_option(&self, index: Option<usize>) -> Result<ProposalSlot, String> {
+        let options = self.open_options();
+        match index {
+            Some(i) => options.get(i).cloned().ok_or_else(|| {
+                format!(
+                    "Option {} doesn't exist; this proposal has {} option(s)",
+                    i,
+                    options.len()
+                )
+            }),
+            None if options.len() == 1 => Ok(options[0].clone()),
+            None => Err(format!(
+                "This proposal offers {} options; choose one by index (0-{})",
+                options.len(),
+                options.len() - 1
+            )),
+        }
+    }
 }
 
 /// Signed proposal format for agent-to-agent communication
@@ -62,23 +200,162 @@ pub struct SignedProposal {
     pub from: String,
     pub from_pubkey: String,
     pub to: String,
+    /// The preferred slot (the first of `slots` when several are offered)
     pub slot: ProposalSlot,
+    /// Every offered slot in order of preference (version 2 and later)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub slots: Vec<ProposalSlot>,
     pub title: Option<String>,
     pub description: Option<String>,
+    /// RRULE value for recurring meetings (version 2 and later)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<String>,
+    /// Where the meeting takes place (version 2 and later)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// How to join remotely (version 2 and later)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conference: Option<Conference>,
     pub nonce: String,
     pub expires_at: DateTime<Utc>,
+    /// When the proposal was made, which picks the sender key that must have
+    /// signed it (version 2 and later)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
     pub signature: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Most slots one proposal may offer
+pub const MAX_PROPOSAL_SLOTS: usize = 10;
+
+/// Signing scheme used for new proposals (see [`SignedProposal::signing_payload`])
+pub const PROPOSAL_VERSION: u32 = 2;
+
+/// How long new proposals stay open, and the longest any proposal may
+pub const PROPOSAL_LIFETIME_DAYS: i64 = 7;
+
+/// How far ahead of this server's clock a proposal's `created_at` may be
+pub const MAX_CLOCK_SKEW_MINUTES: i64 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ProposalSlot {
     pub start: DateTime<Utc>,
     pub duration_minutes: i32,
 }
 
+impl ProposalSlot {
+    /// Check the slot lasts at all
+    pub fn validate(&self) -> Result<(), String> {
+        if self.duration_minutes <= 0 {
+            return Err("A meeting must last at least a minute".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Video conference for a meeting: either generated by the calendar
+/// (`{"type": "google_meet", "auto_create": true}`) or a join URL
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Conference {
+    Generated {
+        #[serde(rename = "type")]
+        kind: ConferenceKind,
+        auto_create: bool,
+    },
+    Url(String),
+}
+
+/// Conference systems a calendar can create meetings in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConferenceKind {
+    GoogleMeet,
+}
+
+impl Conference {
+    /// A Google Meet link created along with the calendar event
+    pub fn google_meet() -> Self {
+        Self::Generated {
+            kind: ConferenceKind::GoogleMeet,
+            auto_create: true,
+        }
+    }
+
+    /// The join URL, when one was given
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            Self::Url(url) => Some(url),
+            Self::Generated { .. } => None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Url(url) if !(url.starts_with("https://") || url.starts_with("http://")) => {
+                Err("Conference URL must start with http:// or https://".to_string())
+            }
+            Self::Generated {
+                auto_create: false, ..
+            } => Err("Generated conferences need \"auto_create\": true".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for Conference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Generated {
+                kind: ConferenceKind::GoogleMeet,
+                ..
+            } => write!(f, "google_meet"),
+            Self::Url(url) => write!(f, "{}", url),
+        }
+    }
+}
+
 impl SignedProposal {
+    /// Encode as base64 JSON, the form proposals are passed around in
+    pub fn to_base64(&self) -> String {
+        BASE64.encode(serde_json::to_string(self).expect("proposals serialize to JSON"))
+    }
+
+    /// Decode a proposal made by [`SignedProposal::to_base64`]
+    pub fn from_base64(encoded: &str) -> Result<Self, String> {
+        let json = BASE64
+            .decode(encoded)
+            .map_err(|e| format!("Invalid base64: {}", e))?;
+        serde_json::from_slice(&json).map_err(|e| format!("Invalid JSON: {}", e))
+    }
+
+    /// When the proposal was made
+    ///
+    /// Proposals without `created_at` are assumed to have had the usual
+    /// lifetime.
+    pub fn signed_at(&self) -> DateTime<Utc> {
+        self.created_at
+            .unwrap_or(self.expires_at - Duration::days(PROPOSAL_LIFETIME_DAYS))
+    }
+
     /// Get the canonical string to sign
+    ///
+    /// Version 2 signs the canonical JSON (RFC 8785) of every field except
+    /// `signature`, so nothing (the description and sender key included) can
+    /// change unnoticed. It's the only version meetd signs.
+    ///
+    /// Version 1 is kept so old proposals still verify. It joins the core
+    /// fields with `|` and covers nothing else, so `validate` refuses version
+    /// 1 proposals carrying the fields it leaves out.
     pub fn signing_payload(&self) -> String {
+        if self.version >= 2 {
+            let mut value = serde_json::to_value(self).expect("proposals serialize to JSON");
+            if let Some(fields) = value.as_object_mut() {
+                fields.remove("signature");
+            }
+            return canonical_json(&value);
+        }
+
         format!(
             "{}|{}|{}|{}|{}|{}|{}|{}",
             self.version,
@@ -91,6 +368,192 @@ impl SignedProposal {
             self.expires_at.to_rfc3339()
         )
     }
+
+    /// SHA-256 (hex) of the canonical JSON of the whole signed proposal
+    ///
+    /// Responses name the proposal they answer by this hash.
+    pub fn hash(&self) -> String {
+        let value = serde_json::to_value(self).expect("proposals serialize to JSON");
+        hex::encode(Sha256::digest(canonical_json(&value).as_bytes()))
+    }
+
+    /// Every offered slot in order of preference
+    pub fn options(&self) -> Vec<ProposalSlot> {
+        if self.version >= 2 && !self.slots.is_empty() {
+            self.slots.clone()
+        } else {
+            vec![self.slot.clone()]
+        }
+    }
+
+    /// Check that the slot list is usable and consistent with `slot`, and
+    /// that the recurrence rule, location and conference are covered by the
+    /// signature and well-formed
+    pub fn validate(&self) -> Result<(), String> {
+        if self.version == 0 || self.version > PROPOSAL_VERSION {
+            return Err(format!("Unsupported proposal version {}", self.version));
+        }
+        match self.created_at {
+            Some(_) if self.version < 2 => {
+                return Err("`created_at` needs proposal version 2 or later".to_string());
+            }
+            Some(created_at) if created_at >= self.expires_at => {
+                return Err("A proposal must be made before it expires".to_string());
+            }
+            // `created_at` picks the key that must have signed, so a proposal
+            // can't claim to be older than its lifetime allows; otherwise a
+            // retired key could keep signing backdated proposals
+            Some(created_at)
+                if self.expires_at - created_at > Duration::days(PROPOSAL_LIFETIME_DAYS) =>
+            {
+                return Err(format!(
+                    "A proposal can't stay open for more than {} days",
+                    PROPOSAL_LIFETIME_DAYS
+                ));
+            }
+            Some(created_at)
+                if created_at > Utc::now() + Duration::minutes(MAX_CLOCK_SKEW_MINUTES) =>
+            {
+                return Err("`created_at` is in the future".to_string());
+            }
+            _ => {}
+        }
+        for slot in std::iter::once(&self.slot).chain(&self.slots) {
+            slot.validate()?;
+        }
+        if self.version < 2 {
+            if !self.slots.is_empty()
+                || self.recurrence.is_some()
+                || self.location.is_some()
+                || self.conference.is_some()
+            {
+                return Err(
+                    "Extra slots, recurrence, location and conference need proposal version 2 or later"
+                        .to_string(),
+                );
+            }
+            return Ok(());
+        }
+        if self.slots.is_empty() || self.slots.len() > MAX_PROPOSAL_SLOTS {
+            return Err(format!(
+                "A proposal must offer between 1 and {} slots",
+                MAX_PROPOSAL_SLOTS
+            ));
+        }
+        if self.slots[0] != self.slot {
+            return Err("The first offered slot must match `slot`".to_string());
+        }
+        if self
+            .slots
+            .iter()
+            .enumerate()
+            .any(|(i, slot)| self.slots[..i].contains(slot))
+        {
+            return Err("The same slot is offered more than once".to_string());
+        }
+        if let Some(rule) = &self.recurrence {
+            RecurrenceRule::parse(rule).map_err(|e| format!("Invalid recurrence: {}", e))?;
+        }
+        if let Some(conference) = &self.conference {
+            conference.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// Signing scheme used for responses (see [`ProposalResponse::signing_payload`])
+pub const RESPONSE_VERSION: u32 = 1;
+
+/// A recipient's signed answer to a proposal
+///
+/// Lets the sender prove to anyone holding the proposal that the recipient
+/// accepted (and which slot) or declined it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProposalResponse {
+    pub version: u32,
+    /// [`SignedProposal::hash`] of the proposal being answered
+    pub proposal_hash: String,
+    /// Who answered (the proposal's recipient)
+    pub from: String,
+    pub from_pubkey: String,
+    /// The proposal's sender
+    pub to: String,
+    /// `accepted` or `declined`
+    pub status: ProposalStatus,
+    /// The slot that was accepted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<ProposalSlot>,
+    pub responded_at: DateTime<Utc>,
+    pub signature: String,
+}
+
+impl ProposalResponse {
+    /// An unsigned response from `from` to the proposal with `proposal_hash`
+    pub fn new(
+        proposal_hash: &str,
+        from: &str,
+        from_pubkey: &str,
+        to: &str,
+        status: ProposalStatus,
+        slot: Option<ProposalSlot>,
+    ) -> Self {
+        Self {
+            version: RESPONSE_VERSION,
+            proposal_hash: proposal_hash.to_string(),
+            from: from.to_string(),
+            from_pubkey: from_pubkey.to_string(),
+            to: to.to_string(),
+            status,
+            slot,
+            responded_at: Utc::now(),
+            signature: String::new(),
+        }
+    }
+
+    /// What gets signed: the canonical JSON (RFC 8785) of every field except `signature`
+    pub fn signing_payload(&self) -> String {
+        let mut value = serde_json::to_value(self).expect("responses serialize to JSON");
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("signature");
+        }
+        canonical_json(&value)
+    }
+
+    /// Check that the response says something meaningful
+    pub fn validate(&self) -> Result<(), String> {
+        if self.version == 0 || self.version > RESPONSE_VERSION {
+            return Err(format!("Unsupported response version {}", self.version));
+        }
+        match (self.status, &self.slot) {
+            (ProposalStatus::Accepted, Some(_)) | (ProposalStatus::Declined, None) => Ok(()),
+            (ProposalStatus::Accepted, None) => {
+                Err("An acceptance must name the accepted slot".to_string())
+            }
+            (ProposalStatus::Declined, Some(_)) => Err("A decline can't name a slot".to_string()),
+            (other, _) => Err(format!("Can't respond with status {}", other)),
+        }
+    }
+
+    /// Check that this response answers `proposal` and, for acceptances,
+    /// picks one of its slots
+    pub fn answers(&self, proposal: &SignedProposal) -> Result<(), String> {
+        if self.proposal_hash != proposal.hash() {
+            return Err("The response is for a different proposal".to_string());
+        }
+        if !self.from.eq_ignore_ascii_case(&proposal.to)
+            || !self.to.eq_ignore_ascii_case(&proposal.from)
+        {
+            return Err(
+                "The response isn't from the proposal's recipient to its sender".to_string(),
+            );
+        }
+        match &self.slot {
+            Some(slot) if !proposal.options().contains(slot) => {
+                Err("The accepted slot wasn't offered".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 /// Request to create a proposal
@@ -98,9 +561,94 @@ impl SignedProposal {
 pub struct CreateProposalRequest {
     pub to_email: String,
     pub slot_start: DateTime<Utc>,
+    /// More start times to offer after `slot_start`, in order of preference
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternative_starts: Vec<DateTime<Utc>>,
     pub duration_minutes: i32,
     pub title: Option<String>,
     pub description: Option<String>,
+    /// RRULE value (e.g. `FREQ=WEEKLY;COUNT=10`) to make the meeting recurring
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conference: Option<Conference>,
+}
+
+impl CreateProposalRequest {
+    /// The proposal this request describes, ready to be signed by `from`
+    ///
+    /// Gets a fresh nonce and expires after [`PROPOSAL_LIFETIME_DAYS`].
+    pub fn unsigned_proposal(&self, from: &str, from_pubkey: &str) -> SignedProposal {
+        let slot = ProposalSlot {
+            start: self.slot_start,
+            duration_minutes: self.duration_minutes,
+        };
+        let slots = std::iter::once(self.slot_start)
+            .chain(self.alternative_starts.iter().copied())
+            .map(|start| ProposalSlot {
+                start,
+                duration_minutes: self.duration_minutes,
+            })
+            .collect();
+        let now = Utc::now();
+        SignedProposal {
+            version: PROPOSAL_VERSION,
+            from: from.to_string(),
+            from_pubkey: from_pubkey.to_string(),
+            to: self.to_email.clone(),
+            slot,
+            slots,
+            title: self.title.clone(),
+            description: self.description.clone(),
+            recurrence: self.recurrence.clone(),
+            location: self.location.clone(),
+            conference: self.conference.clone(),
+            nonce: uuid::Uuid::new_v4().to_string(),
+            expires_at: now + Duration::days(PROPOSAL_LIFETIME_DAYS),
+            created_at: Some(now),
+            signature: String::new(),
+        }
+    }
+}
+
+/// Request to submit a proposal the sender signed themselves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmitSignedProposalRequest {
+    /// Base64 JSON of a version 2 [`SignedProposal`] from the caller
+    pub signed_proposal: String,
+    /// The proposal this one counters (must be addressed to the caller)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counter_of: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Request to answer a proposal with a different time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CounterProposalRequest {
+    pub slot_start: DateTime<Utc>,
+    /// More start times to offer after `slot_start`, in order of preference
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternative_starts: Vec<DateTime<Utc>>,
+    /// Defaults to the original proposal's duration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_minutes: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Request to move an accepted meeting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RescheduleRequest {
+    pub slot_start: DateTime<Utc>,
+    /// Defaults to the current duration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_minutes: Option<i32>,
+    /// Move the meeting even if the new slot clashes with someone's calendar
+    #[serde(default)]
+    pub force: bool,
 }
 
 /// Response after creating a proposal
@@ -109,6 +657,9 @@ pub struct CreateProposalResponse {
     pub proposal_id: String,
     pub signed_proposal: String,
     pub accept_link: String,
+    /// iTIP `METHOD:REQUEST` invitation, for recipients who don't use meetd
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub itip: Option<String>,
 }
 
 /// Proposal info for inbox listing
@@ -117,13 +668,36 @@ pub struct InboxProposal {
     pub id: String,
     pub from: String,
     pub slot: ProposalSlot,
+    /// Slots to pick from, when several are still on offer
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<ProposalSlot>,
     pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
     pub expires_at: DateTime<Utc>,
     pub status: ProposalStatus,
+    /// The proposal this one counters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counter_of: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conference: Option<Conference>,
+    /// The sender signed with a different key than the one pinned for them
+    #[serde(default)]
+    pub key_changed: bool,
+    /// What signed responses to this proposal refer to it by
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proposal_hash: Option<String>,
 }
 
 impl From<Proposal> for InboxProposal {
     fn from(p: Proposal) -> Self {
+        let options = p.open_options();
         Self {
             id: p.id,
             from: p.from_user_id,
@@ -131,9 +705,68 @@ impl From<Proposal> for InboxProposal {
                 start: p.slot_start,
                 duration_minutes: p.duration_minutes,
             },
+            options: if options.len() > 1 { options } else { vec![] },
             title: p.title,
+            description: p.description,
             expires_at: p.expires_at,
             status: p.status,
+            counter_of: p.counter_of,
+            message: p.message,
+            recurrence: p.recurrence,
+            location: p.location,
+            conference: p.conference,
+            key_changed: p.key_changed,
+            proposal_hash: p.proposal_hash,
+        }
+    }
+}
+
+/// Optional body when accepting a proposal
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AcceptProposalRequest {
+    /// Accept even if the slot clashes with someone's calendar
+    #[serde(default)]
+    pub force: bool,
+    /// Acceptance signed by a client that holds its own key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<ProposalResponse>,
+}
+
+/// Optional body when declining a proposal
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeclineProposalRequest {
+    /// Decline signed by a client that holds its own key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<ProposalResponse>,
+}
+
+/// Busy time that overlaps the slot being accepted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotConflict {
+    pub email: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// Error returned when a slot is no longer free at acceptance time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotConflictResponse {
+    /// Always `slot_conflict`
+    pub error: String,
+    pub message: String,
+    /// The proposal, when it was stored before the clash was found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proposal_id: Option<String>,
+    pub conflicts: Vec<SlotConflict>,
+}
+
+impl SlotConflictResponse {
+    pub fn new(conflicts: Vec<SlotConflict>, proposal_id: Option<String>) -> Self {
+        Self {
+            error: "slot_conflict".to_string(),
+            message: "The slot is no longer free; accept with force to book it anyway".to_string(),
+            proposal_id,
+            conflicts,
         }
     }
 }
@@ -143,6 +776,39 @@ impl From<Proposal> for InboxProposal {
 pub struct AcceptProposalResponse {
     pub status: String,
     pub event: Option<CalendarEvent>,
+    /// The signed acceptance, when the recipient's key could sign it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<ProposalResponse>,
+}
+
+/// Response after declining a proposal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeclineProposalResponse {
+    pub status: String,
+    /// The signed decline, when the recipient's key could sign it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<ProposalResponse>,
+    /// Set when the sender's server hasn't been told yet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/// New status of a withdrawn or cancelled proposal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusResponse {
+    pub status: String,
+    /// Set when the other participant's server hasn't been told yet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+impl StatusResponse {
+    pub fn new(status: &str, warning: Option<String>) -> Self {
+        Self {
+            status: status.to_string(),
+            warning,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1,6 +1,8 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 
+use super::Visibility;
+
 /// A time slot representing a period of time
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TimeSlot {
@@ -35,6 +37,9 @@ pub struct AvailableSlot {
     pub end: DateTime<Utc>,
     /// Score from 0.0 to 1.0 indicating preference (higher is better)
     pub score: f64,
+    /// Optional participants who can't make this slot
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unavailable: Vec<String>,
 }
 
 /// A busy period from the calendar
@@ -46,15 +51,228 @@ pub struct BusyPeriod {
     pub title: Option<String>,
 }
 
+/// Someone to include in an availability query
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Participant {
+    pub email: String,
+    /// Optional participants don't block a slot; they only affect ranking
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+}
+
+impl Participant {
+    pub fn required(email: impl Into<String>) -> Self {
+        Self {
+            email: email.into(),
+            optional: false,
+        }
+    }
+
+    pub fn optional(email: impl Into<String>) -> Self {
+        Self {
+            email: email.into(),
+            optional: true,
+        }
+    }
+}
+
 /// Request for availability query
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AvailabilityRequest {
-    pub with_email: String,
+    /// Single required participant (kept for older clients; prefer `participants`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub with_email: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub participants: Vec<Participant>,
     pub duration_minutes: i32,
     pub window_start: DateTime<Utc>,
     pub window_end: DateTime<Utc>,
     #[serde(default)]
     pub timezone: Option<String>,
+    /// RRULE value for a recurring meeting; slots must be free for each occurrence checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<String>,
+    /// Overrides for the requester's saved slot preferences
+    #[serde(flatten)]
+    pub preferences: SlotPreferences,
+}
+
+impl AvailabilityRequest {
+    /// Everyone to meet with, `with_email` included, without duplicates
+    ///
+    /// Someone listed as both required and optional counts as required.
+    pub fn all_participants(&self) -> Vec<Participant> {
+        let mut all: Vec<Participant> = Vec::new();
+        let listed = self
+            .with_email
+            .iter()
+            .map(Participant::required)
+            .chain(self.participants.iter().cloned());
+
+        for participant in listed {
+            match all
+                .iter_mut()
+                .find(|p| p.email.eq_ignore_ascii_case(&participant.email))
+            {
+                Some(existing) => existing.optional &= participant.optional,
+                None => all.push(participant),
+            }
+        }
+
+        all
+    }
+}
+
+/// How slots are searched for; unset fields fall back to saved preferences or defaults
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlotPreferences {
+    /// Minutes between candidate start times (default 30)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step_minutes: Option<i32>,
+    /// Free minutes required before a meeting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffer_before_minutes: Option<i32>,
+    /// Free minutes required after a meeting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffer_after_minutes: Option<i32>,
+    /// Minimum minutes between now and the start of a meeting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_notice_minutes: Option<i32>,
+    /// How many days ahead meetings may be booked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_horizon_days: Option<i32>,
+}
+
+impl SlotPreferences {
+    /// Fill unset fields from `fallback`
+    pub fn or(&self, fallback: &SlotPreferences) -> SlotPreferences {
+        SlotPreferences {
+            step_minutes: self.step_minutes.or(fallback.step_minutes),
+            buffer_before_minutes: self
+                .buffer_before_minutes
+                .or(fallback.buffer_before_minutes),
+            buffer_after_minutes: self.buffer_after_minutes.or(fallback.buffer_after_minutes),
+            min_notice_minutes: self.min_notice_minutes.or(fallback.min_notice_minutes),
+            max_horizon_days: self.max_horizon_days.or(fallback.max_horizon_days),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == SlotPreferences::default()
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        const DAY: i32 = 24 * 60;
+        if let Some(step) = self.step_minutes {
+            if !(5..=DAY).contains(&step) {
+                anyhow::bail!("step_minutes must be between 5 and {}", DAY);
+            }
+        }
+        for (name, value) in [
+            ("buffer_before_minutes", self.buffer_before_minutes),
+            ("buffer_after_minutes", self.buffer_after_minutes),
+        ] {
+            if value.is_some_and(|v| !(0..=DAY).contains(&v)) {
+                anyhow::bail!("{} must be between 0 and {}", name, DAY);
+            }
+        }
+        if self.min_notice_minutes.is_some_and(|v| v < 0) {
+            anyhow::bail!("min_notice_minutes can't be negative");
+        }
+        if self.max_horizon_days.is_some_and(|v| v < 1) {
+            anyhow::bail!("max_horizon_days must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+/// Resolved slot search rules for one query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotRules {
+    pub step: Duration,
+    pub buffer_before: Duration,
+    pub buffer_after: Duration,
+    pub min_notice: Duration,
+    pub max_horizon: Option<Duration>,
+}
+
+impl Default for SlotRules {
+    fn default() -> Self {
+        Self {
+            step: Duration::minutes(30),
+            buffer_before: Duration::zero(),
+            buffer_after: Duration::zero(),
+            min_notice: Duration::zero(),
+            max_horizon: None,
+        }
+    }
+}
+
+impl SlotRules {
+    /// Combine the requester's preferences with everyone else's
+    ///
+    /// The requester picks the step. Buffers and notice take the largest
+    /// value anyone asked for, and the horizon the shortest.
+    pub fn combine(requester: &SlotPreferences, others: &[&SlotPreferences]) -> Self {
+        let minutes = |m: i32| Duration::minutes(m as i64);
+        let defaults = SlotRules::default();
+        let everyone = || std::iter::once(requester).chain(others.iter().copied());
+
+        Self {
+            step: requester.step_minutes.map(minutes).unwrap_or(defaults.step),
+            buffer_before: everyone()
+                .filter_map(|p| p.buffer_before_minutes)
+                .max()
+                .map(minutes)
+                .unwrap_or(defaults.buffer_before),
+            buffer_after: everyone()
+                .filter_map(|p| p.buffer_after_minutes)
+                .max()
+                .map(minutes)
+                .unwrap_or(defaults.buffer_after),
+            min_notice: everyone()
+                .filter_map(|p| p.min_notice_minutes)
+                .max()
+                .map(minutes)
+                .unwrap_or(defaults.min_notice),
+            max_horizon: everyone()
+                .filter_map(|p| p.max_horizon_days)
+                .min()
+                .map(|d| Duration::days(d as i64)),
+        }
+    }
+
+    /// Narrow a search window to respect minimum notice and the maximum horizon
+    pub fn clamp_window(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> (DateTime<Utc>, DateTime<Utc>) {
+        let start = start.max(now + self.min_notice);
+        let end = match self.max_horizon {
+            Some(horizon) => end.min(now + horizon),
+            None => end,
+        };
+        (start, end)
+    }
+}
+
+/// Request for someone's busy periods, redacted by their visibility setting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BusyRequest {
+    pub email: String,
+    pub window_start: DateTime<Utc>,
+    pub window_end: DateTime<Utc>,
+}
+
+/// Response for busy period query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BusyResponse {
+    pub email: String,
+    /// How much detail the owner shares
+    pub visibility: Visibility,
+    pub busy: Vec<BusyPeriod>,
 }
 
 /// Response for availability query
@@ -1,5 +1,9 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 
+use super::{BusyPeriod, SlotPreferences, WorkingHours};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum Visibility {
@@ -29,6 +33,21 @@ impl Visibility {
             _ => None,
         }
     }
+
+    /// Strip busy periods down to what this level shares
+    pub fn redact(&self, periods: Vec<BusyPeriod>) -> Vec<BusyPeriod> {
+        periods
+            .into_iter()
+            .map(|period| BusyPeriod {
+                title: match self {
+                    Visibility::BusyOnly => None,
+                    Visibility::Masked => Some("Meeting".to_string()),
+                    Visibility::Full => period.title.or_else(|| Some("Meeting".to_string())),
+                },
+                ..period
+            })
+            .collect()
+    }
 }
 
 impl std::fmt::Display for Visibility {
@@ -37,12 +56,133 @@ impl std::fmt::Display for Visibility {
     }
 }
 
+/// Who may look up a user's free/busy time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SharingMode {
+    /// Any meetd user
+    Everyone,
+    /// People the user has proposed to, or accepted a proposal from
+    #[default]
+    Contacts,
+    /// People with an email address in the user's domain
+    Domain,
+    /// Only addresses on the allowlist
+    Allowlist,
+}
+
+impl SharingMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SharingMode::Everyone => "everyone",
+            SharingMode::Contacts => "contacts",
+            SharingMode::Domain => "domain",
+            SharingMode::Allowlist => "allowlist",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "everyone" => Some(SharingMode::Everyone),
+            "contacts" => Some(SharingMode::Contacts),
+            "domain" => Some(SharingMode::Domain),
+            "allowlist" => Some(SharingMode::Allowlist),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for SharingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Who a user shares their availability with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SharingPolicy {
+    #[serde(default)]
+    pub mode: SharingMode,
+    /// Addresses (or `@domain`s) that are always allowed, whatever the mode
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+}
+
+impl SharingPolicy {
+    /// Whether `requester` may see `owner`'s availability
+    ///
+    /// Contacts, domain and allowlist matches all go by the requester's
+    /// address, so they only count once the requester has `verified` they
+    /// own it; anyone can register an unclaimed address. `is_contact` is
+    /// only called in contacts mode, since it needs a lookup.
+    pub fn allows(
+        &self,
+        owner: &str,
+        requester: &str,
+        verified: bool,
+        is_contact: impl FnOnce() -> bool,
+    ) -> bool {
+        if owner.eq_ignore_ascii_case(requester) || self.mode == SharingMode::Everyone {
+            return true;
+        }
+        if !verified {
+            return false;
+        }
+        if self.on_allowlist(requester) {
+            return true;
+        }
+
+        match self.mode {
+            SharingMode::Everyone => true,
+            SharingMode::Contacts => is_contact(),
+            SharingMode::Domain => match (email_domain(owner), email_domain(requester)) {
+                (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+                _ => false,
+            },
+            SharingMode::Allowlist => false,
+        }
+    }
+
+    fn on_allowlist(&self, requester: &str) -> bool {
+        self.allow
+            .iter()
+            .any(|entry| match entry.strip_prefix('@') {
+                Some(domain) => {
+                    email_domain(requester).is_some_and(|d| d.eq_ignore_ascii_case(domain))
+                }
+                None => entry.eq_ignore_ascii_case(requester),
+            })
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for entry in &self.allow {
+            let valid = match entry.strip_prefix('@') {
+                Some(domain) => !domain.is_empty() && !domain.contains('@'),
+                None => email_domain(entry).is_some(),
+            };
+            if !valid {
+                anyhow::bail!(
+                    "Invalid allowlist entry '{}'. Use an email address or @domain",
+                    entry
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The part of an email address after the `@`
+fn email_domain(email: &str) -> Option<&str> {
+    email
+        .rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .filter(|d| !d.is_empty())
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: String,
     pub email: String,
-    #[serde(skip_serializing)]
-    pub google_refresh_token: Option<String>,
     pub public_key: String,
     #[serde(skip_serializing)]
     pub private_key: String,
@@ -52,7 +192,163 @@ pub struct User {
     pub webhook_url: Option<String>,
     #[serde(skip_serializing)]
     pub webhook_secret: Option<String>,
+    /// When the user takes meetings (unset = the default schedule)
+    pub working_hours: Option<WorkingHours>,
+    /// Default slot step, buffers, notice and horizon for availability searches
+    #[serde(default)]
+    pub slot_preferences: SlotPreferences,
+    /// Who may see this user's availability
+    #[serde(default)]
+    pub sharing: SharingPolicy,
     pub created_at: i64,
+    /// Whether the user has proven they own `email`
+    #[serde(default)]
+    pub email_verified: bool,
+}
+
+impl User {
+    /// Whether the server holds this user's signing key
+    ///
+    /// Users who sign locally only register their public key; the server
+    /// then verifies their proposals instead of signing them.
+    pub fn has_server_key(&self) -> bool {
+        !self.private_key.is_empty()
+    }
+
+    /// Timezone of the user's working hours (UTC when they haven't set any)
+    ///
+    /// Recurring meetings the user organises repeat in this timezone.
+    pub fn timezone(&self) -> Tz {
+        self.working_hours
+            .as_ref()
+            .and_then(|hours| hours.tz().ok())
+            .unwrap_or(Tz::UTC)
+    }
+}
+
+/// Why a signing key stopped being a user's current key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevocationReason {
+    /// Replaced by a newer key; proposals made while it was current stay valid
+    Superseded,
+    /// The private key leaked; nothing signed with it is trusted
+    Compromised,
+}
+
+impl RevocationReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RevocationReason::Superseded => "superseded",
+            RevocationReason::Compromised => "compromised",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "superseded" => Some(RevocationReason::Superseded),
+            "compromised" => Some(RevocationReason::Compromised),
+            _ => None,
+        }
+    }
+}
+
+/// One of a user's signing keys and when it was their current key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserKey {
+    pub public_key: String,
+    pub valid_from: DateTime<Utc>,
+    /// Unset while the key is current
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revocation_reason: Option<RevocationReason>,
+}
+
+impl UserKey {
+    /// Whether a proposal made at `at` may be signed with this key
+    ///
+    /// Windows are stored to the second, so a replaced key stays valid
+    /// through the second it was replaced in.
+    pub fn valid_at(&self, at: DateTime<Utc>) -> bool {
+        self.revocation_reason != Some(RevocationReason::Compromised)
+            && self.valid_from.timestamp() <= at.timestamp()
+            && self
+                .valid_until
+                .map_or(true, |until| at.timestamp() <= until.timestamp())
+    }
+}
+
+/// Every signing key a user has had, as served by their home server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PubkeyHistoryResponse {
+    pub email: String,
+    /// The key new proposals are signed with
+    pub public_key: String,
+    /// Every key the user has had, oldest first
+    pub keys: Vec<UserKey>,
+}
+
+/// Where a pinned contact key came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    /// The sender is a user of this server
+    Directory,
+    /// Looked up on the sender's home server
+    Server,
+    /// Only the proposal itself vouched for it
+    Proposal,
+    /// Set by the user with `meetd contacts trust`
+    Manual,
+}
+
+impl KeySource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeySource::Directory => "directory",
+            KeySource::Server => "server",
+            KeySource::Proposal => "proposal",
+            KeySource::Manual => "manual",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "directory" => Some(KeySource::Directory),
+            "server" => Some(KeySource::Server),
+            "proposal" => Some(KeySource::Proposal),
+            "manual" => Some(KeySource::Manual),
+            _ => None,
+        }
+    }
+}
+
+/// The signing key a user expects proposals from a contact to carry
+///
+/// The first key seen for an address is pinned; later proposals signed
+/// with another key are flagged until the user trusts it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContactKey {
+    pub email: String,
+    pub public_key: String,
+    pub source: KeySource,
+    /// The server the key was looked up on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    pub pinned_at: DateTime<Utc>,
+}
+
+impl ContactKey {
+    pub fn new(email: &str, public_key: &str, source: KeySource, server: Option<String>) -> Self {
+        Self {
+            email: email.to_lowercase(),
+            public_key: public_key.to_string(),
+            source,
+            server,
+            pinned_at: Utc::now(),
+        }
+    }
 }
 
 /// User info returned by API (excludes sensitive fields)
@@ -116,4 +412,86 @@ impl LocalConfig {
         std::fs::write(&path, content)?;
         Ok(())
     }
+
+    /// Where a locally held signing key is kept (next to the config)
+    pub fn signing_key_path() -> std::path::PathBuf {
+        Self::config_path().with_file_name("signing.key")
+    }
+
+    /// The locally held signing key, if `meetd login --local-key` created one
+    pub fn load_signing_key() -> anyhow::Result<Option<crate::crypto::Keypair>> {
+        let path = Self::signing_key_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)?;
+        crate::crypto::Keypair::from_private_key_base64(content.trim()).map(Some)
+    }
+
+    /// Store a signing key, readable only by its owner
+    pub fn save_signing_key(keypair: &crate::crypto::Keypair) -> anyhow::Result<()> {
+        use std::io::Write;
+
+        let path = Self::signing_key_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&path)?;
+        writeln!(file, "{}", keypair.private_key_base64())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sharing_policy_allows() {
+        let never = || panic!("contact lookup not expected");
+        let owner = "alice@acme.com";
+
+        let domain = SharingPolicy {
+            mode: SharingMode::Domain,
+            allow: vec!["bob@partner.com".to_string(), "@vendor.io".to_string()],
+        };
+        assert!(domain.allows(owner, "carol@ACME.com", true, never));
+        assert!(domain.allows(owner, "bob@partner.com", true, never));
+        assert!(domain.allows(owner, "dan@vendor.io", true, never));
+        assert!(!domain.allows(owner, "eve@evil.com", true, never));
+
+        let contacts = SharingPolicy::default();
+        assert!(contacts.allows(owner, "eve@evil.com", true, || true));
+        assert!(!contacts.allows(owner, "eve@evil.com", true, || false));
+        assert!(contacts.allows(owner, owner, false, never));
+
+        let allowlist = SharingPolicy {
+            mode: SharingMode::Allowlist,
+            allow: vec![],
+        };
+        assert!(!allowlist.allows(owner, "carol@acme.com", true, never));
+
+        // Unverified addresses match nothing but the everyone mode
+        assert!(!domain.allows(owner, "carol@acme.com", false, never));
+        assert!(!domain.allows(owner, "bob@partner.com", false, never));
+        assert!(!contacts.allows(owner, "eve@evil.com", false, never));
+        let everyone = SharingPolicy {
+            mode: SharingMode::Everyone,
+            allow: vec![],
+        };
+        assert!(everyone.allows(owner, "eve@evil.com", false, never));
+        assert!(SharingPolicy {
+            mode: SharingMode::Allowlist,
+            allow: vec!["nope".to_string()],
+        }
+        .validate()
+        .is_err());
+    }
 }
@@ -0,0 +1,417 @@
+use anyhow::{Context, Result};
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use super::BusyPeriod;
+
+/// A range of local wall-clock time, written as `"09:00-17:00"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeRange {
+    pub fn parse(s: &str) -> Result<Self> {
+        let (start, end) = s
+            .trim()
+            .split_once('-')
+            .with_context(|| format!("Invalid time range '{}'. Use: HH:MM-HH:MM", s))?;
+        let start = NaiveTime::parse_from_str(start.trim(), "%H:%M")
+            .with_context(|| format!("Invalid time '{}'", start.trim()))?;
+        let end = NaiveTime::parse_from_str(end.trim(), "%H:%M")
+            .with_context(|| format!("Invalid time '{}'", end.trim()))?;
+        if end <= start {
+            anyhow::bail!("Time range '{}' must end after it starts", s);
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl std::fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+impl Serialize for TimeRange {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeRange {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        TimeRange::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Working time ranges for each day of the week (empty = day off)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WeekSchedule {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mon: Vec<TimeRange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tue: Vec<TimeRange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wed: Vec<TimeRange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub thu: Vec<TimeRange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fri: Vec<TimeRange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sat: Vec<TimeRange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sun: Vec<TimeRange>,
+}
+
+impl WeekSchedule {
+    pub fn get(&self, day: Weekday) -> &[TimeRange] {
+        match day {
+            Weekday::Mon => &self.mon,
+            Weekday::Tue => &self.tue,
+            Weekday::Wed => &self.wed,
+            Weekday::Thu => &self.thu,
+            Weekday::Fri => &self.fri,
+            Weekday::Sat => &self.sat,
+            Weekday::Sun => &self.sun,
+        }
+    }
+
+    pub fn get_mut(&mut self, day: Weekday) -> &mut Vec<TimeRange> {
+        match day {
+            Weekday::Mon => &mut self.mon,
+            Weekday::Tue => &mut self.tue,
+            Weekday::Wed => &mut self.wed,
+            Weekday::Thu => &mut self.thu,
+            Weekday::Fri => &mut self.fri,
+            Weekday::Sat => &mut self.sat,
+            Weekday::Sun => &mut self.sun,
+        }
+    }
+}
+
+/// When someone is available for meetings, in their own timezone
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkingHours {
+    /// IANA timezone, e.g. "Europe/Berlin"
+    pub timezone: String,
+    pub days: WeekSchedule,
+    /// Daily break that is never offered, e.g. "12:00-13:00"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lunch: Option<TimeRange>,
+}
+
+impl Default for WorkingHours {
+    /// Monday to Friday, 09:00-17:00 UTC
+    fn default() -> Self {
+        let nine_to_five = vec![TimeRange {
+            start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        }];
+        Self {
+            timezone: "UTC".to_string(),
+            days: WeekSchedule {
+                mon: nine_to_five.clone(),
+                tue: nine_to_five.clone(),
+                wed: nine_to_five.clone(),
+                thu: nine_to_five.clone(),
+                fri: nine_to_five,
+                sat: Vec::new(),
+                sun: Vec::new(),
+            },
+            lunch: None,
+        }
+    }
+}
+
+impl WorkingHours {
+    /// The default schedule in a given timezone
+    pub fn default_in(timezone: &str) -> Result<Self> {
+        let hours = Self {
+            timezone: timezone.to_string(),
+            ..Self::default()
+        };
+        hours.tz()?;
+        Ok(hours)
+    }
+
+    pub fn tz(&self) -> Result<Tz> {
+        parse_timezone(&self.timezone)
+    }
+
+    /// Check the timezone and ranges make sense
+    pub fn validate(&self) -> Result<()> {
+        self.tz()?;
+        for day in WEEKDAYS {
+            let mut ranges = self.days.get(day).to_vec();
+            ranges.sort_by_key(|r| r.start);
+            if ranges.windows(2).any(|w| w[1].start < w[0].end) {
+                anyhow::bail!("Working hours on {} overlap", day);
+            }
+        }
+        Ok(())
+    }
+
+    /// Convert a UTC time to this schedule's local time
+    pub fn local(&self, time: DateTime<Utc>) -> DateTime<Tz> {
+        time.with_timezone(&self.tz().unwrap_or(Tz::UTC))
+    }
+
+    /// Working periods overlapping `start..end`, in UTC, with lunch removed
+    pub fn working_periods(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let tz = self.tz().unwrap_or(Tz::UTC);
+        let mut periods = Vec::new();
+
+        // Local dates can straddle the UTC window by up to a day either side
+        let first = start.with_timezone(&tz).date_naive() - Duration::days(1);
+        let last = end.with_timezone(&tz).date_naive() + Duration::days(1);
+
+        let mut date = first;
+        while date <= last {
+            for range in self.days.get(date.weekday()) {
+                for (from, to) in self.split_around_lunch(range) {
+                    let from = local_to_utc(&tz, date.and_time(from));
+                    let to = local_to_utc(&tz, date.and_time(to));
+                    let from = from.max(start);
+                    let to = to.min(end);
+                    if from < to {
+                        periods.push((from, to));
+                    }
+                }
+            }
+            date = match date.succ_opt() {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        periods.sort();
+        periods
+    }
+
+    /// Time outside working hours within `start..end`, as busy periods
+    pub fn off_hours(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<BusyPeriod> {
+        let mut off = Vec::new();
+        let mut current = start;
+
+        for (from, to) in self.working_periods(start, end) {
+            if from > current {
+                off.push(BusyPeriod {
+                    start: current,
+                    end: from,
+                    title: None,
+                });
+            }
+            current = current.max(to);
+        }
+
+        if current < end {
+            off.push(BusyPeriod {
+                start: current,
+                end,
+                title: None,
+            });
+        }
+
+        off
+    }
+
+    /// Whether `start..end` lies entirely within a single working period
+    pub fn contains(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> bool {
+        self.working_periods(start, end)
+            .iter()
+            .any(|(from, to)| *from <= start && *to >= end)
+    }
+
+    fn split_around_lunch(&self, range: &TimeRange) -> Vec<(NaiveTime, NaiveTime)> {
+        match self.lunch {
+            Some(lunch) if lunch.start < range.end && lunch.end > range.start => {
+                let mut parts = Vec::new();
+                if range.start < lunch.start {
+                    parts.push((range.start, lunch.start));
+                }
+                if lunch.end < range.end {
+                    parts.push((lunch.end, range.end));
+                }
+                parts
+            }
+            _ => vec![(range.start, range.end)],
+        }
+    }
+
+    /// Set ranges from a spec like `"mon-fri=09:00-17:00"` or `"sat=10:00-12:00,14:00-16:00"`
+    ///
+    /// Days not mentioned in any spec are left untouched.
+    pub fn apply_day_spec(&mut self, spec: &str) -> Result<()> {
+        let (days, ranges) = spec
+            .split_once('=')
+            .with_context(|| format!("Invalid hours '{}'. Use: mon-fri=09:00-17:00", spec))?;
+
+        let ranges = if ranges.trim().eq_ignore_ascii_case("off") {
+            Vec::new()
+        } else {
+            ranges
+                .split(',')
+                .map(TimeRange::parse)
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        for day in parse_days(days)? {
+            *self.days.get_mut(day) = ranges.clone();
+        }
+        Ok(())
+    }
+}
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// Parse an IANA timezone name
+pub fn parse_timezone(name: &str) -> Result<Tz> {
+    name.parse::<Tz>().map_err(|_| {
+        anyhow::anyhow!(
+            "Unknown timezone '{}'. Use an IANA name like Europe/Berlin",
+            name
+        )
+    })
+}
+
+/// Parse `mon`, `mon-fri` or `sat,sun`
+fn parse_days(s: &str) -> Result<Vec<Weekday>> {
+    let mut days = Vec::new();
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let mut day = parse_day(from)?;
+                let to = parse_day(to)?;
+                days.push(day);
+                while day != to {
+                    day = day.succ();
+                    days.push(day);
+                }
+            }
+            None => days.push(parse_day(part)?),
+        }
+    }
+    Ok(days)
+}
+
+fn parse_day(s: &str) -> Result<Weekday> {
+    s.trim()
+        .parse::<Weekday>()
+        .map_err(|_| anyhow::anyhow!("Invalid day '{}'. Use: mon, tue, ... sun", s.trim()))
+}
+
+/// Local wall-clock time to UTC, moving forward out of DST gaps
+///
+/// A time that happens twice (when clocks go back) is the first of the two.
+pub fn local_to_utc(tz: &Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(dt) => dt.with_timezone(&Utc),
+        LocalResult::Ambiguous(earliest, _) => earliest.with_timezone(&Utc),
+        LocalResult::None => tz
+            .from_local_datetime(&(local + Duration::hours(1)))
+            .earliest()
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or_else(|| local.and_utc()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn berlin() -> WorkingHours {
+        let mut hours = WorkingHours::default_in("Europe/Berlin").unwrap();
+        hours.lunch = Some(TimeRange::parse("12:00-13:00").unwrap());
+        hours
+    }
+
+    #[test]
+    fn test_working_periods_use_local_time() {
+        // Tuesday 2026-02-03; Berlin is UTC+1 in winter
+        let periods =
+            berlin().working_periods(utc("2026-02-03T00:00:00Z"), utc("2026-02-04T00:00:00Z"));
+        assert_eq!(
+            periods,
+            vec![
+                (utc("2026-02-03T08:00:00Z"), utc("2026-02-03T11:00:00Z")),
+                (utc("2026-02-03T12:00:00Z"), utc("2026-02-03T16:00:00Z")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_working_periods_follow_dst() {
+        // Summer time: UTC+2
+        let periods =
+            berlin().working_periods(utc("2026-07-07T00:00:00Z"), utc("2026-07-08T00:00:00Z"));
+        assert_eq!(periods[0].0, utc("2026-07-07T07:00:00Z"));
+    }
+
+    #[test]
+    fn test_off_hours_cover_weekend() {
+        let hours = berlin();
+        // Saturday and Sunday are entirely off
+        let off = hours.off_hours(utc("2026-02-07T00:00:00Z"), utc("2026-02-09T00:00:00Z"));
+        assert_eq!(off.len(), 1);
+        assert!(!hours.contains(utc("2026-02-07T10:00:00Z"), utc("2026-02-07T10:30:00Z")));
+        assert!(hours.contains(utc("2026-02-09T08:00:00Z"), utc("2026-02-09T08:30:00Z")));
+        assert!(!hours.contains(utc("2026-02-09T10:30:00Z"), utc("2026-02-09T11:30:00Z")));
+    }
+
+    #[test]
+    fn test_apply_day_spec() {
+        let mut hours = WorkingHours::default();
+        hours.apply_day_spec("fri=09:00-12:00").unwrap();
+        hours
+            .apply_day_spec("sat,sun=10:00-11:00,15:00-16:00")
+            .unwrap();
+        hours.apply_day_spec("mon-tue=off").unwrap();
+
+        assert!(hours.days.mon.is_empty());
+        assert_eq!(hours.days.wed.len(), 1);
+        assert_eq!(hours.days.fri[0].to_string(), "09:00-12:00");
+        assert_eq!(hours.days.sun.len(), 2);
+
+        assert!(hours.apply_day_spec("fri=17:00-09:00").is_err());
+        assert!(hours.apply_day_spec("someday=09:00-10:00").is_err());
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let json = r#"{"timezone":"America/New_York","days":{"mon":["08:30-16:30"]},"lunch":"12:00-12:30"}"#;
+        let hours: WorkingHours = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            hours.days.mon[0].start,
+            NaiveTime::from_hms_opt(8, 30, 0).unwrap()
+        );
+        assert_eq!(serde_json::to_string(&hours).unwrap(), json);
+        assert!(WorkingHours::default_in("Mars/Olympus").is_err());
+    }
+}
@@ -9,11 +9,13 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use uuid::Uuid;
 
+use super::proposals::{answer_pending, book_meeting, meeting_occurrences, sign_response};
 use super::ErrorResponse;
-use crate::calendar::{CalendarProvider, GoogleCalendar};
 use crate::crypto::PublicKey;
 use crate::models::{
-    AcceptProposalResponse, CalendarEvent, Proposal, ProposalStatus, SignedProposal,
+    AcceptProposalResponse, CalendarEvent, ContactKey, KeySource, Proposal, ProposalStatus,
+    PubkeyHistoryResponse, RevocationReason, SignedProposal, SlotConflictResponse, User, UserKey,
+    PROPOSAL_VERSION,
 };
 use crate::server::middleware::AuthenticatedUser;
 use crate::server::AppState;
@@ -46,11 +48,199 @@ pub async fn get_pubkey(State(state): State<Arc<AppState>>, Path(email): Path<St
     }
 }
 
+/// Get every signing key a user has had, with validity windows
+///
+/// Lets anyone check a proposal made before the sender rotated their key.
+pub async fn get_pubkey_history(
+    State(state): State<Arc<AppState>>,
+    Path(email): Path<String>,
+) -> Response {
+    let user = match state.db.get_user_by_email(&email) {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse::new("User not found")),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
+        }
+    };
+
+    match state.db.get_user_keys(&user.id) {
+        Ok(keys) => Json(PubkeyHistoryResponse {
+            email: user.email,
+            public_key: user.public_key,
+            keys,
+        })
+        .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+/// Checks every incoming signed proposal has to pass before it's stored
+///
+/// Verifies the signature, validity and expiry, and uses up the nonce.
+/// Senders on this server must have signed with a key they held at the
+/// time; they're returned so the caller can link the proposal to them.
+pub(crate) fn check_incoming(
+    state: &AppState,
+    recipient: &User,
+    signed: &SignedProposal,
+) -> Result<Option<User>, (StatusCode, String)> {
+    if signed.to != recipient.email {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Proposal is not addressed to you".to_string(),
+        ));
+    }
+
+    // Older signing payloads are ambiguous, so they're only good for verifying old proposals
+    if signed.version != PROPOSAL_VERSION {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Signed proposals must use version {}", PROPOSAL_VERSION),
+        ));
+    }
+
+    let pub_key = PublicKey::from_base64(&signed.from_pubkey).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid public key: {}", e),
+        )
+    })?;
+    match pub_key.verify_proposal(signed) {
+        Ok(true) => {}
+        Ok(false) => return Err((StatusCode::BAD_REQUEST, "Invalid signature".to_string())),
+        Err(e) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Verification error: {}", e),
+            ))
+        }
+    }
+
+    signed
+        .validate()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if signed.expires_at < Utc::now() {
+        return Err((StatusCode::BAD_REQUEST, "Proposal has expired".to_string()));
+    }
+
+    let sender = state
+        .db
+        .get_user_by_email(&signed.from)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if let Some(sender) = &sender {
+        let key_valid = state
+            .db
+            .is_signing_key_valid(&sender.id, &signed.from_pubkey, signed.signed_at())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        if !key_valid {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Proposal is not signed with a key {} held at the time",
+                    signed.from
+                ),
+            ));
+        }
+    }
+
+    // Check nonce hasn't been used
+    match state.db.is_nonce_used(&signed.nonce) {
+        Ok(false) => {}
+        Ok(true) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Nonce already used (replay attack?)".to_string(),
+            ))
+        }
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+    state
+        .db
+        .use_nonce(&signed.nonce)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(sender)
+}
+
+/// Check the sender's key against the one `recipient` pinned for them
+///
+/// The first key seen from an address is pinned, along with where it came
+/// from. `history` is the sender's key history when the key came from this
+/// server's directory or the sender's own server (empty otherwise): if it
+/// shows the pinned key was superseded by a rotation, the new key is pinned
+/// in its place. Returns whether `signed` carries a key the pinned one and
+/// the history don't account for.
+pub(crate) fn check_pinned_key(
+    state: &AppState,
+    recipient: &User,
+    signed: &SignedProposal,
+    source: KeySource,
+    server: Option<String>,
+    history: &[UserKey],
+) -> Result<bool, (StatusCode, String)> {
+    let pinned = state
+        .db
+        .get_contact_key(&recipient.id, &signed.from)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let pin = match &pinned {
+        Some(pinned) if pinned.public_key == signed.from_pubkey => return Ok(false),
+        Some(pinned) => rotated_out(history, &pinned.public_key, &signed.from_pubkey),
+        None => true,
+    };
+    if !pin {
+        return Ok(true);
+    }
+
+    let key = ContactKey::new(&signed.from, &signed.from_pubkey, source, server);
+    state
+        .db
+        .pin_contact_key(&recipient.id, &key)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(false)
+}
+
+/// Whether `history` shows `old` being superseded by `new`, directly or
+/// through later rotations
+///
+/// A key retired as compromised doesn't count: whoever held it may be the
+/// one who rotated it.
+fn rotated_out(history: &[UserKey], old: &str, new: &str) -> bool {
+    let Some(old_index) = history.iter().position(|key| key.public_key == old) else {
+        return false;
+    };
+    let superseded = history[old_index].revocation_reason == Some(RevocationReason::Superseded);
+    superseded
+        && history[old_index + 1..]
+            .iter()
+            .any(|key| key.public_key == new)
+}
+
 #[derive(Deserialize)]
 pub struct ReceiveProposalRequest {
     pub signed_proposal: String,
     #[serde(default)]
     pub action: Option<String>, // "accept" to auto-accept
+    /// Which offered slot to accept, for multi-slot proposals
+    #[serde(default)]
+    pub option: Option<usize>,
+    /// Auto-accept even if the slot clashes with someone's calendar
+    #[serde(default)]
+    pub force: bool,
 }
 
 #[derive(Serialize)]
@@ -58,6 +248,8 @@ pub struct ReceiveProposalResponse {
     pub proposal_id: String,
     pub status: String,
     pub event: Option<CalendarEvent>,
+    /// The sender signed with a different key than the one pinned for them
+    pub key_changed: bool,
 }
 
 /// Receive a signed proposal from another agent
@@ -93,78 +285,36 @@ pub async fn receive_proposal(
         }
     };
 
-    // Verify the proposal is for this user
-    if signed.to != user.email {
-        return (
-            StatusCode::FORBIDDEN,
-            Json(ErrorResponse::new("Proposal is not addressed to you")),
-        )
-            .into_response();
-    }
-
-    // Verify signature
-    let pub_key = match PublicKey::from_base64(&signed.from_pubkey) {
-        Ok(pk) => pk,
-        Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(format!("Invalid public key: {}", e))),
-            )
-                .into_response();
+    let sender = match check_incoming(&state, &user, &signed) {
+        Ok(sender) => sender,
+        Err((status, error)) => {
+            return (status, Json(ErrorResponse::new(error))).into_response();
         }
     };
-
-    match pub_key.verify_proposal(&signed) {
-        Ok(false) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new("Invalid signature")),
-            )
-                .into_response();
-        }
-        Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(format!("Verification error: {}", e))),
-            )
-                .into_response();
+    let (source, history) = match &sender {
+        Some(sender) => match state.db.get_user_keys(&sender.id) {
+            Ok(history) => (KeySource::Directory, history),
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse::new(e.to_string())),
+                )
+                    .into_response();
+            }
+        },
+        None => (KeySource::Proposal, Vec::new()),
+    };
+    let key_changed = match check_pinned_key(&state, &user, &signed, source, None, &history) {
+        Ok(key_changed) => key_changed,
+        Err((status, error)) => {
+            return (status, Json(ErrorResponse::new(error))).into_response();
         }
-        Ok(true) => {}
-    }
-
-    // Check expiration
-    if signed.expires_at < Utc::now() {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse::new("Proposal has expired")),
-        )
-            .into_response();
-    }
-
-    // Check nonce hasn't been used
-    if let Ok(true) = state.db.is_nonce_used(&signed.nonce) {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse::new("Nonce already used (replay attack?)")),
-        )
-            .into_response();
-    }
-
-    // Mark nonce as used
-    if let Err(e) = state.db.use_nonce(&signed.nonce) {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse::new(e.to_string())),
-        )
-            .into_response();
-    }
+    };
 
-    // Find or create sender user ID
-    let from_user_id = if let Ok(Some(sender)) = state.db.get_user_by_email(&signed.from) {
-        sender.id
-    } else {
-        // External sender - use email as ID
-        signed.from.clone()
+    // External senders are stored by email
+    let from_user_id = match &sender {
+        Some(sender) => sender.id.clone(),
+        None => signed.from.clone(),
     };
 
     // Create proposal in database
@@ -173,18 +323,8 @@ pub async fn receive_proposal(
         &Uuid::new_v4().to_string().replace('-', "")[..12]
     );
     let proposal = Proposal {
-        id: proposal_id.clone(),
-        from_user_id: from_user_id.clone(),
-        to_email: user.email.clone(),
-        slot_start: signed.slot.start,
-        duration_minutes: signed.slot.duration_minutes,
-        title: signed.title.clone(),
-        description: signed.description.clone(),
-        nonce: signed.nonce.clone(),
-        expires_at: signed.expires_at,
-        signature: signed.signature.clone(),
-        status: ProposalStatus::Pending,
-        created_at: Utc::now().timestamp(),
+        key_changed,
+        ..Proposal::from_signed(proposal_id.clone(), from_user_id, &signed)
     };
 
     if let Err(e) = state.db.create_proposal(&proposal) {
@@ -198,56 +338,87 @@ pub async fn receive_proposal(
             .into_response();
     }
 
-    // If action is "accept", auto-accept the proposal
-    if req.action.as_deref() == Some("accept") {
-        let end_time = proposal.slot_start + Duration::minutes(proposal.duration_minutes as i64);
-        let mut calendar_link = None;
+    // If action is "accept", auto-accept the proposal, unless the sender's key
+    // changed and the user should take a look first
+    if req.action.as_deref() == Some("accept") && !key_changed {
+        let slot = match proposal.pick_option(req.option) {
+            Ok(slot) => slot,
+            Err(e) => {
+                return (StatusCode::BAD_REQUEST, Json(ErrorResponse::new(e))).into_response();
+            }
+        };
+        let end_time = slot.start + Duration::minutes(slot.duration_minutes as i64);
 
-        // Create calendar event
-        if let Some(ref token) = user.google_refresh_token {
-            if let Ok(cal) =
-                GoogleCalendar::new(&state.google_client_id, &state.google_client_secret, token)
-                    .await
-            {
-                if let Ok(event) = cal
-                    .create_event(
-                        proposal.title.as_deref().unwrap_or("Meeting"),
-                        proposal.description.as_deref(),
-                        proposal.slot_start,
-                        end_time,
-                        Some(&signed.from),
-                    )
-                    .await
-                {
-                    calendar_link = event.html_link;
-                }
+        // The proposal stays pending in the inbox if the slot has been taken since
+        if !req.force {
+            let participants: Vec<&User> = std::iter::once(&user).chain(sender.as_ref()).collect();
+            let conflicts = state
+                .slot_conflicts(
+                    &participants,
+                    &meeting_occurrences(&proposal, &slot, sender.as_ref()),
+                )
+                .await;
+            if !conflicts.is_empty() {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(SlotConflictResponse::new(conflicts, Some(proposal_id))),
+                )
+                    .into_response();
             }
         }
 
-        // Update status
-        if let Err(e) = state
-            .db
-            .update_proposal_status(&proposal_id, ProposalStatus::Accepted)
+        let response = match sign_response(
+            &user,
+            &proposal,
+            &signed.from,
+            ProposalStatus::Accepted,
+            Some(slot.clone()),
+            None,
+        ) {
+            Ok(response) => response,
+            Err((status, error)) => {
+                return (status, Json(ErrorResponse::new(error))).into_response();
+            }
+        };
+
+        if let Err((status, error)) = answer_pending(&state, &proposal_id, ProposalStatus::Accepted)
         {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse::new(e.to_string())),
-            )
-                .into_response();
+            return (status, Json(ErrorResponse::new(error))).into_response();
+        }
+
+        if !proposal.options.is_empty() {
+            if let Err(e) =
+                state
+                    .db
+                    .choose_proposal_option(&proposal_id, req.option.unwrap_or(0), &slot)
+            {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse::new(e.to_string())),
+                )
+                    .into_response();
+            }
         }
 
+        let links = book_meeting(&state, &proposal, &slot).await;
+        let calendar_link = links.recipient;
+
         // Send webhook to sender if they're in our system
-        if let Ok(Some(sender)) = state.db.get_user_by_email(&signed.from) {
+        if let Some(sender) = sender {
             if let (Some(webhook_url), Some(webhook_secret)) =
                 (sender.webhook_url, sender.webhook_secret)
             {
                 let event = WebhookEvent::new(
                     WebhookEventType::ProposalAccepted,
-                    WebhookEventData::proposal_accepted(
-                        proposal_id.clone(),
-                        user.email.clone(),
-                        calendar_link.clone(),
-                    ),
+                    WebhookEventData {
+                        response: response.clone(),
+                        ..WebhookEventData::proposal_accepted(
+                            proposal_id.clone(),
+                            user.email.clone(),
+                            slot.clone(),
+                            links.sender,
+                        )
+                    },
                 );
 
                 let webhook_client = WebhookClient::new();
@@ -266,10 +437,11 @@ pub async fn receive_proposal(
             status: "accepted".to_string(),
             event: Some(CalendarEvent {
                 title: proposal.title.unwrap_or_else(|| "Meeting".to_string()),
-                start: proposal.slot_start,
+                start: slot.start,
                 end: end_time,
                 calendar_link,
             }),
+            response,
         })
         .into_response();
     }
@@ -278,6 +450,7 @@ pub async fn receive_proposal(
         proposal_id,
         status: "pending".to_string(),
         event: None,
+        key_changed,
     })
     .into_response()
 }
@@ -4,14 +4,16 @@ use axum::{
     response::{IntoResponse, Redirect, Response},
     Json,
 };
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
 use uuid::Uuid;
 
+use super::calendars::{link_calendar_account, prepare_calendar_config};
 use super::ErrorResponse;
-use crate::crypto::{generate_api_key, hash_api_key, Keypair};
-use crate::models::{User, Visibility};
+use crate::crypto::{generate_api_key, generate_reply_token, hash_api_key, Keypair, PublicKey};
+use crate::models::{CalendarConfig, RevocationReason, User, Visibility};
 use crate::server::middleware::AuthenticatedUser;
 use crate::server::AppState;
 
@@ -32,7 +34,89 @@ pub async fn register(
     State(state): State<Arc<AppState>>,
     Json(req): Json<RegisterRequest>,
 ) -> Response {
-    match register_user_internal(&state, &req.email, &req.refresh_token).await {
+    if let Err((status, error)) = check_unregistered(&state, &req.email) {
+        return (status, Json(ErrorResponse::new(error))).into_response();
+    }
+
+    let config = CalendarConfig::Google {
+        refresh_token: req.refresh_token,
+        email: Some(req.email.clone()),
+    };
+
+    // Nothing ties the refresh token to `email`, so it isn't verified
+    match register_user_internal(&state, &req.email, config, false).await {
+        Ok((user_id, api_key)) => Json(RegisterResponse { user_id, api_key }).into_response(),
+        Err(error) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(error)),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RegisterCalDavRequest {
+    pub email: String,
+    /// Calendar collection URL, e.g. https://caldav.fastmail.com/dav/calendars/user/me@fastmail.com/Default/
+    pub url: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// Register a new user backed by a CalDAV calendar instead of Google
+pub async fn register_caldav(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<RegisterCalDavRequest>,
+) -> Response {
+    let config = CalendarConfig::Caldav {
+        url: req.url,
+        username: req.username,
+        password: req.password,
+    };
+
+    register_with_config(&state, &req.email, config).await
+}
+
+#[derive(Deserialize)]
+pub struct RegisterIcsRequest {
+    pub email: String,
+    /// ICS feed URL, or a file path inside the server's MEETD_ICS_DIR
+    pub source: String,
+    /// File (inside MEETD_ICS_DIR) that accepted meetings are appended to
+    pub write_path: Option<String>,
+}
+
+/// Register a new user backed by an iCalendar file or feed
+pub async fn register_ics(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<RegisterIcsRequest>,
+) -> Response {
+    let config = CalendarConfig::Ics {
+        source: req.source,
+        write_path: req.write_path,
+    };
+
+    register_with_config(&state, &req.email, config).await
+}
+
+/// Check a client-supplied calendar config, then register the user with it
+async fn register_with_config(state: &AppState, email: &str, config: CalendarConfig) -> Response {
+    if let Err((status, error)) = check_unregistered(state, email) {
+        return (status, Json(ErrorResponse::new(error))).into_response();
+    }
+
+    let config = match prepare_calendar_config(state, config).await {
+        Ok(config) => config,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
+        }
+    };
+
+    match register_user_internal(state, email, config, false).await {
         Ok((user_id, api_key)) => Json(RegisterResponse { user_id, api_key }).into_response(),
         Err(error) => (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -42,6 +126,25 @@ pub async fn register(
     }
 }
 
+/// Refuse to register an email that already has an account
+///
+/// Nothing proves the caller of a register endpoint owns `email`, so taking
+/// over an existing account would hand out its API key. Owners link more
+/// calendars through `/v1/calendars` instead.
+fn check_unregistered(state: &AppState, email: &str) -> Result<(), (StatusCode, String)> {
+    match state.db.get_user_by_email(email) {
+        Ok(None) => Ok(()),
+        Ok(Some(_)) => Err((
+            StatusCode::CONFLICT,
+            format!(
+                "{} is already registered; link another calendar with POST /v1/calendars",
+                email
+            ),
+        )),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
 #[derive(Deserialize)]
 pub struct GoogleRedirectQuery {
     /// CLI callback URL for redirecting after auth (e.g., http://localhost:8899)
@@ -243,10 +346,13 @@ pub async fn google_callback(
         }
     };
 
-    let refresh_token = tokens.refresh_token.unwrap_or_default();
+    let config = CalendarConfig::Google {
+        refresh_token: tokens.refresh_token.unwrap_or_default(),
+        email: Some(email.clone()),
+    };
 
-    // Create/update user
-    let reg_result = register_user_internal(&state, &email, &refresh_token).await;
+    // Create/update user; Google has confirmed they own the address
+    let reg_result = register_user_internal(&state, &email, config, true).await;
 
     match reg_result {
         Ok((user_id, api_key)) => {
@@ -379,13 +485,26 @@ pub async fn google_callback(
 }
 
 /// Internal function to register/update a user, returns (user_id, api_key) or error
+///
+/// The calendar account is linked to the user (or its credentials refreshed,
+/// if already linked) and becomes their primary calendar. Existing users are
+/// only updated when the caller has proven they own `email` (Google OAuth),
+/// which `verified` records.
 async fn register_user_internal(
     state: &AppState,
     email: &str,
-    refresh_token: &str,
+    calendar: CalendarConfig,
+    verified: bool,
 ) -> Result<(String, String), String> {
     // Check if user already exists
     if let Ok(Some(existing)) = state.db.get_user_by_email(email) {
+        if verified {
+            state
+                .db
+                .mark_email_verified(&existing.id)
+                .map_err(|e| e.to_string())?;
+        }
+
         // Generate new API key for existing user
         let api_key = generate_api_key();
         let api_key_hash = hash_api_key(&api_key).map_err(|e| e.to_string())?;
@@ -395,9 +514,7 @@ async fn register_user_internal(
             .update_user_api_key_hash(&existing.id, &api_key_hash)
             .map_err(|e| e.to_string())?;
 
-        state
-            .db
-            .update_user_refresh_token(&existing.id, Some(refresh_token))
+        link_calendar_account(state, &existing.id, calendar, None, true)
             .map_err(|e| e.to_string())?;
 
         return Ok((existing.id, api_key));
@@ -414,21 +531,158 @@ async fn register_user_internal(
     let user = User {
         id: user_id.clone(),
         email: email.to_string(),
-        google_refresh_token: Some(refresh_token.to_string()),
         public_key: keypair.public_key_base64(),
         private_key: keypair.private_key_base64(),
         api_key_hash,
         visibility: Visibility::BusyOnly,
         webhook_url: None,
         webhook_secret: None,
+        working_hours: None,
+        slot_preferences: Default::default(),
+        sharing: Default::default(),
         created_at: Utc::now().timestamp(),
+        email_verified: verified,
     };
 
     state.db.create_user(&user).map_err(|e| e.to_string())?;
+    link_calendar_account(state, &user_id, calendar, None, true).map_err(|e| e.to_string())?;
 
     Ok((user_id, api_key))
 }
 
+/// How long an email verification link works
+const EMAIL_VERIFICATION_HOURS: i64 = 24;
+
+#[derive(Serialize)]
+pub struct EmailVerificationResponse {
+    pub email: String,
+    /// Already verified, so no link was sent
+    pub verified: bool,
+}
+
+/// Mail the user a link that proves they own their address
+///
+/// Accounts registered with CalDAV or ICS start unverified, and don't count
+/// for anyone's contacts, domain or allowlist sharing until they follow it.
+pub async fn request_email_verification(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+) -> Response {
+    if user.email_verified {
+        return Json(EmailVerificationResponse {
+            email: user.email,
+            verified: true,
+        })
+        .into_response();
+    }
+
+    let Some(mailer) = &state.mailer else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ErrorResponse::new(
+                "This server can't send mail; sign in with Google to verify your address",
+            )),
+        )
+            .into_response();
+    };
+
+    let token = generate_reply_token();
+    let expires_at = Utc::now() + Duration::hours(EMAIL_VERIFICATION_HOURS);
+    if let Err(e) =
+        state
+            .db
+            .create_email_verification(&user.id, &hash_token(&token), expires_at.timestamp())
+    {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+            .into_response();
+    }
+
+    let link = format!(
+        "{}/auth/email/confirm?token={}",
+        state.server_url.trim_end_matches('/'),
+        token
+    );
+    let mail = mailer.mail(
+        &user.email,
+        "Confirm your meetd email address",
+        &format!(
+            "Open this link to confirm that {} is your address:\n\n{}\n\n\
+             It works once, for {} hours. If you didn't ask for it, ignore this mail.",
+            user.email, link, EMAIL_VERIFICATION_HOURS
+        ),
+    );
+    if let Err(e) = mailer.send(&mail).await {
+        return (
+            StatusCode::BAD_GATEWAY,
+            Json(ErrorResponse::new(format!(
+                "Failed to send verification mail: {}",
+                e
+            ))),
+        )
+            .into_response();
+    }
+
+    Json(EmailVerificationResponse {
+        email: user.email,
+        verified: false,
+    })
+    .into_response()
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmEmailQuery {
+    pub token: String,
+}
+
+/// Follow a verification link from `request_email_verification`
+pub async fn confirm_email(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ConfirmEmailQuery>,
+) -> Response {
+    let user_id = match state
+        .db
+        .take_email_verification(&hash_token(&query.token), Utc::now().timestamp())
+    {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse::new(
+                    "This link is invalid or has expired; request a new one",
+                )),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
+        }
+    };
+
+    if let Err(e) = state.db.mark_email_verified(&user_id) {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+            .into_response();
+    }
+
+    "Your email address is confirmed. You can close this page.".into_response()
+}
+
+/// Verification tokens are stored hashed, like API keys
+///
+/// They're random and single-use, so a plain SHA-256 is enough.
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
 #[derive(Serialize)]
 pub struct RotateKeyResponse {
     pub api_key: String,
@@ -461,3 +715,151 @@ pub async fn rotate_api_key(
 
     Json(RotateKeyResponse { api_key }).into_response()
 }
+
+#[derive(Deserialize)]
+pub struct SigningKeyRequest {
+    /// Base64 Ed25519 public key; the client keeps the private key
+    pub public_key: String,
+}
+
+#[derive(Serialize)]
+pub struct SigningKeyResponse {
+    pub public_key: String,
+}
+
+/// Register a signing key held by the client
+///
+/// From then on the server no longer signs proposals for the user; they
+/// sign them locally and submit them to `/v1/proposals/signed`. The
+/// previous key is kept in the user's key history as superseded.
+pub async fn set_signing_key(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Json(req): Json<SigningKeyRequest>,
+) -> Response {
+    if let Err(e) = PublicKey::from_base64(&req.public_key) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(format!("Invalid public key: {}", e))),
+        )
+            .into_response();
+    }
+
+    // Logging in again with the same local key changes nothing
+    if req.public_key == user.public_key && !user.has_server_key() {
+        return Json(SigningKeyResponse {
+            public_key: req.public_key,
+        })
+        .into_response();
+    }
+
+    if let Err(e) =
+        state
+            .db
+            .rotate_user_key(&user.id, &req.public_key, "", RevocationReason::Superseded)
+    {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+            .into_response();
+    }
+
+    Json(SigningKeyResponse {
+        public_key: req.public_key,
+    })
+    .into_response()
+}
+
+#[derive(Deserialize)]
+pub struct RotateSigningKeyRequest {
+    /// New client-held public key; the server generates one if unset
+    #[serde(default)]
+    pub public_key: Option<String>,
+    /// Why the current key is retired (default: superseded)
+    #[serde(default)]
+    pub reason: Option<RevocationReason>,
+}
+
+#[derive(Serialize)]
+pub struct RotateSigningKeyResponse {
+    pub public_key: String,
+    /// The key that was retired
+    pub previous_key: String,
+    pub reason: RevocationReason,
+}
+
+/// Replace the user's signing key
+///
+/// The old key stays in the key history, so proposals it signed while it
+/// was current still verify, unless it is revoked as compromised.
+pub async fn rotate_signing_key(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Json(req): Json<RotateSigningKeyRequest>,
+) -> Response {
+    let reason = req.reason.unwrap_or(RevocationReason::Superseded);
+
+    let (public_key, private_key) = match req.public_key {
+        Some(public_key) => {
+            if let Err(e) = PublicKey::from_base64(&public_key) {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(format!("Invalid public key: {}", e))),
+                )
+                    .into_response();
+            }
+            (public_key, String::new())
+        }
+        None if !user.has_server_key() => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new(
+                    "Your signing key is held by your client; send the new `public_key`",
+                )),
+            )
+                .into_response();
+        }
+        None => match Keypair::generate() {
+            Ok(keypair) => (keypair.public_key_base64(), keypair.private_key_base64()),
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse::new(format!(
+                        "Failed to generate keypair: {}",
+                        e
+                    ))),
+                )
+                    .into_response();
+            }
+        },
+    };
+
+    if public_key == user.public_key {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(
+                "The new key must differ from the current one",
+            )),
+        )
+            .into_response();
+    }
+
+    if let Err(e) = state
+        .db
+        .rotate_user_key(&user.id, &public_key, &private_key, reason)
+    {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+            .into_response();
+    }
+
+    Json(RotateSigningKeyResponse {
+        public_key,
+        previous_key: user.public_key,
+        reason,
+    })
+    .into_response()
+}
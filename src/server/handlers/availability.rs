@@ -9,14 +9,30 @@ use serde::Serialize;
 use std::sync::Arc;
 
 use super::ErrorResponse;
-use crate::calendar::{intersect_availability, score_slot, CalendarProvider, GoogleCalendar};
-use crate::models::{AvailabilityRequest, AvailableSlot};
+use crate::calendar::rrule::RecurrenceRule;
+use crate::calendar::{
+    can_attend, intersect_availability, occurrences, score_slot, CHECKED_OCCURRENCES,
+};
+use crate::models::{
+    AvailabilityRequest, AvailableSlot, BusyRequest, BusyResponse, Participant, SlotRules,
+    TimeSlot, User, Visibility, WorkingHours,
+};
 use crate::server::middleware::AuthenticatedUser;
 use crate::server::AppState;
 
+/// Most people (besides the requester) one query may include
+const MAX_PARTICIPANTS: usize = 20;
+
+/// Longest window someone's busy periods can be listed for at once
+const MAX_BUSY_WINDOW_DAYS: i64 = 31;
+
 #[derive(Serialize)]
 pub struct AvailabilityResponse {
     pub slots: Vec<AvailableSlot>,
+    /// Participants whose calendars weren't checked (not on meetd, not sharing
+    /// with you, or their calendar couldn't be read)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unchecked: Vec<String>,
 }
 
 /// Query availability for a meeting
@@ -25,86 +41,244 @@ pub async fn query_availability(
     Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
     Json(req): Json<AvailabilityRequest>,
 ) -> Response {
+    // The requester's own schedule, or the default one in the timezone they asked for
+    let requester_hours = match &user.working_hours {
+        Some(hours) => hours.clone(),
+        None => match WorkingHours::default_in(req.timezone.as_deref().unwrap_or("UTC")) {
+            Ok(hours) => hours,
+            Err(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(e.to_string())),
+                )
+                    .into_response();
+            }
+        },
+    };
+    let mut working_hours = vec![requester_hours];
+
+    // Per-request options override the requester's saved preferences
+    if let Err(e) = req.preferences.validate() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+            .into_response();
+    }
+    let requester_preferences = req.preferences.or(&user.slot_preferences);
+
+    let recurrence = match req.recurrence.as_deref().map(RecurrenceRule::parse) {
+        Some(Ok(rule)) => Some(rule),
+        Some(Err(e)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new(format!("Invalid recurrence: {}", e))),
+            )
+                .into_response();
+        }
+        None => None,
+    };
+
+    // Everyone except the requester, who always has to attend
+    let participants: Vec<Participant> = req
+        .all_participants()
+        .into_iter()
+        .filter(|p| !p.email.eq_ignore_ascii_case(&user.email))
+        .collect();
+    if participants.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new("At least one participant is required")),
+        )
+            .into_response();
+    }
+    if participants.len() > MAX_PARTICIPANTS {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(format!(
+                "At most {} participants are allowed",
+                MAX_PARTICIPANTS
+            ))),
+        )
+            .into_response();
+    }
+
+    // Participants who aren't registered here, or don't share with the
+    // requester, are assumed to be free
+    let registered: Vec<(Participant, Option<User>)> = participants
+        .into_iter()
+        .map(|p| {
+            let found = state
+                .db
+                .get_user_by_email(&p.email)
+                .ok()
+                .flatten()
+                .filter(|other| state.shares_availability(other, &user));
+            (p, found)
+        })
+        .collect();
+    let mut unchecked: Vec<String> = registered
+        .iter()
+        .filter(|(_, found)| found.is_none())
+        .map(|(p, _)| p.email.clone())
+        .collect();
+
+    let rules = SlotRules::combine(
+        &requester_preferences,
+        &registered
+            .iter()
+            .filter_map(|(_, u)| u.as_ref().map(|u| &u.slot_preferences))
+            .collect::<Vec<_>>(),
+    );
+
+    // Drop anything inside the notice period or beyond the horizon
+    let now = Utc::now();
+    let (window_start, window_end) = rules.clamp_window(req.window_start, req.window_end, now);
+    if window_start >= window_end {
+        return Json(AvailabilityResponse {
+            slots: vec![],
+            unchecked,
+        })
+        .into_response();
+    }
+
+    // Busy time just outside the window still counts towards buffers, and a
+    // recurring meeting needs everyone free for its later occurrences too
+    let last_slot = TimeSlot::new(
+        window_end,
+        window_end + chrono::Duration::minutes(req.duration_minutes as i64),
+    );
+    // The requester organises the meeting, so it repeats in their timezone
+    let organiser_tz = user.timezone();
+    let recurring_end = occurrences(
+        &last_slot,
+        recurrence.as_ref(),
+        &organiser_tz,
+        CHECKED_OCCURRENCES,
+    )
+    .last()
+    .map_or(window_end, |slot| slot.end);
+    let busy_start = window_start - rules.buffer_before;
+    let busy_end = recurring_end + rules.buffer_after;
+
     // Get requester's busy periods
-    let requester_busy = if let Some(ref token) = user.google_refresh_token {
-        match GoogleCalendar::new(&state.google_client_id, &state.google_client_secret, token).await
-        {
-            Ok(cal) => match cal.get_busy_periods(req.window_start, req.window_end).await {
-                Ok(busy) => busy,
-                Err(e) => {
-                    return (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        Json(ErrorResponse::new(format!(
-                            "Failed to get your calendar: {}",
-                            e
-                        ))),
-                    )
-                        .into_response();
-                }
-            },
+    let requester_busy = match state.calendar_for(&user).await {
+        Ok(Some(cal)) => match cal.get_busy_periods(busy_start, busy_end).await {
+            Ok(busy) => busy,
             Err(e) => {
                 return (
                     StatusCode::INTERNAL_SERVER_ERROR,
                     Json(ErrorResponse::new(format!(
-                        "Failed to connect to calendar: {}",
+                        "Failed to get your calendar: {}",
                         e
                     ))),
                 )
                     .into_response();
             }
+        },
+        Ok(None) => vec![],
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(format!(
+                    "Failed to connect to calendar: {}",
+                    e
+                ))),
+            )
+                .into_response();
         }
-    } else {
-        vec![]
     };
 
-    // Get target user's busy periods (if they exist in our system)
-    let target_busy = if let Ok(Some(target)) = state.db.get_user_by_email(&req.with_email) {
-        if let Some(ref token) = target.google_refresh_token {
-            match GoogleCalendar::new(&state.google_client_id, &state.google_client_secret, token)
-                .await
-            {
-                Ok(cal) => cal
-                    .get_busy_periods(req.window_start, req.window_end)
-                    .await
-                    .unwrap_or_default(),
-                Err(_) => vec![],
+    // Get other participants' busy periods (if they exist in our system)
+    let mut required_busy = vec![requester_busy];
+    let mut optional = Vec::new();
+    for (participant, found) in &registered {
+        let Some(other) = found else {
+            continue;
+        };
+        // An unreadable calendar isn't a free one; report it like any other unchecked one
+        let busy = match state.calendar_for(other).await {
+            Ok(Some(cal)) => cal.get_busy_periods(busy_start, busy_end).await,
+            Ok(None) => Err(anyhow::anyhow!("no calendar linked")),
+            Err(e) => Err(e),
+        };
+        let busy = match busy {
+            Ok(busy) => busy,
+            Err(e) => {
+                tracing::warn!("Couldn't check {}'s calendar: {}", other.email, e);
+                unchecked.push(participant.email.clone());
+                continue;
             }
+        };
+        let hours = other.working_hours.clone().unwrap_or_default();
+
+        if participant.optional {
+            optional.push((participant.email.as_str(), busy, hours));
         } else {
-            vec![]
+            required_busy.push(busy);
+            working_hours.push(hours);
         }
-    } else {
-        // Target not in system - only use requester's availability
-        vec![]
-    };
+    }
 
-    // Find intersection of availability
+    // Find slots where every required participant is free
     let available = intersect_availability(
-        &requester_busy,
-        &target_busy,
-        req.window_start,
-        req.window_end,
+        &required_busy,
+        window_start,
+        window_end,
         req.duration_minutes,
+        &working_hours,
+        &rules,
     );
 
-    // Score and sort slots
-    let now = Utc::now();
+    // Score slots and note which optional participants would miss them (any
+    // occurrence, for recurring meetings)
     let mut scored_slots: Vec<AvailableSlot> = available
         .into_iter()
-        .map(|slot| {
-            let score = score_slot(&slot, now);
-            AvailableSlot {
+        .filter_map(|slot| {
+            let repeats = occurrences(
+                &slot,
+                recurrence.as_ref(),
+                &organiser_tz,
+                CHECKED_OCCURRENCES,
+            );
+            let everyone_free = required_busy
+                .iter()
+                .zip(&working_hours)
+                .all(|(busy, hours)| {
+                    repeats
+                        .iter()
+                        .all(|occurrence| can_attend(occurrence, busy, Some(hours), &rules))
+                });
+            if !everyone_free {
+                return None;
+            }
+
+            let score = score_slot(&slot, now, &working_hours);
+            let unavailable = optional
+                .iter()
+                .filter(|(_, busy, hours)| {
+                    !repeats
+                        .iter()
+                        .all(|occurrence| can_attend(occurrence, busy, Some(hours), &rules))
+                })
+                .map(|(email, _, _)| email.to_string())
+                .collect();
+            Some(AvailableSlot {
                 start: slot.start,
                 end: slot.end,
                 score,
-            }
+                unavailable,
+            })
         })
         .collect();
 
-    // Sort by score descending
+    // Most optional attendees first, then by score descending
     scored_slots.sort_by(|a, b| {
-        b.score
-            .partial_cmp(&a.score)
-            .unwrap_or(std::cmp::Ordering::Equal)
+        a.unavailable.len().cmp(&b.unavailable.len()).then(
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal),
+        )
     });
 
     // Limit to top 20 slots
@@ -112,6 +286,98 @@ pub async fn query_availability(
 
     Json(AvailabilityResponse {
         slots: scored_slots,
+        unchecked,
+    })
+    .into_response()
+}
+
+/// List someone's busy periods, with as much detail as their visibility allows
+pub async fn query_busy(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Json(req): Json<BusyRequest>,
+) -> Response {
+    if req.window_end <= req.window_start {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new("window_end must be after window_start")),
+        )
+            .into_response();
+    }
+    if req.window_end - req.window_start > chrono::Duration::days(MAX_BUSY_WINDOW_DAYS) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(format!(
+                "Window can be at most {} days",
+                MAX_BUSY_WINDOW_DAYS
+            ))),
+        )
+            .into_response();
+    }
+
+    // Not saying which of the two applies keeps registration private
+    let owner = match state.db.get_user_by_email(&req.email) {
+        Ok(Some(owner)) if state.shares_availability(&owner, &user) => owner,
+        Ok(_) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse::new(
+                    "User not found, or not sharing their calendar with you",
+                )),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
+        }
+    };
+
+    // People always see their own calendar in full
+    let visibility = if owner.id == user.id {
+        Visibility::Full
+    } else {
+        owner.visibility
+    };
+
+    let busy = match state.calendar_for(&owner).await {
+        Ok(Some(cal)) => {
+            // Only look up event titles when they're actually going to be shared
+            let result = match visibility {
+                Visibility::Full => cal.get_busy_events(req.window_start, req.window_end).await,
+                _ => cal.get_busy_periods(req.window_start, req.window_end).await,
+            };
+            match result {
+                Ok(busy) => busy,
+                Err(e) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(ErrorResponse::new(format!("Failed to get calendar: {}", e))),
+                    )
+                        .into_response();
+                }
+            }
+        }
+        Ok(None) => vec![],
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(format!(
+                    "Failed to connect to calendar: {}",
+                    e
+                ))),
+            )
+                .into_response();
+        }
+    };
+
+    Json(BusyResponse {
+        email: owner.email,
+        visibility,
+        busy: visibility.redact(busy),
     })
     .into_response()
 }
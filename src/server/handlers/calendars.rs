@@ -0,0 +1,249 @@
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::ErrorResponse;
+use crate::calendar::IcsCalendar;
+use crate::models::{CalendarAccount, CalendarAccountInfo, CalendarConfig};
+use crate::server::middleware::AuthenticatedUser;
+use crate::server::AppState;
+
+#[derive(Serialize)]
+pub struct CalendarsResponse {
+    pub calendars: Vec<CalendarAccountInfo>,
+}
+
+/// List the user's linked calendar accounts
+pub async fn list_calendars(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+) -> Response {
+    match state.db.get_calendar_accounts(&user.id) {
+        Ok(accounts) => Json(CalendarsResponse {
+            calendars: accounts.into_iter().map(Into::into).collect(),
+        })
+        .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LinkCalendarRequest {
+    #[serde(flatten)]
+    pub config: CalendarConfig,
+    pub label: Option<String>,
+    /// Make this the account new events are created in
+    #[serde(default)]
+    pub primary: bool,
+}
+
+/// Link an additional calendar account
+pub async fn link_calendar(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Json(req): Json<LinkCalendarRequest>,
+) -> Response {
+    let config = match prepare_calendar_config(&state, req.config).await {
+        Ok(config) => config,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
+        }
+    };
+
+    match link_calendar_account(&state, &user.id, config, req.label, req.primary) {
+        Ok(account) => (
+            StatusCode::CREATED,
+            Json(CalendarAccountInfo::from(account)),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+/// Unlink a calendar account
+pub async fn unlink_calendar(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Path(id): Path<String>,
+) -> Response {
+    match state.db.delete_calendar_account(&user.id, &id) {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new("Calendar account not found")),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+/// Make a calendar account the primary one
+pub async fn set_primary_calendar(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Path(id): Path<String>,
+) -> Response {
+    match state.db.set_primary_calendar_account(&user.id, &id) {
+        Ok(true) => list_calendars(State(state), Extension(AuthenticatedUser(user))).await,
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new("Calendar account not found")),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+/// Validate a calendar config supplied by a client and check that it works
+///
+/// Local ICS paths are resolved inside MEETD_ICS_DIR, and a one-day busy
+/// query makes sure the credentials are good before anything is stored.
+pub(crate) async fn prepare_calendar_config(
+    state: &AppState,
+    config: CalendarConfig,
+) -> anyhow::Result<CalendarConfig> {
+    let config = match config {
+        CalendarConfig::Ics { source, write_path } => {
+            let source = if IcsCalendar::is_remote(&source) {
+                source
+            } else {
+                resolve_ics_path(state, &source, true)?
+            };
+
+            // Local sources double as the place new events are written to
+            let write_path = match write_path {
+                Some(path) => Some(resolve_ics_path(state, &path, false)?),
+                None if !IcsCalendar::is_remote(&source) => Some(source.clone()),
+                None => None,
+            };
+
+            CalendarConfig::Ics { source, write_path }
+        }
+        other => other,
+    };
+
+    let provider_name = match &config {
+        CalendarConfig::Google { .. } => "Google",
+        CalendarConfig::Caldav { .. } => "CalDAV",
+        CalendarConfig::Ics { .. } => "ICS",
+    };
+
+    let now = Utc::now();
+    let calendar = state.calendars.connect(&config).await?;
+    calendar
+        .get_busy_periods(now, now + chrono::Duration::days(1))
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to access {} calendar: {}", provider_name, e))?;
+
+    Ok(config)
+}
+
+/// Link a calendar account to a user, refreshing the credentials if it is already linked
+///
+/// The first account a user links is always primary.
+pub(crate) fn link_calendar_account(
+    state: &AppState,
+    user_id: &str,
+    config: CalendarConfig,
+    label: Option<String>,
+    primary: bool,
+) -> anyhow::Result<CalendarAccount> {
+    let accounts = state.db.get_calendar_accounts(user_id)?;
+
+    let account = match accounts.iter().find(|a| a.config.same_account(&config)) {
+        Some(existing) => {
+            state
+                .db
+                .update_calendar_account_config(&existing.id, &config)?;
+            CalendarAccount {
+                config,
+                label: label.or_else(|| existing.label.clone()),
+                ..existing.clone()
+            }
+        }
+        None => {
+            let account = CalendarAccount {
+                id: format!("cal_{}", &uuid::Uuid::new_v4().simple().to_string()[..12]),
+                user_id: user_id.to_string(),
+                config,
+                label,
+                is_primary: accounts.is_empty(),
+                selection: Default::default(),
+                created_at: Utc::now().timestamp(),
+            };
+            state.db.create_calendar_account(&account)?;
+            account
+        }
+    };
+
+    if primary && !account.is_primary {
+        state
+            .db
+            .set_primary_calendar_account(user_id, &account.id)?;
+        return Ok(CalendarAccount {
+            is_primary: true,
+            ..account
+        });
+    }
+
+    Ok(account)
+}
+
+/// Resolve a local ICS path, making sure it stays inside MEETD_ICS_DIR
+fn resolve_ics_path(state: &AppState, path: &str, must_exist: bool) -> anyhow::Result<String> {
+    let dir = state
+        .ics_dir
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Local ICS files are not enabled on this server"))?;
+    let dir = dir
+        .canonicalize()
+        .map_err(|e| anyhow::anyhow!("MEETD_ICS_DIR is not accessible: {}", e))?;
+
+    let candidate = dir.join(path);
+    let resolved = if must_exist || candidate.exists() {
+        candidate
+            .canonicalize()
+            .map_err(|_| anyhow::anyhow!("ICS file not found: {}", path))?
+    } else {
+        let parent = candidate
+            .parent()
+            .and_then(|p| p.canonicalize().ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid ICS path: {}", path))?;
+        let file_name = candidate
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("Invalid ICS path: {}", path))?;
+        parent.join(file_name)
+    };
+
+    if !resolved.starts_with(&dir) {
+        anyhow::bail!("ICS files must be inside the server's ICS directory");
+    }
+
+    Ok(resolved.to_string_lossy().into_owned())
+}
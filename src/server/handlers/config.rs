@@ -9,7 +9,10 @@ use std::sync::Arc;
 
 use super::ErrorResponse;
 use crate::crypto::generate_webhook_secret;
-use crate::models::Visibility;
+use crate::models::{
+    CalendarAccountInfo, CalendarConfig, CalendarSelection, SharingPolicy, SlotPreferences,
+    Visibility, WorkingHours,
+};
 use crate::server::middleware::AuthenticatedUser;
 use crate::server::AppState;
 
@@ -18,23 +21,77 @@ pub struct ConfigResponse {
     pub visibility: String,
     pub webhook_url: Option<String>,
     pub public_key: String,
+    /// Linked calendar accounts and the calendars used within each
+    pub calendars: Vec<CalendarAccountInfo>,
+    /// Working hours (null = Monday to Friday, 09:00-17:00 UTC)
+    pub working_hours: Option<WorkingHours>,
+    /// Default slot step, buffers, notice and horizon
+    pub slot_preferences: SlotPreferences,
+    /// Who may see this user's availability
+    pub sharing: SharingPolicy,
+    /// Whether the user has proven they own their email address
+    pub email_verified: bool,
 }
 
 /// Get current user config
 pub async fn get_config(
+    State(state): State<Arc<AppState>>,
     Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
-) -> Json<ConfigResponse> {
+) -> Response {
+    let accounts = match state.db.get_calendar_accounts(&user.id) {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
+        }
+    };
+
     Json(ConfigResponse {
         visibility: user.visibility.as_str().to_string(),
         webhook_url: user.webhook_url,
         public_key: user.public_key,
+        calendars: accounts.into_iter().map(Into::into).collect(),
+        working_hours: user.working_hours,
+        slot_preferences: user.slot_preferences,
+        sharing: user.sharing,
+        email_verified: user.email_verified,
     })
+    .into_response()
 }
 
 #[derive(Deserialize)]
 pub struct UpdateConfigRequest {
     pub visibility: Option<String>,
     pub webhook_url: Option<String>,
+    pub calendars: Option<CalendarSelectionUpdate>,
+    /// New working hours; `null` resets to the default schedule
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub working_hours: Option<Option<WorkingHours>>,
+    /// New slot preferences; replaces the saved ones entirely
+    pub slot_preferences: Option<SlotPreferences>,
+    /// New sharing policy; replaces the saved one entirely
+    pub sharing: Option<SharingPolicy>,
+}
+
+/// Distinguish an explicit `null` from a missing field
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Calendars to use within one linked account
+#[derive(Deserialize)]
+pub struct CalendarSelectionUpdate {
+    /// Calendar account ID (defaults to the primary account)
+    pub account: Option<String>,
+    #[serde(flatten)]
+    pub selection: CalendarSelection,
 }
 
 #[derive(Serialize)]
@@ -42,6 +99,11 @@ pub struct UpdateConfigResponse {
     pub visibility: String,
     pub webhook_url: Option<String>,
     pub webhook_secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calendars: Option<CalendarAccountInfo>,
+    pub working_hours: Option<WorkingHours>,
+    pub slot_preferences: SlotPreferences,
+    pub sharing: SharingPolicy,
 }
 
 /// Update user config
@@ -53,6 +115,10 @@ pub async fn update_config(
     let mut new_visibility = user.visibility;
     let mut new_webhook_url = user.webhook_url.clone();
     let mut new_webhook_secret = user.webhook_secret.clone();
+    let mut updated_calendars = None;
+    let mut new_working_hours = user.working_hours.clone();
+    let mut new_slot_preferences = user.slot_preferences.clone();
+    let mut new_sharing = user.sharing.clone();
 
     // Update visibility if provided
     if let Some(vis_str) = req.visibility {
@@ -104,10 +170,147 @@ pub async fn update_config(
         new_webhook_secret = secret;
     }
 
+    // Update working hours if provided
+    if let Some(working_hours) = req.working_hours {
+        if let Some(hours) = &working_hours {
+            if let Err(e) = hours.validate() {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(e.to_string())),
+                )
+                    .into_response();
+            }
+        }
+
+        if let Err(e) = state
+            .db
+            .update_user_working_hours(&user.id, working_hours.as_ref())
+        {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
+        }
+
+        new_working_hours = working_hours;
+    }
+
+    // Update slot preferences if provided
+    if let Some(preferences) = req.slot_preferences {
+        if let Err(e) = preferences.validate() {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
+        }
+
+        if let Err(e) = state
+            .db
+            .update_user_slot_preferences(&user.id, &preferences)
+        {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
+        }
+
+        new_slot_preferences = preferences;
+    }
+
+    // Update sharing policy if provided
+    if let Some(sharing) = req.sharing {
+        if let Err(e) = sharing.validate() {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
+        }
+
+        if let Err(e) = state.db.update_user_sharing(&user.id, &sharing) {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
+        }
+
+        new_sharing = sharing;
+    }
+
+    // Update calendar selection if provided
+    if let Some(update) = req.calendars {
+        match update_calendar_selection(&state, &user.id, update).await {
+            Ok(account) => updated_calendars = Some(account),
+            Err((status, error)) => {
+                return (status, Json(ErrorResponse::new(error))).into_response();
+            }
+        }
+    }
+
     Json(UpdateConfigResponse {
         visibility: new_visibility.as_str().to_string(),
         webhook_url: new_webhook_url,
         webhook_secret: new_webhook_secret,
+        calendars: updated_calendars,
+        working_hours: new_working_hours,
+        slot_preferences: new_slot_preferences,
+        sharing: new_sharing,
     })
     .into_response()
 }
+
+/// Check and store the calendars used within one of the user's accounts
+async fn update_calendar_selection(
+    state: &AppState,
+    user_id: &str,
+    update: CalendarSelectionUpdate,
+) -> Result<CalendarAccountInfo, (StatusCode, String)> {
+    let accounts = state
+        .db
+        .get_calendar_accounts(user_id)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let account = match &update.account {
+        Some(id) => accounts.into_iter().find(|a| &a.id == id),
+        None => accounts.into_iter().find(|a| a.is_primary),
+    }
+    .ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            "Calendar account not found".to_string(),
+        )
+    })?;
+
+    if !matches!(account.config, CalendarConfig::Google { .. }) && !update.selection.is_default() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Choosing calendars is only supported for Google accounts".to_string(),
+        ));
+    }
+
+    // Make sure every chosen calendar is readable before relying on it
+    let now = chrono::Utc::now();
+    let calendar = state
+        .calendars
+        .connect_with(&account.config, &update.selection)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    calendar
+        .get_busy_periods(now, now + chrono::Duration::days(1))
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    state
+        .db
+        .update_calendar_selection(&account.id, &update.selection)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(CalendarAccountInfo::from(crate::models::CalendarAccount {
+        selection: update.selection,
+        ..account
+    }))
+}
@@ -0,0 +1,145 @@
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::ErrorResponse;
+use crate::crypto::PublicKey;
+use crate::models::{ContactKey, KeySource};
+use crate::server::middleware::AuthenticatedUser;
+use crate::server::AppState;
+
+#[derive(Serialize)]
+pub struct ContactsResponse {
+    /// The key pinned for each sender, by email
+    pub contacts: Vec<ContactKey>,
+}
+
+/// List the keys pinned for the people who've sent proposals
+pub async fn list_contacts(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+) -> Response {
+    match state.db.get_contact_keys(&user.id) {
+        Ok(contacts) => Json(ContactsResponse { contacts }).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct TrustContactRequest {
+    /// Key to pin; the contact's current key when omitted
+    #[serde(default)]
+    pub public_key: Option<String>,
+}
+
+/// Pin a key for a contact, replacing the one pinned before
+///
+/// Without a key, the contact's current key is looked up on this server or
+/// on their home server.
+pub async fn trust_contact(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Path(email): Path<String>,
+    body: Option<Json<TrustContactRequest>>,
+) -> Response {
+    let req = body.map(|Json(req)| req).unwrap_or_default();
+    let key = match req.public_key {
+        Some(public_key) => {
+            if let Err(e) = PublicKey::from_base64(&public_key) {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(format!("Invalid public key: {}", e))),
+                )
+                    .into_response();
+            }
+            ContactKey::new(&email, &public_key, KeySource::Manual, None)
+        }
+        None => match current_key(&state, &email).await {
+            Ok(key) => key,
+            Err((status, error)) => {
+                return (status, Json(ErrorResponse::new(error))).into_response();
+            }
+        },
+    };
+
+    match state.db.pin_contact_key(&user.id, &key) {
+        Ok(()) => Json(key).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+/// The key `email` signs new proposals with, from wherever it's published
+async fn current_key(state: &AppState, email: &str) -> Result<ContactKey, (StatusCode, String)> {
+    if let Some(contact) = state
+        .db
+        .get_user_by_email(email)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    {
+        return Ok(ContactKey::new(
+            email,
+            &contact.public_key,
+            KeySource::Directory,
+            None,
+        ));
+    }
+
+    let home = state
+        .resolver
+        .home_server(email)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("{:#}", e)))?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!(
+                    "No meetd server publishes {}'s key; pass the key to trust",
+                    email
+                ),
+            )
+        })?;
+    let history = state
+        .resolver
+        .signing_keys(&home, email)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("{:#}", e)))?;
+    Ok(ContactKey::new(
+        email,
+        &history.public_key,
+        KeySource::Server,
+        Some(home.api_base),
+    ))
+}
+
+/// Forget the key pinned for a contact; the next one seen is pinned instead
+pub async fn untrust_contact(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Path(email): Path<String>,
+) -> Response {
+    match state.db.unpin_contact_key(&user.id, &email) {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(format!("No key pinned for {}", email))),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+            .into_response(),
+    }
+}
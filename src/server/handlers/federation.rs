@@ -0,0 +1,634 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::agent::{check_incoming, check_pinned_key};
+use super::proposals::{
+    announce_proposal, answer_pending, book_meeting, move_meeting, notify_participants,
+    notify_user, reopen_countered, reschedule_conflicts, unbook_meeting,
+};
+use super::ErrorResponse;
+use crate::crypto::PublicKey;
+use crate::federation::{FederationClient, FederationMessage, FederationReceipt, ServerMetadata};
+use crate::models::{
+    KeySource, Proposal, ProposalResponse, ProposalSlot, ProposalStatus, RemoteProposal,
+    SignedProposal,
+};
+use crate::server::AppState;
+use crate::webhook::{WebhookEvent, WebhookEventData, WebhookEventType};
+
+/// Describe this server to other meetd servers
+pub async fn metadata(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ServerMetadata>, (StatusCode, Json<ErrorResponse>)> {
+    state.metadata().map(Json).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(e.to_string())),
+        )
+    })
+}
+
+/// Receive a proposal, or news about one, from another meetd server
+///
+/// Proposals are trusted on their signature plus the signature of the
+/// server the sender's domain publishes; responses, withdrawals,
+/// cancellations and reschedules on the reply token the two servers share
+/// for the proposal they're about.
+pub async fn receive(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let message: FederationMessage = match serde_json::from_str(&body) {
+        Ok(message) => message,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new(format!("Invalid message: {}", e))),
+            )
+                .into_response()
+        }
+    };
+
+    let result = match message {
+        FederationMessage::Proposal {
+            signed_proposal,
+            proposal_id,
+            reply_to,
+            reply_token,
+            counter_of,
+            message,
+        } => {
+            receive_proposal(
+                &state,
+                &headers,
+                &body,
+                &signed_proposal,
+                RemoteProposal {
+                    server: reply_to.trim_end_matches('/').to_string(),
+                    id: proposal_id,
+                    reply_token,
+                },
+                counter_of,
+                message,
+            )
+            .await
+        }
+        FederationMessage::Response {
+            proposal_id,
+            reply_token,
+            status,
+            option,
+            response,
+        } => receive_response(&state, &proposal_id, &reply_token, status, option, response).await,
+        FederationMessage::Withdraw {
+            proposal_id,
+            reply_token,
+        } => receive_withdraw(&state, &proposal_id, &reply_token),
+        FederationMessage::Cancel {
+            proposal_id,
+            reply_token,
+        } => receive_cancel(&state, &proposal_id, &reply_token).await,
+        FederationMessage::Reschedule {
+            proposal_id,
+            reply_token,
+            slot,
+            force,
+        } => receive_reschedule(&state, &proposal_id, &reply_token, slot, force).await,
+    };
+
+    match result {
+        Ok(receipt) => Json(receipt).into_response(),
+        Err((status, error)) => (status, Json(ErrorResponse::new(error))).into_response(),
+    }
+}
+
+/// Check a proposal came from the server its sender's domain advertises
+///
+/// The sender's domain must publish a meetd server (or be pinned to one in
+/// `MEETD_PEERS`) whose key signed the message; anyone could sign a
+/// proposal with a key of their own. Returns the sender's server.
+async fn check_origin(
+    state: &AppState,
+    headers: &HeaderMap,
+    body: &str,
+    from_email: &str,
+    reply_to: &str,
+) -> Result<ServerMetadata, (StatusCode, String)> {
+    let home = state.resolver.home_server(from_email).await.map_err(|e| {
+        (
+            StatusCode::BAD_GATEWAY,
+            format!("Couldn't find {}'s server: {:#}", from_email, e),
+        )
+    })?;
+    // A failed lookup looks the same as no server; either way there's no key to check
+    let Some(home) = home else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!(
+                "No meetd server found for {}, so the message can't be verified",
+                from_email
+            ),
+        ));
+    };
+
+    if home.api_base != reply_to {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("{}'s server is {}", from_email, home.api_base),
+        ));
+    }
+
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| (StatusCode::UNAUTHORIZED, format!("Missing {} header", name)))
+    };
+    let timestamp = header("X-Meetd-Timestamp")?;
+    let signature = header("X-Meetd-Signature")?;
+    match FederationClient::verify_signature(body, timestamp, signature, &home.server_key) {
+        Ok(true) => Ok(home),
+        _ => Err((
+            StatusCode::UNAUTHORIZED,
+            "Invalid server signature".to_string(),
+        )),
+    }
+}
+
+/// Store a proposal from a user of another server for one of ours
+async fn receive_proposal(
+    state: &AppState,
+    headers: &HeaderMap,
+    body: &str,
+    signed_proposal: &str,
+    remote: RemoteProposal,
+    counter_of: Option<String>,
+    message: Option<String>,
+) -> Result<FederationReceipt, (StatusCode, String)> {
+    let signed =
+        SignedProposal::from_base64(signed_proposal).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    url::Url::parse(&remote.server)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid reply_to: {}", e)))?;
+    let home = check_origin(state, headers, body, &signed.from, &remote.server).await?;
+
+    // Look the key up on the sender's server rather than taking the proposal's word for it
+    let (source, key_server, history) = match state.resolver.signing_keys(&home, &signed.from).await
+    {
+        Ok(history) => {
+            let key_valid = history.keys.iter().any(|key| {
+                key.public_key == signed.from_pubkey && key.valid_at(signed.signed_at())
+            });
+            if !key_valid {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    format!(
+                        "Proposal is not signed with a key {} held at the time",
+                        signed.from
+                    ),
+                ));
+            }
+            (KeySource::Server, Some(home.api_base.clone()), history.keys)
+        }
+        Err(e) => {
+            tracing::warn!("Couldn't look up {}'s keys: {:#}", signed.from, e);
+            (KeySource::Proposal, None, Vec::new())
+        }
+    };
+
+    let recipient = state
+        .db
+        .get_user_by_email(&signed.to)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                "No such user on this server".to_string(),
+            )
+        })?;
+
+    // A counter must answer a pending proposal our user sent to its sender
+    let original = match &counter_of {
+        Some(id) => {
+            let original = state
+                .db
+                .get_proposal(id)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+                .filter(|p| {
+                    p.from_user_id == recipient.id
+                        && p.to_email.eq_ignore_ascii_case(&signed.from)
+                        && p.remote.as_ref().map(|r| r.server.as_str())
+                            == Some(remote.server.as_str())
+                })
+                .ok_or_else(|| {
+                    (
+                        StatusCode::NOT_FOUND,
+                        "The countered proposal wasn't found".to_string(),
+                    )
+                })?;
+            if original.status != ProposalStatus::Pending {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Proposal is already {}", original.status),
+                ));
+            }
+            Some(original)
+        }
+        None => None,
+    };
+
+    if check_incoming(state, &recipient, &signed)?.is_some() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Proposals from users of this server can't arrive through federation".to_string(),
+        ));
+    }
+    let key_changed = check_pinned_key(state, &recipient, &signed, source, key_server, &history)?;
+
+    let proposal_id = format!(
+        "prop_{}",
+        &Uuid::new_v4().to_string().replace('-', "")[..12]
+    );
+    // Senders on other servers are stored by email
+    let proposal = Proposal {
+        counter_of,
+        message,
+        remote: Some(remote),
+        key_changed,
+        ..Proposal::from_signed(proposal_id.clone(), signed.from.clone(), &signed)
+    };
+    if let Some(original) = &original {
+        answer_pending(state, &original.id, ProposalStatus::Countered)?;
+    }
+    if let Err(e) = state.db.create_proposal(&proposal) {
+        if let Some(original) = &original {
+            reopen_countered(state, &original.id);
+        }
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+
+    announce_proposal(state, &proposal, &signed.from, &signed);
+
+    Ok(FederationReceipt { proposal_id })
+}
+
+/// Record the answer to a proposal one of our users sent to another server
+async fn receive_response(
+    state: &AppState,
+    proposal_id: &str,
+    reply_token: &str,
+    status: ProposalStatus,
+    option: Option<usize>,
+    response: Option<ProposalResponse>,
+) -> Result<FederationReceipt, (StatusCode, String)> {
+    let proposal = federated_proposal(state, proposal_id, reply_token)?;
+    if proposal.status != ProposalStatus::Pending {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Proposal is already {}", proposal.status),
+        ));
+    }
+
+    let slot = match status {
+        ProposalStatus::Accepted => Some(
+            proposal
+                .pick_option(option)
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+        ),
+        _ => None,
+    };
+    let sender = state
+        .db
+        .get_user(&proposal.from_user_id)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| {
+            (
+                StatusCode::FORBIDDEN,
+                "Only proposals sent from this server can be answered".to_string(),
+            )
+        })?;
+    // The signed response is the only proof the recipient answered
+    let Some(response) = response else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Answers must carry the recipient's signed response".to_string(),
+        ));
+    };
+    check_response(
+        state,
+        &proposal,
+        &sender.email,
+        status,
+        slot.as_ref(),
+        &response,
+    )
+    .await?;
+    let response = Some(response);
+
+    let event = match (status, slot) {
+        (ProposalStatus::Accepted, Some(slot)) => {
+            answer_pending(state, proposal_id, status)?;
+            if !proposal.options.is_empty() {
+                state
+                    .db
+                    .choose_proposal_option(proposal_id, option.unwrap_or(0), &slot)
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            }
+            // The recipient's server books their side
+            let calendar_link = book_meeting(state, &proposal, &slot).await.sender;
+            WebhookEvent::new(
+                WebhookEventType::ProposalAccepted,
+                WebhookEventData {
+                    response,
+                    ..WebhookEventData::proposal_accepted(
+                        proposal.id.clone(),
+                        proposal.to_email.clone(),
+                        slot,
+                        calendar_link,
+                    )
+                },
+            )
+        }
+        (ProposalStatus::Declined, _) => {
+            answer_pending(state, proposal_id, status)?;
+            WebhookEvent::new(
+                WebhookEventType::ProposalDeclined,
+                WebhookEventData {
+                    response,
+                    ..WebhookEventData::proposal_declined(
+                        proposal.id.clone(),
+                        proposal.to_email.clone(),
+                    )
+                },
+            )
+        }
+        (other, _) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Can't respond with status {}", other),
+            ));
+        }
+    };
+
+    notify_user(sender, event);
+
+    Ok(FederationReceipt {
+        proposal_id: proposal.id,
+    })
+}
+
+/// Withdraw a proposal a user on another server sent one of ours
+fn receive_withdraw(
+    state: &AppState,
+    proposal_id: &str,
+    reply_token: &str,
+) -> Result<FederationReceipt, (StatusCode, String)> {
+    let proposal = federated_proposal(state, proposal_id, reply_token)?;
+    // Only the sender's server can take a proposal back
+    let sender_is_local = state
+        .db
+        .get_user(&proposal.from_user_id)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .is_some();
+    if sender_is_local {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Only the sender can withdraw a proposal".to_string(),
+        ));
+    }
+
+    answer_pending(state, proposal_id, ProposalStatus::Withdrawn)?;
+
+    if let Ok(Some(recipient)) = state.db.get_user_by_email(&proposal.to_email) {
+        notify_user(
+            recipient,
+            WebhookEvent::new(
+                WebhookEventType::ProposalWithdrawn,
+                WebhookEventData::proposal_withdrawn(
+                    proposal.id.clone(),
+                    proposal.from_user_id.clone(),
+                ),
+            ),
+        );
+    }
+
+    Ok(FederationReceipt {
+        proposal_id: proposal.id,
+    })
+}
+
+/// Call off a meeting the participant on the other server cancelled
+async fn receive_cancel(
+    state: &AppState,
+    proposal_id: &str,
+    reply_token: &str,
+) -> Result<FederationReceipt, (StatusCode, String)> {
+    let proposal = federated_proposal(state, proposal_id, reply_token)?;
+    let cancelled = state
+        .db
+        .transition_proposal_status(
+            proposal_id,
+            ProposalStatus::Accepted,
+            ProposalStatus::Cancelled,
+        )
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if cancelled == 0 {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Proposal is {}, not accepted", proposal.status),
+        ));
+    }
+
+    unbook_meeting(state, &proposal).await;
+    notify_participants(
+        state,
+        &proposal,
+        WebhookEvent::new(
+            WebhookEventType::ProposalCancelled,
+            WebhookEventData::proposal_cancelled(
+                proposal.id.clone(),
+                remote_participant(state, &proposal),
+            ),
+        ),
+    );
+
+    Ok(FederationReceipt {
+        proposal_id: proposal.id,
+    })
+}
+
+/// Move a meeting the participant on the other server rescheduled
+///
+/// Refused with a 409 when the new slot clashes with our user's calendar,
+/// unless the other side forced it.
+async fn receive_reschedule(
+    state: &AppState,
+    proposal_id: &str,
+    reply_token: &str,
+    slot: ProposalSlot,
+    force: bool,
+) -> Result<FederationReceipt, (StatusCode, String)> {
+    let proposal = federated_proposal(state, proposal_id, reply_token)?;
+    if proposal.status != ProposalStatus::Accepted {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Proposal is {}, not accepted", proposal.status),
+        ));
+    }
+    if slot.start < Utc::now() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Can't move a meeting into the past".to_string(),
+        ));
+    }
+    slot.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if !force {
+        let conflicts = reschedule_conflicts(state, &proposal, &slot).await;
+        if !conflicts.is_empty() {
+            let emails: Vec<&str> = conflicts.iter().map(|c| c.email.as_str()).collect();
+            return Err((
+                StatusCode::CONFLICT,
+                format!(
+                    "The slot is no longer free in {}'s calendar",
+                    emails.join(", ")
+                ),
+            ));
+        }
+    }
+
+    let links = move_meeting(state, &proposal, &slot).await?;
+
+    let sender_is_local = matches!(state.db.get_user(&proposal.from_user_id), Ok(Some(_)));
+    let calendar_link = if sender_is_local {
+        links.sender
+    } else {
+        links.recipient
+    };
+    notify_participants(
+        state,
+        &proposal,
+        WebhookEvent::new(
+            WebhookEventType::ProposalRescheduled,
+            WebhookEventData::proposal_rescheduled(
+                proposal.id.clone(),
+                remote_participant(state, &proposal),
+                slot,
+                calendar_link,
+            ),
+        ),
+    );
+
+    Ok(FederationReceipt {
+        proposal_id: proposal.id,
+    })
+}
+
+/// A proposal shared with another server, checked against the reply token
+fn federated_proposal(
+    state: &AppState,
+    proposal_id: &str,
+    reply_token: &str,
+) -> Result<Proposal, (StatusCode, String)> {
+    let proposal = state
+        .db
+        .get_proposal(proposal_id)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Proposal not found".to_string()))?;
+
+    if proposal.remote.as_ref().map(|r| r.reply_token.as_str()) != Some(reply_token) {
+        return Err((StatusCode::FORBIDDEN, "Invalid reply token".to_string()));
+    }
+    Ok(proposal)
+}
+
+/// Email of the participant on the other server
+fn remote_participant(state: &AppState, proposal: &Proposal) -> String {
+    match state.db.get_user(&proposal.from_user_id) {
+        Ok(Some(_)) => proposal.to_email.clone(),
+        // Senders on other servers are stored by email
+        _ => proposal.from_user_id.clone(),
+    }
+}
+
+/// Check a signed response from another server answers `proposal` the way it
+/// says, with a key the responder's server lists as theirs at the time
+async fn check_response(
+    state: &AppState,
+    proposal: &Proposal,
+    sender_email: &str,
+    status: ProposalStatus,
+    slot: Option<&ProposalSlot>,
+    response: &ProposalResponse,
+) -> Result<(), (StatusCode, String)> {
+    let matches = Some(&response.proposal_hash) == proposal.proposal_hash.as_ref()
+        && response.from.eq_ignore_ascii_case(&proposal.to_email)
+        && response.to.eq_ignore_ascii_case(sender_email)
+        && response.status == status
+        && response.slot.as_ref() == slot;
+    if !matches {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "The signed response doesn't match this answer".to_string(),
+        ));
+    }
+    let valid = PublicKey::from_base64(&response.from_pubkey)
+        .and_then(|key| key.verify_response(response))
+        .unwrap_or(false);
+    if !valid {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Invalid response signature".to_string(),
+        ));
+    }
+
+    // Anyone can sign with a key of their own; it has to be the responder's
+    let home = state
+        .resolver
+        .home_server(&response.from)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::BAD_GATEWAY,
+                format!("Couldn't find {}'s server: {:#}", response.from, e),
+            )
+        })?
+        .filter(|home| proposal.remote.as_ref().map(|r| r.server.as_str()) == Some(&home.api_base))
+        .ok_or_else(|| {
+            (
+                StatusCode::FORBIDDEN,
+                format!("{} isn't hosted by the server that answered", response.from),
+            )
+        })?;
+    let key_valid = state
+        .remote_key_valid(
+            &home,
+            &response.from,
+            &response.from_pubkey,
+            response.responded_at,
+        )
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::BAD_GATEWAY,
+                format!("Couldn't look up {}'s keys: {:#}", response.from, e),
+            )
+        })?;
+    if !key_valid {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!(
+                "Response is not signed with a key {} held at the time",
+                response.from
+            ),
+        ));
+    }
+    Ok(())
+}
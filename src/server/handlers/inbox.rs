@@ -14,7 +14,8 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use super::ErrorResponse;
-use crate::models::{InboxProposal, ProposalStatus};
+use crate::db::Database;
+use crate::models::{InboxProposal, Proposal, ProposalStatus};
 use crate::server::middleware::AuthenticatedUser;
 use crate::server::AppState;
 
@@ -36,27 +37,10 @@ pub async fn list_inbox(
 
     match state.db.get_proposals_for_email(&user.email, status) {
         Ok(proposals) => {
-            // Get sender emails for each proposal
-            let mut inbox: Vec<InboxProposal> = Vec::new();
-            for p in proposals {
-                let from_email = if let Ok(Some(sender)) = state.db.get_user(&p.from_user_id) {
-                    sender.email
-                } else {
-                    p.from_user_id.clone()
-                };
-
-                inbox.push(InboxProposal {
-                    id: p.id,
-                    from: from_email,
-                    slot: crate::models::ProposalSlot {
-                        start: p.slot_start,
-                        duration_minutes: p.duration_minutes,
-                    },
-                    title: p.title,
-                    expires_at: p.expires_at,
-                    status: p.status,
-                });
-            }
+            let inbox: Vec<InboxProposal> = proposals
+                .into_iter()
+                .map(|p| inbox_entry(&state.db, p))
+                .collect();
             Json(serde_json::json!({ "proposals": inbox })).into_response()
         }
         Err(e) => (
@@ -67,6 +51,20 @@ pub async fn list_inbox(
     }
 }
 
+/// A proposal as shown in the inbox, with the sender's email
+///
+/// Senders on other servers are stored by email already.
+fn inbox_entry(db: &Database, p: Proposal) -> InboxProposal {
+    let from = match db.get_user(&p.from_user_id) {
+        Ok(Some(sender)) => sender.email,
+        _ => p.from_user_id.clone(),
+    };
+    InboxProposal {
+        from,
+        ..InboxProposal::from(p)
+    }
+}
+
 /// Stream inbox updates via SSE
 pub async fn stream_inbox(
     State(state): State<Arc<AppState>>,
@@ -75,7 +73,7 @@ pub async fn stream_inbox(
     let email = user.email.clone();
     let db = state.db.clone();
 
-    // Keep track of last seen proposal IDs
+    // Keep track of pending proposals already sent to the client
     let mut seen_ids: std::collections::HashSet<String> = std::collections::HashSet::new();
 
     // Initialize with current proposals
@@ -93,30 +91,30 @@ pub async fn stream_inbox(
 
             // Check for new proposals
             if let Ok(proposals) = db.get_proposals_for_email(&email, Some(ProposalStatus::Pending)) {
+                // Proposals that stopped being pending because the sender took them back
+                let pending: std::collections::HashSet<&str> =
+                    proposals.iter().map(|p| p.id.as_str()).collect();
+                let gone: Vec<String> = seen_ids
+                    .iter()
+                    .filter(|id| !pending.contains(id.as_str()))
+                    .cloned()
+                    .collect();
+                for id in gone {
+                    seen_ids.remove(&id);
+                    if let Ok(Some(p)) = db.get_proposal(&id) {
+                        if p.status == ProposalStatus::Withdrawn {
+                            if let Ok(json) = serde_json::to_string(&inbox_entry(&db, p)) {
+                                yield Ok(Event::default().event("withdrawn").data(json));
+                            }
+                        }
+                    }
+                }
+
                 for p in proposals {
                     if !seen_ids.contains(&p.id) {
                         seen_ids.insert(p.id.clone());
 
-                        // Get sender email
-                        let from_email = if let Ok(Some(sender)) = db.get_user(&p.from_user_id) {
-                            sender.email
-                        } else {
-                            p.from_user_id.clone()
-                        };
-
-                        let inbox_proposal = InboxProposal {
-                            id: p.id,
-                            from: from_email,
-                            slot: crate::models::ProposalSlot {
-                                start: p.slot_start,
-                                duration_minutes: p.duration_minutes,
-                            },
-                            title: p.title,
-                            expires_at: p.expires_at,
-                            status: p.status,
-                        };
-
-                        if let Ok(json) = serde_json::to_string(&inbox_proposal) {
+                        if let Ok(json) = serde_json::to_string(&inbox_entry(&db, p)) {
                             yield Ok(Event::default().data(json));
                         }
                     }
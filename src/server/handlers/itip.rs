@@ -0,0 +1,151 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde::Serialize;
+use std::sync::Arc;
+
+use super::proposals::{answer_pending, book_meeting, meeting_occurrences, notify_user};
+use super::ErrorResponse;
+use crate::calendar::itip;
+use crate::models::ProposalStatus;
+use crate::server::AppState;
+use crate::webhook::{WebhookEvent, WebhookEventData, WebhookEventType};
+
+#[derive(Serialize)]
+pub struct ItipReplyResponse {
+    pub proposal_id: String,
+    pub status: ProposalStatus,
+}
+
+/// Record a calendar client's `METHOD:REPLY` to an emailed invitation
+///
+/// Replies are trusted on the invitation UID, which only this server can
+/// make. An acceptance is booked in the sender's calendar unless the slot
+/// has filled up there since. The sender gets the usual `proposal.accepted`
+/// or `proposal.declined` webhook, without a signed response since the
+/// recipient has no meetd key.
+pub async fn receive_reply(State(state): State<Arc<AppState>>, body: String) -> Response {
+    match record_reply(&state, &body).await {
+        Ok(response) => Json(response).into_response(),
+        Err((status, error)) => (status, Json(ErrorResponse::new(error))).into_response(),
+    }
+}
+
+async fn record_reply(
+    state: &AppState,
+    body: &str,
+) -> Result<ItipReplyResponse, (StatusCode, String)> {
+    let reply = itip::parse_reply(body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid reply: {:#}", e)))?;
+    let server_key = state
+        .db
+        .server_keypair()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let proposal_id = itip::proposal_id_from_uid(&reply.uid, &server_key).ok_or_else(|| {
+        (
+            StatusCode::FORBIDDEN,
+            "Not an invitation from this server".to_string(),
+        )
+    })?;
+
+    let proposal = state
+        .db
+        .get_proposal(&proposal_id)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Proposal not found".to_string()))?;
+
+    if !reply.attendee.eq_ignore_ascii_case(&proposal.to_email) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("{} wasn't invited to this meeting", reply.attendee),
+        ));
+    }
+
+    if proposal.status != ProposalStatus::Pending {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Proposal is already {}", proposal.status),
+        ));
+    }
+
+    if proposal.expires_at < Utc::now() {
+        let _ = state
+            .db
+            .update_proposal_status(&proposal.id, ProposalStatus::Expired);
+        return Err((StatusCode::BAD_REQUEST, "Proposal has expired".to_string()));
+    }
+
+    let status = match reply.partstat.as_str() {
+        "ACCEPTED" => ProposalStatus::Accepted,
+        "DECLINED" => ProposalStatus::Declined,
+        other => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("PARTSTAT {} isn't an answer meetd can record", other),
+            ));
+        }
+    };
+
+    let sender = state
+        .db
+        .get_user(&proposal.from_user_id)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let event = if status == ProposalStatus::Accepted {
+        // The invitation offered the preferred slot
+        let slot = proposal.slot();
+
+        // The recipient's calendar isn't ours to see, but the sender may have booked the slot since
+        if let Some(sender) = &sender {
+            let conflicts = state
+                .slot_conflicts(
+                    &[sender],
+                    &meeting_occurrences(&proposal, &slot, Some(sender)),
+                )
+                .await;
+            if !conflicts.is_empty() {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!("The slot is no longer free in {}'s calendar", sender.email),
+                ));
+            }
+        }
+
+        answer_pending(state, &proposal.id, status)?;
+        if !proposal.options.is_empty() {
+            state
+                .db
+                .choose_proposal_option(&proposal.id, 0, &slot)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+        let calendar_link = book_meeting(state, &proposal, &slot).await.sender;
+        WebhookEvent::new(
+            WebhookEventType::ProposalAccepted,
+            WebhookEventData::proposal_accepted(
+                proposal.id.clone(),
+                proposal.to_email.clone(),
+                slot,
+                calendar_link,
+            ),
+        )
+    } else {
+        answer_pending(state, &proposal.id, status)?;
+        WebhookEvent::new(
+            WebhookEventType::ProposalDeclined,
+            WebhookEventData::proposal_declined(proposal.id.clone(), proposal.to_email.clone()),
+        )
+    };
+
+    if let Some(sender) = sender {
+        notify_user(sender, event);
+    }
+
+    Ok(ItipReplyResponse {
+        proposal_id: proposal.id,
+        status,
+    })
+}
@@ -1,8 +1,12 @@
 pub mod agent;
 pub mod auth;
 pub mod availability;
+pub mod calendars;
 pub mod config;
+pub mod contacts;
+pub mod federation;
 pub mod inbox;
+pub mod itip;
 pub mod proposals;
 pub mod webhooks;
 
@@ -4,17 +4,25 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use uuid::Uuid;
 
+use super::agent::check_pinned_key;
 use super::ErrorResponse;
-use crate::calendar::{CalendarProvider, GoogleCalendar};
-use crate::crypto::{Keypair, PublicKey};
+use crate::calendar::itip;
+use crate::calendar::rrule::RecurrenceRule;
+use crate::calendar::{occurrences, EventDetails, EventRef, CHECKED_OCCURRENCES};
+use crate::crypto::{generate_reply_token, Keypair, PublicKey};
+use crate::federation::{FederationClient, FederationMessage, FEDERATION_INBOX_PATH};
 use crate::models::{
-    AcceptProposalResponse, CalendarEvent, CreateProposalRequest, CreateProposalResponse,
-    InboxProposal, Proposal, ProposalSlot, ProposalStatus, SignedProposal,
+    AcceptProposalRequest, AcceptProposalResponse, CalendarEvent, CounterProposalRequest,
+    CreateProposalRequest, CreateProposalResponse, DeclineProposalRequest, DeclineProposalResponse,
+    InboxProposal, KeySource, Proposal, ProposalResponse, ProposalSlot, ProposalStatus,
+    RemoteProposal, RescheduleRequest, SignedProposal, SlotConflict, SlotConflictResponse,
+    StatusResponse, SubmitSignedProposalRequest, TimeSlot, User, PROPOSAL_VERSION,
 };
 use crate::server::middleware::AuthenticatedUser;
 use crate::server::AppState;
@@ -26,115 +34,580 @@ pub async fn create_proposal(
     Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
     Json(req): Json<CreateProposalRequest>,
 ) -> Response {
-    let proposal_id = format!(
-        "prop_{}",
-        &Uuid::new_v4().to_string().replace('-', "")[..12]
-    );
-    let nonce = Uuid::new_v4().to_string();
-    let expires_at = Utc::now() + Duration::days(7);
+    match send_proposal(&state, &user, req, None, None).await {
+        Ok(resp) => Json(resp).into_response(),
+        Err((status, error)) => (status, Json(ErrorResponse::new(error))).into_response(),
+    }
+}
+
+/// Submit a proposal the sender signed with their own key
+///
+/// The server checks the signature against the sender's registered key
+/// instead of signing. With `counter_of` set, it answers that proposal like
+/// `counter_proposal` does.
+pub async fn submit_signed_proposal(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Json(req): Json<SubmitSignedProposalRequest>,
+) -> Response {
+    match submit_signed(&state, &user, req).await {
+        Ok(resp) => Json(resp).into_response(),
+        Err((status, error)) => (status, Json(ErrorResponse::new(error))).into_response(),
+    }
+}
+
+async fn submit_signed(
+    state: &AppState,
+    user: &User,
+    req: SubmitSignedProposalRequest,
+) -> Result<CreateProposalResponse, (StatusCode, String)> {
+    let signed = SignedProposal::from_base64(&req.signed_proposal)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    signed
+        .validate()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if signed.version != PROPOSAL_VERSION {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Signed proposals must use version {}", PROPOSAL_VERSION),
+        ));
+    }
+    if signed.from != user.email {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Proposal is not from you".to_string(),
+        ));
+    }
+    let key_valid = state
+        .db
+        .is_signing_key_valid(&user.id, &signed.from_pubkey, signed.signed_at())
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !key_valid {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Proposal is signed with a key that wasn't registered for your account when it was made"
+                .to_string(),
+        ));
+    }
+
+    let pub_key = PublicKey::from_base64(&signed.from_pubkey).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid public key: {}", e),
+        )
+    })?;
+    match pub_key.verify_proposal(&signed) {
+        Ok(true) => {}
+        Ok(false) => return Err((StatusCode::BAD_REQUEST, "Invalid signature".to_string())),
+        Err(e) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Verification error: {}", e),
+            ))
+        }
+    }
+
+    if signed.expires_at < Utc::now() {
+        return Err((StatusCode::BAD_REQUEST, "Proposal has expired".to_string()));
+    }
+
+    if let Some(id) = &req.counter_of {
+        let (_, sender_email) = counterable(state, user, id)?;
+        if signed.to != sender_email {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "A counter-proposal must be addressed to the original sender".to_string(),
+            ));
+        }
+    }
+
+    // Check nonce hasn't been used
+    match state.db.is_nonce_used(&signed.nonce) {
+        Ok(false) => {}
+        Ok(true) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Nonce already used (replay attack?)".to_string(),
+            ))
+        }
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+    state
+        .db
+        .use_nonce(&signed.nonce)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if let Some(id) = &req.counter_of {
+        answer_pending(state, id, ProposalStatus::Countered)?;
+    }
+    let resp = store_proposal(state, user, signed, req.counter_of.clone(), req.message).await;
+    if let (Err(_), Some(id)) = (&resp, &req.counter_of) {
+        reopen_countered(state, id);
+    }
+    resp
+}
+
+/// Put a proposal back to pending after its counter-proposal couldn't be sent
+pub(crate) fn reopen_countered(state: &AppState, id: &str) {
+    if let Err(e) =
+        state
+            .db
+            .transition_proposal_status(id, ProposalStatus::Countered, ProposalStatus::Pending)
+    {
+        tracing::warn!("Failed to reopen {}: {}", id, e);
+    }
+}
+
+/// Sign, store and announce a proposal from `user`
+async fn send_proposal(
+    state: &AppState,
+    user: &User,
+    req: CreateProposalRequest,
+    counter_of: Option<String>,
+    message: Option<String>,
+) -> Result<CreateProposalResponse, (StatusCode, String)> {
+    let mut signed = req.unsigned_proposal(&user.email, &user.public_key);
+    signed
+        .validate()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if !user.has_server_key() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Your signing key is held by your client; sign the proposal yourself and submit it to /v1/proposals/signed".to_string(),
+        ));
+    }
 
     // Load user's keypair
     let keypair = match Keypair::from_private_key_base64(&user.private_key) {
         Ok(kp) => kp,
         Err(e) => {
-            return (
+            return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse::new(format!("Failed to load keypair: {}", e))),
-            )
-                .into_response();
+                format!("Failed to load keypair: {}", e),
+            ));
         }
     };
 
-    // Create signed proposal
-    let mut signed = SignedProposal {
-        version: 1,
-        from: user.email.clone(),
-        from_pubkey: user.public_key.clone(),
-        to: req.to_email.clone(),
-        slot: ProposalSlot {
-            start: req.slot_start,
-            duration_minutes: req.duration_minutes,
-        },
-        title: req.title.clone(),
-        description: req.description.clone(),
-        nonce: nonce.clone(),
-        expires_at,
-        signature: String::new(),
-    };
-
     keypair.sign_proposal(&mut signed);
 
+    store_proposal(state, user, signed, counter_of, message).await
+}
+
+/// Store and announce a signed proposal from `user`
+///
+/// `counter_of` links a counter-proposal to the proposal it answers; the
+/// recipient then gets a `proposal.countered` webhook instead of
+/// `proposal.received`. Proposals for users of another meetd server are
+/// forwarded to it, and anyone else gets an iTIP invitation.
+async fn store_proposal(
+    state: &AppState,
+    user: &User,
+    signed: SignedProposal,
+    counter_of: Option<String>,
+    message: Option<String>,
+) -> Result<CreateProposalResponse, (StatusCode, String)> {
+    let proposal_id = format!(
+        "prop_{}",
+        &Uuid::new_v4().to_string().replace('-', "")[..12]
+    );
+
+    // Recipients on this server pin the sender's key like any other
+    let recipient = state
+        .db
+        .get_user_by_email(&signed.to)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let key_changed = match &recipient {
+        Some(recipient) => {
+            let history = state
+                .db
+                .get_user_keys(&user.id)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            check_pinned_key(
+                state,
+                recipient,
+                &signed,
+                KeySource::Directory,
+                None,
+                &history,
+            )?
+        }
+        None => false,
+    };
+
     // Store proposal in database
     let proposal = Proposal {
-        id: proposal_id.clone(),
-        from_user_id: user.id.clone(),
-        to_email: req.to_email.clone(),
-        slot_start: req.slot_start,
-        duration_minutes: req.duration_minutes,
-        title: req.title,
-        description: req.description,
-        nonce,
-        expires_at,
-        signature: signed.signature.clone(),
-        status: ProposalStatus::Pending,
-        created_at: Utc::now().timestamp(),
+        counter_of,
+        message,
+        key_changed,
+        ..Proposal::from_signed(proposal_id.clone(), user.id.clone(), &signed)
     };
 
     if let Err(e) = state.db.create_proposal(&proposal) {
-        return (
+        return Err((
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse::new(format!(
-                "Failed to create proposal: {}",
-                e
-            ))),
+            format!("Failed to create proposal: {}", e),
+        ));
+    }
+
+    announce_proposal(state, &proposal, &user.email, &signed);
+    let federated = federate_proposal(state, &proposal, &signed).await?;
+    let itip = if recipient.is_none() && !federated {
+        Some(send_invitation(state, &proposal, &signed)?)
+    } else {
+        None
+    };
+
+    Ok(CreateProposalResponse {
+        proposal_id,
+        signed_proposal: signed.to_base64(),
+        accept_link: format!("{}/accept/{}", state.server_url, proposal.id),
+        itip,
+    })
+}
+
+/// Send the recipient a `proposal.received` (or `proposal.countered`) webhook
+pub(crate) fn announce_proposal(
+    state: &AppState,
+    proposal: &Proposal,
+    from_email: &str,
+    signed: &SignedProposal,
+) {
+    let Ok(Some(recipient)) = state.db.get_user_by_email(&signed.to) else {
+        return;
+    };
+    let event_type = if proposal.counter_of.is_some() {
+        WebhookEventType::ProposalCountered
+    } else {
+        WebhookEventType::ProposalReceived
+    };
+    let event = WebhookEvent::new(
+        event_type,
+        WebhookEventData {
+            slots: signed.slots.clone(),
+            counter_of: proposal.counter_of.clone(),
+            message: proposal.message.clone(),
+            recurrence: signed.recurrence.clone(),
+            location: signed.location.clone(),
+            conference: signed.conference.clone(),
+            key_changed: proposal.key_changed,
+            ..WebhookEventData::proposal_received(
+                proposal.id.clone(),
+                from_email.to_string(),
+                signed.from_pubkey.clone(),
+                signed.slot.clone(),
+                signed.title.clone(),
+                signed.expires_at,
+                signed.signature.clone(),
+            )
+        },
+    );
+    notify_user(recipient, event);
+}
+
+/// Forward a proposal to the recipient's home server, if that's another meetd server
+///
+/// A proposal that can't be delivered is withdrawn, so it doesn't sit
+/// pending forever. Returns whether the proposal went to another server.
+async fn federate_proposal(
+    state: &AppState,
+    proposal: &Proposal,
+    signed: &SignedProposal,
+) -> Result<bool, (StatusCode, String)> {
+    let withdraw = |error: String| {
+        let _ = state
+            .db
+            .update_proposal_status(&proposal.id, ProposalStatus::Withdrawn);
+        (StatusCode::BAD_GATEWAY, error)
+    };
+    let home = match state.remote_home_server(&signed.to).await {
+        Ok(Some(home)) => home,
+        Ok(None) => return Ok(false),
+        Err(e) => {
+            return Err(withdraw(format!(
+                "Couldn't find {}'s server: {:#}",
+                signed.to, e
+            )))
+        }
+    };
+    let server = home.api_base;
+
+    // A counter refers to the original by its ID on the other server
+    let counter_of = proposal
+        .counter_of
+        .as_ref()
+        .and_then(|id| state.db.get_proposal(id).ok().flatten())
+        .and_then(|original| original.remote)
+        .filter(|remote| remote.server == server)
+        .map(|remote| remote.id);
+    let reply_token = generate_reply_token();
+    let message = FederationMessage::Proposal {
+        signed_proposal: signed.to_base64(),
+        proposal_id: proposal.id.clone(),
+        reply_to: state.server_url.clone(),
+        reply_token: reply_token.clone(),
+        counter_of,
+        message: proposal.message.clone(),
+    };
+
+    let client = state
+        .federation_client()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let receipt = match client.deliver(&home.federation_inbox, &message).await {
+        Ok(receipt) => receipt,
+        Err(e) => {
+            return Err(withdraw(format!(
+                "Couldn't deliver the proposal to {}: {:#}",
+                server, e
+            )))
+        }
+    };
+
+    state
+        .db
+        .set_proposal_remote(
+            &proposal.id,
+            &RemoteProposal {
+                server,
+                id: receipt.proposal_id,
+                reply_token,
+            },
         )
-            .into_response();
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(true)
+}
+
+/// Invite a recipient who doesn't use meetd with an iTIP request
+///
+/// The invitation is emailed when a mailer is configured, and returned
+/// either way so the sender can pass it on. Calendar clients can only answer
+/// one time, so multi-slot proposals offer their preferred slot.
+fn send_invitation(
+    state: &AppState,
+    proposal: &Proposal,
+    signed: &SignedProposal,
+) -> Result<String, (StatusCode, String)> {
+    let server_key = state
+        .db
+        .server_keypair()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let domain = url::Url::parse(&state.server_url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "meetd".to_string());
+    let uid = itip::invite_uid(&proposal.id, &server_key, &domain);
+
+    // Calendar clients reply to the organizer, which is our address when we send mail
+    let organizer = state
+        .mailer
+        .as_ref()
+        .map_or(signed.from.as_str(), |mailer| mailer.from());
+    let sender = state.db.get_user(&proposal.from_user_id).ok().flatten();
+    let details = meeting_event(
+        proposal,
+        &proposal.slot(),
+        Some(&signed.to),
+        sender.as_ref(),
+    );
+    let calendar = itip::request(&uid, organizer, &details, signed).to_string();
+
+    if let Some(mailer) = &state.mailer {
+        let title = proposal.title.as_deref().unwrap_or("Meeting");
+        let mut text = format!(
+            "{} invited you to \"{}\" on {} UTC ({} minutes).\n\nAccept or decline from your calendar.",
+            signed.from,
+            title,
+            details.start.format("%A %Y-%m-%d %H:%M"),
+            signed.slot.duration_minutes
+        );
+        if let Some(message) = &proposal.message {
+            text = format!("{}\n\n{}", message, text);
+        }
+        let mail = mailer
+            .mail(&signed.to, &format!("Invitation: {}", title), &text)
+            .with_reply_to(&signed.from)
+            .with_calendar("REQUEST", &calendar);
+        let mailer = mailer.clone();
+        tokio::spawn(async move {
+            if let Err(e) = mailer.send(&mail).await {
+                tracing::warn!("Failed to email invitation: {:#}", e);
+            }
+        });
     }
 
-    // Send webhook if recipient has one configured
-    if let Ok(Some(recipient)) = state.db.get_user_by_email(&req.to_email) {
-        if let (Some(webhook_url), Some(webhook_secret)) =
-            (recipient.webhook_url, recipient.webhook_secret)
-        {
-            let event = WebhookEvent::new(
-                WebhookEventType::ProposalReceived,
-                WebhookEventData::proposal_received(
-                    proposal_id.clone(),
-                    user.email.clone(),
-                    user.public_key.clone(),
-                    signed.slot.clone(),
-                    signed.title.clone(),
-                    expires_at,
-                    signed.signature.clone(),
-                ),
-            );
+    Ok(calendar)
+}
 
-            let webhook_client = WebhookClient::new();
-            // Fire and forget - don't block on webhook delivery
-            tokio::spawn(async move {
-                if let Err(e) = webhook_client
-                    .deliver(&webhook_url, &webhook_secret, &event)
-                    .await
-                {
-                    tracing::warn!("Failed to deliver webhook: {}", e);
-                }
-            });
+/// Tell the server a federated proposal came from how its recipient answered
+async fn send_response(
+    state: &AppState,
+    remote: &RemoteProposal,
+    status: ProposalStatus,
+    option: Option<usize>,
+    response: Option<ProposalResponse>,
+) -> Option<String> {
+    send_federated(
+        state,
+        remote,
+        FederationMessage::Response {
+            proposal_id: remote.id.clone(),
+            reply_token: remote.reply_token.clone(),
+            status,
+            option,
+            response,
+        },
+    )
+    .await
+}
+
+/// Post a message about a federated proposal to the other server
+///
+/// If the server can't be reached the message goes to the outbox and is
+/// retried in the background. Returns a warning for the caller whenever
+/// the other server hasn't taken the message yet.
+async fn send_federated(
+    state: &AppState,
+    remote: &RemoteProposal,
+    message: FederationMessage,
+) -> Option<String> {
+    let inbox = format!("{}{}", remote.server, FEDERATION_INBOX_PATH);
+    let result = match state.federation_client() {
+        Ok(client) => client.deliver(&inbox, &message).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    let error = result.err()?;
+
+    if !FederationClient::is_retryable(&error) {
+        tracing::warn!("{} refused a message: {:#}", remote.server, error);
+        return Some(format!("{} refused the update: {:#}", remote.server, error));
+    }
+    if let Err(e) = state.queue_federation_message(&remote.server, &message, &error) {
+        tracing::warn!("Failed to queue a message for {}: {:#}", remote.server, e);
+        return Some(format!("{} couldn't be told: {:#}", remote.server, error));
+    }
+    Some(format!(
+        "{} couldn't be reached ({:#}); the update will be retried",
+        remote.server, error
+    ))
+}
+
+/// Post a message about a federated proposal to the other server and wait for it
+///
+/// Used where the other server may refuse (a withdrawn proposal, a clash in
+/// its user's calendar) and nothing should change here if it does.
+async fn deliver_now(
+    state: &AppState,
+    remote: &RemoteProposal,
+    message: &FederationMessage,
+) -> Result<(), (StatusCode, String)> {
+    let client = state
+        .federation_client()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    client
+        .deliver(
+            &format!("{}{}", remote.server, FEDERATION_INBOX_PATH),
+            message,
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| {
+            (
+                StatusCode::BAD_GATEWAY,
+                format!("{} refused the change: {:#}", remote.server, e),
+            )
+        })
+}
+
+/// `user`'s signed answer to `proposal`
+///
+/// The server signs when it holds the user's key; otherwise a response the
+/// client signed itself is checked and used. `None` when there's neither, or
+/// the proposal was stored before proposal hashes were kept; federated
+/// proposals can't be answered without one, since the sender's server
+/// only takes signed answers.
+pub(crate) fn sign_response(
+    user: &User,
+    proposal: &Proposal,
+    sender_email: &str,
+    status: ProposalStatus,
+    slot: Option<ProposalSlot>,
+    signed_by_client: Option<ProposalResponse>,
+) -> Result<Option<ProposalResponse>, (StatusCode, String)> {
+    let Some(proposal_hash) = &proposal.proposal_hash else {
+        return Ok(None);
+    };
+
+    if let Some(response) = signed_by_client {
+        let matches = response.proposal_hash == *proposal_hash
+            && response.from == user.email
+            && response.from_pubkey == user.public_key
+            && response.to.eq_ignore_ascii_case(sender_email)
+            && response.status == status
+            && response.slot == slot;
+        if !matches {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The signed response doesn't match this answer".to_string(),
+            ));
         }
+        response
+            .validate()
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let pub_key = PublicKey::from_base64(&user.public_key)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        return match pub_key.verify_response(&response) {
+            Ok(true) => Ok(Some(response)),
+            _ => Err((
+                StatusCode::BAD_REQUEST,
+                "Invalid response signature".to_string(),
+            )),
+        };
     }
 
-    // Serialize signed proposal
-    let signed_json = serde_json::to_string(&signed).unwrap();
-    let signed_b64 = base64::Engine::encode(
-        &base64::engine::general_purpose::STANDARD,
-        signed_json.as_bytes(),
+    if !user.has_server_key() {
+        if proposal.remote.is_some() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "The sender's server needs your signed `response` to this proposal".to_string(),
+            ));
+        }
+        return Ok(None);
+    }
+    let keypair = Keypair::from_private_key_base64(&user.private_key).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to load keypair: {}", e),
+        )
+    })?;
+    let mut response = ProposalResponse::new(
+        proposal_hash,
+        &user.email,
+        &user.public_key,
+        sender_email,
+        status,
+        slot,
     );
+    keypair.sign_response(&mut response);
+    Ok(Some(response))
+}
 
-    Json(CreateProposalResponse {
-        proposal_id,
-        signed_proposal: signed_b64,
-        accept_link: format!("{}/accept/{}", state.server_url, proposal.id),
-    })
-    .into_response()
+/// Answer pending proposal `id` with `status`
+///
+/// The status only changes if the proposal is still pending, so of two
+/// concurrent answers exactly one wins; the other gets a 409.
+pub(crate) fn answer_pending(
+    state: &AppState,
+    id: &str,
+    status: ProposalStatus,
+) -> Result<(), (StatusCode, String)> {
+    match state.db.update_proposal_status(id, status) {
+        Ok(0) => Err((
+            StatusCode::CONFLICT,
+            "Proposal is no longer pending".to_string(),
+        )),
+        Ok(_) => Ok(()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
 }
 
 /// Get a proposal by ID
@@ -154,7 +627,16 @@ pub async fn get_proposal(
                     .into_response();
             }
 
-            Json(InboxProposal::from(proposal)).into_response()
+            // External senders are stored by email
+            let from_email = match state.db.get_user(&proposal.from_user_id) {
+                Ok(Some(sender)) => sender.email,
+                _ => proposal.from_user_id.clone(),
+            };
+            Json(InboxProposal {
+                from: from_email,
+                ..InboxProposal::from(proposal)
+            })
+            .into_response()
         }
         Ok(None) => (
             StatusCode::NOT_FOUND,
@@ -193,9 +675,32 @@ pub async fn accept_proposal(
     State(state): State<Arc<AppState>>,
     Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
     Path(id): Path<String>,
+    body: Option<Json<AcceptProposalRequest>>,
+) -> Response {
+    let Json(req) = body.unwrap_or_default();
+    accept(&state, &user, &id, None, req).await
+}
+
+/// Accept one of the slots offered by a multi-slot proposal
+pub async fn accept_proposal_option(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Path((id, option)): Path<(String, usize)>,
+    body: Option<Json<AcceptProposalRequest>>,
+) -> Response {
+    let Json(req) = body.unwrap_or_default();
+    accept(&state, &user, &id, Some(option), req).await
+}
+
+async fn accept(
+    state: &AppState,
+    user: &User,
+    id: &str,
+    option: Option<usize>,
+    req: AcceptProposalRequest,
 ) -> Response {
     // Get proposal
-    let proposal = match state.db.get_proposal(&id) {
+    let proposal = match state.db.get_proposal(id) {
         Ok(Some(p)) => p,
         Ok(None) => {
             return (
@@ -236,9 +741,7 @@ pub async fn accept_proposal(
 
     // Check if proposal has expired
     if proposal.expires_at < Utc::now() {
-        let _ = state
-            .db
-            .update_proposal_status(&id, ProposalStatus::Expired);
+        let _ = state.db.update_proposal_status(id, ProposalStatus::Expired);
         return (
             StatusCode::BAD_REQUEST,
             Json(ErrorResponse::new("Proposal has expired")),
@@ -246,82 +749,120 @@ pub async fn accept_proposal(
             .into_response();
     }
 
-    // Create calendar event
-    let end_time = proposal.slot_start + Duration::minutes(proposal.duration_minutes as i64);
-    let mut calendar_link = None;
-
-    if let Some(ref token) = user.google_refresh_token {
-        if let Ok(cal) =
-            GoogleCalendar::new(&state.google_client_id, &state.google_client_secret, token).await
-        {
-            // Get sender email
-            let sender_email = if let Ok(Some(sender)) = state.db.get_user(&proposal.from_user_id) {
-                Some(sender.email)
-            } else {
-                None
-            };
+    let slot = match proposal.pick_option(option) {
+        Ok(slot) => slot,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, Json(ErrorResponse::new(e))).into_response();
+        }
+    };
+    let end_time = slot.start + Duration::minutes(slot.duration_minutes as i64);
 
-            if let Ok(event) = cal
-                .create_event(
-                    proposal.title.as_deref().unwrap_or("Meeting"),
-                    proposal.description.as_deref(),
-                    proposal.slot_start,
-                    end_time,
-                    sender_email.as_deref(),
-                )
-                .await
-            {
-                calendar_link = event.html_link;
-            }
+    // The proposal may be days old; make sure neither side has booked the slot since
+    let sender = state.db.get_user(&proposal.from_user_id).ok().flatten();
+    if !req.force {
+        let participants: Vec<&User> = std::iter::once(user).chain(sender.as_ref()).collect();
+        let conflicts = state
+            .slot_conflicts(
+                &participants,
+                &meeting_occurrences(&proposal, &slot, sender.as_ref()),
+            )
+            .await;
+        if !conflicts.is_empty() {
+            return (
+                StatusCode::CONFLICT,
+                Json(SlotConflictResponse::new(conflicts, None)),
+            )
+                .into_response();
         }
     }
 
-    // Update proposal status
-    if let Err(e) = state
-        .db
-        .update_proposal_status(&id, ProposalStatus::Accepted)
-    {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse::new(e.to_string())),
-        )
-            .into_response();
+    // Senders on other servers are stored by email
+    let sender_email = match &sender {
+        Some(sender) => sender.email.clone(),
+        None => proposal.from_user_id.clone(),
+    };
+    let response = match sign_response(
+        user,
+        &proposal,
+        &sender_email,
+        ProposalStatus::Accepted,
+        Some(slot.clone()),
+        req.response,
+    ) {
+        Ok(response) => response,
+        Err((status, error)) => {
+            return (status, Json(ErrorResponse::new(error))).into_response();
+        }
+    };
+
+    if let Err((status, error)) = answer_pending(state, id, ProposalStatus::Accepted) {
+        return (status, Json(ErrorResponse::new(error))).into_response();
     }
 
-    // Send webhook to sender
-    if let Ok(Some(sender)) = state.db.get_user(&proposal.from_user_id) {
-        if let (Some(webhook_url), Some(webhook_secret)) =
-            (sender.webhook_url, sender.webhook_secret)
-        {
-            let event = WebhookEvent::new(
-                WebhookEventType::ProposalAccepted,
-                WebhookEventData::proposal_accepted(
-                    id.clone(),
-                    user.email.clone(),
-                    calendar_link.clone(),
-                ),
+    // A federated sender may have withdrawn the proposal since it arrived, so
+    // the meeting is only booked once their server has taken the acceptance
+    if let Some(remote) = &proposal.remote {
+        let message = FederationMessage::Response {
+            proposal_id: remote.id.clone(),
+            reply_token: remote.reply_token.clone(),
+            status: ProposalStatus::Accepted,
+            option,
+            response: response.clone(),
+        };
+        if let Err((status, error)) = deliver_now(state, remote, &message).await {
+            let _ = state.db.transition_proposal_status(
+                id,
+                ProposalStatus::Accepted,
+                ProposalStatus::Pending,
             );
+            return (status, Json(ErrorResponse::new(error))).into_response();
+        }
+    }
 
-            let webhook_client = WebhookClient::new();
-            tokio::spawn(async move {
-                if let Err(e) = webhook_client
-                    .deliver(&webhook_url, &webhook_secret, &event)
-                    .await
-                {
-                    tracing::warn!("Failed to deliver webhook: {}", e);
-                }
-            });
+    // Record the choice; the other options are released
+    if !proposal.options.is_empty() {
+        if let Err(e) = state
+            .db
+            .choose_proposal_option(id, option.unwrap_or(0), &slot)
+        {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
         }
     }
 
+    let links = book_meeting(state, &proposal, &slot).await;
+    let calendar_link = links.recipient;
+
+    // Send webhook to sender
+    if let Some(sender) = sender {
+        notify_user(
+            sender,
+            WebhookEvent::new(
+                WebhookEventType::ProposalAccepted,
+                WebhookEventData {
+                    response: response.clone(),
+                    ..WebhookEventData::proposal_accepted(
+                        id.to_string(),
+                        user.email.clone(),
+                        slot.clone(),
+                        links.sender,
+                    )
+                },
+            ),
+        );
+    }
     Json(AcceptProposalResponse {
         status: "accepted".to_string(),
         event: Some(CalendarEvent {
             title: proposal.title.unwrap_or_else(|| "Meeting".to_string()),
-            start: proposal.slot_start,
+            start: slot.start,
             end: end_time,
             calendar_link,
         }),
+        response,
     })
     .into_response()
 }
@@ -331,7 +872,10 @@ pub async fn decline_proposal(
     State(state): State<Arc<AppState>>,
     Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
     Path(id): Path<String>,
+    body: Option<Json<DeclineProposalRequest>>,
 ) -> Response {
+    let Json(req) = body.unwrap_or_default();
+
     // Get proposal
     let proposal = match state.db.get_proposal(&id) {
         Ok(Some(p)) => p,
@@ -362,41 +906,697 @@ pub async fn decline_proposal(
             .into_response();
     }
 
-    // Update status
-    if let Err(e) = state
-        .db
-        .update_proposal_status(&id, ProposalStatus::Declined)
-    {
+    // Check if proposal is still pending
+    if proposal.status != ProposalStatus::Pending {
         return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse::new(e.to_string())),
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(format!(
+                "Proposal is already {}",
+                proposal.status
+            ))),
         )
             .into_response();
     }
 
+    // Senders on other servers are stored by email
+    let sender = state.db.get_user(&proposal.from_user_id).ok().flatten();
+    let sender_email = match &sender {
+        Some(sender) => sender.email.clone(),
+        None => proposal.from_user_id.clone(),
+    };
+    let response = match sign_response(
+        &user,
+        &proposal,
+        &sender_email,
+        ProposalStatus::Declined,
+        None,
+        req.response,
+    ) {
+        Ok(response) => response,
+        Err((status, error)) => {
+            return (status, Json(ErrorResponse::new(error))).into_response();
+        }
+    };
+
+    // Update status
+    if let Err((status, error)) = answer_pending(&state, &id, ProposalStatus::Declined) {
+        return (status, Json(ErrorResponse::new(error))).into_response();
+    }
+
     // Send webhook to sender
-    if let Ok(Some(sender)) = state.db.get_user(&proposal.from_user_id) {
-        if let (Some(webhook_url), Some(webhook_secret)) =
-            (sender.webhook_url, sender.webhook_secret)
-        {
-            let event = WebhookEvent::new(
+    if let Some(sender) = sender {
+        notify_user(
+            sender,
+            WebhookEvent::new(
                 WebhookEventType::ProposalDeclined,
-                WebhookEventData::proposal_declined(id.clone(), user.email.clone()),
-            );
+                WebhookEventData {
+                    response: response.clone(),
+                    ..WebhookEventData::proposal_declined(id.clone(), user.email.clone())
+                },
+            ),
+        );
+    }
+    let warning = match &proposal.remote {
+        Some(remote) => {
+            send_response(
+                &state,
+                remote,
+                ProposalStatus::Declined,
+                None,
+                response.clone(),
+            )
+            .await
+        }
+        None => None,
+    };
+
+    Json(DeclineProposalResponse {
+        status: "declined".to_string(),
+        response,
+        warning,
+    })
+    .into_response()
+}
+
+/// Answer a proposal with a different time
+///
+/// Sends a new signed proposal back to the original sender, linked through
+/// `counter_of`, and marks the original `countered`.
+pub async fn counter_proposal(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Path(id): Path<String>,
+    Json(req): Json<CounterProposalRequest>,
+) -> Response {
+    let (proposal, sender_email) = match counterable(&state, &user, &id) {
+        Ok(found) => found,
+        Err((status, error)) => {
+            return (status, Json(ErrorResponse::new(error))).into_response();
+        }
+    };
+
+    let counter = CreateProposalRequest {
+        to_email: sender_email,
+        slot_start: req.slot_start,
+        alternative_starts: req.alternative_starts,
+        duration_minutes: req.duration_minutes.unwrap_or(proposal.duration_minutes),
+        title: proposal.title,
+        description: proposal.description,
+        recurrence: proposal.recurrence,
+        location: proposal.location,
+        conference: proposal.conference,
+    };
+    if let Err((status, error)) = answer_pending(&state, &id, ProposalStatus::Countered) {
+        return (status, Json(ErrorResponse::new(error))).into_response();
+    }
+    match send_proposal(&state, &user, counter, Some(id.clone()), req.message).await {
+        Ok(resp) => Json(resp).into_response(),
+        Err((status, error)) => {
+            reopen_countered(&state, &id);
+            (status, Json(ErrorResponse::new(error))).into_response()
+        }
+    }
+}
+
+/// Check that `user` may counter proposal `id`
+///
+/// Returns the proposal and its sender's email (external senders are stored
+/// by email). An expired proposal is marked as such.
+fn counterable(
+    state: &AppState,
+    user: &User,
+    id: &str,
+) -> Result<(Proposal, String), (StatusCode, String)> {
+    let proposal = match state.db.get_proposal(id) {
+        Ok(Some(p)) => p,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Proposal not found".to_string())),
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    };
+
+    // Check if user is the recipient
+    if proposal.to_email != user.email {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Not authorized to counter this proposal".to_string(),
+        ));
+    }
+
+    // Check if proposal is still pending
+    if proposal.status != ProposalStatus::Pending {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Proposal is already {}", proposal.status),
+        ));
+    }
+
+    // Check if proposal has expired
+    if proposal.expires_at < Utc::now() {
+        let _ = state.db.update_proposal_status(id, ProposalStatus::Expired);
+        return Err((StatusCode::BAD_REQUEST, "Proposal has expired".to_string()));
+    }
+
+    let sender_email = match state.db.get_user(&proposal.from_user_id) {
+        Ok(Some(sender)) => sender.email,
+        _ => proposal.from_user_id.clone(),
+    };
+    Ok((proposal, sender_email))
+}
+
+/// Withdraw a pending proposal (sender only)
+pub async fn withdraw_proposal(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Path(id): Path<String>,
+) -> Response {
+    // Get proposal
+    let proposal = match state.db.get_proposal(&id) {
+        Ok(Some(p)) => p,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse::new("Proposal not found")),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
+        }
+    };
+
+    // Check if user is the sender
+    if proposal.from_user_id != user.id {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse::new(
+                "Not authorized to withdraw this proposal",
+            )),
+        )
+            .into_response();
+    }
+
+    // Check if proposal is still pending
+    if proposal.status != ProposalStatus::Pending {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(format!(
+                "Proposal is already {}",
+                proposal.status
+            ))),
+        )
+            .into_response();
+    }
 
-            let webhook_client = WebhookClient::new();
-            tokio::spawn(async move {
-                if let Err(e) = webhook_client
-                    .deliver(&webhook_url, &webhook_secret, &event)
-                    .await
-                {
-                    tracing::warn!("Failed to deliver webhook: {}", e);
+    if let Err((status, error)) = answer_pending(&state, &id, ProposalStatus::Withdrawn) {
+        return (status, Json(ErrorResponse::new(error))).into_response();
+    }
+
+    let warning = match &proposal.remote {
+        Some(remote) => {
+            send_federated(
+                &state,
+                remote,
+                FederationMessage::Withdraw {
+                    proposal_id: remote.id.clone(),
+                    reply_token: remote.reply_token.clone(),
+                },
+            )
+            .await
+        }
+        None => None,
+    };
+
+    // Send webhook to recipient
+    if let Ok(Some(recipient)) = state.db.get_user_by_email(&proposal.to_email) {
+        notify_user(
+            recipient,
+            WebhookEvent::new(
+                WebhookEventType::ProposalWithdrawn,
+                WebhookEventData::proposal_withdrawn(id.clone(), user.email.clone()),
+            ),
+        );
+    }
+
+    Json(StatusResponse::new("withdrawn", warning)).into_response()
+}
+
+/// Cancel an accepted meeting (either party)
+pub async fn cancel_proposal(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Path(id): Path<String>,
+) -> Response {
+    let proposal = match accepted_meeting(&state, &user, &id) {
+        Ok(p) => p,
+        Err((status, error)) => {
+            return (status, Json(ErrorResponse::new(error))).into_response();
+        }
+    };
+
+    match state.db.transition_proposal_status(
+        &id,
+        ProposalStatus::Accepted,
+        ProposalStatus::Cancelled,
+    ) {
+        Ok(0) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(ErrorResponse::new("Meeting was already cancelled")),
+            )
+                .into_response();
+        }
+        Ok(_) => {}
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(e.to_string())),
+            )
+                .into_response();
+        }
+    }
+
+    unbook_meeting(&state, &proposal).await;
+    let warning = match &proposal.remote {
+        Some(remote) => {
+            send_federated(
+                &state,
+                remote,
+                FederationMessage::Cancel {
+                    proposal_id: remote.id.clone(),
+                    reply_token: remote.reply_token.clone(),
+                },
+            )
+            .await
+        }
+        None => None,
+    };
+
+    notify_participants(
+        &state,
+        &proposal,
+        WebhookEvent::new(
+            WebhookEventType::ProposalCancelled,
+            WebhookEventData::proposal_cancelled(id.clone(), user.email.clone()),
+        ),
+    );
+
+    Json(StatusResponse::new("cancelled", warning)).into_response()
+}
+
+/// Move an accepted meeting to a new time (either party)
+pub async fn reschedule_proposal(
+    State(state): State<Arc<AppState>>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Path(id): Path<String>,
+    Json(req): Json<RescheduleRequest>,
+) -> Response {
+    let proposal = match accepted_meeting(&state, &user, &id) {
+        Ok(p) => p,
+        Err((status, error)) => {
+            return (status, Json(ErrorResponse::new(error))).into_response();
+        }
+    };
+
+    if req.slot_start < Utc::now() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new("Can't move a meeting into the past")),
+        )
+            .into_response();
+    }
+
+    let slot = ProposalSlot {
+        start: req.slot_start,
+        duration_minutes: req.duration_minutes.unwrap_or(proposal.duration_minutes),
+    };
+    if let Err(e) = slot.validate() {
+        return (StatusCode::BAD_REQUEST, Json(ErrorResponse::new(e))).into_response();
+    }
+    let end_time = slot.start + Duration::minutes(slot.duration_minutes as i64);
+    let title = proposal
+        .title
+        .clone()
+        .unwrap_or_else(|| "Meeting".to_string());
+
+    if !req.force {
+        let conflicts = reschedule_conflicts(&state, &proposal, &slot).await;
+        if !conflicts.is_empty() {
+            return (
+                StatusCode::CONFLICT,
+                Json(SlotConflictResponse::new(conflicts, None)),
+            )
+                .into_response();
+        }
+    }
+
+    // The other server checks its user's calendar, and may refuse
+    if let Some(remote) = &proposal.remote {
+        let message = FederationMessage::Reschedule {
+            proposal_id: remote.id.clone(),
+            reply_token: remote.reply_token.clone(),
+            slot: slot.clone(),
+            force: req.force,
+        };
+        if let Err((status, error)) = deliver_now(&state, remote, &message).await {
+            return (status, Json(ErrorResponse::new(error))).into_response();
+        }
+    }
+
+    let calendar_link = match move_meeting(&state, &proposal, &slot).await {
+        Ok(links) => links.for_user(&proposal, &user),
+        Err((status, error)) => {
+            return (status, Json(ErrorResponse::new(error))).into_response();
+        }
+    };
+
+    notify_participants(
+        &state,
+        &proposal,
+        WebhookEvent::new(
+            WebhookEventType::ProposalRescheduled,
+            WebhookEventData::proposal_rescheduled(
+                id.clone(),
+                user.email.clone(),
+                slot.clone(),
+                calendar_link.clone(),
+            ),
+        ),
+    );
+
+    Json(AcceptProposalResponse {
+        status: "rescheduled".to_string(),
+        event: Some(CalendarEvent {
+            title,
+            start: slot.start,
+            end: end_time,
+            calendar_link,
+        }),
+        response: None,
+    })
+    .into_response()
+}
+
+/// The calendar event for `proposal` held at `slot`
+///
+/// `organiser` is the sender, when they're on this server; a recurring
+/// meeting repeats in their timezone.
+pub(crate) fn meeting_event(
+    proposal: &Proposal,
+    slot: &ProposalSlot,
+    attendee_email: Option<&str>,
+    organiser: Option<&User>,
+) -> EventDetails {
+    let (start, end) = slot_times(slot);
+    EventDetails {
+        description: proposal.description.clone(),
+        attendee_email: attendee_email.map(|e| e.to_string()),
+        recurrence: proposal.recurrence.clone(),
+        time_zone: organiser.map_or(Tz::UTC, User::timezone),
+        location: proposal.location.clone(),
+        conference: proposal.conference.clone(),
+        ..EventDetails::new(proposal.title.as_deref().unwrap_or("Meeting"), start, end)
+    }
+}
+
+/// Occurrences of `proposal` at `slot` that have to be free (just the one
+/// unless the meeting recurs in `organiser`'s timezone)
+pub(crate) fn meeting_occurrences(
+    proposal: &Proposal,
+    slot: &ProposalSlot,
+    organiser: Option<&User>,
+) -> Vec<TimeSlot> {
+    let (start, end) = slot_times(slot);
+    let rule = proposal
+        .recurrence
+        .as_deref()
+        .and_then(|rule| RecurrenceRule::parse(rule).ok());
+    occurrences(
+        &TimeSlot::new(start, end),
+        rule.as_ref(),
+        &organiser.map_or(Tz::UTC, User::timezone),
+        CHECKED_OCCURRENCES,
+    )
+}
+
+fn slot_times(slot: &ProposalSlot) -> (DateTime<Utc>, DateTime<Utc>) {
+    (
+        slot.start,
+        slot.start + Duration::minutes(slot.duration_minutes as i64),
+    )
+}
+
+/// Load an accepted proposal that `user` sent or received
+fn accepted_meeting(
+    state: &AppState,
+    user: &User,
+    id: &str,
+) -> Result<Proposal, (StatusCode, String)> {
+    let proposal = state
+        .db
+        .get_proposal(id)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Proposal not found".to_string()))?;
+
+    if proposal.from_user_id != user.id && proposal.to_email != user.email {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Not authorized to change this meeting".to_string(),
+        ));
+    }
+
+    if proposal.status != ProposalStatus::Accepted {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Proposal is {}, not accepted", proposal.status),
+        ));
+    }
+
+    Ok(proposal)
+}
+
+/// Links to each participant's copy of a meeting in their calendar
+#[derive(Debug, Default)]
+pub(crate) struct MeetingLinks {
+    pub sender: Option<String>,
+    pub recipient: Option<String>,
+}
+
+impl MeetingLinks {
+    /// The link to `user`'s own copy
+    pub fn for_user(self, proposal: &Proposal, user: &User) -> Option<String> {
+        if proposal.from_user_id == user.id {
+            self.sender
+        } else {
+            self.recipient
+        }
+    }
+}
+
+/// A participant on this server and the event meetd keeps in their calendar
+struct MeetingCopy {
+    owner: User,
+    is_sender: bool,
+    event: Option<EventRef>,
+}
+
+/// Participants of `proposal` on this server, with their events
+///
+/// A participant on another meetd server has their calendar kept by that server.
+fn meeting_copies(state: &AppState, proposal: &Proposal) -> Vec<MeetingCopy> {
+    let mut copies = Vec::new();
+    if let Ok(Some(sender)) = state.db.get_user(&proposal.from_user_id) {
+        copies.push(MeetingCopy {
+            owner: sender,
+            is_sender: true,
+            event: proposal.sender_event_id.as_ref().map(|id| EventRef {
+                id: id.clone(),
+                account_id: proposal.sender_event_account_id.clone(),
+                calendar_id: proposal.sender_event_calendar_id.clone(),
+            }),
+        });
+    }
+    if let Ok(Some(recipient)) = state.db.get_user_by_email(&proposal.to_email) {
+        copies.push(MeetingCopy {
+            owner: recipient,
+            is_sender: false,
+            event: proposal.event_id.as_ref().map(|id| EventRef {
+                id: id.clone(),
+                account_id: proposal.event_account_id.clone(),
+                calendar_id: proposal.event_calendar_id.clone(),
+            }),
+        });
+    }
+    copies
+}
+
+fn record_copy_event(
+    state: &AppState,
+    proposal: &Proposal,
+    copy: &MeetingCopy,
+    event: Option<&EventRef>,
+) {
+    let result = if copy.is_sender {
+        state.db.set_proposal_sender_event(&proposal.id, event)
+    } else {
+        state.db.set_proposal_event(&proposal.id, event)
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to record calendar event for {}: {}", proposal.id, e);
+    }
+}
+
+/// Put the meeting at `slot` into the calendar of each participant on this server
+///
+/// Every participant gets their own event, made by `meeting_event`, rather
+/// than relying on their provider to pass an invitation on. Events made
+/// before are moved; meetings accepted before event IDs were kept get a
+/// fresh one.
+pub(crate) async fn book_meeting(
+    state: &AppState,
+    proposal: &Proposal,
+    slot: &ProposalSlot,
+) -> MeetingLinks {
+    let mut links = MeetingLinks::default();
+    let copies = meeting_copies(state, proposal);
+    let organiser = copies.iter().find(|c| c.is_sender).map(|c| &c.owner);
+    let event = meeting_event(proposal, slot, None, organiser);
+    for copy in &copies {
+        let Ok(Some(cal)) = state.calendar_for(&copy.owner).await else {
+            continue;
+        };
+        let result = match &copy.event {
+            Some(existing) => cal.update_event(existing, &event).await,
+            None => cal.create_event(&event).await,
+        };
+        match result {
+            Ok(created) => {
+                record_copy_event(state, proposal, copy, Some(&created.event));
+                if copy.is_sender {
+                    links.sender = created.html_link;
+                } else {
+                    links.recipient = created.html_link;
                 }
-            });
+            }
+            Err(e) => tracing::warn!(
+                "Failed to put {} in {}'s calendar: {}",
+                proposal.id,
+                copy.owner.email,
+                e
+            ),
+        }
+    }
+    links
+}
+
+/// Busy time that clashes with accepted `proposal` moved to `slot`, in the
+/// calendars of its participants on this server
+///
+/// The meeting's own events are still at the old time, so busy time that
+/// lies within them doesn't count.
+pub(crate) async fn reschedule_conflicts(
+    state: &AppState,
+    proposal: &Proposal,
+    slot: &ProposalSlot,
+) -> Vec<SlotConflict> {
+    let copies = meeting_copies(state, proposal);
+    let organiser = copies.iter().find(|c| c.is_sender).map(|c| &c.owner);
+    let current = meeting_occurrences(proposal, &proposal.slot(), organiser);
+    let participants: Vec<&User> = copies.iter().map(|c| &c.owner).collect();
+    state
+        .slot_conflicts(
+            &participants,
+            &meeting_occurrences(proposal, slot, organiser),
+        )
+        .await
+        .into_iter()
+        .filter(|c| {
+            !current
+                .iter()
+                .any(|own| own.start <= c.start && c.end <= own.end)
+        })
+        .collect()
+}
+
+/// Move accepted `proposal` to `slot`, then move its events
+///
+/// The slot only changes while the meeting is still accepted, so a
+/// concurrent cancellation wins with a 409. One that lands while the events
+/// are being moved takes them out again.
+pub(crate) async fn move_meeting(
+    state: &AppState,
+    proposal: &Proposal,
+    slot: &ProposalSlot,
+) -> Result<MeetingLinks, (StatusCode, String)> {
+    match state.db.update_proposal_slot(&proposal.id, slot) {
+        Ok(0) => {
+            return Err((
+                StatusCode::CONFLICT,
+                "Meeting is no longer accepted".to_string(),
+            ))
+        }
+        Ok(_) => {}
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+
+    let links = book_meeting(state, proposal, slot).await;
+    if let Ok(Some(moved)) = state.db.get_proposal(&proposal.id) {
+        if moved.status != ProposalStatus::Accepted {
+            unbook_meeting(state, &moved).await;
+        }
+    }
+    Ok(links)
+}
+
+/// Remove the meeting's events from the participants' calendars
+pub(crate) async fn unbook_meeting(state: &AppState, proposal: &Proposal) {
+    for copy in meeting_copies(state, proposal) {
+        let Some(event) = &copy.event else {
+            continue;
+        };
+        if let Ok(Some(cal)) = state.calendar_for(&copy.owner).await {
+            if let Err(e) = cal.delete_event(event).await {
+                tracing::warn!(
+                    "Failed to delete {}'s calendar event for {}: {}",
+                    copy.owner.email,
+                    proposal.id,
+                    e
+                );
+            }
         }
+        record_copy_event(state, proposal, &copy, None);
     }
+}
+
+/// Send an event to the webhooks of both the sender and the recipient
+pub(crate) fn notify_participants(state: &AppState, proposal: &Proposal, event: WebhookEvent) {
+    let sender = state.db.get_user(&proposal.from_user_id).ok().flatten();
+    let recipient = state
+        .db
+        .get_user_by_email(&proposal.to_email)
+        .ok()
+        .flatten();
 
-    Json(serde_json::json!({ "status": "declined" })).into_response()
+    for participant in [sender, recipient].into_iter().flatten() {
+        notify_user(participant, event.clone());
+    }
+}
+
+/// Deliver an event to a user's webhook, if they have one (fire and forget)
+pub(crate) fn notify_user(user: User, event: WebhookEvent) {
+    if let (Some(webhook_url), Some(webhook_secret)) = (user.webhook_url, user.webhook_secret) {
+        let webhook_client = WebhookClient::new();
+        tokio::spawn(async move {
+            if let Err(e) = webhook_client
+                .deliver(&webhook_url, &webhook_secret, &event)
+                .await
+            {
+                tracing::warn!("Failed to deliver webhook: {}", e);
+            }
+        });
+    }
 }
 
 #[derive(Deserialize)]
@@ -412,7 +1612,13 @@ pub struct VerifyProposalResponse {
 }
 
 /// Verify a signed proposal
-pub async fn verify_proposal(Json(req): Json<VerifyProposalRequest>) -> Response {
+///
+/// For senders on this server, the key must also have been one of theirs
+/// when the proposal was made.
+pub async fn verify_proposal(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<VerifyProposalRequest>,
+) -> Response {
     // Decode base64
     let json_bytes = match base64::Engine::decode(
         &base64::engine::general_purpose::STANDARD,
@@ -456,12 +1662,35 @@ pub async fn verify_proposal(Json(req): Json<VerifyProposalRequest>) -> Response
     };
 
     match pub_key.verify_proposal(&proposal) {
-        Ok(true) => Json(VerifyProposalResponse {
-            valid: true,
-            proposal: Some(proposal),
-            error: None,
-        })
-        .into_response(),
+        Ok(true) => {
+            // Fields the version doesn't sign would otherwise pass unchecked
+            let error = match proposal
+                .validate()
+                .map(|_| state.db.get_user_by_email(&proposal.from))
+            {
+                Err(e) => Some(e),
+                Ok(Ok(Some(sender))) => match state.db.is_signing_key_valid(
+                    &sender.id,
+                    &proposal.from_pubkey,
+                    proposal.signed_at(),
+                ) {
+                    Ok(true) => None,
+                    Ok(false) => Some(format!(
+                        "Not signed with a key {} held at the time",
+                        proposal.from
+                    )),
+                    Err(e) => Some(format!("Verification error: {}", e)),
+                },
+                Ok(Ok(None)) => None,
+                Ok(Err(e)) => Some(format!("Verification error: {}", e)),
+            };
+            Json(VerifyProposalResponse {
+                valid: error.is_none(),
+                proposal: Some(proposal),
+                error,
+            })
+            .into_response()
+        }
         Ok(false) => Json(VerifyProposalResponse {
             valid: false,
             proposal: Some(proposal),
@@ -476,3 +1705,111 @@ pub async fn verify_proposal(Json(req): Json<VerifyProposalRequest>) -> Response
         .into_response(),
     }
 }
+
+#[derive(Deserialize)]
+pub struct VerifyResponseRequest {
+    pub response: ProposalResponse,
+    /// The proposal the response claims to answer (base64, as sent)
+    #[serde(default)]
+    pub signed_proposal: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct VerifyResponseResponse {
+    pub valid: bool,
+    pub response: ProposalResponse,
+    pub error: Option<String>,
+}
+
+/// Verify a signed acceptance or decline
+///
+/// For responders on this server, the key must also have been one of theirs
+/// when they answered. With the proposal, also checks the response answers
+/// it and accepted one of its slots.
+pub async fn verify_response(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<VerifyResponseRequest>,
+) -> Response {
+    let error = check_response(&state, &req.response, req.signed_proposal.as_deref())
+        .await
+        .err();
+    Json(VerifyResponseResponse {
+        valid: error.is_none(),
+        response: req.response,
+        error,
+    })
+    .into_response()
+}
+
+async fn check_response(
+    state: &AppState,
+    response: &ProposalResponse,
+    signed_proposal: Option<&str>,
+) -> Result<(), String> {
+    response.validate()?;
+
+    let pub_key = PublicKey::from_base64(&response.from_pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    match pub_key.verify_response(response) {
+        Ok(true) => {}
+        Ok(false) => return Err("Invalid signature".to_string()),
+        Err(e) => return Err(format!("Verification error: {}", e)),
+    }
+
+    if let Some(responder) = state
+        .db
+        .get_user_by_email(&response.from)
+        .map_err(|e| format!("Verification error: {}", e))?
+    {
+        let key_valid = state
+            .db
+            .is_signing_key_valid(&responder.id, &response.from_pubkey, response.responded_at)
+            .map_err(|e| format!("Verification error: {}", e))?;
+        if !key_valid {
+            return Err(format!(
+                "Not signed with a key {} held at the time",
+                response.from
+            ));
+        }
+    } else {
+        // Responders elsewhere are checked against the keys their server lists
+        let home = state
+            .remote_home_server(&response.from)
+            .await
+            .map_err(|e| format!("Couldn't find {}'s server: {:#}", response.from, e))?
+            .ok_or_else(|| {
+                format!(
+                    "No meetd server found for {}, so their key can't be checked",
+                    response.from
+                )
+            })?;
+        let key_valid = state
+            .remote_key_valid(
+                &home,
+                &response.from,
+                &response.from_pubkey,
+                response.responded_at,
+            )
+            .await
+            .map_err(|e| format!("Couldn't look up {}'s keys: {:#}", response.from, e))?;
+        if !key_valid {
+            return Err(format!(
+                "Not signed with a key {} held at the time",
+                response.from
+            ));
+        }
+    }
+
+    if let Some(signed_proposal) = signed_proposal {
+        let proposal = SignedProposal::from_base64(signed_proposal)?;
+        let proposal_key = PublicKey::from_base64(&proposal.from_pubkey)
+            .map_err(|e| format!("Invalid proposal public key: {}", e))?;
+        if !proposal_key.verify_proposal(&proposal).unwrap_or(false) {
+            return Err("Invalid proposal signature".to_string());
+        }
+        proposal.validate()?;
+        response.answers(&proposal)?;
+    }
+
+    Ok(())
+}
@@ -123,12 +123,8 @@ pub async fn test_webhook(
         WebhookEventData {
             proposal_id: "test_proposal".to_string(),
             from: "test@meetd.example.com".to_string(),
-            from_pubkey: None,
-            slot: None,
             title: Some("Test Webhook".to_string()),
-            expires_at: None,
-            signature: None,
-            calendar_link: None,
+            ..Default::default()
         },
     );
 
@@ -5,10 +5,19 @@ mod routes;
 pub use routes::create_router;
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 
+use crate::calendar::{CalendarProvider, CalendarRegistry};
+use crate::crypto::MasterKey;
 use crate::db::Database;
+use crate::federation::{
+    FederationClient, FederationMessage, Peers, Resolver, ServerMetadata, FEDERATION_INBOX_PATH,
+};
+use crate::mail::Mailer;
+use crate::models::{SlotConflict, TimeSlot, User};
 
 /// Shared application state
 pub struct AppState {
@@ -16,6 +25,13 @@ pub struct AppState {
     pub google_client_id: String,
     pub google_client_secret: String,
     pub server_url: String,
+    /// Directory that local ICS calendars must live in (unset = URLs only)
+    pub ics_dir: Option<PathBuf>,
+    pub calendars: CalendarRegistry,
+    /// Finds other meetd servers by the email domains they host
+    pub resolver: Resolver,
+    /// Sends iMIP invitations to recipients without meetd (unset = no email)
+    pub mailer: Option<Mailer>,
 }
 
 impl AppState {
@@ -24,14 +40,216 @@ impl AppState {
         google_client_id: String,
         google_client_secret: String,
         server_url: String,
+        ics_dir: Option<PathBuf>,
     ) -> Self {
+        let calendars = CalendarRegistry::new(&google_client_id, &google_client_secret);
         Self {
             db,
             google_client_id,
             google_client_secret,
             server_url,
+            ics_dir,
+            calendars,
+            resolver: Resolver::default(),
+            mailer: None,
         }
     }
+
+    /// Use the servers in `peers` for their domains instead of discovering them
+    pub fn with_peers(mut self, peers: Peers) -> Self {
+        self.resolver = Resolver::new(peers);
+        self
+    }
+
+    /// Email invitations to recipients who don't use meetd
+    pub fn with_mailer(mut self, mailer: Mailer) -> Self {
+        self.mailer = Some(mailer);
+        self
+    }
+
+    /// How this server describes itself in `/.well-known/meetd`
+    pub fn metadata(&self) -> Result<ServerMetadata> {
+        let server_key = self.db.server_keypair()?;
+        Ok(ServerMetadata::new(
+            &self.server_url,
+            server_key.public_key_base64(),
+        ))
+    }
+
+    /// Client for other servers' federated inboxes, signing as this server
+    pub fn federation_client(&self) -> Result<FederationClient> {
+        Ok(FederationClient::new(
+            &self.server_url,
+            self.db.server_keypair()?,
+        ))
+    }
+
+    /// The other meetd server `email` belongs to
+    ///
+    /// `None` for users of this server and for addresses no meetd server
+    /// hosts.
+    pub async fn remote_home_server(&self, email: &str) -> Result<Option<ServerMetadata>> {
+        if self.db.get_user_by_email(email)?.is_some() {
+            return Ok(None);
+        }
+        Ok(self
+            .resolver
+            .home_server(email)
+            .await?
+            .filter(|metadata| metadata.api_base != self.server_url.trim_end_matches('/')))
+    }
+
+    /// Queue a message for `server` to be retried after a failed delivery
+    pub fn queue_federation_message(
+        &self,
+        server: &str,
+        message: &FederationMessage,
+        error: &anyhow::Error,
+    ) -> Result<()> {
+        self.db.queue_federation_message(
+            server,
+            message,
+            &format!("{:#}", error),
+            next_federation_attempt(1),
+        )
+    }
+
+    /// Try the queued federated messages that are due again
+    ///
+    /// Delivered messages leave the outbox, as do ones the other server
+    /// turns down or that have run out of attempts.
+    pub async fn flush_federation_outbox(&self) {
+        let queued = match self.db.due_federation_messages(Utc::now()) {
+            Ok(queued) => queued,
+            Err(e) => {
+                tracing::warn!("Failed to read the federation outbox: {:#}", e);
+                return;
+            }
+        };
+        if queued.is_empty() {
+            return;
+        }
+        let client = match self.federation_client() {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("Failed to retry federated messages: {:#}", e);
+                return;
+            }
+        };
+
+        for queued in queued {
+            let inbox = format!("{}{}", queued.server, FEDERATION_INBOX_PATH);
+            let result = match client.deliver(&inbox, &queued.message).await {
+                Ok(_) => self.db.remove_federation_message(queued.id),
+                Err(e)
+                    if !FederationClient::is_retryable(&e)
+                        || queued.attempts >= MAX_FEDERATION_ATTEMPTS =>
+                {
+                    tracing::warn!("Giving up on a message for {}: {:#}", queued.server, e);
+                    self.db.remove_federation_message(queued.id)
+                }
+                Err(e) => self.db.retry_federation_message(
+                    queued.id,
+                    &format!("{:#}", e),
+                    next_federation_attempt(queued.attempts + 1),
+                ),
+            };
+            if let Err(e) = result {
+                tracing::warn!("Failed to update the federation outbox: {:#}", e);
+            }
+        }
+    }
+
+    /// Whether `public_key` was `email`'s signing key at `at`, going by the
+    /// key history their server publishes
+    pub async fn remote_key_valid(
+        &self,
+        home: &ServerMetadata,
+        email: &str,
+        public_key: &str,
+        at: DateTime<Utc>,
+    ) -> Result<bool> {
+        let history = self.resolver.signing_keys(home, email).await?;
+        Ok(history
+            .keys
+            .iter()
+            .any(|key| key.public_key == public_key && key.valid_at(at)))
+    }
+
+    /// Connect to a user's linked calendars, if they have any
+    pub async fn calendar_for(&self, user: &User) -> Result<Option<Box<dyn CalendarProvider>>> {
+        let accounts = self.db.get_calendar_accounts(&user.id)?;
+        self.calendars.for_accounts(&accounts).await
+    }
+
+    /// Whether `owner`'s sharing policy lets `requester` see their availability
+    ///
+    /// A failed contact lookup counts as not shared.
+    pub fn shares_availability(&self, owner: &User, requester: &User) -> bool {
+        owner.sharing.allows(
+            &owner.email,
+            &requester.email,
+            requester.email_verified,
+            || self.db.is_contact(owner, &requester.email).unwrap_or(false),
+        )
+    }
+
+    /// Busy time in `participants`' calendars that overlaps any of `slots`
+    ///
+    /// `slots` are the occurrences of one meeting, in order. Calendars that
+    /// can't be read are skipped, so this never blocks an acceptance on a
+    /// provider outage.
+    pub async fn slot_conflicts(
+        &self,
+        participants: &[&User],
+        slots: &[TimeSlot],
+    ) -> Vec<SlotConflict> {
+        let (Some(first), Some(last)) = (slots.first(), slots.last()) else {
+            return vec![];
+        };
+        let (start, end) = (first.start, last.end);
+
+        let mut conflicts = Vec::new();
+        for user in participants {
+            let busy = match self.calendar_for(user).await {
+                Ok(Some(cal)) => cal.get_busy_periods(start, end).await,
+                Ok(None) => continue,
+                Err(e) => Err(e),
+            };
+            match busy {
+                Ok(periods) => conflicts.extend(
+                    periods
+                        .into_iter()
+                        .filter(|p| slots.iter().any(|s| p.start < s.end && p.end > s.start))
+                        .map(|p| SlotConflict {
+                            email: user.email.clone(),
+                            start: p.start,
+                            end: p.end,
+                        }),
+                ),
+                Err(e) => {
+                    tracing::warn!(
+                        "Couldn't check {}'s calendar for conflicts: {}",
+                        user.email,
+                        e
+                    )
+                }
+            }
+        }
+        conflicts
+    }
+}
+
+/// Most times a queued federated message is tried before it's dropped
+const MAX_FEDERATION_ATTEMPTS: u32 = 12;
+
+/// When to try a message that has failed `attempts` times again
+///
+/// The wait doubles from a minute up to about four hours, so a server that
+/// is down for most of a day still gets its messages.
+fn next_federation_attempt(attempts: u32) -> DateTime<Utc> {
+    let minutes = 1i64 << attempts.saturating_sub(1).min(8);
+    Utc::now() + chrono::Duration::minutes(minutes)
 }
 
 /// Run the API server
@@ -41,16 +259,47 @@ pub async fn run_server(addr: SocketAddr, db_path: &str, server_url: &str) -> Re
     let google_client_secret =
         std::env::var("GOOGLE_CLIENT_SECRET").unwrap_or_else(|_| "".to_string());
 
-    // Open database
-    let db = Database::open(db_path)?;
+    // Local ICS calendars are only allowed inside this directory
+    let ics_dir = std::env::var("MEETD_ICS_DIR").ok().map(PathBuf::from);
+
+    // Open database, encrypting secrets if a master key is configured
+    let mut db = Database::open(db_path)?;
+    match MasterKey::from_env()? {
+        Some(key) => db = db.with_master_key(key),
+        None => tracing::warn!(
+            "No MEETD_MASTER_KEY or MEETD_MASTER_KEY_FILE set; private keys and calendar credentials are stored unencrypted"
+        ),
+    }
+
+    // Other meetd servers that proposals can be exchanged with
+    let peers = Peers::from_env()?;
 
     // Create app state
-    let state = Arc::new(AppState::new(
+    let mut state = AppState::new(
         db,
         google_client_id,
         google_client_secret,
         server_url.to_string(),
-    ));
+        ics_dir,
+    )
+    .with_peers(peers);
+
+    // SMTP relay for inviting people who don't use meetd
+    match Mailer::from_env()? {
+        Some(mailer) => state = state.with_mailer(mailer),
+        None => tracing::info!("No MEETD_SMTP_URL set; recipients without meetd aren't emailed"),
+    }
+    let state = Arc::new(state);
+
+    // Retry federated messages other servers couldn't be reached for
+    let outbox_state = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            outbox_state.flush_federation_outbox().await;
+        }
+    });
 
     // Create router
     let app = create_router(state);
@@ -63,3 +312,803 @@ pub async fn run_server(addr: SocketAddr, db_path: &str, server_url: &str) -> Re
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::EventDetails;
+    use crate::models::{
+        CalendarAccount, CalendarConfig, CalendarSelection, SharingPolicy, SlotPreferences,
+        Visibility, PROPOSAL_VERSION,
+    };
+    use chrono::{DateTime, Duration, Utc};
+
+    fn user_with_ics(db: &Database, email: &str, path: &std::path::Path) -> User {
+        let user = User {
+            id: uuid::Uuid::new_v4().to_string(),
+            email: email.to_string(),
+            public_key: "pubkey".to_string(),
+            private_key: "privkey".to_string(),
+            api_key_hash: String::new(),
+            visibility: Visibility::BusyOnly,
+            webhook_url: None,
+            webhook_secret: None,
+            working_hours: None,
+            slot_preferences: SlotPreferences::default(),
+            sharing: SharingPolicy::default(),
+            created_at: 0,
+            email_verified: true,
+        };
+        db.create_user(&user).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        db.create_calendar_account(&CalendarAccount {
+            id: format!("cal_{}", user.id),
+            user_id: user.id.clone(),
+            config: CalendarConfig::Ics {
+                source: path.clone(),
+                write_path: Some(path),
+            },
+            label: None,
+            is_primary: true,
+            selection: CalendarSelection::default(),
+            created_at: 0,
+        })
+        .unwrap();
+        user
+    }
+
+    #[tokio::test]
+    async fn test_slot_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::new(
+            Database::open_in_memory().unwrap(),
+            String::new(),
+            String::new(),
+            String::new(),
+            None,
+        );
+        let alice = user_with_ics(&state.db, "alice@example.com", &dir.path().join("a.ics"));
+        let bob = user_with_ics(&state.db, "bob@example.com", &dir.path().join("b.ics"));
+
+        let start =
+            DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap() + Duration::days(1);
+        let end = start + Duration::minutes(30);
+        let slot = [TimeSlot::new(start, end)];
+        assert!(state
+            .slot_conflicts(&[&alice, &bob], &slot)
+            .await
+            .is_empty());
+
+        let bob_cal = state.calendar_for(&bob).await.unwrap().unwrap();
+        bob_cal
+            .create_event(&EventDetails::new(
+                "Dentist",
+                start - Duration::minutes(15),
+                end,
+            ))
+            .await
+            .unwrap();
+
+        let conflicts = state.slot_conflicts(&[&alice, &bob], &slot).await;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].email, "bob@example.com");
+        assert_eq!(conflicts[0].start, start - Duration::minutes(15));
+
+        // Later occurrences of a recurring meeting are checked too
+        let next_week = start + Duration::weeks(1);
+        let alice_cal = state.calendar_for(&alice).await.unwrap().unwrap();
+        alice_cal
+            .create_event(&EventDetails::new(
+                "Offsite",
+                next_week,
+                next_week + Duration::hours(1),
+            ))
+            .await
+            .unwrap();
+        let weekly: Vec<TimeSlot> = (1..3)
+            .map(|w| TimeSlot::new(start + Duration::weeks(w), end + Duration::weeks(w)))
+            .collect();
+        let conflicts = state.slot_conflicts(&[&alice, &bob], &weekly).await;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].email, "alice@example.com");
+        assert_eq!(conflicts[0].start, next_week);
+    }
+
+    #[tokio::test]
+    async fn test_book_meeting_in_both_calendars() {
+        use crate::models::{Proposal, ProposalSlot, SignedProposal};
+        use handlers::proposals::{book_meeting, unbook_meeting};
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::new(
+            Database::open_in_memory().unwrap(),
+            String::new(),
+            String::new(),
+            String::new(),
+            None,
+        );
+        let alice = user_with_ics(&state.db, "alice@example.com", &dir.path().join("a.ics"));
+        let bob = user_with_ics(&state.db, "bob@example.com", &dir.path().join("b.ics"));
+
+        let start =
+            DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap() + Duration::days(1);
+        let slot = ProposalSlot {
+            start,
+            duration_minutes: 30,
+        };
+        let signed = SignedProposal {
+            version: 1,
+            from: alice.email.clone(),
+            from_pubkey: alice.public_key.clone(),
+            to: bob.email.clone(),
+            slot: slot.clone(),
+            slots: vec![],
+            title: Some("Sync".to_string()),
+            description: None,
+            recurrence: None,
+            location: None,
+            conference: None,
+            nonce: uuid::Uuid::new_v4().to_string(),
+            expires_at: start,
+            created_at: None,
+            signature: String::new(),
+        };
+        let proposal = Proposal::from_signed("prop_1".to_string(), alice.id.clone(), &signed);
+        state.db.create_proposal(&proposal).unwrap();
+        let busy = |slot: &ProposalSlot| {
+            [TimeSlot::new(
+                slot.start,
+                slot.start + Duration::minutes(slot.duration_minutes as i64),
+            )]
+        };
+
+        book_meeting(&state, &proposal, &slot).await;
+        let proposal = state.db.get_proposal("prop_1").unwrap().unwrap();
+        assert!(proposal.event_id.is_some() && proposal.sender_event_id.is_some());
+        assert_eq!(
+            state
+                .slot_conflicts(&[&alice, &bob], &busy(&slot))
+                .await
+                .len(),
+            2
+        );
+
+        // Moving the meeting moves both events
+        let moved = ProposalSlot {
+            start: start + Duration::hours(2),
+            duration_minutes: 45,
+        };
+        book_meeting(&state, &proposal, &moved).await;
+        assert!(state
+            .slot_conflicts(&[&alice, &bob], &busy(&slot))
+            .await
+            .is_empty());
+        assert_eq!(
+            state
+                .slot_conflicts(&[&alice, &bob], &busy(&moved))
+                .await
+                .len(),
+            2
+        );
+
+        // Cancelling clears both calendars
+        let proposal = state.db.get_proposal("prop_1").unwrap().unwrap();
+        unbook_meeting(&state, &proposal).await;
+        assert!(state
+            .slot_conflicts(&[&alice, &bob], &busy(&moved))
+            .await
+            .is_empty());
+        let proposal = state.db.get_proposal("prop_1").unwrap().unwrap();
+        assert_eq!((proposal.event_id, proposal.sender_event_id), (None, None));
+    }
+
+    #[tokio::test]
+    async fn test_reschedule_checks_conflicts_and_status() {
+        use crate::models::{Proposal, ProposalSlot, ProposalStatus, SignedProposal};
+        use axum::http::StatusCode;
+        use handlers::proposals::{book_meeting, move_meeting, reschedule_conflicts};
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::new(
+            Database::open_in_memory().unwrap(),
+            String::new(),
+            String::new(),
+            String::new(),
+            None,
+        );
+        let alice = user_with_ics(&state.db, "alice@example.com", &dir.path().join("a.ics"));
+        let bob = user_with_ics(&state.db, "bob@example.com", &dir.path().join("b.ics"));
+
+        let start =
+            DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap() + Duration::days(1);
+        let slot = ProposalSlot {
+            start,
+            duration_minutes: 60,
+        };
+        let signed = SignedProposal {
+            version: PROPOSAL_VERSION,
+            from: alice.email.clone(),
+            from_pubkey: alice.public_key.clone(),
+            to: bob.email.clone(),
+            slot: slot.clone(),
+            slots: vec![],
+            title: Some("Sync".to_string()),
+            description: None,
+            recurrence: None,
+            location: None,
+            conference: None,
+            nonce: uuid::Uuid::new_v4().to_string(),
+            expires_at: start,
+            created_at: None,
+            signature: String::new(),
+        };
+        let proposal = Proposal {
+            status: ProposalStatus::Accepted,
+            ..Proposal::from_signed("prop_1".to_string(), alice.id.clone(), &signed)
+        };
+        state.db.create_proposal(&proposal).unwrap();
+        book_meeting(&state, &proposal, &slot).await;
+        let proposal = state.db.get_proposal("prop_1").unwrap().unwrap();
+
+        // Sliding within its own time doesn't clash with itself
+        let nudged = ProposalSlot {
+            start: start + Duration::minutes(15),
+            duration_minutes: 30,
+        };
+        assert!(reschedule_conflicts(&state, &proposal, &nudged)
+            .await
+            .is_empty());
+
+        // Someone else's busy time at the new slot does
+        let later = ProposalSlot {
+            start: start + Duration::hours(3),
+            duration_minutes: 30,
+        };
+        let bob_cal = state.calendar_for(&bob).await.unwrap().unwrap();
+        bob_cal
+            .create_event(&EventDetails::new(
+                "Dentist",
+                later.start,
+                later.start + Duration::minutes(30),
+            ))
+            .await
+            .unwrap();
+        let conflicts = reschedule_conflicts(&state, &proposal, &later).await;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].email, "bob@example.com");
+
+        // A meeting cancelled in the meantime isn't moved or booked again
+        state
+            .db
+            .transition_proposal_status(
+                "prop_1",
+                ProposalStatus::Accepted,
+                ProposalStatus::Cancelled,
+            )
+            .unwrap();
+        let err = move_meeting(&state, &proposal, &nudged).await.unwrap_err();
+        assert_eq!(err.0, StatusCode::CONFLICT);
+        assert_eq!(
+            state.db.get_proposal("prop_1").unwrap().unwrap().slot(),
+            slot
+        );
+    }
+
+    #[tokio::test]
+    async fn test_itip_reply_books_meeting() {
+        use crate::calendar::itip;
+        use crate::models::{Proposal, ProposalSlot, ProposalStatus, SignedProposal};
+        use axum::{extract::State, http::StatusCode};
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = Arc::new(AppState::new(
+            Database::open_in_memory().unwrap(),
+            String::new(),
+            String::new(),
+            String::new(),
+            None,
+        ));
+        let alice = user_with_ics(&state.db, "alice@example.com", &dir.path().join("a.ics"));
+        let start =
+            DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap() + Duration::days(1);
+        let slot = [TimeSlot::new(start, start + Duration::minutes(30))];
+        let propose = |id: &str| {
+            let signed = SignedProposal {
+                version: PROPOSAL_VERSION,
+                from: alice.email.clone(),
+                from_pubkey: alice.public_key.clone(),
+                to: "carol@elsewhere.org".to_string(),
+                slot: ProposalSlot {
+                    start,
+                    duration_minutes: 30,
+                },
+                slots: vec![],
+                title: Some("Sync".to_string()),
+                description: None,
+                recurrence: None,
+                location: None,
+                conference: None,
+                nonce: uuid::Uuid::new_v4().to_string(),
+                expires_at: start,
+                created_at: None,
+                signature: String::new(),
+            };
+            let proposal = Proposal::from_signed(id.to_string(), alice.id.clone(), &signed);
+            state.db.create_proposal(&proposal).unwrap();
+        };
+        let reply = |id: &str| {
+            let uid = itip::invite_uid(id, &state.db.server_keypair().unwrap(), "meetd");
+            format!(
+                "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nMETHOD:REPLY\r\nBEGIN:VEVENT\r\nUID:{}\r\nATTENDEE;PARTSTAT=ACCEPTED:mailto:carol@elsewhere.org\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n",
+                uid
+            )
+        };
+
+        // Accepting books the meeting in the sender's calendar
+        propose("prop_1");
+        let response = handlers::itip::receive_reply(State(state.clone()), reply("prop_1")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let proposal = state.db.get_proposal("prop_1").unwrap().unwrap();
+        assert_eq!(proposal.status, ProposalStatus::Accepted);
+        assert!(proposal.sender_event_id.is_some());
+        assert_eq!(state.slot_conflicts(&[&alice], &slot).await.len(), 1);
+
+        // A second invitation for the same time can no longer be accepted
+        propose("prop_2");
+        let response = handlers::itip::receive_reply(State(state.clone()), reply("prop_2")).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let proposal = state.db.get_proposal("prop_2").unwrap().unwrap();
+        assert_eq!(proposal.status, ProposalStatus::Pending);
+
+        // Answering twice is a conflict too
+        let response = handlers::itip::receive_reply(State(state.clone()), reply("prop_1")).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_unverified_address_needs_mailed_link() {
+        use crate::mail::{Mail, MailTransport};
+        use axum::extract::{Extension, Query, State};
+        use handlers::auth::{confirm_email, request_email_verification, ConfirmEmailQuery};
+        use middleware::AuthenticatedUser;
+
+        #[derive(Default)]
+        struct Outbox(std::sync::Mutex<Vec<Mail>>);
+
+        #[async_trait::async_trait]
+        impl MailTransport for Outbox {
+            async fn send(&self, mail: &Mail) -> anyhow::Result<()> {
+                self.0.lock().unwrap().push(mail.clone());
+                Ok(())
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let outbox = Arc::new(Outbox::default());
+        let state = Arc::new(
+            AppState::new(
+                Database::open_in_memory().unwrap(),
+                String::new(),
+                String::new(),
+                "https://meetd.example.com".to_string(),
+                None,
+            )
+            .with_mailer(Mailer::new("meetd@example.com", outbox.clone())),
+        );
+        let mut alice = user_with_ics(&state.db, "alice@example.com", &dir.path().join("a.ics"));
+        let bob = User {
+            id: uuid::Uuid::new_v4().to_string(),
+            email: "bob@example.com".to_string(),
+            email_verified: false,
+            ..alice.clone()
+        };
+        state.db.create_user(&bob).unwrap();
+        alice.sharing = SharingPolicy {
+            mode: crate::models::SharingMode::Allowlist,
+            allow: vec![bob.email.clone()],
+        };
+
+        // Someone who registered bob's address without owning it sees nothing
+        assert!(!state.shares_availability(&alice, &bob));
+
+        request_email_verification(
+            State(state.clone()),
+            Extension(AuthenticatedUser(bob.clone())),
+        )
+        .await;
+        let mail = outbox.0.lock().unwrap().pop().unwrap();
+        assert_eq!(mail.to, "bob@example.com");
+        let token = mail
+            .text
+            .split("token=")
+            .nth(1)
+            .and_then(|rest| rest.split_whitespace().next())
+            .unwrap()
+            .to_string();
+
+        let response = confirm_email(
+            State(state.clone()),
+            Query(ConfirmEmailQuery {
+                token: token.clone(),
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let bob = state.db.get_user(&bob.id).unwrap().unwrap();
+        assert!(bob.email_verified);
+        assert!(state.shares_availability(&alice, &bob));
+
+        // Links work once
+        let response =
+            confirm_email(State(state.clone()), Query(ConfirmEmailQuery { token })).await;
+        assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_backdated_proposal_from_rotated_key() {
+        use crate::crypto::Keypair;
+        use crate::models::{ProposalSlot, RevocationReason, SignedProposal};
+        use handlers::agent::check_incoming;
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::new(
+            Database::open_in_memory().unwrap(),
+            String::new(),
+            String::new(),
+            String::new(),
+            None,
+        );
+        let alice = user_with_ics(&state.db, "alice@example.com", &dir.path().join("a.ics"));
+        let bob = user_with_ics(&state.db, "bob@example.com", &dir.path().join("b.ics"));
+        let old_key = Keypair::generate().unwrap();
+        for key in [&old_key, &Keypair::generate().unwrap()] {
+            state
+                .db
+                .rotate_user_key(
+                    &alice.id,
+                    &key.public_key_base64(),
+                    "",
+                    RevocationReason::Superseded,
+                )
+                .unwrap();
+        }
+        let retired_at = state.db.get_user_keys(&alice.id).unwrap()[1]
+            .valid_until
+            .unwrap();
+
+        // Whoever still holds the superseded key backdates a proposal into its window
+        let start = Utc::now() + Duration::days(1);
+        let sign = |expires_at: DateTime<Utc>| {
+            let mut signed = SignedProposal {
+                version: PROPOSAL_VERSION,
+                from: alice.email.clone(),
+                from_pubkey: old_key.public_key_base64(),
+                to: bob.email.clone(),
+                slot: ProposalSlot {
+                    start,
+                    duration_minutes: 30,
+                },
+                slots: vec![ProposalSlot {
+                    start,
+                    duration_minutes: 30,
+                }],
+                title: None,
+                description: None,
+                recurrence: None,
+                location: None,
+                conference: None,
+                nonce: uuid::Uuid::new_v4().to_string(),
+                expires_at,
+                created_at: Some(retired_at),
+                signature: String::new(),
+            };
+            old_key.sign_proposal(&mut signed);
+            signed
+        };
+
+        // It can't outlive the usual lifetime, so the key stops working within a week
+        let long_lived = sign(Utc::now() + Duration::days(30));
+        assert!(check_incoming(&state, &bob, &long_lived).is_err());
+        let last_chance = sign(retired_at + Duration::days(7));
+        assert!(check_incoming(&state, &bob, &last_chance).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_pinned_key_follows_rotation() {
+        use crate::models::{KeySource, ProposalSlot, RevocationReason, SignedProposal};
+        use handlers::agent::check_pinned_key;
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::new(
+            Database::open_in_memory().unwrap(),
+            String::new(),
+            String::new(),
+            String::new(),
+            None,
+        );
+        let alice = user_with_ics(&state.db, "alice@example.com", &dir.path().join("a.ics"));
+        let bob = user_with_ics(&state.db, "bob@example.com", &dir.path().join("b.ics"));
+        let signed_with = |key: &str| SignedProposal {
+            version: PROPOSAL_VERSION,
+            from: alice.email.clone(),
+            from_pubkey: key.to_string(),
+            to: bob.email.clone(),
+            slot: ProposalSlot {
+                start: Utc::now(),
+                duration_minutes: 30,
+            },
+            slots: vec![],
+            title: None,
+            description: None,
+            recurrence: None,
+            location: None,
+            conference: None,
+            nonce: uuid::Uuid::new_v4().to_string(),
+            expires_at: Utc::now(),
+            created_at: None,
+            signature: String::new(),
+        };
+        let key_changed = |key: &str| {
+            let history = state.db.get_user_keys(&alice.id).unwrap();
+            check_pinned_key(
+                &state,
+                &bob,
+                &signed_with(key),
+                KeySource::Directory,
+                None,
+                &history,
+            )
+            .unwrap()
+        };
+        let pinned = || {
+            state
+                .db
+                .get_contact_key(&bob.id, &alice.email)
+                .unwrap()
+                .unwrap()
+                .public_key
+        };
+
+        // First use pins the key
+        assert!(!key_changed("pubkey"));
+        assert_eq!(pinned(), "pubkey");
+
+        // A rotation the history shows is followed silently
+        state
+            .db
+            .rotate_user_key(&alice.id, "pubkey2", "", RevocationReason::Superseded)
+            .unwrap();
+        assert!(!key_changed("pubkey2"));
+        assert_eq!(pinned(), "pubkey2");
+
+        // A key the history doesn't explain is flagged and not pinned
+        assert!(key_changed("stranger"));
+        assert_eq!(pinned(), "pubkey2");
+
+        // So is the replacement for a compromised key
+        state
+            .db
+            .rotate_user_key(&alice.id, "pubkey3", "", RevocationReason::Compromised)
+            .unwrap();
+        assert!(key_changed("pubkey3"));
+        assert_eq!(pinned(), "pubkey2");
+    }
+
+    #[tokio::test]
+    async fn test_federated_answer_needs_responders_key() {
+        use crate::crypto::Keypair;
+        use crate::federation::{FederationMessage, Peers, WELL_KNOWN_PATH};
+        use crate::models::{
+            Proposal, ProposalResponse, ProposalSlot, ProposalStatus, PubkeyHistoryResponse,
+            RemoteProposal, SignedProposal, UserKey,
+        };
+        use axum::{extract::State, http::HeaderMap, http::StatusCode};
+
+        // carol's server publishes her key history
+        let carol_key = Keypair::generate().unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let partner = format!("http://{}", listener.local_addr().unwrap());
+        let metadata = ServerMetadata::new(&partner, "serverkey".to_string());
+        let history = PubkeyHistoryResponse {
+            email: "carol@partner.com".to_string(),
+            public_key: carol_key.public_key_base64(),
+            keys: vec![UserKey {
+                public_key: carol_key.public_key_base64(),
+                valid_from: DateTime::from_timestamp(0, 0).unwrap(),
+                valid_until: None,
+                revocation_reason: None,
+            }],
+        };
+        let app = axum::Router::new()
+            .route(
+                WELL_KNOWN_PATH,
+                axum::routing::get(move || async move { axum::Json(metadata) }),
+            )
+            .route(
+                "/v1/agent/pubkey/:email/history",
+                axum::routing::get(move || async move { axum::Json(history) }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = Arc::new(
+            AppState::new(
+                Database::open_in_memory().unwrap(),
+                String::new(),
+                String::new(),
+                String::new(),
+                None,
+            )
+            .with_peers(Peers::parse(&format!("partner.com={}", partner)).unwrap()),
+        );
+        let alice = user_with_ics(&state.db, "alice@example.com", &dir.path().join("a.ics"));
+        let slot = ProposalSlot {
+            start: DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap() + Duration::days(1),
+            duration_minutes: 30,
+        };
+        let signed = SignedProposal {
+            version: PROPOSAL_VERSION,
+            from: alice.email.clone(),
+            from_pubkey: alice.public_key.clone(),
+            to: "carol@partner.com".to_string(),
+            slot: slot.clone(),
+            slots: vec![slot.clone()],
+            title: Some("Sync".to_string()),
+            description: None,
+            recurrence: None,
+            location: None,
+            conference: None,
+            nonce: uuid::Uuid::new_v4().to_string(),
+            expires_at: slot.start,
+            created_at: None,
+            signature: String::new(),
+        };
+        let mut proposal = Proposal::from_signed("prop_1".to_string(), alice.id.clone(), &signed);
+        proposal.remote = Some(RemoteProposal {
+            server: partner.clone(),
+            id: "prop_remote".to_string(),
+            reply_token: "token".to_string(),
+        });
+        state.db.create_proposal(&proposal).unwrap();
+
+        let answer = |response: Option<ProposalResponse>| {
+            let body = serde_json::to_string(&FederationMessage::Response {
+                proposal_id: "prop_1".to_string(),
+                reply_token: "token".to_string(),
+                status: ProposalStatus::Accepted,
+                option: None,
+                response,
+            })
+            .unwrap();
+            handlers::federation::receive(State(state.clone()), HeaderMap::new(), body)
+        };
+        let signed_by = |keypair: &Keypair| {
+            let mut response = ProposalResponse::new(
+                &signed.hash(),
+                "carol@partner.com",
+                &keypair.public_key_base64(),
+                &alice.email,
+                ProposalStatus::Accepted,
+                Some(slot.clone()),
+            );
+            keypair.sign_response(&mut response);
+            response
+        };
+        let status = || state.db.get_proposal("prop_1").unwrap().unwrap().status;
+
+        // An unsigned answer isn't taken
+        assert_eq!(answer(None).await.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(status(), ProposalStatus::Pending);
+
+        // Nor one signed with a key carol's server doesn't list
+        let stranger = Keypair::generate().unwrap();
+        let response = answer(Some(signed_by(&stranger))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(status(), ProposalStatus::Pending);
+
+        let response = answer(Some(signed_by(&carol_key))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(status(), ProposalStatus::Accepted);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server_gets_message_later() {
+        use crate::federation::FederationReceipt;
+        use crate::models::{Proposal, ProposalSlot, RemoteProposal, SignedProposal};
+        use axum::extract::{Extension, Path, State};
+        use middleware::AuthenticatedUser;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // The partner's address, with nothing listening yet
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let partner = format!("http://{}", addr);
+        drop(listener);
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = Arc::new(AppState::new(
+            Database::open_in_memory().unwrap(),
+            String::new(),
+            String::new(),
+            "https://meetd.example.com".to_string(),
+            None,
+        ));
+        let alice = user_with_ics(&state.db, "alice@example.com", &dir.path().join("a.ics"));
+        let slot = ProposalSlot {
+            start: DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap() + Duration::days(1),
+            duration_minutes: 30,
+        };
+        let signed = SignedProposal {
+            version: PROPOSAL_VERSION,
+            from: alice.email.clone(),
+            from_pubkey: alice.public_key.clone(),
+            to: "carol@partner.com".to_string(),
+            slot: slot.clone(),
+            slots: vec![slot.clone()],
+            title: None,
+            description: None,
+            recurrence: None,
+            location: None,
+            conference: None,
+            nonce: uuid::Uuid::new_v4().to_string(),
+            expires_at: slot.start,
+            created_at: None,
+            signature: String::new(),
+        };
+        let mut proposal = Proposal::from_signed("prop_1".to_string(), alice.id.clone(), &signed);
+        proposal.remote = Some(RemoteProposal {
+            server: partner.clone(),
+            id: "prop_remote".to_string(),
+            reply_token: "token".to_string(),
+        });
+        state.db.create_proposal(&proposal).unwrap();
+
+        // The withdrawal goes through here, and the caller hears the partner wasn't told
+        let response = handlers::proposals::withdraw_proposal(
+            State(state.clone()),
+            Extension(AuthenticatedUser(alice.clone())),
+            Path("prop_1".to_string()),
+        )
+        .await;
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["status"], "withdrawn");
+        assert!(body["warning"].as_str().unwrap().contains("retried"));
+
+        let later = Utc::now() + Duration::hours(1);
+        let queued = state.db.due_federation_messages(later).unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].server, partner);
+
+        // Once the partner is back, the next retry delivers it
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = axum::Router::new().route(
+            crate::federation::FEDERATION_INBOX_PATH,
+            axum::routing::post(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    axum::Json(FederationReceipt {
+                        proposal_id: "prop_remote".to_string(),
+                    })
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        state.flush_federation_outbox().await;
+        assert_eq!(hits.load(Ordering::SeqCst), 0, "not due yet");
+
+        state
+            .db
+            .retry_federation_message(queued[0].id, "", Utc::now())
+            .unwrap();
+        state.flush_federation_outbox().await;
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        assert!(state.db.due_federation_messages(later).unwrap().is_empty());
+    }
+}
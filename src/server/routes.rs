@@ -1,6 +1,6 @@
 use axum::{
     middleware,
-    routing::{delete, get, patch, post},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use std::sync::Arc;
@@ -22,22 +22,56 @@ pub fn create_router(state: Arc<AppState>) -> Router {
     let public_routes = Router::new()
         .route("/health", get(handlers::health))
         .route("/auth/register", post(handlers::auth::register))
+        .route(
+            "/auth/register/caldav",
+            post(handlers::auth::register_caldav),
+        )
+        .route("/auth/register/ics", post(handlers::auth::register_ics))
         .route("/auth/google", get(handlers::auth::google_redirect))
         .route("/auth/callback", get(handlers::auth::google_callback))
-        .route("/v1/agent/pubkey/:email", get(handlers::agent::get_pubkey));
+        .route("/auth/email/confirm", get(handlers::auth::confirm_email))
+        .route("/v1/agent/pubkey/:email", get(handlers::agent::get_pubkey))
+        .route(
+            "/v1/agent/pubkey/:email/history",
+            get(handlers::agent::get_pubkey_history),
+        )
+        // Other meetd servers (authenticated by signature or reply token)
+        .route("/.well-known/meetd", get(handlers::federation::metadata))
+        .route("/federation/inbox", post(handlers::federation::receive))
+        // Calendar clients answering emailed invitations (authenticated by UID)
+        .route("/v1/itip/reply", post(handlers::itip::receive_reply));
 
     // Protected routes (require API key)
     let protected_routes = Router::new()
         // Config
         .route("/v1/config", get(handlers::config::get_config))
         .route("/v1/config", patch(handlers::config::update_config))
+        // Calendar accounts
+        .route("/v1/calendars", get(handlers::calendars::list_calendars))
+        .route("/v1/calendars", post(handlers::calendars::link_calendar))
+        .route(
+            "/v1/calendars/:id",
+            delete(handlers::calendars::unlink_calendar),
+        )
+        .route(
+            "/v1/calendars/:id/primary",
+            post(handlers::calendars::set_primary_calendar),
+        )
         // Availability
         .route(
             "/v1/availability",
             post(handlers::availability::query_availability),
         )
+        .route(
+            "/v1/availability/busy",
+            post(handlers::availability::query_busy),
+        )
         // Proposals
         .route("/v1/proposals", post(handlers::proposals::create_proposal))
+        .route(
+            "/v1/proposals/signed",
+            post(handlers::proposals::submit_signed_proposal),
+        )
         .route(
             "/v1/proposals/sent",
             get(handlers::proposals::get_sent_proposals),
@@ -47,25 +81,65 @@ pub fn create_router(state: Arc<AppState>) -> Router {
             "/v1/proposals/:id/accept",
             post(handlers::proposals::accept_proposal),
         )
+        .route(
+            "/v1/proposals/:id/accept/:option",
+            post(handlers::proposals::accept_proposal_option),
+        )
         .route(
             "/v1/proposals/:id/decline",
             post(handlers::proposals::decline_proposal),
         )
+        .route(
+            "/v1/proposals/:id/counter",
+            post(handlers::proposals::counter_proposal),
+        )
+        .route(
+            "/v1/proposals/:id/withdraw",
+            post(handlers::proposals::withdraw_proposal),
+        )
+        .route(
+            "/v1/proposals/:id/cancel",
+            post(handlers::proposals::cancel_proposal),
+        )
+        .route(
+            "/v1/proposals/:id/reschedule",
+            post(handlers::proposals::reschedule_proposal),
+        )
         .route(
             "/v1/proposals/verify",
             post(handlers::proposals::verify_proposal),
         )
+        .route(
+            "/v1/proposals/verify-response",
+            post(handlers::proposals::verify_response),
+        )
         // Inbox
         .route("/v1/inbox", get(handlers::inbox::list_inbox))
         .route("/v1/inbox/stream", get(handlers::inbox::stream_inbox))
         // Agent-to-agent
         .route("/v1/agent/inbox", post(handlers::agent::receive_proposal))
+        // Pinned contact keys
+        .route("/v1/contacts", get(handlers::contacts::list_contacts))
+        .route(
+            "/v1/contacts/:email/key",
+            put(handlers::contacts::trust_contact).delete(handlers::contacts::untrust_contact),
+        )
         // Webhooks
         .route("/v1/webhooks", post(handlers::webhooks::register_webhook))
         .route("/v1/webhooks", delete(handlers::webhooks::remove_webhook))
         .route("/v1/webhooks/test", post(handlers::webhooks::test_webhook))
         // API key rotation
         .route("/auth/key/rotate", post(handlers::auth::rotate_api_key))
+        .route(
+            "/auth/email/verify",
+            post(handlers::auth::request_email_verification),
+        )
+        // Client-held signing keys
+        .route("/auth/signing-key", put(handlers::auth::set_signing_key))
+        .route(
+            "/auth/signing-key/rotate",
+            post(handlers::auth::rotate_signing_key),
+        )
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
@@ -1,7 +1,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::models::ProposalSlot;
+use crate::models::{Conference, ProposalResponse, ProposalSlot};
 
 /// Types of webhook events
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -15,6 +15,14 @@ pub enum WebhookEventType {
     ProposalDeclined,
     /// A proposal expired without response
     ProposalExpired,
+    /// Your proposal was answered with a counter-proposal
+    ProposalCountered,
+    /// An accepted meeting was cancelled
+    ProposalCancelled,
+    /// An accepted meeting was moved
+    ProposalRescheduled,
+    /// The sender withdrew a proposal sent to you
+    ProposalWithdrawn,
 }
 
 impl WebhookEventType {
@@ -24,6 +32,10 @@ impl WebhookEventType {
             WebhookEventType::ProposalAccepted => "proposal.accepted",
             WebhookEventType::ProposalDeclined => "proposal.declined",
             WebhookEventType::ProposalExpired => "proposal.expired",
+            WebhookEventType::ProposalCountered => "proposal.countered",
+            WebhookEventType::ProposalCancelled => "proposal.cancelled",
+            WebhookEventType::ProposalRescheduled => "proposal.rescheduled",
+            WebhookEventType::ProposalWithdrawn => "proposal.withdrawn",
         }
     }
 }
@@ -56,7 +68,7 @@ impl WebhookEvent {
 }
 
 /// Event-specific data
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct WebhookEventData {
     /// Proposal ID
     pub proposal_id: String,
@@ -65,9 +77,12 @@ pub struct WebhookEventData {
     /// Sender's public key
     #[serde(skip_serializing_if = "Option::is_none")]
     pub from_pubkey: Option<String>,
-    /// Proposed time slot
+    /// Proposed time slot (the chosen one, for accepted proposals)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub slot: Option<ProposalSlot>,
+    /// Every offered slot, when the proposal offers several
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub slots: Vec<ProposalSlot>,
     /// Meeting title
     #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
@@ -80,6 +95,27 @@ pub struct WebhookEventData {
     /// Calendar event link (for accepted proposals)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub calendar_link: Option<String>,
+    /// The proposal being countered (for countered proposals)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counter_of: Option<String>,
+    /// Note from the sender
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// RRULE value, for recurring meetings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<String>,
+    /// Where the meeting takes place
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// How to join remotely
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conference: Option<Conference>,
+    /// The sender signed with a different key than the one pinned for them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub key_changed: bool,
+    /// The recipient's signed acceptance or decline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<ProposalResponse>,
 }
 
 impl WebhookEventData {
@@ -101,7 +137,7 @@ impl WebhookEventData {
             title,
             expires_at: Some(expires_at),
             signature: Some(signature),
-            calendar_link: None,
+            ..Default::default()
         }
     }
 
@@ -109,17 +145,15 @@ impl WebhookEventData {
     pub fn proposal_accepted(
         proposal_id: String,
         from: String,
+        slot: ProposalSlot,
         calendar_link: Option<String>,
     ) -> Self {
         Self {
             proposal_id,
             from,
-            from_pubkey: None,
-            slot: None,
-            title: None,
-            expires_at: None,
-            signature: None,
+            slot: Some(slot),
             calendar_link,
+            ..Default::default()
         }
     }
 
@@ -128,12 +162,7 @@ impl WebhookEventData {
         Self {
             proposal_id,
             from,
-            from_pubkey: None,
-            slot: None,
-            title: None,
-            expires_at: None,
-            signature: None,
-            calendar_link: None,
+            ..Default::default()
         }
     }
 
@@ -142,12 +171,41 @@ impl WebhookEventData {
         Self {
             proposal_id,
             from,
-            from_pubkey: None,
-            slot: None,
-            title: None,
-            expires_at: None,
-            signature: None,
-            calendar_link: None,
+            ..Default::default()
+        }
+    }
+
+    /// Create data for a proposal.cancelled event
+    pub fn proposal_cancelled(proposal_id: String, from: String) -> Self {
+        Self {
+            proposal_id,
+            from,
+            ..Default::default()
+        }
+    }
+
+    /// Create data for a proposal.rescheduled event
+    pub fn proposal_rescheduled(
+        proposal_id: String,
+        from: String,
+        slot: ProposalSlot,
+        calendar_link: Option<String>,
+    ) -> Self {
+        Self {
+            proposal_id,
+            from,
+            slot: Some(slot),
+            calendar_link,
+            ..Default::default()
+        }
+    }
+
+    /// Create data for a proposal.withdrawn event
+    pub fn proposal_withdrawn(proposal_id: String, from: String) -> Self {
+        Self {
+            proposal_id,
+            from,
+            ..Default::default()
         }
     }
 }